@@ -835,6 +835,7 @@ fn main() {
         skip_logging: true,
         skip_host_settings: true,
         appname: Some(String::from("router")),
+        ..init::InitOptions::new()
     };
 
     init::with_options(&init_ops).unwrap();
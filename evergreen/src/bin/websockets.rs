@@ -8,32 +8,40 @@ use eg::Client;
 use eg::EgResult;
 use evergreen as eg;
 use mptc;
+use native_tls::{Identity, TlsAcceptor, TlsStream};
 use std::any::Any;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Write};
 use std::net::TcpListener;
-use std::net::{SocketAddr, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tungstenite as ws;
 use ws::protocol::Message as WebSocketMessage;
 use ws::protocol::WebSocket;
 
 const DEFAULT_PORT: u16 = 7682;
 
+/// Number of independently locked shards in a ConnectionRegistry.
+const REGISTRY_SHARDS: usize = 16;
+
 /// Prevent huge session threads
 const MAX_THREAD_SIZE: usize = 256;
 
-/// Largest allowed inbound websocket message.
+/// Default largest allowed inbound websocket message.
 ///
 /// Message size is typically limited by the the HTTP proxy,
 /// e.g. nginx, so this is more of a backstop.
-const MAX_MESSAGE_SIZE: usize = 10485760; // ~10M
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 10485760; // ~10M
 
 const WEBSOCKET_INGRESS: &str = "ws-translator-v3";
 
@@ -46,11 +54,42 @@ const MAX_ACTIVE_REQUESTS: usize = 8;
 ///
 /// If we reach MAX_ACTIVE_REQUESTS, we start leaving new requests in
 /// the backlog.  If the size of the baclkog exceeds this amount,
-/// discard all of the pending requests and disconnect the client.
+/// the BacklogPolicy decides what happens to new requests.
 const MAX_BACKLOG_SIZE: usize = 1000;
 
 const SIG_POLL_INTERVAL: u64 = 3;
 
+/// How many times we retry sending a message to the websocket client
+/// after a transient (would-block, interrupted, queue-full) failure.
+const DEFAULT_SEND_RETRIES: usize = 3;
+
+/// Log a warning once the number of active sessions reaches this
+/// percentage of the maximum allowed clients.
+const DEFAULT_SATURATION_WARN_PERCENT: usize = 90;
+
+/// How long we wait for a client to complete the TLS and websocket
+/// handshakes.
+const TLS_HANDSHAKE_TIMEOUT: u64 = 10;
+
+/// Default read timeout for TLS client streams.
+///
+/// The inbound and outbound halves of a TLS connection share a lock,
+/// so reads give up the lock this often to let responses through.
+/// A response may wait up to this long for the lock, while shorter
+/// timeouts mean more idle wakeups per connection.  See ClientStream.
+const DEFAULT_TLS_READ_TIMEOUT_MS: u64 = 50;
+
+/// Seconds between keepalive Pings sent to the websocket client.
+const DEFAULT_PING_INTERVAL: u64 = 30;
+
+/// Number of consecutive unanswered Pings after which the client
+/// is considered gone.
+const MAX_UNANSWERED_PINGS: usize = 2;
+
+/// Base delay between websocket send retries.  The delay grows
+/// linearly with each attempt.
+const SEND_RETRY_INTERVAL_MS: u64 = 50;
+
 /* Server spawns a new client session per connection.
  *
  * Each client session is composed of 3 threads: Inbound, Main, and Outbound.
@@ -65,6 +104,205 @@ const SIG_POLL_INTERVAL: u64 = 3;
  * tracks connected sessions.
  */
 
+/// Client connection stream, optionally wrapped in TLS.
+///
+/// Unlike a TcpStream, a TLS stream cannot be split into independent
+/// read and write halves, so clones of a TLS ClientStream share the
+/// underlying stream behind a mutex.
+///
+/// This has a cost for TLS connections.  The inbound thread holds the
+/// lock while it waits for client data, up to the TLS read timeout, so
+/// outbound responses may be delayed by up to that long.  And an idle
+/// connection wakes its inbound thread once per timeout to release the
+/// lock.  The timeout is set via EG_WEBSOCKETS_TLS_READ_TIMEOUT_MS.
+/// Terminating TLS in a proxy instead avoids both.
+enum ClientStream {
+    Plain(TcpStream),
+    Tls(Arc<Mutex<TlsStream<TcpStream>>>),
+}
+
+impl ClientStream {
+    /// Wrap a newly accepted connection, performing the TLS handshake
+    /// first if we have a TlsAcceptor.
+    fn accept(stream: TcpStream, tls: Option<&TlsAcceptor>) -> Result<ClientStream, String> {
+        let acceptor = match tls {
+            Some(a) => a,
+            None => return Ok(ClientStream::Plain(stream)),
+        };
+
+        stream
+            .set_read_timeout(Some(Duration::from_secs(TLS_HANDSHAKE_TIMEOUT)))
+            .or_else(|e| Err(format!("Error setting TLS handshake timeout: {e}")))?;
+
+        let tls_stream = acceptor
+            .accept(stream)
+            .or_else(|e| Err(format!("TLS handshake failed: {e}")))?;
+
+        Ok(ClientStream::Tls(Arc::new(Mutex::new(tls_stream))))
+    }
+
+    fn try_clone(&self) -> io::Result<ClientStream> {
+        match self {
+            ClientStream::Plain(s) => Ok(ClientStream::Plain(s.try_clone()?)),
+            ClientStream::Tls(s) => Ok(ClientStream::Tls(s.clone())),
+        }
+    }
+
+    /// Called once the websocket handshake is complete so the reader
+    /// only holds the shared TLS stream for short stretches.
+    fn set_tls_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        if let ClientStream::Tls(s) = self {
+            ClientStream::lock(s)?
+                .get_ref()
+                .set_read_timeout(Some(timeout))?;
+        }
+        Ok(())
+    }
+
+    fn lock(
+        s: &Mutex<TlsStream<TcpStream>>,
+    ) -> io::Result<std::sync::MutexGuard<'_, TlsStream<TcpStream>>> {
+        s.lock().or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "TLS stream lock poisoned",
+            ))
+        })
+    }
+}
+
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(s) => s.read(buf),
+            ClientStream::Tls(s) => {
+                let result = ClientStream::lock(s)?.read(buf);
+
+                if let Err(ref e) = result {
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        // Give any waiting writer a chance at the lock.
+                        thread::yield_now();
+                    }
+                }
+
+                result
+            }
+        }
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(s) => s.write(buf),
+            ClientStream::Tls(s) => ClientStream::lock(s)?.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientStream::Plain(s) => s.flush(),
+            ClientStream::Tls(s) => ClientStream::lock(s)?.flush(),
+        }
+    }
+}
+
+/// Build a TlsAcceptor from PEM-encoded certificate (chain) and
+/// PKCS #8 private key files.
+fn load_tls_acceptor(cert_file: &str, key_file: &str) -> Result<TlsAcceptor, String> {
+    let cert = fs::read(cert_file)
+        .or_else(|e| Err(format!("Cannot read TLS certificate {cert_file}: {e}")))?;
+
+    let key =
+        fs::read(key_file).or_else(|e| Err(format!("Cannot read TLS key {key_file}: {e}")))?;
+
+    let identity = Identity::from_pkcs8(&cert, &key)
+        .or_else(|e| Err(format!("Invalid TLS certificate or key: {e}")))?;
+
+    TlsAcceptor::new(identity).or_else(|e| Err(format!("Cannot create TLS acceptor: {e}")))
+}
+
+/// True if a client presenting the provided Origin header may connect.
+///
+/// An empty allow-list allows all origins.  Otherwise, the Origin must
+/// be present and match an entry, ignoring case and trailing slashes.
+fn origin_allowed(allowed_origins: &[String], origin: Option<&str>) -> bool {
+    if allowed_origins.is_empty() {
+        return true;
+    }
+
+    let Some(origin) = origin else {
+        return false;
+    };
+
+    let origin = origin.trim().trim_end_matches('/');
+
+    allowed_origins
+        .iter()
+        .any(|o| o.trim_end_matches('/').eq_ignore_ascii_case(origin))
+}
+
+/// Complete the (optional) TLS and websocket handshakes for a new
+/// client connection.
+///
+/// Connections whose Origin header is not allowed by allowed_origins
+/// are refused during the websocket handshake.
+///
+/// Returns the client address and the receiving and sending
+/// endpoints for the websocket.
+fn accept_websocket(
+    stream: TcpStream,
+    tls: Option<&TlsAcceptor>,
+    allowed_origins: &[String],
+    tls_read_timeout: Duration,
+    metrics: &SessionMetrics,
+) -> Result<(SocketAddr, WebSocket<ClientStream>, WebSocket<ClientStream>), String> {
+    let client_ip = stream
+        .peer_addr()
+        .or_else(|e| Err(format!("Could not determine client IP address: {e}")))?;
+
+    let instream = ClientStream::accept(stream, tls)?;
+
+    // Split the stream into a read/write pair so each endpoint
+    // can be managed within its own thread.
+    let outstream = instream
+        .try_clone()
+        .or_else(|e| Err(format!("Fatal error splitting client streams: {e}")))?;
+
+    let check_origin = |request: &ws::handshake::server::Request,
+                        response: ws::handshake::server::Response| {
+        let origin = request
+            .headers()
+            .get(ws::http::header::ORIGIN)
+            .and_then(|v| v.to_str().ok());
+
+        if origin_allowed(allowed_origins, origin) {
+            return Ok(response);
+        }
+
+        log::warn!("Refusing connection from {client_ip}; origin not allowed: {origin:?}");
+        metrics.rejected_origin.fetch_add(1, Ordering::SeqCst);
+
+        let mut error =
+            ws::handshake::server::ErrorResponse::new(Some("Origin not allowed".to_string()));
+        *error.status_mut() = ws::http::StatusCode::FORBIDDEN;
+
+        Err(error)
+    };
+
+    // Wrap each endpoint in a WebSocket container.
+    let receiver = ws::accept_hdr(instream, check_origin)
+        .or_else(|e| Err(format!("Error accepting new connection: {}", e)))?;
+
+    outstream
+        .set_tls_read_timeout(tls_read_timeout)
+        .or_else(|e| Err(format!("Error setting TLS read timeout: {e}")))?;
+
+    let sender = WebSocket::from_raw_socket(outstream, ws::protocol::Role::Server, None);
+
+    Ok((client_ip, receiver, sender))
+}
+
 /// ChannelMessage's are delivered to the main thread.  There are 2
 /// types: Inbound websocket request and Ooutbound opensrf response.
 #[derive(Debug, PartialEq)]
@@ -76,6 +314,157 @@ enum ChannelMessage {
     Outbound(message::TransportMessage),
 }
 
+/// What to do with inbound requests once a session's backlog is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BacklogPolicy {
+    /// Close the connection with a 1013 (Try Again Later) code.
+    Disconnect,
+    /// Log and discard the request.
+    Drop,
+}
+
+impl TryFrom<&str> for BacklogPolicy {
+    type Error = String;
+    fn try_from(s: &str) -> Result<BacklogPolicy, String> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(Self::Drop),
+            "disconnect" => Ok(Self::Disconnect),
+            _ => Err(format!("Invalid backlog policy: {s}")),
+        }
+    }
+}
+
+/// Close frame sent to clients whose backlog has overflowed.
+fn backlog_close_frame() -> WebSocketMessage {
+    WebSocketMessage::Close(Some(ws::protocol::CloseFrame {
+        code: ws::protocol::frame::coding::CloseCode::Again,
+        reason: "Request backlog is full".into(),
+    }))
+}
+
+/// Outbound half of a websocket connection.
+///
+/// Abstracted so the send retry logic can be tested without a socket.
+trait MessageSender {
+    fn write_message(&mut self, msg: WebSocketMessage) -> Result<(), ws::Error>;
+    fn write_pending(&mut self) -> Result<(), ws::Error>;
+}
+
+impl<S: Read + Write> MessageSender for WebSocket<S> {
+    fn write_message(&mut self, msg: WebSocketMessage) -> Result<(), ws::Error> {
+        WebSocket::write_message(self, msg)
+    }
+
+    fn write_pending(&mut self) -> Result<(), ws::Error> {
+        WebSocket::write_pending(self)
+    }
+}
+
+/// Send a message to a websocket client, retrying a few times
+/// with a short backoff when the failure is transient.
+///
+/// A would-block/interrupted IO error means the message has already
+/// been queued by the websocket and was only partially flushed, so
+/// retries flush the pending data instead of re-sending the message.
+/// A full send queue hands the unsent message back to us, which is
+/// then safe to send again.  Anything else is considered fatal.
+fn send_with_retries(
+    sender: &mut impl MessageSender,
+    msg: WebSocketMessage,
+    retries: usize,
+    client_ip: &SocketAddr,
+) -> Result<(), String> {
+    let mut result = sender.write_message(msg);
+    let mut attempts = 0;
+
+    loop {
+        let err = match result {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        let transient = match err {
+            ws::Error::Io(ref e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+            ),
+            ws::Error::SendQueueFull(_) => true,
+            _ => false,
+        };
+
+        if !transient || attempts >= retries {
+            return Err(format!(
+                "Session ({client_ip}) Error sending response to websocket client: {err}"
+            ));
+        }
+
+        attempts += 1;
+
+        log::warn!(
+            "Session ({client_ip}) transient websocket send error; retry #{attempts}: {err}"
+        );
+
+        thread::sleep(Duration::from_millis(
+            SEND_RETRY_INTERVAL_MS * attempts as u64,
+        ));
+
+        result = match err {
+            ws::Error::SendQueueFull(m) => sender.write_message(m),
+            _ => sender.write_pending(),
+        };
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum KeepaliveAction {
+    /// Nothing to do yet.
+    Wait,
+    /// Time to send a Ping.
+    Ping,
+    /// Too many Pings went unanswered.
+    Dead,
+}
+
+/// Tracks Pings sent to a websocket client and the Pongs it returns
+/// so half-open connections can be detected.
+#[derive(Debug)]
+struct Keepalive {
+    interval: Duration,
+    last_ping: Instant,
+    unanswered: usize,
+}
+
+impl Keepalive {
+    fn new(interval: Duration) -> Self {
+        Keepalive {
+            interval,
+            last_ping: Instant::now(),
+            unanswered: 0,
+        }
+    }
+
+    /// What to do now.  Returning Ping assumes the Ping is sent.
+    fn next_action(&mut self) -> KeepaliveAction {
+        if self.last_ping.elapsed() < self.interval {
+            return KeepaliveAction::Wait;
+        }
+
+        if self.unanswered >= MAX_UNANSWERED_PINGS {
+            return KeepaliveAction::Dead;
+        }
+
+        self.unanswered += 1;
+        self.last_ping = Instant::now();
+
+        KeepaliveAction::Ping
+    }
+
+    /// The client answered.
+    fn pong(&mut self) {
+        self.unanswered = 0;
+    }
+}
+
 /// Listens for inbound websocket requests from our connected client
 /// and relay them to the main thread.
 struct SessionInbound {
@@ -96,7 +485,7 @@ impl fmt::Display for SessionInbound {
 }
 
 impl SessionInbound {
-    fn run(&mut self, mut receiver: WebSocket<TcpStream>) {
+    fn run(&mut self, mut receiver: WebSocket<ClientStream>) {
         // Pull messages from our websocket TCP stream, forwarding each to
         // the Session thread for processing.
 
@@ -203,6 +592,66 @@ impl SessionOutbound {
     }
 }
 
+/// A stateful/connected OpenSRF session.
+struct OsrfSession {
+    /// Service the client connected to.
+    service: String,
+
+    /// Bus address of the OpenSRF worker handling the session.
+    /// None until the worker responds to our CONNECT.
+    worker: Option<String>,
+}
+
+/// Returns the worker address to which a message for the given
+/// thread and service may be sent directly.
+///
+/// Only threads belonging to a session we connected for the same
+/// service qualify.  Anything else is treated as a new stateless
+/// request and goes via the router.
+fn cached_worker(
+    osrf_sessions: &HashMap<String, OsrfSession>,
+    thread: &str,
+    service: &str,
+) -> Option<String> {
+    let ses = osrf_sessions.get(thread)?;
+
+    if ses.service != service {
+        log::warn!(
+            "Thread {thread} is connected to {} not {service}; routing via router",
+            ses.service
+        );
+        return None;
+    }
+
+    ses.worker.clone()
+}
+
+/// Per-session settings shared by all sessions of a WebsocketStream.
+#[derive(Clone)]
+struct SessionConfig {
+    /// Maximum number of active/parallel websocket requests to
+    /// relay to OpenSRF at a time.
+    max_parallel: usize,
+
+    /// Number of websocket send retries on transient errors.
+    send_retries: usize,
+
+    /// Largest allowed inbound websocket message.
+    max_message_size: usize,
+
+    /// How often to Ping clients.  None disables keepalive Pings.
+    ping_interval: Option<Duration>,
+
+    /// What to do when the request backlog is full.
+    backlog_policy: BacklogPolicy,
+
+    /// Browser origins allowed to connect.  Empty means any origin.
+    allowed_origins: Arc<Vec<String>>,
+
+    /// How long TLS reads hold the shared stream lock.
+    tls_read_timeout: Duration,
+}
+
 /// Manages a single websocket client connection.  Sessions run in the
 /// main thread for each websocket connection.
 struct Session {
@@ -210,7 +659,7 @@ struct Session {
     to_main_rx: mpsc::Receiver<ChannelMessage>,
 
     /// For posting responses to the outbound websocket stream.
-    sender: WebSocket<TcpStream>,
+    sender: WebSocket<ClientStream>,
 
     /// Relays request to the OpenSRF bus.
     osrf_sender: Bus,
@@ -225,7 +674,7 @@ struct Session {
     /// These must be tracked so that subsequent requests for the
     /// same OpenSRF session may be routed to the OpenSRF worker
     /// we have already connected to.
-    osrf_sessions: HashMap<String, String>,
+    osrf_sessions: HashMap<String, OsrfSession>,
 
     /// Number of inbound connects/requests that are currently
     /// awaiting a final response.
@@ -239,6 +688,22 @@ struct Session {
     /// are queued for delivery and relayed as soon as possible.
     max_parallel: usize,
 
+    /// Number of times to retry sending a message to the websocket
+    /// client after a transient error before giving up.
+    send_retries: usize,
+
+    /// Largest allowed inbound websocket message.
+    max_message_size: usize,
+
+    /// Pings the client periodically, if enabled.
+    keepalive: Option<Keepalive>,
+
+    /// What to do when the request backlog is full.
+    backlog_policy: BacklogPolicy,
+
+    /// Gateway-wide counters.
+    metrics: Arc<SessionMetrics>,
+
     /// Any time we receive a 'format' request in a message, we
     /// set that as our default format going forward for this
     /// client session.  It's assumed that clients will generally
@@ -256,26 +721,23 @@ impl fmt::Display for Session {
 }
 
 impl Session {
-    fn run(stream: TcpStream, max_parallel: usize, shutdown: Arc<AtomicBool>) -> EgResult<()> {
-        let client_ip = stream
-            .peer_addr()
-            .or_else(|e| Err(format!("Could not determine client IP address: {e}")))?;
+    fn run(
+        stream: TcpStream,
+        tls: Option<&TlsAcceptor>,
+        config: &SessionConfig,
+        metrics: Arc<SessionMetrics>,
+        shutdown: Arc<AtomicBool>,
+    ) -> EgResult<()> {
+        let (client_ip, receiver, sender) = accept_websocket(
+            stream,
+            tls,
+            &config.allowed_origins,
+            config.tls_read_timeout,
+            &metrics,
+        )?;
 
         log::debug!("Starting new session for {client_ip}");
 
-        // Split the TcpStream into a read/write pair so each endpoint
-        // can be managed within its own thread.
-        let instream = stream;
-        let outstream = instream
-            .try_clone()
-            .or_else(|e| Err(format!("Fatal error splitting client streams: {e}")))?;
-
-        // Wrap each endpoint in a WebSocket container.
-        let receiver = ws::accept(instream)
-            .or_else(|e| Err(format!("Error accepting new connection: {}", e)))?;
-
-        let sender = WebSocket::from_raw_socket(outstream, ws::protocol::Role::Server, None);
-
         let (to_main_tx, to_main_rx) = mpsc::channel();
 
         let gateway = conf::config().gateway();
@@ -316,7 +778,12 @@ impl Session {
             to_main_rx,
             sender,
             osrf_sender,
-            max_parallel,
+            max_parallel: config.max_parallel,
+            send_retries: config.send_retries,
+            max_message_size: config.max_message_size,
+            keepalive: config.ping_interval.map(Keepalive::new),
+            backlog_policy: config.backlog_policy,
+            metrics,
             reqs_in_flight: 0,
             format: None,
             shutdown,
@@ -388,6 +855,35 @@ impl Session {
         return false;
     }
 
+    /// Ping the client when it's time.  Returns true if the client
+    /// has stopped answering and the session should end.
+    ///
+    /// Pings are websocket control frames and have no effect on our
+    /// OpenSRF request tracking.
+    fn send_keepalive(&mut self) -> bool {
+        let action = match self.keepalive.as_mut() {
+            Some(k) => k.next_action(),
+            None => return false,
+        };
+
+        match action {
+            KeepaliveAction::Wait => false,
+            KeepaliveAction::Ping => {
+                log::trace!("{self} sending keepalive Ping");
+                if let Err(e) = self.write_to_websocket(WebSocketMessage::Ping(Vec::new())) {
+                    log::error!("{self} Error sending Ping: {e}");
+                    return true;
+                }
+                false
+            }
+            KeepaliveAction::Dead => {
+                log::info!("{self} client stopped answering Pings; closing");
+                self.shutdown_session.store(true, Ordering::Relaxed);
+                true
+            }
+        }
+    }
+
     /// Main Session listen loop
     fn listen(&mut self) {
         loop {
@@ -395,6 +891,10 @@ impl Session {
                 return;
             }
 
+            if self.send_keepalive() {
+                return;
+            }
+
             let recv_result = self
                 .to_main_rx
                 .recv_timeout(Duration::from_secs(SIG_POLL_INTERVAL));
@@ -452,8 +952,19 @@ impl Session {
     fn process_message_queue(&mut self) -> Result<(), String> {
         while self.reqs_in_flight < self.max_parallel {
             if let Some(text) = self.request_queue.pop_front() {
+                let wrapper = match parse_ws_message(&text) {
+                    Ok(w) => w,
+                    Err((thread, err)) => {
+                        // A malformed message only affects itself.
+                        // Tell the client and keep the connection.
+                        log::error!("{self} {err}: {text}");
+                        self.write_to_websocket(client_error_frame(thread.as_deref(), &err))?;
+                        continue;
+                    }
+                };
+
                 // relay_to_osrf() increments self.reqs_in_flight as needed.
-                self.relay_to_osrf(&text)?;
+                self.relay_to_osrf(wrapper)?;
             } else {
                 // Backlog is empty
                 log::trace!("{self} message queue is now empty");
@@ -478,17 +989,25 @@ impl Session {
             WebSocketMessage::Text(text) => {
                 let tlen = text.len();
 
-                if tlen >= MAX_MESSAGE_SIZE {
+                if tlen >= self.max_message_size {
                     log::error!("{self} Dropping huge websocket message size={tlen}");
+
+                    // Let the client know why it will get no response.
+                    let err = format!(
+                        "Message size {tlen} exceeds maximum size {}",
+                        self.max_message_size
+                    );
+
+                    self.write_to_websocket(client_error_frame(None, &err))?;
                 } else if self.request_queue.len() >= MAX_BACKLOG_SIZE {
-                    // Client is getting out of handle.  Let them go.
-                    return Err(format!(
-                        "Backlog exceeds max size={}; dropping connectino",
-                        MAX_BACKLOG_SIZE
-                    ));
+                    return self.handle_backlog_overflow();
                 } else {
                     log::trace!("{self} Queueing inbound message for processing");
                     self.request_queue.push_back(text);
+
+                    self.metrics
+                        .backlog_peak
+                        .fetch_max(self.request_queue.len(), Ordering::Relaxed);
                 }
 
                 Ok(false)
@@ -500,6 +1019,12 @@ impl Session {
                     .or_else(|e| Err(format!("{self} Error sending Pong to client: {e}")))?;
                 Ok(false)
             }
+            WebSocketMessage::Pong(_) => {
+                if let Some(k) = self.keepalive.as_mut() {
+                    k.pong();
+                }
+                Ok(false)
+            }
             WebSocketMessage::Close(_) => {
                 // Let the main session loop know we're all done.
                 Ok(true)
@@ -511,15 +1036,37 @@ impl Session {
         }
     }
 
+    /// Apply our BacklogPolicy to a request which arrived while the
+    /// backlog is full.
+    ///
+    /// Returns true if the session should end.
+    fn handle_backlog_overflow(&mut self) -> Result<bool, String> {
+        match self.backlog_policy {
+            BacklogPolicy::Drop => {
+                log::error!("{self} Backlog exceeds max size={MAX_BACKLOG_SIZE}; dropping request");
+                Ok(false)
+            }
+            BacklogPolicy::Disconnect => {
+                // A client this far behind is not going to catch up.
+                log::error!(
+                    "{self} Backlog exceeds max size={MAX_BACKLOG_SIZE}; disconnecting client"
+                );
+
+                self.shutdown_session.store(true, Ordering::Relaxed);
+
+                // Errors are moot, since we are closing anyway.
+                self.sender.write_message(backlog_close_frame()).ok();
+
+                Ok(true)
+            }
+        }
+    }
+
     /// Wrap a websocket request in an OpenSRF transport message and
     /// put on the OpenSRF bus for delivery.
-    fn relay_to_osrf(&mut self, json_text: &str) -> Result<(), String> {
-        let mut wrapper = json::parse(json_text).or_else(|e| {
-            Err(format!(
-                "{self} Cannot parse websocket message: {e} {json_text}"
-            ))
-        })?;
-
+    ///
+    /// The message must have been verified by parse_ws_message().
+    fn relay_to_osrf(&mut self, mut wrapper: json::JsonValue) -> Result<(), String> {
         let thread = wrapper["thread"].take();
         let log_xid = wrapper["log_xid"].take();
         let mut msg_list = wrapper["osrf_msg"].take();
@@ -534,10 +1081,6 @@ impl Session {
             .as_str()
             .ok_or_else(|| format!("{self} websocket message has no 'thread' key"))?;
 
-        if thread.len() > MAX_THREAD_SIZE {
-            Err(format!("{self} Thread exceeds max thread size; dropping"))?;
-        }
-
         let service = wrapper["service"]
             .as_str()
             .ok_or_else(|| format!("{self} service name is required"))?;
@@ -546,10 +1089,12 @@ impl Session {
         // message into the queue of the router as needed.
         let mut send_to_router: Option<String> = None;
 
-        let recipient = match self.osrf_sessions.get(thread) {
+        let cached = cached_worker(&self.osrf_sessions, thread, service);
+
+        let recipient = match cached {
             Some(a) => {
                 log::debug!("{self} Found cached recipient for thread {thread} {a}");
-                a.clone()
+                a
             }
             None => {
                 let username = self.osrf_sender.router_name();
@@ -601,9 +1146,18 @@ impl Session {
                 message::MessageType::Connect => {
                     self.reqs_in_flight += 1;
                     log::debug!("{self} WS received CONNECT request: {thread}");
+
+                    self.osrf_sessions.insert(
+                        thread.to_string(),
+                        OsrfSession {
+                            service: service.to_string(),
+                            worker: None,
+                        },
+                    );
                 }
                 message::MessageType::Request => {
                     self.reqs_in_flight += 1;
+                    self.metrics.requests.fetch_add(1, Ordering::Relaxed);
 
                     // Inbound requests using a hash format need to be
                     // turned into Fieldmapper objects before they
@@ -678,9 +1232,16 @@ impl Session {
                     message::MessageStatus::Complete => self.subtract_reqs(),
                     message::MessageStatus::Ok => {
                         self.subtract_reqs();
-                        // Connection successful message.  Track the worker address.
-                        self.osrf_sessions
-                            .insert(tm.thread().to_string(), tm.from().to_string());
+                        // Connection successful message.  Track the worker
+                        // address, but only for threads we sent a CONNECT for.
+                        if let Some(ses) = self.osrf_sessions.get_mut(tm.thread()) {
+                            ses.worker = Some(tm.from().to_string());
+                        } else {
+                            log::warn!(
+                                "{self} Ignoring connect response for unknown thread {}",
+                                tm.thread()
+                            );
+                        }
                     }
                     // We don't need to analyze every non-error message.
                     s if (s as usize) < 400 => {}
@@ -733,13 +1294,12 @@ impl Session {
 
         log::trace!("{self} replying with message: {msg_json}");
 
-        let msg = WebSocketMessage::Text(msg_json);
+        self.write_to_websocket(WebSocketMessage::Text(msg_json))
+    }
 
-        self.sender.write_message(msg).or_else(|e| {
-            Err(format!(
-                "{self} Error sending response to websocket client: {e}"
-            ))
-        })
+    /// Send a message to our websocket client.  See send_with_retries.
+    fn write_to_websocket(&mut self, msg: WebSocketMessage) -> Result<(), String> {
+        send_with_retries(&mut self.sender, msg, self.send_retries, &self.client_ip)
     }
 
     /// Log an API call, honoring the log-protect configs.
@@ -778,8 +1338,217 @@ impl Session {
 
 // -- Here starts the MPTC glue --
 
+/// Websocket session counters shared by the request stream and
+/// the session handlers.
+#[derive(Default)]
+struct SessionMetrics {
+    /// Currently active sessions.
+    active: AtomicUsize,
+
+    /// Most sessions active at one time.
+    peak: AtomicUsize,
+
+    /// Connections refused because we had too many active sessions.
+    rejected: AtomicUsize,
+
+    /// Connections refused because their client IP had too many
+    /// active sessions.
+    rejected_per_ip: AtomicUsize,
+
+    /// Connections refused because their Origin was not allowed.
+    rejected_origin: AtomicUsize,
+
+    /// API requests relayed to OpenSRF.
+    requests: AtomicUsize,
+
+    /// Most messages queued at one time by a single session.
+    backlog_peak: AtomicUsize,
+}
+
+impl SessionMetrics {
+    /// Count a new session, unless we are at max_clients, in which
+    /// case the rejected count is incremented and false is returned.
+    fn try_add(&self, max_clients: Option<usize>) -> bool {
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Some(max) = max_clients {
+            if active > max {
+                self.active.fetch_sub(1, Ordering::SeqCst);
+                self.rejected.fetch_add(1, Ordering::SeqCst);
+                return false;
+            }
+        }
+
+        self.peak.fetch_max(active, Ordering::SeqCst);
+
+        true
+    }
+
+    fn remove(&self) {
+        // Avoid underflow.
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_sub(1))
+            .ok();
+    }
+
+    fn to_json(&self) -> json::JsonValue {
+        json::object! {
+            active: self.active.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            rejected_per_ip: self.rejected_per_ip.load(Ordering::Relaxed),
+            rejected_origin: self.rejected_origin.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+            backlog_peak: self.backlog_peak.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Build the HTTP response for a status listener request.
+///
+/// Only GET /status is supported.
+fn status_response(request_line: &str, metrics: &SessionMetrics) -> String {
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/status")) => ("200 OK", metrics.to_json().dump()),
+        _ => ("404 Not Found", json::object! {error: "Not Found"}.dump()),
+    };
+
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// Answer status requests until shutdown.
+///
+/// Requests are handled one at a time, which is plenty for
+/// monitoring tools.
+fn run_status_listener(
+    listener: TcpListener,
+    metrics: Arc<SessionMetrics>,
+    shutdown: Arc<AtomicBool>,
+) {
+    while !shutdown.load(Ordering::Relaxed) {
+        let (mut stream, address) = match listener.accept() {
+            Ok(s) => s,
+            Err(e) => match e.kind() {
+                // socket read timeout.
+                std::io::ErrorKind::WouldBlock => continue,
+                _ => {
+                    log::error!("Status listener accept() failed: {e}");
+                    return;
+                }
+            },
+        };
+
+        stream
+            .set_read_timeout(Some(Duration::from_secs(SIG_POLL_INTERVAL)))
+            .ok();
+
+        let mut buf = [0u8; 1024];
+        let request = match stream.read(&mut buf) {
+            Ok(n) => String::from_utf8_lossy(&buf[..n]).to_string(),
+            Err(e) => {
+                log::debug!("Error reading status request from {address}: {e}");
+                continue;
+            }
+        };
+
+        let request_line = request.lines().next().unwrap_or("");
+        log::debug!("Status request from {address}: {request_line}");
+
+        let response = status_response(request_line, &metrics);
+
+        if let Err(e) = stream.write_all(response.as_bytes()) {
+            log::debug!("Error writing status response to {address}: {e}");
+        }
+
+        stream.shutdown(std::net::Shutdown::Both).ok();
+    }
+}
+
+impl fmt::Display for SessionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sessions active={} peak={} rejected={}",
+            self.active.load(Ordering::Relaxed),
+            self.peak.load(Ordering::Relaxed),
+            self.rejected.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Connection counts per client IP address.
+///
+/// Accessed by the accept loop and every session thread, so keys are
+/// spread across separately locked shards to limit lock contention.
+struct ConnectionRegistry {
+    shards: Vec<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionRegistry {
+    fn new() -> Self {
+        ConnectionRegistry {
+            shards: (0..REGISTRY_SHARDS)
+                .map(|_| Mutex::new(HashMap::new()))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, ip: &IpAddr) -> &Mutex<HashMap<IpAddr, usize>> {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// Add a connection for the IP.
+    fn register(&self, ip: IpAddr) {
+        *self.shard(&ip).lock().unwrap().entry(ip).or_insert(0) += 1;
+    }
+
+    /// Remove a connection for the IP.
+    fn unregister(&self, ip: IpAddr) {
+        let mut shard = self.shard(&ip).lock().unwrap();
+
+        if let Some(count) = shard.get_mut(&ip) {
+            *count = count.saturating_sub(1);
+
+            // Avoid collecting entries for IPs with no connections.
+            if *count == 0 {
+                shard.remove(&ip);
+            }
+        }
+    }
+
+    /// Add a connection for the IP unless it already has `max`
+    /// connections.  Returns true if the connection was added.
+    fn try_register(&self, ip: IpAddr, max: usize) -> bool {
+        let mut shard = self.shard(&ip).lock().unwrap();
+        let count = shard.entry(ip).or_insert(0);
+
+        if *count >= max {
+            if *count == 0 {
+                shard.remove(&ip);
+            }
+            return false;
+        }
+
+        *count += 1;
+        true
+    }
+
+    /// Number of connections for the IP.
+    fn count(&self, ip: &IpAddr) -> usize {
+        self.shard(ip).lock().unwrap().get(ip).copied().unwrap_or(0)
+    }
+}
+
 struct WebsocketRequest {
     stream: Option<TcpStream>,
+    address: SocketAddr,
 }
 
 impl WebsocketRequest {
@@ -796,9 +1565,50 @@ impl mptc::Request for WebsocketRequest {
     }
 }
 
+/// Parse an inbound websocket message and verify it has the values
+/// needed to route it.
+///
+/// On error, returns the message thread, if one could be read, along
+/// with a description of the problem.
+fn parse_ws_message(json_text: &str) -> Result<json::JsonValue, (Option<String>, String)> {
+    let wrapper = json::parse(json_text)
+        .map_err(|e| (None, format!("Cannot parse websocket message: {e}")))?;
+
+    let thread = match wrapper["thread"].as_str() {
+        Some(t) if t.len() > MAX_THREAD_SIZE => {
+            return Err((None, "Thread exceeds max thread size".to_string()))
+        }
+        Some(t) => t.to_string(),
+        None => return Err((None, "Websocket message has no 'thread' key".to_string())),
+    };
+
+    if wrapper["service"].as_str().is_none() {
+        return Err((Some(thread), "Service name is required".to_string()));
+    }
+
+    Ok(wrapper)
+}
+
+/// Text frame sent to a client whose message could not be processed.
+fn client_error_frame(thread: Option<&str>, error: &str) -> WebSocketMessage {
+    let mut obj = json::object! {
+        transport_error: true,
+        error: error,
+    };
+
+    if let Some(t) = thread {
+        obj["thread"] = json::from(t);
+    }
+
+    WebSocketMessage::Text(obj.dump())
+}
+
 struct WebsocketHandler {
-    max_parallel: usize,
+    tls: Option<Arc<TlsAcceptor>>,
+    config: SessionConfig,
     shutdown: Arc<AtomicBool>,
+    metrics: Arc<SessionMetrics>,
+    connections: Arc<ConnectionRegistry>,
 }
 
 impl mptc::RequestHandler for WebsocketHandler {
@@ -820,10 +1630,18 @@ impl mptc::RequestHandler for WebsocketHandler {
 
         let shutdown = self.shutdown.clone();
 
-        if let Err(e) = Session::run(stream, self.max_parallel, shutdown) {
+        let tls = self.tls.as_deref();
+
+        let result = Session::run(stream, tls, &self.config, self.metrics.clone(), shutdown);
+
+        if let Err(e) = result {
             log::error!("Websocket session ended with error: {e}");
         }
 
+        // Our slot was claimed by the stream when the connection arrived.
+        self.metrics.remove();
+        self.connections.unregister(request.address.ip());
+
         Ok(())
     }
 }
@@ -832,11 +1650,50 @@ struct WebsocketStream {
     listener: TcpListener,
     client: Client,
 
+    /// Accept wss:// connections directly when set.  Otherwise,
+    /// TLS is left to the HTTP proxy.
+    tls: Option<Arc<TlsAcceptor>>,
+
+    /// How long TLS reads hold the shared stream lock.
+    tls_read_timeout: Duration,
+
     /// Maximum number of active/parallel websocket requests to
     /// relay to OpenSRF at a time.  Once exceeded, new messages
     /// are queued for delivery and relayed as soon as possible.
     max_parallel: usize,
 
+    /// Number of websocket send retries on transient errors.
+    send_retries: usize,
+
+    /// Largest allowed inbound websocket message.
+    max_message_size: usize,
+
+    /// How often to Ping clients.  None disables keepalive Pings.
+    ping_interval: Option<Duration>,
+
+    /// What sessions do when their request backlog is full.
+    backlog_policy: BacklogPolicy,
+
+    /// Browser origins allowed to connect.  Empty means any origin.
+    allowed_origins: Arc<Vec<String>>,
+
+    /// Maximum number of concurrent websocket sessions.  Connections
+    /// beyond this are closed immediately.  None means no limit beyond
+    /// the mptc max workers.
+    max_clients: Option<usize>,
+
+    /// Maximum number of concurrent sessions from a single client IP.
+    /// None, the default, means no per-IP limit.
+    max_clients_per_ip: Option<usize>,
+
+    /// Warn when active sessions reach this many.
+    saturation_warn: Option<usize>,
+
+    metrics: Arc<SessionMetrics>,
+
+    /// Active connections per client IP.
+    connections: Arc<ConnectionRegistry>,
+
     /// Set to true of the mptc::Server tells us it's time to shutdown.
     ///
     /// Read by our Sessions
@@ -844,7 +1701,13 @@ struct WebsocketStream {
 }
 
 impl WebsocketStream {
-    fn new(client: Client, address: &str, port: u16, max_parallel: usize) -> Result<Self, String> {
+    fn new(
+        client: Client,
+        address: &str,
+        port: u16,
+        max_parallel: usize,
+        send_retries: usize,
+    ) -> Result<Self, String> {
         log::info!("EG Websocket listening at {address}:{port}");
 
         let listener = eg::util::tcp_listener(address, port, SIG_POLL_INTERVAL).or_else(|e| {
@@ -856,18 +1719,123 @@ impl WebsocketStream {
         let stream = WebsocketStream {
             listener,
             client,
+            tls: None,
+            tls_read_timeout: Duration::from_millis(DEFAULT_TLS_READ_TIMEOUT_MS),
             max_parallel,
+            send_retries,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            ping_interval: Some(Duration::from_secs(DEFAULT_PING_INTERVAL)),
+            backlog_policy: BacklogPolicy::Drop,
+            allowed_origins: Arc::new(Vec::new()),
+            max_clients: None,
+            max_clients_per_ip: None,
+            saturation_warn: None,
+            metrics: Arc::new(SessionMetrics::default()),
+            connections: Arc::new(ConnectionRegistry::new()),
             shutdown: Arc::new(AtomicBool::new(false)),
         };
 
         Ok(stream)
     }
+
+    /// Terminate TLS for all client connections using the provided
+    /// PEM certificate and private key files.
+    fn set_tls(&mut self, cert_file: &str, key_file: &str) -> Result<(), String> {
+        log::info!("EG Websocket accepting TLS connections");
+        self.tls = Some(Arc::new(load_tls_acceptor(cert_file, key_file)?));
+        Ok(())
+    }
+
+    /// How long, in milliseconds, TLS reads wait for client data
+    /// before releasing the stream to any waiting writer.
+    fn set_tls_read_timeout(&mut self, millis: u64) {
+        if millis > 0 {
+            self.tls_read_timeout = Duration::from_millis(millis);
+        } else {
+            log::warn!(
+                "TLS read timeout must be positive; using {:?}",
+                self.tls_read_timeout
+            );
+        }
+    }
+
+    /// Set the largest inbound websocket message we accept.
+    fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size;
+    }
+
+    /// Ping clients every this many seconds.  Zero disables Pings.
+    fn set_ping_interval(&mut self, secs: u64) {
+        self.ping_interval = if secs > 0 {
+            Some(Duration::from_secs(secs))
+        } else {
+            None
+        };
+    }
+
+    /// Either "drop" (the default) or "disconnect".  Unknown values
+    /// leave the current policy in place.
+    fn set_backlog_policy(&mut self, policy: &str) {
+        match BacklogPolicy::try_from(policy) {
+            Ok(p) => self.backlog_policy = p,
+            Err(e) => log::warn!("{e}; using {:?}", self.backlog_policy),
+        }
+    }
+
+    /// Report session metrics as JSON via GET /status on a separate
+    /// HTTP listener, leaving websocket traffic untouched.
+    fn start_status_listener(&self, address: &str, port: u16) -> Result<(), String> {
+        log::info!("EG Websocket status listening at {address}:{port}");
+
+        let listener = eg::util::tcp_listener(address, port, SIG_POLL_INTERVAL).or_else(|e| {
+            Err(format!(
+                "Cannot listen for status requests at {address}:{port} {e}"
+            ))
+        })?;
+
+        let metrics = self.metrics.clone();
+        let shutdown = self.shutdown.clone();
+
+        thread::spawn(move || run_status_listener(listener, metrics, shutdown));
+
+        Ok(())
+    }
+
+    /// Only accept connections from these browser origins, provided
+    /// as a comma-separated list.  An empty list allows all origins.
+    fn set_allowed_origins(&mut self, origins: &str) {
+        let origins: Vec<String> = origins
+            .split(',')
+            .map(|o| o.trim())
+            .filter(|o| !o.is_empty())
+            .map(|o| o.to_string())
+            .collect();
+
+        if !origins.is_empty() {
+            log::info!("EG Websocket allowing origins: {}", origins.join(", "));
+        }
+
+        self.allowed_origins = Arc::new(origins);
+    }
+
+    /// Limit the number of concurrent sessions from a single client IP.
+    /// Zero means no limit.
+    fn set_max_clients_per_ip(&mut self, max: usize) {
+        self.max_clients_per_ip = if max > 0 { Some(max) } else { None };
+    }
+
+    /// Limit the number of concurrent sessions, warning once the
+    /// active sessions reach warn_percent of the limit.
+    fn set_max_clients(&mut self, max_clients: usize, warn_percent: usize) {
+        self.max_clients = Some(max_clients);
+        self.saturation_warn = Some((max_clients * warn_percent / 100).max(1));
+    }
 }
 
 impl mptc::RequestStream for WebsocketStream {
     /// Returns the next client request stream.
     fn next(&mut self) -> Result<Option<Box<dyn mptc::Request>>, String> {
-        let (stream, _address) = match self.listener.accept() {
+        let (stream, address) = match self.listener.accept() {
             Ok((s, a)) => (s, a),
             Err(e) => match e.kind() {
                 // socket read timeout.
@@ -876,8 +1844,46 @@ impl mptc::RequestStream for WebsocketStream {
             },
         };
 
+        if !self.metrics.try_add(self.max_clients) {
+            log::warn!(
+                "Refusing connection from {address}; max clients reached: {}",
+                self.metrics
+            );
+            stream.shutdown(std::net::Shutdown::Both).ok();
+            return Ok(None);
+        }
+
+        let ip = address.ip();
+
+        if let Some(max) = self.max_clients_per_ip {
+            if !self.connections.try_register(ip, max) {
+                log::warn!("Refusing connection from {address}; max clients per IP reached: {max}");
+
+                // Release the slot claimed above.
+                self.metrics.remove();
+                self.metrics.rejected_per_ip.fetch_add(1, Ordering::SeqCst);
+                stream.shutdown(std::net::Shutdown::Both).ok();
+                return Ok(None);
+            }
+        } else {
+            self.connections.register(ip);
+        }
+
+        let active = self.metrics.active.load(Ordering::Relaxed);
+        let from_ip = self.connections.count(&address.ip());
+
+        if self.saturation_warn.map(|w| active == w).unwrap_or(false) {
+            log::warn!("Websocket server nearing capacity: {}", self.metrics);
+        } else {
+            log::debug!(
+                "Accepted connection from {address} ({from_ip} from this IP): {}",
+                self.metrics
+            );
+        }
+
         let request = WebsocketRequest {
             stream: Some(stream),
+            address,
         };
 
         return Ok(Some(Box::new(request)));
@@ -886,7 +1892,18 @@ impl mptc::RequestStream for WebsocketStream {
     fn new_handler(&mut self) -> Box<dyn mptc::RequestHandler> {
         let handler = WebsocketHandler {
             shutdown: self.shutdown.clone(),
-            max_parallel: self.max_parallel,
+            tls: self.tls.clone(),
+            config: SessionConfig {
+                max_parallel: self.max_parallel,
+                send_retries: self.send_retries,
+                max_message_size: self.max_message_size,
+                ping_interval: self.ping_interval,
+                backlog_policy: self.backlog_policy,
+                allowed_origins: self.allowed_origins.clone(),
+                tls_read_timeout: self.tls_read_timeout,
+            },
+            metrics: self.metrics.clone(),
+            connections: self.connections.clone(),
         };
 
         Box::new(handler)
@@ -920,6 +1937,7 @@ fn main() {
         // the gateway() config instead.
         skip_logging: true,
         appname: Some(String::from("http-gateway")),
+        ..eg::init::InitOptions::new()
     };
 
     // Connect to OpenSRF, parse the IDL
@@ -948,7 +1966,60 @@ fn main() {
 
     let address = env::var("EG_WEBSOCKETS_ADDRESS").unwrap_or(DEFAULT_LISTEN_ADDRESS.to_string());
 
-    let stream = WebsocketStream::new(client, &address, port, max_parallel).expect("Build stream");
+    let send_retries = match env::var("EG_WEBSOCKETS_SEND_RETRIES") {
+        Ok(v) => v.parse::<usize>().expect("Invalid send-retries value"),
+        _ => DEFAULT_SEND_RETRIES,
+    };
+
+    let mut stream = WebsocketStream::new(client, &address, port, max_parallel, send_retries)
+        .expect("Build stream");
+
+    if let Ok(cert_file) = env::var("EG_WEBSOCKETS_TLS_CERT") {
+        let key_file = env::var("EG_WEBSOCKETS_TLS_KEY").expect("TLS key file required");
+        stream.set_tls(&cert_file, &key_file).expect("TLS setup");
+    }
+
+    if let Ok(n) = env::var("EG_WEBSOCKETS_TLS_READ_TIMEOUT_MS") {
+        stream.set_tls_read_timeout(n.parse::<u64>().expect("Invalid tls-read-timeout"));
+    }
+
+    if let Ok(n) = env::var("EG_WEBSOCKETS_MAX_MESSAGE_SIZE") {
+        stream.set_max_message_size(n.parse::<usize>().expect("Invalid max-message-size"));
+    }
+
+    if let Ok(n) = env::var("EG_WEBSOCKETS_PING_INTERVAL") {
+        stream.set_ping_interval(n.parse::<u64>().expect("Invalid ping-interval"));
+    }
+
+    // The status listener is disabled unless a port is provided.
+    if let Ok(n) = env::var("EG_WEBSOCKETS_STATUS_PORT") {
+        let status_port = n.parse::<u16>().expect("Invalid status port number");
+        stream
+            .start_status_listener(&address, status_port)
+            .expect("Status listener setup");
+    }
+
+    if let Ok(policy) = env::var("EG_WEBSOCKETS_BACKLOG_POLICY") {
+        stream.set_backlog_policy(&policy);
+    }
+
+    if let Ok(origins) = env::var("EG_WEBSOCKETS_ALLOWED_ORIGINS") {
+        stream.set_allowed_origins(&origins);
+    }
+
+    if let Ok(n) = env::var("EG_WEBSOCKETS_MAX_CLIENTS_PER_IP") {
+        stream.set_max_clients_per_ip(n.parse::<usize>().expect("Invalid max-clients-per-ip"));
+    }
+
+    if let Ok(n) = env::var("EG_WEBSOCKETS_MAX_CLIENTS") {
+        let warn_percent = match env::var("EG_WEBSOCKETS_SATURATION_WARN_PERCENT") {
+            Ok(v) => v.parse::<usize>().expect("Invalid saturation-warn-percent"),
+            _ => DEFAULT_SATURATION_WARN_PERCENT,
+        };
+
+        let max_clients = n.parse::<usize>().expect("Invalid max-clients");
+        stream.set_max_clients(max_clients, warn_percent);
+    }
 
     let mut server = mptc::Server::new(Box::new(stream));
 
@@ -969,3 +2040,429 @@ fn main() {
 
     server.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use native_tls::TlsConnector;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Builder, X509NameBuilder};
+
+    #[test]
+    fn connection_registry() {
+        let registry = Arc::new(ConnectionRegistry::new());
+        let ips: Vec<IpAddr> = (1..=8)
+            .map(|n| format!("10.0.0.{n}").parse().unwrap())
+            .collect();
+
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let registry = registry.clone();
+                let ips = ips.clone();
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let ip = ips[(t + i) % ips.len()];
+                        registry.register(ip);
+                        // Leave one connection per IP per thread open.
+                        if i >= ips.len() {
+                            registry.unregister(ip);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for t in threads {
+            t.join().unwrap();
+        }
+
+        for ip in &ips {
+            assert_eq!(registry.count(ip), 8);
+        }
+
+        for ip in &ips {
+            for _ in 0..8 {
+                registry.unregister(*ip);
+            }
+            assert_eq!(registry.count(ip), 0);
+        }
+
+        // Unknown IPs do not underflow.
+        registry.unregister(ips[0]);
+        assert_eq!(registry.count(&ips[0]), 0);
+    }
+
+    #[test]
+    fn connection_registry_per_ip_limit() {
+        let registry = ConnectionRegistry::new();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(registry.try_register(ip, 2));
+        assert!(registry.try_register(ip, 2));
+        assert!(!registry.try_register(ip, 2));
+        assert_eq!(registry.count(&ip), 2);
+
+        // Other IPs are unaffected.
+        assert!(registry.try_register(other, 2));
+
+        // A closed session frees a slot for its IP.
+        registry.unregister(ip);
+        assert!(registry.try_register(ip, 2));
+
+        // Refusals do not leave empty entries behind.
+        assert!(!registry.try_register("10.0.0.3".parse().unwrap(), 0));
+        assert_eq!(registry.count(&"10.0.0.3".parse().unwrap()), 0);
+    }
+
+    #[test]
+    fn backlog_policy() {
+        assert_eq!(BacklogPolicy::try_from("drop"), Ok(BacklogPolicy::Drop));
+        assert_eq!(BacklogPolicy::try_from("DROP"), Ok(BacklogPolicy::Drop));
+        assert_eq!(
+            BacklogPolicy::try_from("disconnect"),
+            Ok(BacklogPolicy::Disconnect)
+        );
+        assert!(BacklogPolicy::try_from("").is_err());
+        assert!(BacklogPolicy::try_from("kick").is_err());
+
+        let WebSocketMessage::Close(Some(frame)) = backlog_close_frame() else {
+            panic!("backlog overflow should send a Close frame with a code");
+        };
+
+        assert_eq!(u16::from(frame.code), 1013);
+    }
+
+    #[test]
+    fn session_metrics_rejected() {
+        let metrics = SessionMetrics::default();
+
+        assert!(metrics.try_add(Some(2)));
+        assert!(metrics.try_add(Some(2)));
+
+        // Saturated.
+        assert!(!metrics.try_add(Some(2)));
+        assert!(!metrics.try_add(Some(2)));
+
+        assert_eq!(metrics.rejected.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.active.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.peak.load(Ordering::Relaxed), 2);
+
+        // A closed session frees a slot.
+        metrics.remove();
+        assert!(metrics.try_add(Some(2)));
+        assert_eq!(metrics.rejected.load(Ordering::Relaxed), 2);
+
+        // No limit, no rejections.
+        assert!(metrics.try_add(None));
+        assert_eq!(metrics.rejected.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.to_json()["rejected"], 2);
+    }
+
+    #[test]
+    fn status_endpoint() {
+        let metrics = SessionMetrics::default();
+        assert!(metrics.try_add(None));
+        metrics.requests.fetch_add(3, Ordering::Relaxed);
+        metrics.rejected_origin.fetch_add(1, Ordering::Relaxed);
+
+        let response = status_response("GET /status HTTP/1.1", &metrics);
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+
+        let (headers, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains(&format!("Content-Length: {}", body.len())));
+
+        let status = json::parse(body).unwrap();
+        assert_eq!(status["active"], 1);
+        assert_eq!(status["peak"], 1);
+        assert_eq!(status["requests"], 3);
+        assert_eq!(status["rejected_origin"], 1);
+        assert_eq!(status["rejected"], 0);
+
+        let response = status_response("GET /other HTTP/1.1", &metrics);
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let response = status_response("POST /status HTTP/1.1", &metrics);
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn allowed_origins() {
+        assert!(origin_allowed(&[], None));
+        assert!(origin_allowed(&[], Some("https://evil.example.org")));
+
+        let allowed = vec![
+            "https://catalog.example.org".to_string(),
+            "https://staff.example.org/".to_string(),
+        ];
+
+        assert!(origin_allowed(
+            &allowed,
+            Some("https://catalog.example.org")
+        ));
+        assert!(origin_allowed(&allowed, Some("HTTPS://Staff.Example.org")));
+        assert!(!origin_allowed(&allowed, Some("https://evil.example.org")));
+        assert!(!origin_allowed(
+            &allowed,
+            Some("http://catalog.example.org")
+        ));
+        assert!(!origin_allowed(&allowed, None));
+    }
+
+    #[test]
+    fn keepalive() {
+        let interval = Duration::from_millis(20);
+        let mut keepalive = Keepalive::new(interval);
+
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Wait);
+
+        thread::sleep(interval);
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Ping);
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Wait);
+
+        // An answered Ping resets the count.
+        keepalive.pong();
+        thread::sleep(interval);
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Ping);
+        thread::sleep(interval);
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Ping);
+
+        // Two unanswered Pings in a row.
+        thread::sleep(interval);
+        assert_eq!(keepalive.next_action(), KeepaliveAction::Dead);
+    }
+
+    #[test]
+    fn inbound_parse_errors() {
+        let (thread, err) = parse_ws_message("{not json").unwrap_err();
+        assert!(thread.is_none());
+        assert!(err.starts_with("Cannot parse websocket message"));
+
+        let (thread, _) = parse_ws_message(r#"{"service": "open-ils.actor"}"#).unwrap_err();
+        assert!(thread.is_none());
+
+        let (thread, _) = parse_ws_message(r#"{"thread": "abc123"}"#).unwrap_err();
+        assert_eq!(thread.as_deref(), Some("abc123"));
+
+        let wrapper = parse_ws_message(
+            r#"{"thread": "abc123", "service": "open-ils.actor", "osrf_msg": []}"#,
+        )
+        .unwrap();
+        assert_eq!(wrapper["service"].as_str(), Some("open-ils.actor"));
+
+        // The client is told which request failed and why.
+        let frame = client_error_frame(Some("abc123"), "Service name is required");
+        let WebSocketMessage::Text(text) = frame else {
+            panic!("error frames are text");
+        };
+
+        let obj = json::parse(&text).unwrap();
+        assert_eq!(obj["transport_error"].as_bool(), Some(true));
+        assert_eq!(obj["thread"].as_str(), Some("abc123"));
+        assert_eq!(obj["error"].as_str(), Some("Service name is required"));
+    }
+
+    #[test]
+    fn cached_worker_routing() {
+        let mut sessions = HashMap::new();
+
+        sessions.insert(
+            "thread1".to_string(),
+            OsrfSession {
+                service: "open-ils.actor".to_string(),
+                worker: Some("opensrf:client:worker1".to_string()),
+            },
+        );
+
+        sessions.insert(
+            "thread2".to_string(),
+            OsrfSession {
+                service: "open-ils.actor".to_string(),
+                worker: None,
+            },
+        );
+
+        assert_eq!(
+            cached_worker(&sessions, "thread1", "open-ils.actor").as_deref(),
+            Some("opensrf:client:worker1")
+        );
+
+        // Unknown threads go to the router.
+        assert!(cached_worker(&sessions, "thread3", "open-ils.actor").is_none());
+
+        // A thread reused for another service does not reach the
+        // worker connected for the original service.
+        assert!(cached_worker(&sessions, "thread1", "open-ils.circ").is_none());
+
+        // Connected, but the worker has not responded yet.
+        assert!(cached_worker(&sessions, "thread2", "open-ils.actor").is_none());
+    }
+
+    /// Sender whose calls fail with queued errors before succeeding.
+    #[derive(Default)]
+    struct FlakySender {
+        errors: VecDeque<ws::Error>,
+        sent: Vec<WebSocketMessage>,
+        flushes: usize,
+    }
+
+    impl MessageSender for FlakySender {
+        fn write_message(&mut self, msg: WebSocketMessage) -> Result<(), ws::Error> {
+            match self.errors.pop_front() {
+                // A full queue hands the message back unsent.
+                Some(ws::Error::SendQueueFull(_)) => Err(ws::Error::SendQueueFull(msg)),
+                Some(e) => {
+                    // Other IO errors leave the message queued.
+                    self.sent.push(msg);
+                    Err(e)
+                }
+                None => {
+                    self.sent.push(msg);
+                    Ok(())
+                }
+            }
+        }
+
+        fn write_pending(&mut self) -> Result<(), ws::Error> {
+            self.flushes += 1;
+            match self.errors.pop_front() {
+                Some(e) => Err(e),
+                None => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn send_retries() {
+        let ip: SocketAddr = "10.0.0.1:7682".parse().unwrap();
+        let msg = || WebSocketMessage::Text("hello".to_string());
+        let would_block = || ws::Error::Io(io::Error::from(io::ErrorKind::WouldBlock));
+
+        // Partial write: the retry flushes instead of re-sending.
+        let mut sender = FlakySender::default();
+        sender.errors.push_back(would_block());
+
+        assert!(send_with_retries(&mut sender, msg(), 3, &ip).is_ok());
+        assert_eq!(sender.sent, vec![msg()]);
+        assert_eq!(sender.flushes, 1);
+
+        // Full queue: the returned message is sent exactly once.
+        let mut sender = FlakySender::default();
+        sender
+            .errors
+            .push_back(ws::Error::SendQueueFull(WebSocketMessage::Text(
+                String::new(),
+            )));
+
+        assert!(send_with_retries(&mut sender, msg(), 3, &ip).is_ok());
+        assert_eq!(sender.sent, vec![msg()]);
+        assert_eq!(sender.flushes, 0);
+
+        // Retries are exhausted.
+        let mut sender = FlakySender::default();
+        sender.errors.extend([would_block(), would_block()]);
+
+        assert!(send_with_retries(&mut sender, msg(), 1, &ip).is_err());
+        assert_eq!(sender.sent.len(), 1);
+
+        // Fatal errors are not retried.
+        let mut sender = FlakySender::default();
+        sender.errors.push_back(ws::Error::ConnectionClosed);
+
+        assert!(send_with_retries(&mut sender, msg(), 3, &ip).is_err());
+        assert_eq!(sender.flushes, 0);
+    }
+
+    /// Returns a PEM-encoded self-signed certificate and PKCS #8 key.
+    fn self_signed_cert() -> (Vec<u8>, Vec<u8>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        (
+            builder.build().to_pem().unwrap(),
+            key.private_key_to_pem_pkcs8().unwrap(),
+        )
+    }
+
+    #[test]
+    fn tls_handshake() {
+        let (cert, key) = self_signed_cert();
+
+        let dir = env::temp_dir();
+        let cert_file = dir.join(format!("eg-websockets-test-{}.crt", std::process::id()));
+        let key_file = dir.join(format!("eg-websockets-test-{}.key", std::process::id()));
+
+        fs::write(&cert_file, cert).unwrap();
+        fs::write(&key_file, key).unwrap();
+
+        let acceptor =
+            load_tls_acceptor(cert_file.to_str().unwrap(), key_file.to_str().unwrap()).unwrap();
+
+        fs::remove_file(&cert_file).ok();
+        fs::remove_file(&key_file).ok();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Echo a single message back to the client.
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let (_, mut receiver, mut sender) = accept_websocket(
+                stream,
+                Some(&acceptor),
+                &[],
+                Duration::from_millis(DEFAULT_TLS_READ_TIMEOUT_MS),
+                &SessionMetrics::default(),
+            )
+            .unwrap();
+
+            let msg = loop {
+                match receiver.read_message() {
+                    Ok(m) => break m,
+                    Err(ws::error::Error::Io(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                        continue
+                    }
+                    Err(e) => panic!("Error reading message: {e}"),
+                }
+            };
+
+            sender.write_message(msg).unwrap();
+        });
+
+        let connector = TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let stream = connector.connect("localhost", stream).unwrap();
+
+        let (mut client, _) = ws::client(format!("wss://localhost:{port}/"), stream).unwrap();
+
+        let msg = WebSocketMessage::Text("Hello, Evergreen".to_string());
+        client.write_message(msg.clone()).unwrap();
+
+        assert_eq!(client.read_message().unwrap(), msg);
+
+        server.join().unwrap();
+    }
+}
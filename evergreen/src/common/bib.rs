@@ -117,6 +117,29 @@ impl DisplayAttrSet {
     }
 }
 
+/// Returns the IDs of the non-deleted bib records which make up
+/// a metarecord.
+///
+/// Returns an empty list if the metarecord has no constituent records.
+pub fn bibs_for_metarecord(editor: &mut Editor, mr_id: i64) -> EgResult<Vec<i64>> {
+    let query = eg::hash! {
+        "select": {"mmrsm": ["source"]},
+        "from": {"mmrsm": "bre"},
+        "where": {
+            "+mmrsm": {"metarecord": mr_id},
+            "+bre": {"deleted": "f"},
+        },
+        "order_by": {"mmrsm": "source"},
+    };
+
+    let mut bib_ids = Vec::new();
+    for map in editor.json_query(query)? {
+        bib_ids.push(map["source"].int()?);
+    }
+
+    Ok(bib_ids)
+}
+
 /// Build a virtual mvr from a bib record's display attributes
 pub fn map_to_mvr(editor: &mut Editor, bib_id: i64) -> EgResult<EgValue> {
     let mut maps = get_display_attrs(editor, &[bib_id])?;
@@ -10,7 +10,7 @@ use eg::result::EgResult;
 use eg::util;
 use eg::EgValue;
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 const DAY_OF_SECONDS: i64 = 86400;
 
@@ -68,6 +68,46 @@ pub fn void_bills(
     Ok(())
 }
 
+/// Void every unvoided billing of a billing type on a transaction,
+/// e.g. to forgive all overdue fines on a circulation.
+///
+/// Returns the total amount voided, which is 0.0 when there are no
+/// matching billings.  Like all updates, this must be run within an
+/// editor transaction.
+pub fn void_billings_by_type(editor: &mut Editor, xact_id: i64, btype_id: i64) -> EgResult<f64> {
+    let query = eg::hash! {
+        "xact": xact_id,
+        "btype": btype_id,
+        "voided": "f",
+    };
+
+    let bills = editor.search("mb", query)?;
+
+    if bills.len() == 0 {
+        log::debug!("No billings of type {btype_id} to void on xact {xact_id}");
+        return Ok(0.0);
+    }
+
+    let mut total = 0.0;
+    let mut bill_ids = Vec::new();
+
+    for bill in bills.iter() {
+        total = util::fpsum(total, bill["amount"].float()?);
+        bill_ids.push(bill.id()?);
+    }
+
+    let note = format!(
+        "System: VOIDED {}",
+        bills[0]["billing_type"].as_str().unwrap_or("")
+    );
+
+    log::info!("Voiding {total:.2} in billings of type {btype_id} on xact {xact_id}");
+
+    void_bills(editor, &bill_ids, Some(note.trim_end()))?;
+
+    Ok(total)
+}
+
 /// Sets or clears xact_finish on a transaction as needed.
 pub fn check_open_xact(editor: &mut Editor, xact_id: i64) -> EgResult<()> {
     let mut xact = match editor.retrieve("mbt", xact_id)? {
@@ -572,7 +612,65 @@ pub fn generate_fines_for_circ(editor: &mut Editor, circ_id: i64) -> EgResult<()
     )
 }
 
+/// Generate overdue fines for a circulation from its due date up to
+/// `until`, ignoring any previously voided overdue fines.
+///
+/// Used to rebuild a circulation's fines after its existing overdue
+/// fines have been voided, e.g. when its due date has changed.
+pub fn replay_fines_for_circ(
+    editor: &mut Editor,
+    circ_id: i64,
+    until: date::EgDate,
+) -> EgResult<()> {
+    log::info!("Replaying fines for circulation {circ_id} until {until}");
+
+    let circ = editor
+        .retrieve("circ", circ_id)?
+        .ok_or_else(|| editor.die_event())?;
+
+    generate_fines(
+        editor,
+        circ_id,
+        circ["due_date"].str()?,
+        circ["circ_lib"].int()?,
+        circ["recurring_fine"].float()?,
+        circ["fine_interval"].str()?,
+        circ["max_fine"].float()?,
+        circ["grace_period"].as_str(),
+        BillableTransactionType::Circ,
+        Some(until),
+    )
+}
+
 pub fn generate_fines_for_xact(
+    editor: &mut Editor,
+    xact_id: i64,
+    due_date: &str,
+    circ_lib: i64,
+    recurring_fine: f64,
+    fine_interval: &str,
+    max_fine: f64,
+    grace_period: Option<&str>,
+    xact_type: BillableTransactionType,
+) -> EgResult<()> {
+    generate_fines(
+        editor,
+        xact_id,
+        due_date,
+        circ_lib,
+        recurring_fine,
+        fine_interval,
+        max_fine,
+        grace_period,
+        xact_type,
+        None,
+    )
+}
+
+/// When `replay_until` is set, voided fines are not considered when
+/// finding the most recent fine and fines are generated up to that
+/// time instead of now.
+fn generate_fines(
     editor: &mut Editor,
     xact_id: i64,
     due_date: &str,
@@ -582,12 +680,13 @@ pub fn generate_fines_for_xact(
     mut max_fine: f64,
     grace_period: Option<&str>,
     xact_type: BillableTransactionType,
+    replay_until: Option<date::EgDate>,
 ) -> EgResult<()> {
     let mut settings = Settings::new(&editor);
 
     let fine_interval_secs = date::interval_to_seconds(fine_interval)?;
     let mut grace_period = date::interval_to_seconds(grace_period.unwrap_or("0s"))?;
-    let now = date::now();
+    let now = replay_until.unwrap_or_else(date::now);
 
     if fine_interval_secs == 0 || recurring_fine * 100.0 == 0.0 || max_fine * 100.0 == 0.0 {
         log::info!(
@@ -636,6 +735,7 @@ pub fn generate_fines_for_xact(
     let fines: Vec<EgValue> = fines
         .drain(..)
         .filter(|f| f["billing_ts"].as_str().unwrap() > due_date)
+        .filter(|f| replay_until.is_none() || !f["voided"].boolish())
         .collect();
 
     let due_date_dt = date::parse_datetime(due_date)?;
@@ -701,8 +801,16 @@ pub fn generate_fines_for_xact(
         None => "local",
     };
 
+    let mut closed_dates = org::ClosedDates::new();
+
     for slot in 0..pending_fine_count {
         if current_fine_total >= max_fine {
+            if replay_until.is_some() {
+                // Leave the circulation's stop fines state as-is.
+                log::info!("Max fines reached replaying fines for {xact_id}");
+                break;
+            }
+
             if xact_type == BillableTransactionType::Circ {
                 log::info!("Max fines reached for circulation {xact_id}");
 
@@ -733,8 +841,7 @@ pub fn generate_fines_for_xact(
         let period_start = period_end - duration;
 
         if !skip_closed_check {
-            let org_open_data = org::next_open_date(editor, circ_lib, &period_end)?;
-            if org_open_data != org::OrgOpenState::Open {
+            if closed_dates.is_closed(editor, circ_lib, &period_end)? {
                 // Avoid adding a fine if the org unit is closed
                 // on the day of the period_end date.
                 continue;
@@ -1054,3 +1161,332 @@ pub fn get_copy_price(editor: &mut Editor, copy_id: i64) -> EgResult<f64> {
 
     Ok(price)
 }
+
+/// Order in which a payment is distributed across a patron's open
+/// transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaymentOrder {
+    OldestFirst,
+    NewestFirst,
+}
+
+/// What to do with the portion of a payment that exceeds the amount owed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overpayment {
+    /// Refuse the payment altogether.
+    Reject,
+    /// Add the excess to the patron's credit balance.
+    Credit,
+}
+
+#[derive(Debug, Default)]
+pub struct PaymentResult {
+    /// (transaction ID, amount) for each payment applied.
+    pub payments: Vec<(i64, f64)>,
+    /// Amount added to the patron's credit balance.
+    pub credited: f64,
+    /// Total owed across the patron's open transactions after payment.
+    pub balance_owed: f64,
+}
+
+/// Maps a payment type (e.g. "cash_payment") to its IDL class.
+pub fn payment_class(payment_type: &str) -> EgResult<&'static str> {
+    let class = match payment_type {
+        "cash_payment" => "mcp",
+        "check_payment" => "mckp",
+        "credit_card_payment" => "mccp",
+        "credit_payment" => "mcrp",
+        "debit_card_payment" => "mdcp",
+        "forgive_payment" => "mfp",
+        "goods_payment" => "mgp",
+        "work_payment" => "mwp",
+        _ => return Err(format!("Invalid payment type: {payment_type}").into()),
+    };
+
+    Ok(class)
+}
+
+/// Distribute a payment amount across a list of (transaction ID,
+/// balance owed) pairs, in the order provided.
+///
+/// Returns the (transaction ID, amount) payments and the amount left
+/// over once all balances are paid.  Transactions with no positive
+/// balance are skipped.
+///
+/// ```
+/// use evergreen::common::billing::allocate_payment;
+///
+/// let (payments, remaining) = allocate_payment(5.0, &[(1, 1.25), (2, 0.0), (3, 10.0)]);
+///
+/// assert_eq!(payments, vec![(1, 1.25), (3, 3.75)]);
+/// assert_eq!(remaining, 0.0);
+/// ```
+pub fn allocate_payment(amount: f64, balances: &[(i64, f64)]) -> (Vec<(i64, f64)>, f64) {
+    // Work in whole cents to avoid accumulating floating point errors.
+    let cents = |v: f64| (v * 100.0).round() as i64;
+
+    let mut payments = Vec::new();
+    let mut remaining = cents(amount);
+
+    for (xact_id, balance) in balances.iter() {
+        if remaining <= 0 {
+            break;
+        }
+
+        let balance = cents(*balance);
+        if balance <= 0 {
+            continue;
+        }
+
+        let payment = balance.min(remaining);
+
+        remaining -= payment;
+        payments.push((*xact_id, payment as f64 / 100.0));
+    }
+
+    (payments, remaining as f64 / 100.0)
+}
+
+/// Apply a payment to a patron's open transactions.
+///
+/// Creates one payment of the requested type per transaction paid,
+/// closing transactions which reach a zero balance and recalculating
+/// penalties.  Any amount beyond what the patron owes is either
+/// rejected or added to the patron's credit balance.
+pub fn apply_payment(
+    editor: &mut Editor,
+    user_id: i64,
+    amount: f64,
+    payment_type: &str,
+    ordering: PaymentOrder,
+    overpayment: Overpayment,
+) -> EgResult<PaymentResult> {
+    if amount <= 0.0 {
+        return Err(format!("Invalid payment amount: {amount}").into());
+    }
+
+    // Verify the payment type before doing any work.
+    payment_class(payment_type)?;
+
+    let direction = match ordering {
+        PaymentOrder::OldestFirst => "asc",
+        PaymentOrder::NewestFirst => "desc",
+    };
+
+    let query = eg::hash! {
+        "usr": user_id,
+        "balance_owed": {">": 0},
+    };
+    let ops = eg::hash! {
+        "order_by": {"mbts": {"xact_start": {"direction": direction}}}
+    };
+
+    let summaries = editor.search_with_ops("mbts", query, ops)?;
+
+    let mut balances = Vec::new();
+    for sum in summaries.iter() {
+        balances.push((sum.id()?, sum["balance_owed"].float()?));
+    }
+
+    let (payments, remaining) = allocate_payment(amount, &balances);
+
+    if remaining > 0.0 && overpayment == Overpayment::Reject {
+        return Err(format!("Payment of {amount:.2} exceeds the amount owed").into());
+    }
+
+    let mut result = PaymentResult::default();
+    let mut penalty_orgs: HashSet<i64> = HashSet::new();
+
+    for (xact_id, pay_amount) in payments.iter() {
+        create_payment(editor, payment_type, *xact_id, *pay_amount)?;
+        penalty_orgs.insert(xact_org(editor, *xact_id)?);
+    }
+
+    if remaining > 0.0 {
+        let mut user = editor
+            .retrieve("au", user_id)?
+            .ok_or_else(|| editor.die_event())?;
+
+        let credit = user["credit_forward_balance"].float().unwrap_or(0.0);
+        user["credit_forward_balance"] = util::fpsum(credit, remaining).into();
+
+        log::info!("Crediting {remaining:.2} of overpayment to user {user_id}");
+
+        editor.update(user)?;
+        result.credited = remaining;
+    }
+
+    for org_id in penalty_orgs.iter() {
+        penalty::calculate_penalties(editor, user_id, *org_id, None)?;
+    }
+
+    let owed: f64 = balances.iter().map(|(_, b)| *b).sum();
+    let paid: f64 = payments.iter().map(|(_, p)| *p).sum();
+
+    result.balance_owed = ((owed - paid) * 100.0).round() / 100.0;
+
+    result.payments = payments;
+
+    Ok(result)
+}
+
+/// Create a payment of the requested type against a transaction and
+/// close the transaction if it's now paid off.
+fn create_payment(
+    editor: &mut Editor,
+    payment_type: &str,
+    xact_id: i64,
+    amount: f64,
+) -> EgResult<()> {
+    let class = payment_class(payment_type)?;
+
+    log::info!("Applying {payment_type} of {amount:.2} to xact {xact_id}");
+
+    let mut payment = eg::hash! {
+        "xact": xact_id,
+        "amount": amount,
+        "amount_collected": amount,
+        "accepting_usr": editor.requestor_id()?,
+        "payment_ts": "now",
+    };
+
+    // Desk payments track the workstation that accepted them.
+    if matches!(class, "mcp" | "mckp" | "mccp" | "mdcp") {
+        if let Some(ws_id) = editor.requestor_ws_id() {
+            payment["cash_drawer"] = ws_id.into();
+        }
+    }
+
+    editor.create(EgValue::create(class, payment)?)?;
+
+    check_open_xact(editor, xact_id)
+}
+
+/// Outcome of a payment applied to a specific transaction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetedPayment {
+    pub xact_id: i64,
+    /// Amount paid.
+    pub amount: f64,
+    /// Balance owed on the transaction after payment.
+    pub balance_owed: f64,
+}
+
+/// Verify a set of (transaction ID, amount) payments against the
+/// (transaction ID, balance owed) of each transaction.
+///
+/// Every amount must be positive and no transaction may be paid more
+/// than it owes, including across multiple payments to the same
+/// transaction.  Returns the outcome of each payment in the order
+/// provided.
+///
+/// ```
+/// use evergreen::common::billing::check_targeted_payments;
+///
+/// let balances = [(1, 5.00), (2, 0.75)];
+///
+/// let results = check_targeted_payments(&[(2, 0.50), (1, 5.00)], &balances).unwrap();
+/// assert_eq!(results[0].balance_owed, 0.25);
+/// assert_eq!(results[1].balance_owed, 0.0);
+///
+/// assert!(check_targeted_payments(&[(2, 1.00)], &balances).is_err());
+/// assert!(check_targeted_payments(&[(3, 1.00)], &balances).is_err());
+/// ```
+pub fn check_targeted_payments(
+    payments: &[(i64, f64)],
+    balances: &[(i64, f64)],
+) -> EgResult<Vec<TargetedPayment>> {
+    // Work in whole cents to avoid accumulating floating point errors.
+    let cents = |v: f64| (v * 100.0).round() as i64;
+
+    let mut owed: HashMap<i64, i64> = balances
+        .iter()
+        .map(|(xact_id, balance)| (*xact_id, cents(*balance)))
+        .collect();
+
+    let mut results = Vec::new();
+
+    for (xact_id, amount) in payments.iter() {
+        let amount = cents(*amount);
+
+        if amount <= 0 {
+            return Err(format!(
+                "Invalid payment amount for transaction {xact_id}: {:.2}",
+                amount as f64 / 100.0
+            )
+            .into());
+        }
+
+        let balance = owed
+            .get_mut(xact_id)
+            .ok_or_else(|| format!("Transaction {xact_id} has no balance owed"))?;
+
+        if amount > *balance {
+            return Err(format!(
+                "Payment of {:.2} exceeds the balance of {:.2} owed on transaction {xact_id}",
+                amount as f64 / 100.0,
+                *balance as f64 / 100.0
+            )
+            .into());
+        }
+
+        *balance -= amount;
+
+        results.push(TargetedPayment {
+            xact_id: *xact_id,
+            amount: amount as f64 / 100.0,
+            balance_owed: *balance as f64 / 100.0,
+        });
+    }
+
+    Ok(results)
+}
+
+/// Apply payments of specific amounts to specific transactions for
+/// a patron, instead of distributing a single amount across all of
+/// their open transactions.
+///
+/// All payments are verified with check_targeted_payments() before
+/// any are created, so an invalid set of payments changes nothing.
+/// If creating a payment fails part way through, the error is
+/// returned and the caller should roll back its transaction.
+pub fn apply_targeted_payments(
+    editor: &mut Editor,
+    user_id: i64,
+    payments: &[(i64, f64)],
+    payment_type: &str,
+) -> EgResult<Vec<TargetedPayment>> {
+    payment_class(payment_type)?;
+
+    if payments.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let xact_ids: Vec<i64> = payments.iter().map(|(id, _)| *id).collect();
+
+    let query = eg::hash! {
+        "id": xact_ids,
+        "usr": user_id,
+        "balance_owed": {">": 0},
+    };
+
+    let mut balances = Vec::new();
+    for sum in editor.search("mbts", query)?.iter() {
+        balances.push((sum.id()?, sum["balance_owed"].float()?));
+    }
+
+    let results = check_targeted_payments(payments, &balances)?;
+
+    let mut penalty_orgs: HashSet<i64> = HashSet::new();
+
+    for result in results.iter() {
+        create_payment(editor, payment_type, result.xact_id, result.amount)?;
+        penalty_orgs.insert(xact_org(editor, result.xact_id)?);
+    }
+
+    for org_id in penalty_orgs.iter() {
+        penalty::calculate_penalties(editor, user_id, *org_id, None)?;
+    }
+
+    Ok(results)
+}
@@ -1,8 +1,54 @@
 //! Shared, circ-focused utility functions
 use crate as eg;
+use eg::common::billing;
+use eg::common::settings::Settings;
+use eg::constants as C;
+use eg::date::{self, EgDate};
 use eg::Editor;
 use eg::EgResult;
 use eg::EgValue;
+use std::collections::HashMap;
+
+/// Void a circulation's overdue fines and generate them again from
+/// its current due date, e.g. after the due date was backdated or
+/// closed dates were added after the fines accrued.
+///
+/// Fines are generated up to the circulation's stop fines time, or
+/// up to now for circulations which are still accruing fines.  Org
+/// unit closed dates are honored as they are by the fine generator.
+///
+/// Returns the net change to the transaction's balance owed.  Must
+/// be run within an editor transaction.
+pub fn recalculate_overdue_fines(editor: &mut Editor, circ_id: i64) -> EgResult<f64> {
+    let circ = editor
+        .retrieve("circ", circ_id)?
+        .ok_or_else(|| editor.die_event())?;
+
+    let until = match circ["stop_fines_time"].as_str() {
+        Some(t) => date::parse_datetime(t)?,
+        None => date::now(),
+    };
+
+    let balance_before = xact_balance_owed(editor, circ_id)?;
+
+    let voided = billing::void_billings_by_type(editor, circ_id, C::BTYPE_OVERDUE_MATERIALS)?;
+
+    log::info!("Recalculating overdue fines for circ {circ_id} after voiding {voided:.2}");
+
+    billing::replay_fines_for_circ(editor, circ_id, until)?;
+
+    let balance_after = xact_balance_owed(editor, circ_id)?;
+
+    Ok(((balance_after - balance_before) * 100.0).round() / 100.0)
+}
+
+fn xact_balance_owed(editor: &mut Editor, xact_id: i64) -> EgResult<f64> {
+    let summary = editor
+        .retrieve("mbts", xact_id)?
+        .ok_or_else(|| editor.die_event())?;
+
+    summary["balance_owed"].float()
+}
 
 pub fn summarize_circ_chain(e: &mut Editor, circ_id: i64) -> EgResult<EgValue> {
     let query = eg::hash! {
@@ -34,3 +80,198 @@ pub fn circ_chain(e: &mut Editor, circ_id: i64) -> EgResult<Vec<EgValue>> {
 
     Ok(chains)
 }
+
+/// Days overdue and estimated overdue fines for a circulation.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OverduePreview {
+    pub days_overdue: i64,
+    pub fine: f64,
+}
+
+impl OverduePreview {
+    /// Estimate the overdue fine accrued between the due date and `now`.
+    ///
+    /// One recurring fine is charged for each fine interval, including
+    /// the one we are currently inside, capped at max_fine.  No fines
+    /// accrue while still within the grace period.
+    ///
+    /// ```
+    /// use evergreen::common::circ::OverduePreview;
+    /// use evergreen::date;
+    ///
+    /// let due = date::parse_datetime("2024-01-01T23:59:59-0500").unwrap();
+    /// let now = date::parse_datetime("2024-01-04T12:00:00-0500").unwrap();
+    ///
+    /// let preview = OverduePreview::calculate(&due, &now, 0.25, 86400, 5.0, 0);
+    ///
+    /// assert_eq!(preview.days_overdue, 3);
+    /// assert_eq!(preview.fine, 0.75);
+    /// ```
+    pub fn calculate(
+        due_date: &EgDate,
+        now: &EgDate,
+        recurring_fine: f64,
+        fine_interval_secs: i64,
+        max_fine: f64,
+        grace_period_secs: i64,
+    ) -> OverduePreview {
+        let overdue_secs = now.timestamp() - due_date.timestamp();
+
+        if overdue_secs <= 0 {
+            return OverduePreview::default();
+        }
+
+        // Partial days count as a full day overdue.
+        let days_overdue = (overdue_secs as f64 / 86400.0).ceil() as i64;
+
+        if overdue_secs <= grace_period_secs || fine_interval_secs <= 0 {
+            return OverduePreview {
+                days_overdue,
+                fine: 0.0,
+            };
+        }
+
+        let intervals = (overdue_secs as f64 / fine_interval_secs as f64).ceil();
+
+        // Work in cents to avoid float drift.
+        let mut fine = intervals * (recurring_fine * 100.0).round();
+        let max_fine = (max_fine * 100.0).round();
+
+        if fine > max_fine {
+            fine = max_fine;
+        }
+
+        OverduePreview {
+            days_overdue,
+            fine: fine / 100.0,
+        }
+    }
+}
+
+/// Caches the fine rule intervals (e.g. "1 day") parsed while
+/// previewing overdue fines, since most circulations share a small
+/// number of fine rules.
+#[derive(Debug, Default)]
+pub struct IntervalCache {
+    seconds: HashMap<String, i64>,
+    parsed: usize,
+}
+
+impl IntervalCache {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Interval as seconds, parsed on first use.
+    pub fn seconds(&mut self, interval: &str) -> EgResult<i64> {
+        if let Some(secs) = self.seconds.get(interval) {
+            return Ok(*secs);
+        }
+
+        let secs = date::interval_to_seconds(interval)?;
+
+        self.parsed += 1;
+        self.seconds.insert(interval.to_string(), secs);
+
+        Ok(secs)
+    }
+
+    /// Number of intervals actually parsed, i.e. cache misses.
+    pub fn parsed(&self) -> usize {
+        self.parsed
+    }
+}
+
+impl OverduePreview {
+    /// Preview the overdue fine for a circulation using the fine rules
+    /// stored on the circ and the provided grace period, which should
+    /// already be extended for closed dates as needed.
+    pub fn from_circ(
+        circ: &EgValue,
+        now: &EgDate,
+        grace_period_secs: i64,
+        intervals: &mut IntervalCache,
+    ) -> EgResult<OverduePreview> {
+        if !circ["checkin_time"].is_null() || !circ["stop_fines"].is_null() {
+            return Ok(OverduePreview::default());
+        }
+
+        let due_date = date::parse_datetime(circ["due_date"].str()?)?;
+
+        Ok(OverduePreview::calculate(
+            &due_date,
+            now,
+            circ["recurring_fine"].float()?,
+            intervals.seconds(circ["fine_interval"].str()?)?,
+            circ["max_fine"].float()?,
+            grace_period_secs,
+        ))
+    }
+}
+
+/// Preview the days overdue and accrued overdue fine for a circulation
+/// using the fine rules stored on the circ, without creating billings.
+///
+/// Circulations that are not overdue, are checked in, or have stopped
+/// accruing fines return zeroes.
+pub fn overdue_preview(editor: &mut Editor, circ: &EgValue) -> EgResult<OverduePreview> {
+    let mut settings = Settings::new(editor);
+    let mut intervals = IntervalCache::new();
+
+    preview_one(editor, circ, &date::now(), &mut settings, &mut intervals)
+}
+
+/// Batch variant of overdue_preview().
+///
+/// Previews are computed in a single pass which shares org settings
+/// and parsed fine intervals across all of the circulations, using the
+/// same "now" for each, so previews for patrons with many overdue items
+/// stay cheap.  Previews are returned in the same order as the circs.
+pub fn overdue_previews(editor: &mut Editor, circs: &[EgValue]) -> EgResult<Vec<OverduePreview>> {
+    let now = date::now();
+    let mut settings = Settings::new(editor);
+    let mut intervals = IntervalCache::new();
+    let mut previews = Vec::new();
+
+    for circ in circs.iter() {
+        previews.push(preview_one(
+            editor,
+            circ,
+            &now,
+            &mut settings,
+            &mut intervals,
+        )?);
+    }
+
+    Ok(previews)
+}
+
+fn preview_one(
+    editor: &mut Editor,
+    circ: &EgValue,
+    now: &EgDate,
+    settings: &mut Settings,
+    intervals: &mut IntervalCache,
+) -> EgResult<OverduePreview> {
+    if !circ["checkin_time"].is_null() || !circ["stop_fines"].is_null() {
+        return Ok(OverduePreview::default());
+    }
+
+    let due_date = date::parse_datetime(circ["due_date"].str()?)?;
+
+    if due_date >= *now {
+        return Ok(OverduePreview::default());
+    }
+
+    let grace_period = intervals.seconds(circ["grace_period"].as_str().unwrap_or("0s"))?;
+
+    let grace_period = billing::extend_grace_period(
+        editor,
+        circ["circ_lib"].int()?,
+        grace_period,
+        due_date,
+        Some(settings),
+    )?;
+
+    OverduePreview::from_circ(circ, now, grace_period, intervals)
+}
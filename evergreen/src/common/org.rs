@@ -5,6 +5,11 @@ use eg::date;
 use eg::Editor;
 use eg::EgResult;
 use eg::EgValue;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Org unit tree shared by all threads.  Loaded on first use.
+static ORG_TREE: RwLock<Option<Arc<OrgTree>>> = RwLock::new(None);
 
 /// Apply a variety of DB transforms to an org unit and return
 /// the calculated org unit IDs.
@@ -60,8 +65,137 @@ pub fn full_path(editor: &mut Editor, org_id: i64, depth: Option<i64>) -> EgResu
     org_relations_query(editor, org_id, "actor.org_unit_full_path", depth)
 }
 
+/// In-memory copy of the org unit hierarchy for resolving ancestors
+/// and descendants without a database query per lookup.
+///
+/// Lookups on unknown org units return empty lists.
+///
+/// ```
+/// use evergreen as eg;
+/// use eg::common::org::OrgTree;
+///
+/// let tree = OrgTree::from_org_units(&[
+///     eg::hash! {"id": 1, "parent_ou": eg::NULL},
+///     eg::hash! {"id": 2, "parent_ou": 1},
+///     eg::hash! {"id": 4, "parent_ou": 2},
+/// ])
+/// .unwrap();
+///
+/// assert_eq!(tree.ancestors(4), vec![1, 2, 4]);
+/// assert_eq!(tree.descendants(2), vec![2, 4]);
+/// ```
+#[derive(Debug, Default)]
+pub struct OrgTree {
+    parents: HashMap<i64, Option<i64>>,
+    children: HashMap<i64, Vec<i64>>,
+}
+
+impl OrgTree {
+    /// Build the tree from a list of org units.
+    pub fn from_org_units(orgs: &[EgValue]) -> EgResult<OrgTree> {
+        let mut tree = OrgTree::default();
+
+        for org in orgs {
+            let id = org.id()?;
+            let parent = org["parent_ou"].as_int();
+
+            tree.parents.insert(id, parent);
+
+            if let Some(p) = parent {
+                tree.children.entry(p).or_default().push(id);
+            }
+        }
+
+        for list in tree.children.values_mut() {
+            list.sort();
+        }
+
+        Ok(tree)
+    }
+
+    /// Load the tree from the database.
+    pub fn load(editor: &mut Editor) -> EgResult<OrgTree> {
+        let orgs = editor.search("aou", eg::hash! {"id": {"!=": EgValue::Null}})?;
+        OrgTree::from_org_units(&orgs)
+    }
+
+    /// Returns the shared tree, loading it from the database if needed.
+    pub fn cached(editor: &mut Editor) -> EgResult<Arc<OrgTree>> {
+        if let Some(tree) = ORG_TREE.read().unwrap().as_ref() {
+            return Ok(tree.clone());
+        }
+
+        let tree = Arc::new(OrgTree::load(editor)?);
+
+        log::debug!("Loaded org tree with {} org units", tree.parents.len());
+
+        *ORG_TREE.write().unwrap() = Some(tree.clone());
+
+        Ok(tree)
+    }
+
+    /// Discard the shared tree so the next call to cached() reloads
+    /// it, e.g. after org units are added or moved.
+    pub fn invalidate() {
+        *ORG_TREE.write().unwrap() = None;
+    }
+
+    pub fn contains(&self, org_id: i64) -> bool {
+        self.parents.contains_key(&org_id)
+    }
+
+    /// The org unit and its ancestors, starting at the root.
+    pub fn ancestors(&self, org_id: i64) -> Vec<i64> {
+        let mut list = Vec::new();
+        let mut next = Some(org_id);
+
+        while let Some(id) = next {
+            // Guard against bad data creating a loop.
+            if !self.contains(id) || list.contains(&id) {
+                break;
+            }
+            list.push(id);
+            next = self.parents[&id];
+        }
+
+        list.reverse();
+        list
+    }
+
+    /// The org unit and its descendants, one tree depth at a time.
+    pub fn descendants(&self, org_id: i64) -> Vec<i64> {
+        if !self.contains(org_id) {
+            return Vec::new();
+        }
+
+        let mut list = vec![org_id];
+        let mut idx = 0;
+
+        while let Some(id) = list.get(idx) {
+            if let Some(children) = self.children.get(id) {
+                let children: Vec<i64> = children
+                    .iter()
+                    .filter(|c| !list.contains(c))
+                    .copied()
+                    .collect();
+                list.extend(children);
+            }
+            idx += 1;
+        }
+
+        list
+    }
+
+    /// Ancestors of the org unit followed by its descendants.
+    pub fn full_path(&self, org_id: i64) -> Vec<i64> {
+        let mut list = self.ancestors(org_id);
+        list.extend(self.descendants(org_id).into_iter().skip(1));
+        list
+    }
+}
+
 /// Conveys the open state of an org unit on a specific day.
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum OrgOpenState {
     /// Open on the requested date.
     Open,
@@ -72,93 +206,186 @@ pub enum OrgOpenState {
     OpensOnDate(date::EgDate),
 }
 
-/// Returns an OrgOpenState descibing the open state of the org unit
-/// on the provided day in the timezone of the provided date.
-///
-/// If the result is OrgOpenState::OpensOnDate(date), the date value
-/// will be a fully-qualified DateTime with fixed timezone (so the
-/// original time zone can be retained).  However, only the date portion
-/// of the datetime is meaningful.  To get the final unadorned Date,
-/// in the timezone of the returned DateTime, without time or timzone:
-/// date.date_naive()
-pub fn next_open_date(
-    editor: &mut Editor,
-    org_id: i64,
-    date: &date::EgDate,
-) -> EgResult<OrgOpenState> {
-    let start_date = date.clone();
-    let mut date = date.clone();
-
-    let mut closed_days: Vec<i64> = Vec::new();
-    if let Some(h) = editor.retrieve("aouhoo", org_id)? {
-        for day in 0..7 {
-            let open = h[&format!("dow_{day}_open")].as_str().unwrap();
-            let close = h[&format!("dow_{day}_close")].as_str().unwrap();
-            if open == "00:00:00" && close == open {
-                closed_days.push(day);
-            }
+/// A span of time during which an org unit is closed.
+#[derive(Debug, Clone)]
+pub struct ClosedRange {
+    pub start: date::EgDate,
+    pub end: date::EgDate,
+    /// Full-day and multi-day closings close every calendar day they
+    /// touch.  Partial-day closings only cover the times between
+    /// start and end.
+    pub full_day: bool,
+}
+
+impl ClosedRange {
+    fn from_aoucd(closing: &EgValue) -> EgResult<ClosedRange> {
+        Ok(ClosedRange {
+            start: date::parse_datetime(closing["close_start"].str()?)?,
+            end: date::parse_datetime(closing["close_end"].str()?)?,
+            full_day: closing["full_day"].boolish() || closing["multi_day"].boolish(),
+        })
+    }
+
+    /// True if this closing applies to the provided moment in time.
+    pub fn covers(&self, date: &date::EgDate) -> bool {
+        if self.start <= *date && *date <= self.end {
+            return true;
         }
 
-        // Always closed.
-        if closed_days.len() == 7 {
-            return Ok(OrgOpenState::Never);
+        if !self.full_day {
+            return false;
         }
+
+        // Compare calendar days in the timezone of the date in question.
+        let day = date.date_naive();
+        let start = self.start.with_timezone(date.offset()).date_naive();
+        let end = self.end.with_timezone(date.offset()).date_naive();
+
+        start <= day && day <= end
     }
+}
 
-    let mut counter = 0;
-    while counter < 366 {
-        // inspect at most 1 year of data
-        counter += 1;
+/// Hours of operation and closed dates for a single org unit,
+/// including closed dates inherited from its ancestors.
+#[derive(Debug, Clone, Default)]
+pub struct OrgClosings {
+    /// Zero-based (Sunday) days of the week the org unit is closed.
+    pub closed_weekdays: Vec<u32>,
+    pub ranges: Vec<ClosedRange>,
+}
 
-        // Zero-based day of week
+impl OrgClosings {
+    /// True if the org unit is closed at the provided time.
+    pub fn is_closed(&self, date: &date::EgDate) -> bool {
         let weekday = date.date_naive().weekday().num_days_from_sunday();
 
-        if closed_days.contains(&(weekday as i64)) {
-            // Closed for the current day based on hours of operation.
-            // Jump ahead one day and start over.
-            date = date + Duration::try_days(1).expect("In Bounds");
-            continue;
+        self.closed_weekdays.contains(&weekday) || self.ranges.iter().any(|r| r.covers(date))
+    }
+
+    /// See [`next_open_date`]
+    pub fn next_open_date(&self, date: &date::EgDate) -> OrgOpenState {
+        if self.closed_weekdays.len() == 7 {
+            // Always closed.
+            return OrgOpenState::Never;
         }
 
-        // Open this day based on hours of operation.
-        // See if any overlapping closings are configured instead.
-
-        let timestamp = date::to_iso(&date);
-        let query = eg::hash! {
-            "org_unit": org_id,
-            "close_start": {"<=": EgValue::from(timestamp.clone())},
-            "close_end": {">=": EgValue::from(timestamp)},
-        };
-
-        let org_closed = editor.search("aoucd", query)?;
-
-        if org_closed.len() == 0 {
-            // No overlapping closings.  We've found our open day.
-            if start_date == date {
-                // No changes were made.  We're open on the requested day.
-                return Ok(OrgOpenState::Open);
-            } else {
-                // Advancements were made to the date in progress to
-                // find an open day.
-                return Ok(OrgOpenState::OpensOnDate(date));
+        let mut date = date.clone();
+
+        // inspect at most 1 year of data
+        for counter in 0..366 {
+            if !self.is_closed(&date) {
+                if counter == 0 {
+                    // No changes were made.  We're open on the requested day.
+                    return OrgOpenState::Open;
+                } else {
+                    // Advancements were made to the date in progress to
+                    // find an open day.
+                    return OrgOpenState::OpensOnDate(date);
+                }
             }
+
+            // Closed for the current day.  Jump ahead one day and
+            // start over.
+            date = date + Duration::try_days(1).expect("In Bounds");
+        }
+
+        // If we get here it means we never found an open day.
+        OrgOpenState::Never
+    }
+}
+
+/// Caches org unit closing information so repeated closed date
+/// checks within a request only hit the database once per org unit.
+#[derive(Default)]
+pub struct ClosedDates {
+    orgs: HashMap<i64, OrgClosings>,
+}
+
+impl ClosedDates {
+    pub fn new() -> ClosedDates {
+        Default::default()
+    }
+
+    /// Returns the closing information for an org unit, loading it
+    /// from the database on first access.
+    pub fn closings(&mut self, editor: &mut Editor, org_id: i64) -> EgResult<&OrgClosings> {
+        if !self.orgs.contains_key(&org_id) {
+            let closings = ClosedDates::load(editor, org_id)?;
+            self.orgs.insert(org_id, closings);
         }
 
-        // Find the end of the closed date range and jump ahead to that.
-        let mut range_end = org_closed[0]["close_end"].as_str().unwrap();
-        for day in org_closed.iter() {
-            let end = day["close_end"].as_str().unwrap();
-            if end > range_end {
-                range_end = end;
+        Ok(self.orgs.get(&org_id).unwrap())
+    }
+
+    fn load(editor: &mut Editor, org_id: i64) -> EgResult<OrgClosings> {
+        let mut closings = OrgClosings::default();
+
+        if let Some(h) = editor.retrieve("aouhoo", org_id)? {
+            for day in 0..7 {
+                let open = h[&format!("dow_{day}_open")].as_str().unwrap();
+                let close = h[&format!("dow_{day}_close")].as_str().unwrap();
+                if open == "00:00:00" && close == open {
+                    closings.closed_weekdays.push(day);
+                }
             }
         }
 
-        date = date::parse_datetime(&range_end)?;
-        date = date + Duration::try_days(1).expect("In Bounds");
+        // Closings at any ancestor org unit apply to this org unit.
+        let query = eg::hash! {"org_unit": ancestors(editor, org_id)?};
+
+        for closing in editor.search("aoucd", query)?.iter() {
+            closings.ranges.push(ClosedRange::from_aoucd(closing)?);
+        }
+
+        Ok(closings)
+    }
+
+    /// True if the org unit is closed at the provided time, either
+    /// per its hours of operation or a closed date.
+    pub fn is_closed(
+        &mut self,
+        editor: &mut Editor,
+        org_id: i64,
+        date: &date::EgDate,
+    ) -> EgResult<bool> {
+        Ok(self.closings(editor, org_id)?.is_closed(date))
     }
 
-    // If we get here it means we never found an open day.
-    Ok(OrgOpenState::Never)
+    /// See [`next_open_date`]
+    pub fn next_open_date(
+        &mut self,
+        editor: &mut Editor,
+        org_id: i64,
+        date: &date::EgDate,
+    ) -> EgResult<OrgOpenState> {
+        Ok(self.closings(editor, org_id)?.next_open_date(date))
+    }
+}
+
+/// True if the org unit is closed at the provided time.
+///
+/// Use a [`ClosedDates`] instead when checking multiple dates.
+pub fn is_closed(editor: &mut Editor, org_id: i64, date: &date::EgDate) -> EgResult<bool> {
+    ClosedDates::new().is_closed(editor, org_id, date)
+}
+
+/// Returns an OrgOpenState descibing the open state of the org unit
+/// on the provided day in the timezone of the provided date.
+///
+/// If the result is OrgOpenState::OpensOnDate(date), the date value
+/// will be a fully-qualified DateTime with fixed timezone (so the
+/// original time zone can be retained).  However, only the date portion
+/// of the datetime is meaningful.  To get the final unadorned Date,
+/// in the timezone of the returned DateTime, without time or timzone:
+/// date.date_naive()
+///
+/// Use a [`ClosedDates`] instead when checking multiple dates.
+pub fn next_open_date(
+    editor: &mut Editor,
+    org_id: i64,
+    date: &date::EgDate,
+) -> EgResult<OrgOpenState> {
+    ClosedDates::new().next_open_date(editor, org_id, date)
 }
 
 /// Returns the proximity from from_org to to_org.
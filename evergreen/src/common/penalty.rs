@@ -1,10 +1,21 @@
 //! Standing penalty utility functions
 use crate as eg;
+use eg::common::org;
 use eg::common::settings::Settings;
 use eg::common::trigger;
 use eg::editor::Editor;
+use eg::osrf::message;
 use eg::result::EgResult;
 use eg::EgValue;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// Penalty type labels by locale and penalty type ID.
+    ///
+    /// Penalty types rarely change, so cache them for the life of the thread.
+    static PENALTY_LABELS: RefCell<HashMap<(String, i64), String>> = RefCell::new(HashMap::new());
+}
 
 // Shortcut for unckecked int conversions for values that are known good.
 // We coul compare EgValue's directly, but there's a chance a number may be
@@ -13,12 +24,56 @@ fn number(v: &EgValue) -> i64 {
     v.int().expect("Has Number")
 }
 
+/// Standing penalties added and removed by a penalty calculation.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PenaltyChanges {
+    /// IDs of newly applied actor.usr_standing_penalty rows.
+    pub applied: Vec<i64>,
+    /// IDs of removed actor.usr_standing_penalty rows.
+    pub removed: Vec<i64>,
+}
+
+/// Recalculate a patron's system penalties now, e.g. after staff
+/// adjust fines outside of the usual circulation paths, so the
+/// patron's blocks are up to date.
+///
+/// The context org unit defaults to the patron's home org unit.
+pub fn recalculate_penalties(
+    editor: &mut Editor,
+    user_id: i64,
+    context_org: Option<i64>,
+) -> EgResult<PenaltyChanges> {
+    let context_org = match context_org {
+        Some(o) => o,
+        None => {
+            let user = editor
+                .retrieve("au", user_id)?
+                .ok_or_else(|| editor.die_event())?;
+
+            user["home_ou"].int()?
+        }
+    };
+
+    apply_system_penalties(editor, user_id, context_org, None)
+}
+
 pub fn calculate_penalties(
     editor: &mut Editor,
     user_id: i64,
     context_org: i64,
     only_penalties: Option<&Vec<EgValue>>,
 ) -> EgResult<()> {
+    apply_system_penalties(editor, user_id, context_org, only_penalties).map(|_| ())
+}
+
+fn apply_system_penalties(
+    editor: &mut Editor,
+    user_id: i64,
+    context_org: i64,
+    only_penalties: Option<&Vec<EgValue>>,
+) -> EgResult<PenaltyChanges> {
+    let mut changes = PenaltyChanges::default();
+
     let query = eg::hash! {
         from: [
             "actor.calculate_system_penalties",
@@ -34,7 +89,7 @@ pub fn calculate_penalties(
 
     if penalties.len() == 0 {
         // Nothing to change.
-        return Ok(());
+        return Ok(changes);
     }
 
     // Applied penalties have a DB ID.
@@ -75,6 +130,8 @@ pub fn calculate_penalties(
             let new_pen = EgValue::create("ausp", pen_hash.clone())?;
             let new_pen = editor.create(new_pen)?;
 
+            changes.applied.push(new_pen.id()?);
+
             // Track new penalties so we can fire related A/T events.
             let csp_id = pen_hash["standing_penalty"].clone();
 
@@ -89,6 +146,8 @@ pub fn calculate_penalties(
 
     // Delete applied penalties that are no longer wanted.
     for pen_hash in existing_penalties {
+        changes.removed.push(number(&pen_hash["id"]));
+
         let del_pen = EgValue::create("ausp", pen_hash.clone())?;
         editor.delete(del_pen)?;
     }
@@ -104,7 +163,7 @@ pub fn calculate_penalties(
         )?;
     }
 
-    Ok(())
+    Ok(changes)
 }
 
 /// If the caller specifies a limited set of penalties to process,
@@ -184,3 +243,127 @@ fn trim_to_wanted_penalties(
 
     Ok(final_penalties)
 }
+
+/// Returns the labels of the blocking penalties (penalty types with
+/// a block list) a user has in effect at the context org unit.
+///
+/// Labels are translated to the current thread locale.
+pub fn active_block_descriptions(
+    editor: &mut Editor,
+    user_id: i64,
+    context_org: i64,
+) -> EgResult<Vec<String>> {
+    let org_ids = org::full_path(editor, context_org, None)?;
+
+    let query = eg::hash! {
+        select: {ausp: ["standing_penalty"]},
+        from: {ausp: "csp"},
+        where: {
+            "+ausp": {
+                usr: user_id,
+                org_unit: org_ids,
+                "-or": [
+                    {stop_date: eg::NULL},
+                    {stop_date: {">": "now"}},
+                ],
+            },
+            "+csp": {block_list: {"!=": eg::NULL}},
+        },
+        order_by: {ausp: "set_date"},
+    };
+
+    let mut penalty_ids: Vec<i64> = Vec::new();
+    for pen in editor.json_query(query)? {
+        let id = pen["standing_penalty"].int()?;
+        if !penalty_ids.contains(&id) {
+            penalty_ids.push(id);
+        }
+    }
+
+    let locale = message::thread_locale();
+
+    let missing: Vec<i64> = PENALTY_LABELS.with(|labels| {
+        let labels = labels.borrow();
+        penalty_ids
+            .iter()
+            .filter(|id| !labels.contains_key(&(locale.clone(), **id)))
+            .copied()
+            .collect()
+    });
+
+    if missing.len() > 0 {
+        for csp in editor.search("csp", eg::hash! {id: missing})? {
+            let key = (locale.clone(), csp.id()?);
+            let label = csp["label"].as_str().unwrap_or("").to_string();
+            PENALTY_LABELS.with(|labels| labels.borrow_mut().insert(key, label));
+        }
+    }
+
+    let descriptions = PENALTY_LABELS.with(|labels| {
+        let labels = labels.borrow();
+        penalty_ids
+            .iter()
+            .filter_map(|id| labels.get(&(locale.clone(), *id)).cloned())
+            .collect()
+    });
+
+    Ok(descriptions)
+}
+
+/// Standing penalty type for patrons exceeding their fine threshold.
+pub const PATRON_EXCEEDS_FINES: i64 = 1;
+
+/// Returns the threshold of the closest matching group penalty
+/// threshold (pgpt) from the provided set.
+///
+/// Group and org unit ancestor lists are ordered nearest first.  As
+/// with actor.calculate_system_penalties, the closest group wins, then
+/// the closest org unit within that group.
+pub fn closest_threshold(
+    thresholds: &[EgValue],
+    grp_ancestors: &[i64],
+    org_ancestors: &[i64],
+) -> EgResult<Option<f64>> {
+    for grp_id in grp_ancestors {
+        for org_id in org_ancestors {
+            for pgpt in thresholds {
+                if pgpt["grp"].int()? == *grp_id && pgpt["org_unit"].int()? == *org_id {
+                    return pgpt["threshold"].float().map(Some);
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the penalty threshold that applies to users in the
+/// provided profile group at the context org unit, e.g. the maximum
+/// fines allowed before PATRON_EXCEEDS_FINES is applied.
+pub fn penalty_threshold(
+    editor: &mut Editor,
+    profile: i64,
+    context_org: i64,
+    penalty: i64,
+) -> EgResult<Option<f64>> {
+    // Both ancestor functions return the root first.
+    let query = eg::hash! {"from": ["permission.grp_ancestors", profile]};
+    let mut grp_ids = Vec::new();
+    for grp in editor.json_query(query)? {
+        grp_ids.push(grp.id()?);
+    }
+    grp_ids.reverse();
+
+    let mut org_ids = org::ancestors(editor, context_org)?;
+    org_ids.reverse();
+
+    let search = eg::hash! {
+        grp: grp_ids.clone(),
+        org_unit: org_ids.clone(),
+        penalty: penalty,
+    };
+
+    let thresholds = editor.search("pgpt", search)?;
+
+    closest_threshold(&thresholds, &grp_ids, &org_ids)
+}
@@ -2,9 +2,11 @@ use crate as eg;
 use eg::common::circulator::{CircOp, Circulator};
 use eg::common::holds;
 use eg::date;
+use eg::editor::Editor;
 use eg::EgEvent;
 use eg::EgResult;
 use eg::EgValue;
+use std::collections::HashMap;
 
 /// Performs item checkins
 impl Circulator<'_> {
@@ -52,11 +54,10 @@ impl Circulator<'_> {
             }
         };
 
-        let mut circ = self
-            .editor()
-            .search_with_ops("circ", query, flesh)?
-            .pop()
-            .ok_or_else(|| self.editor().die_event())?;
+        let mut circ = match self.editor().search_with_ops("circ", query, flesh)?.pop() {
+            Some(c) => c,
+            None => return self.exit_err_on_event_code("ACTION_CIRCULATION_NOT_FOUND"),
+        };
 
         let circ_id = circ.id()?;
         let patron = circ["usr"].take(); // fleshed
@@ -126,6 +127,12 @@ impl Circulator<'_> {
         if block_for_holds {
             let holds = holds::find_nearest_permitted_hold(self.editor(), copy_id, true)?;
             if holds.is_some() {
+                if self.get_option_bool("selfcheck_renewal") {
+                    // Self-check renewals have no staff member on hand
+                    // to override the block, so exit now instead of
+                    // collecting an overridable event.
+                    self.exit_err_on_event_code("COPY_NEEDED_FOR_HOLD")?;
+                }
                 self.add_event(EgEvent::new("COPY_NEEDED_FOR_HOLD"));
             }
         }
@@ -182,3 +189,129 @@ impl Circulator<'_> {
         Ok(self.circ_lib)
     }
 }
+
+/// Why a renewal was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenewFailure {
+    /// The circulation has no renewals remaining.
+    MaxRenewals,
+    /// The item is not checked out to the patron.
+    NotCheckedOut,
+    /// The item is needed to fill a hold.
+    HoldPresent,
+    /// Any other failure event, by textcode.
+    Event(String),
+}
+
+impl RenewFailure {
+    /// Map the textcode of a failed renewal event to a failure reason.
+    ///
+    /// ```
+    /// use evergreen::common::renew::RenewFailure;
+    ///
+    /// assert_eq!(
+    ///     RenewFailure::from_textcode("MAX_RENEWALS_REACHED"),
+    ///     RenewFailure::MaxRenewals
+    /// );
+    /// assert_eq!(
+    ///     RenewFailure::from_textcode("PATRON_EXCEEDS_FINES"),
+    ///     RenewFailure::Event("PATRON_EXCEEDS_FINES".to_string())
+    /// );
+    /// ```
+    pub fn from_textcode(textcode: &str) -> RenewFailure {
+        match textcode {
+            "MAX_RENEWALS_REACHED" => RenewFailure::MaxRenewals,
+            "ACTION_CIRCULATION_NOT_FOUND" => RenewFailure::NotCheckedOut,
+            "COPY_NEEDED_FOR_HOLD" => RenewFailure::HoldPresent,
+            _ => RenewFailure::Event(textcode.to_string()),
+        }
+    }
+
+    /// Textcode of the event which caused the failure.
+    pub fn textcode(&self) -> &str {
+        match self {
+            RenewFailure::MaxRenewals => "MAX_RENEWALS_REACHED",
+            RenewFailure::NotCheckedOut => "ACTION_CIRCULATION_NOT_FOUND",
+            RenewFailure::HoldPresent => "COPY_NEEDED_FOR_HOLD",
+            RenewFailure::Event(code) => code,
+        }
+    }
+}
+
+/// A successful renewal.
+#[derive(Debug, Clone)]
+pub struct Renewal {
+    /// ID of the new circulation.
+    pub circ_id: i64,
+    /// ISO due date of the new circulation.
+    pub due_date: String,
+    pub renewal_remaining: i64,
+}
+
+/// Renew the open circulation for a copy checked out to a patron,
+/// both identified by barcode.
+///
+/// Runs as a self-check renewal, so hold blocks are not overridable
+/// and no events are overridden.  The renewal runs in its own
+/// transaction, which is committed on success.
+///
+/// The new circulation is recorded at `circ_lib`, falling back to the
+/// requestor's workstation org when None.
+///
+/// Returns Ok(Err(RenewFailure)) when the renewal is refused and
+/// Err(EgError) for unexpected errors.
+pub fn renew_by_copy_barcode(
+    editor: &mut Editor,
+    patron_barcode: &str,
+    copy_barcode: &str,
+    circ_lib: Option<i64>,
+) -> EgResult<Result<Renewal, RenewFailure>> {
+    let mut options: HashMap<String, EgValue> = HashMap::new();
+
+    options.insert("copy_barcode".to_string(), copy_barcode.into());
+    options.insert("patron_barcode".to_string(), patron_barcode.into());
+    options.insert("selfcheck_renewal".to_string(), EgValue::from(true));
+
+    if let Some(id) = circ_lib {
+        options.insert("circ_lib".to_string(), id.into());
+    }
+
+    let mut circulator = Circulator::new(editor, options)?;
+    circulator.begin()?;
+
+    if let Err(err) = circulator.renew() {
+        circulator.rollback()?;
+
+        let evt = err.event_or_default();
+        if evt.textcode() == "INTERNAL_SERVER_ERROR" {
+            return Err(err);
+        }
+
+        return Ok(Err(RenewFailure::from_textcode(evt.textcode())));
+    }
+
+    let evt = circulator
+        .events()
+        .iter()
+        .find(|e| !e.is_success())
+        .or_else(|| circulator.events().first())
+        .cloned()
+        .ok_or("Renewal failed to return an event")?;
+
+    if !evt.is_success() {
+        // Non-fatal events, e.g. COPY_NEEDED_FOR_HOLD, are only
+        // allowed through on override.
+        circulator.rollback()?;
+        return Ok(Err(RenewFailure::from_textcode(evt.textcode())));
+    }
+
+    circulator.commit()?;
+
+    let circ = &evt.payload()["circ"];
+
+    Ok(Ok(Renewal {
+        circ_id: circ.id()?,
+        due_date: circ["due_date"].str()?.to_string(),
+        renewal_remaining: circ["renewal_remaining"].int()?,
+    }))
+}
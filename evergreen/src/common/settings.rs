@@ -1,16 +1,89 @@
 //! General purpose org / workstation / user setting fetcher and cache.
 //! Primarily uses the 'actor.get_cascade_setting()' DB function.
 use crate as eg;
+use eg::osrf::cache::Cache;
 use eg::{Editor, EgResult, EgValue};
 use regex::Regex;
 use std::collections::HashMap;
 use std::fmt;
+use std::time::{Duration, Instant};
 
 // Setting names consist only of letters, numbers, unders, and dots.
 // This is crucial since the names are encoded as an SQL TEXT[] parameter
 // during lookuping.
 const SETTING_NAME_REGEX: &str = "[^a-zA-Z0-9_\\.]";
 
+/// Global cache key whose value changes any time settings are modified.
+const SETTINGS_VERSION_CACHE_KEY: &str = "eg.settings.version";
+
+/// Let Settings consumers know that setting values have changed.
+///
+/// Settings instances with a max age compare their version to the
+/// cached version once their max age has passed and clear their local
+/// cache if the version has changed.
+///
+/// Each call stores a new unique version, so multiple invalidations
+/// within the same second are all detected.
+pub fn invalidate_cache() -> EgResult<()> {
+    let version = format!(
+        "{}.{}",
+        eg::date::epoch_secs_str(),
+        eg::util::random_number(12)
+    );
+
+    Cache::set_global(SETTINGS_VERSION_CACHE_KEY, EgValue::from(version))
+}
+
+/// Create, update, or, when the value is null, delete the value of a
+/// setting at an org unit.
+///
+/// Callers are responsible for permission checks and should call
+/// invalidate_cache() once their transaction is committed.
+pub fn apply_org_setting(
+    editor: &mut Editor,
+    org_id: i64,
+    name: &str,
+    value: &EgValue,
+) -> EgResult<()> {
+    let search = eg::hash! {org_unit: org_id, name: name};
+    let existing = editor.search("aous", search)?.pop();
+
+    if value.is_null() {
+        if let Some(setting) = existing {
+            editor.delete(setting)?;
+        }
+        return Ok(());
+    }
+
+    if let Some(mut setting) = existing {
+        setting["value"] = EgValue::from(value.dump());
+        return editor.update(setting);
+    }
+
+    let setting = eg::hash! {
+        org_unit: org_id,
+        name: name,
+        value: value.dump(),
+    };
+
+    editor.create(EgValue::create("aous", setting)?)?;
+
+    Ok(())
+}
+
+/// Returns the current settings version from the global cache.
+///
+/// Returns None if no version has been set or the cache is unavailable.
+fn cached_version() -> Option<EgValue> {
+    match Cache::get_global(SETTINGS_VERSION_CACHE_KEY) {
+        Ok(v) => v,
+        Err(e) => {
+            log::debug!("Cannot read settings version: {e}");
+            None
+        }
+    }
+}
+
 /// SettingType may come in handy later when we need to know
 /// more about the types.
 #[derive(Debug, Clone, PartialEq)]
@@ -120,6 +193,17 @@ pub struct Settings {
     default_context: SettingContext,
     name_regex: Option<Regex>,
     cache: HashMap<SettingContext, HashMap<String, SettingEntry>>,
+
+    /// How long cached values may be used before checking for
+    /// invalidations.  If None, values are cached for the life of
+    /// this Settings instance.
+    max_age: Option<Duration>,
+
+    /// Last time we checked the cached settings version.
+    version_checked: Instant,
+
+    /// Settings version as of our last check.
+    version: Option<EgValue>,
 }
 
 impl Settings {
@@ -133,6 +217,9 @@ impl Settings {
             editor: editor.clone(),
             cache: HashMap::new(),
             default_context: SettingContext::new(),
+            max_age: None,
+            version_checked: Instant::now(),
+            version: None,
         };
 
         sc.apply_editor(&editor);
@@ -183,6 +270,39 @@ impl Settings {
         self.cache.clear();
     }
 
+    /// Check for invalidated settings at most once per max_age,
+    /// clearing the local cache when settings have changed.
+    ///
+    /// Useful for long-lived Settings instances.
+    pub fn set_max_age(&mut self, max_age: Duration) {
+        self.max_age = Some(max_age);
+        self.version = cached_version();
+        self.version_checked = Instant::now();
+    }
+
+    /// Clear our cache if it's past its max age and the settings
+    /// version has changed since our last check.
+    fn check_version(&mut self) {
+        let max_age = match self.max_age {
+            Some(m) => m,
+            None => return,
+        };
+
+        if self.version_checked.elapsed() < max_age {
+            return;
+        }
+
+        self.version_checked = Instant::now();
+
+        let version = cached_version();
+
+        if version != self.version {
+            log::debug!("Settings have changed; clearing local cache");
+            self.version = version;
+            self.reset();
+        }
+    }
+
     /// Returns a setting value using the default context.
     ///
     /// Returns JSON null if no setting exists.
@@ -193,6 +313,9 @@ impl Settings {
     }
 
     /// Shortcut for get_context_value with an org unit ID set.
+    ///
+    /// If the setting has no value at the requested org unit, the value
+    /// from the nearest ancestor org unit with a value is returned.
     pub fn get_value_at_org(&mut self, name: &str, org_id: i64) -> EgResult<&EgValue> {
         let mut ctx = SettingContext::new();
         ctx.set_org_id(org_id);
@@ -205,6 +328,8 @@ impl Settings {
         context: &SettingContext,
         name: &str,
     ) -> EgResult<&EgValue> {
+        self.check_version();
+
         if self.cache.get(context).is_none() {
             self.cache.insert(context.clone(), HashMap::new());
         }
@@ -244,6 +369,8 @@ impl Settings {
 
     /// Fetch (pre-cache) a batch of values for a given org unit.
     pub fn fetch_values_for_org(&mut self, org_id: i64, names: &[&str]) -> EgResult<()> {
+        self.check_version();
+
         let mut ctx = SettingContext::new();
         ctx.set_org_id(org_id);
 
@@ -1,4 +1,5 @@
 use crate as eg;
+use eg::common::bib;
 use eg::common::holds;
 use eg::common::settings::Settings;
 use eg::common::trigger;
@@ -76,6 +77,10 @@ pub struct HoldTargetContext {
 
     /// Maps proximities to the weighted list of copy IDs.
     weighted_prox_map: HashMap<i64, Vec<i64>>,
+
+    /// IDs of copies which passed the status and closed date checks
+    /// and were considered for targeting.
+    considered_copies: Vec<i64>,
 }
 
 impl HoldTargetContext {
@@ -98,6 +103,7 @@ impl HoldTargetContext {
             valid_previous_copy: None,
             previous_copy_id: 0,
             found_copy: false,
+            considered_copies: Vec::new(),
         }
     }
 
@@ -110,6 +116,18 @@ impl HoldTargetContext {
     pub fn found_copy(&self) -> bool {
         self.found_copy
     }
+    /// ID of the targeted copy, if one was found.
+    pub fn target(&self) -> Option<i64> {
+        if self.target > 0 {
+            Some(self.target)
+        } else {
+            None
+        }
+    }
+    /// IDs of the copies considered for targeting.
+    pub fn considered_copies(&self) -> &[i64] {
+        &self.considered_copies
+    }
     /// Returns a summary of this context as a JSON object.
     pub fn to_json(&self) -> EgValue {
         eg::hash! {
@@ -119,10 +137,30 @@ impl HoldTargetContext {
             "old_target": self.previous_copy_id,
             "found_copy": self.found_copy,
             "eligible_copies": self.eligible_copy_count,
+            "considered_copies": self.considered_copies.clone(),
         }
     }
 }
 
+/// Target a single hold now, outside of a batch targeter run, e.g.
+/// when staff ask for a hold to be retargeted.
+///
+/// The hold's potential copy maps are rebuilt and the same frozen,
+/// canceled, expired, and org unit setting checks apply as for batch
+/// targeting.  Targeting runs in its own transaction, which is
+/// committed on success.  The returned context has the targeted copy
+/// (if any) and the copies considered.
+pub fn target_one_hold(
+    editor: &mut Editor,
+    hold_id: i64,
+    find_copy: Option<i64>,
+) -> EgResult<HoldTargetContext> {
+    let mut targeter = HoldTargeter::new(editor);
+
+    targeter.init()?;
+    targeter.target_hold(hold_id, find_copy)
+}
+
 /// Targets a batch of holds.
 pub struct HoldTargeter<'a> {
     editor: &'a mut Editor,
@@ -630,6 +668,13 @@ impl<'a> HoldTargeter<'a> {
         } else {
             // Metarecord hold
 
+            let bib_ids = bib::bibs_for_metarecord(self.editor(), hold_target)?;
+
+            if bib_ids.is_empty() {
+                log::info!("{self} metarecord {hold_target} has no bib records");
+                return Ok(());
+            }
+
             query["from"]["acp"]["acn"] = eg::hash! {
                 "field": "id",
                 "fkey": "call_number",
@@ -637,13 +682,7 @@ impl<'a> HoldTargeter<'a> {
                     "bre": {
                         "field": "id",
                         "fkey": "record",
-                        "join": {
-                            "mmrsm": {
-                                "field": "source",
-                                "fkey": "id",
-                                "filter": {"metarecord": hold_target},
-                            }
-                        }
+                        "filter": {"id": bib_ids},
                     }
                 }
             };
@@ -1611,6 +1650,8 @@ impl<'a> HoldTargeter<'a> {
         self.filter_copies_by_status_and_targeted(ctx);
         self.filter_closed_date_copies(ctx)?;
 
+        ctx.considered_copies = ctx.copies.iter().map(|c| c.id).collect();
+
         if self.inspect_previous_target(ctx)? {
             // Exits early if we are retargeting the previous copy.
             return Ok(context);
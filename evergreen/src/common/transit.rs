@@ -6,6 +6,64 @@ use eg::EgEvent;
 use eg::EgResult;
 use eg::EgValue;
 
+/// True if the transit has been neither received nor canceled.
+pub fn transit_is_open(transit: &EgValue) -> bool {
+    transit["dest_recv_time"].is_null() && transit["cancel_time"].is_null()
+}
+
+/// Status a copy in transit adopts when its transit is aborted, based
+/// on the copy status stored on the transit.
+///
+/// ```
+/// use evergreen::common::transit;
+/// use evergreen::constants as C;
+///
+/// assert_eq!(
+///     transit::aborted_copy_status(C::COPY_STATUS_AVAILABLE),
+///     C::COPY_STATUS_CANCELED_TRANSIT
+/// );
+/// assert_eq!(
+///     transit::aborted_copy_status(C::COPY_STATUS_DAMAGED),
+///     C::COPY_STATUS_DAMAGED
+/// );
+/// ```
+pub fn aborted_copy_status(transit_copy_status: i64) -> i64 {
+    match transit_copy_status {
+        // These transit copy statuses are discarded.
+        C::COPY_STATUS_AVAILABLE
+        | C::COPY_STATUS_CHECKED_OUT
+        | C::COPY_STATUS_IN_PROCESS
+        | C::COPY_STATUS_ON_HOLDS_SHELF
+        | C::COPY_STATUS_IN_TRANSIT
+        | C::COPY_STATUS_CATALOGING
+        | C::COPY_STATUS_ON_RESV_SHELF
+        | C::COPY_STATUS_RESHELVING => C::COPY_STATUS_CANCELED_TRANSIT,
+        // Otherwise, adopt the copy status stored on the transit.
+        _ => transit_copy_status,
+    }
+}
+
+/// Abort an open transit, e.g. when staff correct a mis-scan.
+///
+/// Returns an error if the transit has already been received or
+/// canceled.  Otherwise the transit is canceled as with
+/// cancel_transit(), which restores the copy status and, for hold
+/// transits, resets the hold so it can be targeted again.
+///
+/// Caller is responsible for beginning and committing the `Editor`
+/// transaction.
+pub fn abort_transit(editor: &mut Editor, transit_id: i64) -> EgResult<()> {
+    let transit = editor
+        .retrieve("atc", transit_id)?
+        .ok_or_else(|| editor.die_event())?;
+
+    if !transit_is_open(&transit) {
+        return Err(format!("Transit {transit_id} is already closed").into());
+    }
+
+    cancel_transit(editor, transit_id, false)
+}
+
 /// Cancel a transit
 ///
 /// Caller is responsible for beginning and committing the `Editor`
@@ -65,21 +123,7 @@ pub fn cancel_transit(editor: &mut Editor, transit_id: i64, skip_hold_reset: boo
     // The status adopted by the copy in transit depends on
     // the intended destination status of the copy.
     if copy_status == C::COPY_STATUS_IN_TRANSIT {
-        if tc_status == C::COPY_STATUS_AVAILABLE
-            || tc_status == C::COPY_STATUS_CHECKED_OUT
-            || tc_status == C::COPY_STATUS_IN_PROCESS
-            || tc_status == C::COPY_STATUS_ON_HOLDS_SHELF
-            || tc_status == C::COPY_STATUS_IN_TRANSIT
-            || tc_status == C::COPY_STATUS_CATALOGING
-            || tc_status == C::COPY_STATUS_ON_RESV_SHELF
-            || tc_status == C::COPY_STATUS_RESHELVING
-        {
-            // These transit copy statuses are discarded.
-            copy["status"] = EgValue::from(C::COPY_STATUS_CANCELED_TRANSIT);
-        } else {
-            // Otherwise, adopt the copy status stored on the transit.
-            copy["status"] = EgValue::from(tc_status);
-        }
+        copy["status"] = EgValue::from(aborted_copy_status(tc_status));
 
         copy["editor"] = EgValue::from(editor.requestor_id()?);
         copy["edit_date"] = EgValue::from("now");
@@ -116,6 +116,96 @@ pub fn create_event_for_object_and_def(
     Ok(Some(editor.create(event)?))
 }
 
+/// Result of firing a single event via fire_event().
+#[derive(Debug, Clone)]
+pub struct FiredEvent {
+    pub event_id: i64,
+    pub state: EventState,
+    /// Output created by the reactor, if any.
+    pub template_output: Option<String>,
+    /// Error output, if processing failed.
+    pub error_output: Option<String>,
+}
+
+/// Create an event for an event definition and target, and when
+/// `run_now` is set, process it (collect, validate, react) before
+/// returning.
+///
+/// Without `run_now` the event is only queued for the A/T runner, the
+/// same as events created for hooks.
+///
+/// Event processing commits its own state changes, so the new event
+/// is created and committed in a transaction of its own.  The editor
+/// must not have a transaction in progress.
+///
+/// Returns None if the event def does not apply to the target, e.g.
+/// the target's user has not opted in.
+pub fn fire_event(
+    editor: &mut Editor,
+    event_def_id: i64,
+    target_id: i64,
+    run_now: bool,
+) -> EgResult<Option<FiredEvent>> {
+    let flesh = eg::hash! {
+        "flesh": 1,
+        "flesh_fields": {"atevdef": ["hook"]}
+    };
+
+    let event_def = editor
+        .retrieve_with_ops("atevdef", event_def_id, flesh)?
+        .ok_or_else(|| editor.die_event())?;
+
+    let core_type = event_def["hook"]["core_type"].str()?.to_string();
+
+    let target = editor
+        .retrieve(&core_type, target_id)?
+        .ok_or_else(|| editor.die_event())?;
+
+    editor.xact_begin()?;
+
+    let event =
+        match create_event_for_object_and_def(editor, &event_def, &target, None, None, false) {
+            Ok(Some(e)) => e,
+            Ok(None) => {
+                editor.rollback()?;
+                return Ok(None);
+            }
+            Err(e) => {
+                editor.rollback()?;
+                return Err(e);
+            }
+        };
+
+    editor.xact_commit()?;
+
+    let event_id = event.id()?;
+
+    if run_now {
+        log::info!("Processing event {event_id} for def {event_def_id} and target {target_id}");
+        Processor::process_event_once(editor, event_id)?;
+    }
+
+    let flesh = eg::hash! {
+        "flesh": 1,
+        "flesh_fields": {"atev": ["template_output", "error_output"]}
+    };
+
+    let event = editor
+        .retrieve_with_ops("atev", event_id, flesh)?
+        .ok_or_else(|| editor.die_event())?;
+
+    Ok(Some(FiredEvent {
+        event_id,
+        state: event["state"].str()?.try_into()?,
+        template_output: event["template_output"]["data"]
+            .as_str()
+            .map(|s| s.to_string()),
+        error_output: event["error_output"]["data"]
+            .as_str()
+            .map(|s| s.to_string()),
+    }))
+}
+
 // Non-doc test required since this is a private function.
 #[test]
 fn test_calc_runtime() {
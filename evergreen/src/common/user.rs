@@ -1,6 +1,6 @@
 //! Shared, user-focused utility functions
 use crate as eg;
-use eg::editor::Editor;
+use eg::editor::{Editor, EditorRead};
 use eg::result::EgResult;
 use eg::EgValue;
 use md5;
@@ -95,8 +95,8 @@ pub fn has_work_perm_at(e: &mut Editor, user_id: i64, perm: &str) -> EgResult<Ve
 }
 
 /// Returns counts of items out, overdue, etc. for a user.
-pub fn open_checkout_counts(e: &mut Editor, user_id: i64) -> EgResult<EgValue> {
-    match e.retrieve("ocirccount", user_id)? {
+pub fn open_checkout_counts(e: &mut impl EditorRead, user_id: i64) -> EgResult<EgValue> {
+    match e.retrieve("ocirccount", EgValue::from(user_id))? {
         Some(mut c) => {
             c["total_out"] = EgValue::from(c["out"].int()? + c["overdue"].int()?);
             c.unbless();
@@ -117,7 +117,7 @@ pub fn open_checkout_counts(e: &mut Editor, user_id: i64) -> EgResult<EgValue> {
 }
 
 /// Returns a summary of fines owed by a user
-pub fn fines_summary(e: &mut Editor, user_id: i64) -> EgResult<EgValue> {
+pub fn fines_summary(e: &mut impl EditorRead, user_id: i64) -> EgResult<EgValue> {
     let mut fines_list = e.search("mous", eg::hash! {usr: user_id})?;
 
     if let Some(mut fines) = fines_list.pop() {
@@ -135,7 +135,7 @@ pub fn fines_summary(e: &mut Editor, user_id: i64) -> EgResult<EgValue> {
 }
 
 /// Returns a total/ready hold counts for a user.
-pub fn active_hold_counts(e: &mut Editor, user_id: i64) -> EgResult<EgValue> {
+pub fn active_hold_counts(e: &mut impl EditorRead, user_id: i64) -> EgResult<EgValue> {
     let query = eg::hash! {
         select: {ahr: ["pickup_lib", "current_shelf_lib", "behind_desk"]},
         from: "ahr",
@@ -7,9 +7,91 @@ use eg::result::{EgError, EgResult};
 use eg::Client;
 use eg::ClientSession;
 use eg::EgValue;
+use std::collections::HashMap;
 
 const DEFAULT_TIMEOUT: i32 = 60;
 
+/// Error text which indicates the underlying connection was dropped
+/// out from under us, as opposed to a failure of the request itself.
+const CONNECTION_RESET_ERRORS: &[&str] = &[
+    "connection reset",
+    "broken pipe",
+    "connection closed",
+    "connection aborted",
+];
+
+/// True if the error was caused by a reset connection.
+///
+/// ```
+/// use evergreen::editor;
+/// use evergreen::result::EgError;
+///
+/// let err = EgError::from("Redis blpop error: Connection reset by peer (os error 104)");
+/// assert!(editor::is_connection_reset(&err));
+///
+/// let err = EgError::from("Unexpected response to method open-ils.cstore.json_query");
+/// assert!(!editor::is_connection_reset(&err));
+/// ```
+pub fn is_connection_reset(err: &EgError) -> bool {
+    let EgError::Debug(msg) = err else {
+        return false;
+    };
+
+    let msg = msg.to_lowercase();
+    CONNECTION_RESET_ERRORS.iter().any(|e| msg.contains(e))
+}
+
+/// Run a read operation, retrying it once after calling `reconnect`
+/// if the first attempt failed because its connection was reset.
+///
+/// Only use this for idempotent reads.
+pub fn retry_read_once<C, T>(
+    ctx: &mut C,
+    mut read: impl FnMut(&mut C) -> EgResult<T>,
+    mut reconnect: impl FnMut(&mut C) -> EgResult<()>,
+) -> EgResult<T> {
+    match read(ctx) {
+        Err(e) if is_connection_reset(&e) => {
+            log::warn!("Retrying read after connection reset: {e}");
+            reconnect(ctx)?;
+            read(ctx)
+        }
+        result => result,
+    }
+}
+
+/// Arrange rows in the order of the requested primary key values.
+///
+/// IDs with no matching row are skipped.  Duplicate IDs only
+/// produce one row.
+///
+/// ```
+/// use evergreen as eg;
+/// use eg::editor;
+///
+/// let rows = vec![
+///     eg::hash! {"id": 3, "name": "c"},
+///     eg::hash! {"id": 1, "name": "a"},
+/// ];
+///
+/// let rows = editor::order_by_ids(rows, "id", &[1, 2, 3, 1]);
+///
+/// assert_eq!(rows.len(), 2);
+/// assert_eq!(rows[0]["name"].as_str(), Some("a"));
+/// assert_eq!(rows[1]["name"].as_str(), Some("c"));
+/// ```
+pub fn order_by_ids(rows: Vec<EgValue>, pkey: &str, ids: &[i64]) -> Vec<EgValue> {
+    let mut by_id: HashMap<i64, EgValue> = HashMap::new();
+
+    for row in rows {
+        if let Some(id) = row[pkey].as_int() {
+            by_id.insert(id, row);
+        }
+    }
+
+    ids.iter().filter_map(|id| by_id.remove(id)).collect()
+}
+
 /// Specifies Which service are we communicating with.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Personality {
@@ -46,6 +128,22 @@ pub struct QueryOps {
 }
 */
 
+/// Read-only data access shared by the Editor and stand-ins like
+/// testing::MockEditor, so lookup logic can be written once and
+/// exercised without a live connection.
+pub trait EditorRead {
+    fn retrieve(&mut self, idlclass: &str, id: EgValue) -> EgResult<Option<EgValue>>;
+    fn search(&mut self, idlclass: &str, query: EgValue) -> EgResult<Vec<EgValue>>;
+    fn json_query(&mut self, query: EgValue) -> EgResult<Vec<EgValue>>;
+}
+
+/// Wrapper around CStore-style API calls.
+///
+/// Accessors for the editor's own state (authtoken, requestor,
+/// personality, etc.) only require a shared borrow.  Every database
+/// call, reads included, requires a mutable borrow, since it sends
+/// requests through our OpenSRF session, may connect or reconnect that
+/// session, and updates the transaction and last-event state.
 pub struct Editor {
     client: Client,
     session: Option<ClientSession>,
@@ -67,6 +165,9 @@ pub struct Editor {
     last_event: Option<EgEvent>,
 
     has_pending_changes: bool,
+
+    /// Retry reads once if they fail because our connection was reset.
+    retry_reads: bool,
 }
 
 impl Clone for Editor {
@@ -75,6 +176,7 @@ impl Clone for Editor {
         e.personality = self.personality().clone();
         e.authtoken = self.authtoken().map(str::to_string);
         e.requestor = self.requestor().map(|r| r.clone());
+        e.retry_reads = self.retry_reads;
         e
     }
 }
@@ -94,6 +196,7 @@ impl Editor {
             requestor: None,
             last_event: None,
             has_pending_changes: false,
+            retry_reads: false,
         }
     }
 
@@ -107,6 +210,19 @@ impl Editor {
         self.timeout = DEFAULT_TIMEOUT;
     }
 
+    /// Retry search, retrieve, and json_query calls once, on a new
+    /// connection, when they fail because the connection was reset.
+    ///
+    /// Reads within a transaction are never retried, since the
+    /// transaction does not survive the reset.
+    pub fn set_retry_reads(&mut self, retry: bool) {
+        self.retry_reads = retry;
+    }
+
+    pub fn retry_reads(&self) -> bool {
+        self.retry_reads
+    }
+
     pub fn client_mut(&mut self) -> &mut Client {
         &mut self.client
     }
@@ -509,6 +625,26 @@ impl Editor {
         req.first_with_timeout(self.timeout)
     }
 
+    /// Send a read-only API request, retrying once if the connection
+    /// was reset and retries are enabled.
+    fn read_request(&mut self, method: &str, params: ApiParams) -> EgResult<Option<EgValue>> {
+        if !self.retry_reads || self.has_xact_id() {
+            return self.request(method, params);
+        }
+
+        let params = params.params().clone();
+
+        retry_read_once(
+            self,
+            |e| e.request(method, params.clone()),
+            |e| {
+                // Our session may be bound to a worker on the dead connection.
+                e.session = None;
+                e.client.reconnect()
+            },
+        )
+    }
+
     /// Returns our mutable session, creating a new one if needed.
     fn session(&mut self) -> &mut ClientSession {
         if self.session.is_none() {
@@ -550,7 +686,7 @@ impl Editor {
             params.add(ops);
         }
 
-        if let Some(jvec) = self.request(&method, params)? {
+        if let Some(jvec) = self.read_request(&method, params)? {
             if let EgValue::Array(vec) = jvec {
                 return Ok(vec);
             }
@@ -582,7 +718,7 @@ impl Editor {
             params.add(ops);
         }
 
-        let resp_op = self.request(&method, params)?;
+        let resp_op = self.read_request(&method, params)?;
 
         if resp_op.is_none() {
             // not-found is not necessarily an error.
@@ -593,6 +729,28 @@ impl Editor {
         Ok(resp_op)
     }
 
+    /// Retrieve multiple objects by primary key using a single search.
+    ///
+    /// Objects are returned in the order of the requested IDs.  IDs
+    /// with no matching object are not represented in the results.
+    pub fn retrieve_multi(&mut self, idlclass: &str, ids: &[i64]) -> EgResult<Vec<EgValue>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let pkey = idl::get_class(idlclass)?
+            .pkey()
+            .ok_or_else(|| format!("IDL class {idlclass} has no primary key"))?
+            .to_string();
+
+        let mut query = eg::hash! {};
+        query[&pkey] = EgValue::from(ids.to_vec());
+
+        let rows = self.search(idlclass, query)?;
+
+        Ok(order_by_ids(rows, &pkey, ids))
+    }
+
     pub fn search(&mut self, idlclass: &str, query: EgValue) -> EgResult<Vec<EgValue>> {
         self.search_with_ops(idlclass, query, EgValue::Null)
     }
@@ -612,7 +770,7 @@ impl Editor {
             params.add(ops);
         }
 
-        if let Some(jvec) = self.request(&method, params)? {
+        if let Some(jvec) = self.read_request(&method, params)? {
             if let EgValue::Array(vec) = jvec {
                 return Ok(vec);
             }
@@ -740,3 +898,17 @@ impl Editor {
         Ok(has_perm)
     }
 }
+
+impl EditorRead for Editor {
+    fn retrieve(&mut self, idlclass: &str, id: EgValue) -> EgResult<Option<EgValue>> {
+        Editor::retrieve(self, idlclass, id)
+    }
+
+    fn search(&mut self, idlclass: &str, query: EgValue) -> EgResult<Vec<EgValue>> {
+        Editor::search(self, idlclass, query)
+    }
+
+    fn json_query(&mut self, query: EgValue) -> EgResult<Vec<EgValue>> {
+        Editor::json_query(self, query)
+    }
+}
@@ -8,6 +8,7 @@ use std::fmt;
 use std::fs;
 use std::sync::Arc;
 use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
 
 /// Parse the IDL once and store it here, making it accessible to all
 /// threads as a read-only value.
@@ -19,6 +20,10 @@ const OILS_NS_PERSIST: &str = "http://open-ils.org/spec/opensrf/IDL/persistence/
 const OILS_NS_REPORTER: &str = "http://open-ils.org/spec/opensrf/IDL/reporter/v1";
 const AUTO_FIELDS: [&str; 3] = ["isnew", "ischanged", "isdeleted"];
 
+/// Bump when the layout of the IDL cache file changes so older
+/// cache files are treated as stale.
+const CACHE_VERSION: u32 = 1;
+
 /// Returns a ref to the global IDL parser instance
 pub fn parser() -> &'static Parser {
     if let Some(idl) = GLOBAL_IDL.get() {
@@ -39,6 +44,66 @@ pub fn get_class(classname: &str) -> EgResult<&Arc<Class>> {
         .ok_or_else(|| format!("No such IDL class: {classname}").into())
 }
 
+/// Coerce a scalar value into the canonical form for an IDL datatype.
+///
+/// Numeric strings are accepted for numeric types, bools follow the
+/// rules of EgValue::boolish(), and timestamps are normalized to ISO
+/// format.  Null values are returned as-is.
+///
+/// ```
+/// use evergreen as eg;
+/// use eg::idl::{self, DataType};
+/// use eg::EgValue;
+///
+/// let v = idl::coerce_value(&DataType::Int, &EgValue::from("42")).unwrap();
+/// assert_eq!(v.as_i64(), Some(42));
+///
+/// let v = idl::coerce_value(&DataType::Bool, &EgValue::from("f")).unwrap();
+/// assert_eq!(v.as_bool(), Some(false));
+///
+/// assert!(idl::coerce_value(&DataType::Float, &EgValue::from("abc")).is_err());
+/// ```
+pub fn coerce_value(datatype: &DataType, value: &EgValue) -> EgResult<EgValue> {
+    if value.is_null() {
+        return Ok(EgValue::Null);
+    }
+
+    if !value.is_string() && !value.is_number() && !value.is_boolean() {
+        return Err(format!("Cannot coerce non-scalar value to {datatype}: {value}").into());
+    }
+
+    let coerced = match datatype {
+        DataType::Id | DataType::Int | DataType::OrgUnit => value
+            .as_int()
+            .map(EgValue::from)
+            .ok_or_else(|| format!("Cannot coerce to {datatype}: {value}"))?,
+
+        DataType::Float | DataType::Money => value
+            .as_float()
+            .map(EgValue::from)
+            .ok_or_else(|| format!("Cannot coerce to {datatype}: {value}"))?,
+
+        DataType::Bool => EgValue::from(value.boolish()),
+
+        DataType::Timestamp => {
+            let s = value
+                .as_str()
+                .ok_or_else(|| format!("Cannot coerce to {datatype}: {value}"))?;
+            EgValue::from(eg::date::to_iso(&eg::date::parse_datetime(s)?))
+        }
+
+        // Links may point to text or numeric keys.
+        DataType::Link => value.clone(),
+
+        DataType::Text => match value.to_string() {
+            Some(s) => EgValue::from(s),
+            None => return Err(format!("Cannot coerce to {datatype}: {value}").into()),
+        },
+    };
+
+    Ok(coerced)
+}
+
 /// Various forms an IDL-classed object can take internally and on
 /// the wire.
 #[derive(Debug, Clone, PartialEq)]
@@ -181,6 +246,31 @@ impl Field {
     pub fn suppress_controller(&self) -> Option<&str> {
         self.suppress_controller.as_deref()
     }
+
+    fn to_cache_json(&self) -> json::JsonValue {
+        let datatype: &str = (&self.datatype).into();
+        json::object! {
+            "name": self.name.as_str(),
+            "label": self.label.as_str(),
+            "datatype": datatype,
+            "i18n": self.i18n,
+            "array_pos": self.array_pos,
+            "is_virtual": self.is_virtual,
+            "suppress_controller": self.suppress_controller.as_deref(),
+        }
+    }
+
+    fn from_cache_json(v: &json::JsonValue) -> Option<Field> {
+        Some(Field {
+            name: v["name"].as_str()?.to_string(),
+            label: v["label"].as_str()?.to_string(),
+            datatype: v["datatype"].as_str()?.into(),
+            i18n: v["i18n"].as_bool()?,
+            array_pos: v["array_pos"].as_usize()?,
+            is_virtual: v["is_virtual"].as_bool()?,
+            suppress_controller: v["suppress_controller"].as_str().map(|s| s.to_string()),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -253,6 +343,27 @@ impl Link {
     pub fn class(&self) -> &str {
         &self.class
     }
+
+    fn to_cache_json(&self) -> json::JsonValue {
+        let reltype: &str = (&self.reltype).into();
+        json::object! {
+            "field": self.field.as_str(),
+            "reltype": reltype,
+            "key": self.key.as_str(),
+            "map": self.map.as_deref(),
+            "class": self.class.as_str(),
+        }
+    }
+
+    fn from_cache_json(v: &json::JsonValue) -> Option<Link> {
+        Some(Link {
+            field: v["field"].as_str()?.to_string(),
+            reltype: v["reltype"].as_str()?.into(),
+            key: v["key"].as_str()?.to_string(),
+            map: v["map"].as_str().map(|s| s.to_string()),
+            class: v["class"].as_str()?.to_string(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -375,6 +486,66 @@ impl Class {
             .filter(|f| f.name().eq(field) && !f.is_virtual())
             .next()
     }
+
+    fn to_cache_json(&self) -> json::JsonValue {
+        let mut fields = json::JsonValue::new_array();
+        for field in self.fields.values() {
+            fields.push(field.to_cache_json()).ok();
+        }
+
+        let mut links = json::JsonValue::new_array();
+        for link in self.links.values() {
+            links.push(link.to_cache_json()).ok();
+        }
+
+        json::object! {
+            "classname": self.classname.as_str(),
+            "label": self.label.as_str(),
+            "field_safe": self.field_safe,
+            "read_only": self.read_only,
+            "pkey": self.pkey.as_deref(),
+            "selector": self.selector.as_deref(),
+            "fieldmapper": self.fieldmapper.as_deref(),
+            "fields": fields,
+            "links": links,
+            "tablename": self.tablename.as_deref(),
+            "source_definition": self.source_definition.as_deref(),
+            "controller": self.controller.as_deref(),
+            "is_virtual": self.is_virtual,
+        }
+    }
+
+    fn from_cache_json(v: &json::JsonValue) -> Option<Class> {
+        let opt = |key: &str| v[key].as_str().map(|s| s.to_string());
+
+        let mut fields = HashMap::new();
+        for f in v["fields"].members() {
+            let field = Field::from_cache_json(f)?;
+            fields.insert(field.name.to_string(), field);
+        }
+
+        let mut links = HashMap::new();
+        for l in v["links"].members() {
+            let link = Link::from_cache_json(l)?;
+            links.insert(link.field.to_string(), link);
+        }
+
+        Some(Class {
+            classname: v["classname"].as_str()?.to_string(),
+            label: v["label"].as_str()?.to_string(),
+            field_safe: v["field_safe"].as_bool()?,
+            read_only: v["read_only"].as_bool()?,
+            pkey: opt("pkey"),
+            selector: opt("selector"),
+            fieldmapper: opt("fieldmapper"),
+            fields,
+            links,
+            tablename: opt("tablename"),
+            source_definition: opt("source_definition"),
+            controller: opt("controller"),
+            is_virtual: v["is_virtual"].as_bool()?,
+        })
+    }
 }
 
 impl fmt::Display for Class {
@@ -390,6 +561,102 @@ impl fmt::Display for Class {
     }
 }
 
+/// Builds the flesh options used by retrieve and search calls,
+/// verifying each class and field against the IDL.
+///
+/// Unknown classes and fields which do not link to another class
+/// are reported as errors by build() instead of silently leaving
+/// the data unfleshed.
+///
+/// ```no_run
+/// use evergreen as eg;
+/// use eg::idl::FleshBuilder;
+///
+/// let flesh = FleshBuilder::new()
+///     .depth(2)
+///     .flesh("ac", &["usr"])
+///     .flesh("au", &["home_ou", "profile"])
+///     .build()
+///     .expect("Valid flesh fields");
+///
+/// assert_eq!(flesh["flesh"].as_usize(), Some(2));
+/// assert_eq!(flesh["flesh_fields"]["au"].len(), 2);
+/// ```
+pub struct FleshBuilder<'a> {
+    parser: &'a Parser,
+    depth: usize,
+    flesh_fields: EgValue,
+    error: Option<String>,
+}
+
+impl FleshBuilder<'static> {
+    /// Builder which validates against the global IDL.
+    pub fn new() -> Self {
+        FleshBuilder::with_parser(parser())
+    }
+}
+
+impl<'a> FleshBuilder<'a> {
+    pub fn with_parser(parser: &'a Parser) -> Self {
+        FleshBuilder {
+            parser,
+            depth: 1,
+            flesh_fields: eg::hash! {},
+            error: None,
+        }
+    }
+
+    /// How many levels deep to flesh.  Defaults to 1.
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Flesh the provided link fields on objects of this class.
+    pub fn flesh(mut self, classname: &str, fields: &[&str]) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+
+        let Some(class) = self.parser.class(classname) else {
+            self.error = Some(format!("No such IDL class: {classname}"));
+            return self;
+        };
+
+        for field in fields {
+            if class.links().get(*field).is_none() {
+                self.error = Some(format!("Class '{classname}' cannot flesh '{field}'"));
+                return self;
+            }
+
+            let list = &mut self.flesh_fields[classname];
+
+            if list.is_null() {
+                *list = eg::array![];
+            }
+
+            if !list.contains(*field) {
+                list.push(*field).expect("Is Array");
+            }
+        }
+
+        self
+    }
+
+    /// Returns the flesh options, e.g.
+    /// {"flesh": 1, "flesh_fields": {"au": ["home_ou"]}}
+    pub fn build(self) -> EgResult<EgValue> {
+        if let Some(e) = self.error {
+            return Err(e.into());
+        }
+
+        Ok(eg::hash! {
+            "flesh": self.depth,
+            "flesh_fields": self.flesh_fields,
+        })
+    }
+}
+
 pub struct Parser {
     /// Store each class in an Arc so it's easier for components
     /// to have an owned ref to the Class, which comes in handy quite
@@ -408,6 +675,29 @@ impl Parser {
         &self.classes
     }
 
+    /// IDL class by classname/hint.
+    pub fn class(&self, classname: &str) -> Option<&Arc<Class>> {
+        self.classes.get(classname)
+    }
+
+    /// Field definition, including its datatype and virtual flag.
+    pub fn field(&self, classname: &str, field: &str) -> Option<&Field> {
+        self.class(classname)?.get_field(field)
+    }
+
+    /// Link definition for a field which links to another class.
+    ///
+    /// Returns None for unknown classes and fields and for fields
+    /// which do not link to another class.
+    pub fn field_link(&self, classname: &str, field: &str) -> Option<&Link> {
+        self.class(classname)?.links().get(field)
+    }
+
+    /// The class a field links to, e.g. "aou" for aou.parent_ou.
+    pub fn linked_class(&self, classname: &str, field: &str) -> Option<&Arc<Class>> {
+        self.class(self.field_link(classname, field)?.class())
+    }
+
     /// Load the IDL from a file.
     ///
     /// Returns an Err if the IDL has already been parsed and loaded, in
@@ -427,8 +717,117 @@ impl Parser {
         Ok(())
     }
 
+    /// Load the IDL from a file, using a previously cached copy of
+    /// the parsed IDL when it's still fresh.
+    ///
+    /// See parse_file_cached().
+    pub fn load_file_cached(filename: &str, cache_file: &str) -> EgResult<()> {
+        let p = Parser::parse_file_cached(filename, cache_file)?;
+
+        if GLOBAL_IDL.set(p).is_err() {
+            return Err(format!("Cannot initialize IDL more than once").into());
+        }
+
+        Ok(())
+    }
+
+    /// Parse the IDL file, reading from and updating the cache file
+    /// along the way.
+    ///
+    /// The cache file holds the parsed classes as JSON along with the
+    /// modification time and size of the IDL file it was built from.
+    /// When the cache is missing, unreadable, or stale, the IDL XML
+    /// is parsed in full and the cache is rewritten.  Failing to write
+    /// the cache is logged but is not an error.
+    pub fn parse_file_cached(filename: &str, cache_file: &str) -> EgResult<Parser> {
+        let source_key = Parser::cache_source_key(filename)?;
+
+        if let Some(parser) = Parser::read_cache(cache_file, &source_key) {
+            log::debug!("Loaded IDL from cache file {cache_file}");
+            return Ok(parser);
+        }
+
+        let xml = match fs::read_to_string(filename) {
+            Ok(x) => x,
+            Err(e) => Err(format!("Cannot parse IDL file '{filename}': {e}"))?,
+        };
+
+        let parser = Parser::parse_string(&xml)?;
+
+        if let Err(e) = parser.write_cache(cache_file, source_key) {
+            log::warn!("Cannot write IDL cache file {cache_file}: {e}");
+        }
+
+        Ok(parser)
+    }
+
+    /// Identifies the version of the IDL file a cache was built from.
+    fn cache_source_key(filename: &str) -> EgResult<json::JsonValue> {
+        let meta = fs::metadata(filename)
+            .map_err(|e| format!("Cannot read IDL file '{filename}': {e}"))?;
+
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos().to_string())
+            .unwrap_or_default();
+
+        Ok(json::object! {
+            "version": CACHE_VERSION,
+            "filename": filename,
+            "mtime": mtime,
+            "size": meta.len(),
+        })
+    }
+
+    /// Returns None if the cache cannot be used.
+    fn read_cache(cache_file: &str, source_key: &json::JsonValue) -> Option<Parser> {
+        let content = fs::read_to_string(cache_file).ok()?;
+        let cache = json::parse(&content).ok()?;
+
+        if cache["source"] != *source_key {
+            log::info!("IDL cache file {cache_file} is stale");
+            return None;
+        }
+
+        let mut parser = Parser {
+            classes: HashMap::new(),
+        };
+
+        for c in cache["classes"].members() {
+            let class = Class::from_cache_json(c)?;
+            parser
+                .classes
+                .insert(class.classname.to_string(), Arc::new(class));
+        }
+
+        Some(parser)
+    }
+
+    /// Write the cache to a temp file then move it into place so
+    /// processes starting up at the same time never read a partial file.
+    fn write_cache(&self, cache_file: &str, source_key: json::JsonValue) -> EgResult<()> {
+        let mut classes = json::JsonValue::new_array();
+        for class in self.classes.values() {
+            classes.push(class.to_cache_json()).ok();
+        }
+
+        let cache = json::object! {
+            "source": source_key,
+            "classes": classes,
+        };
+
+        let tmp_file = format!("{cache_file}.{}", std::process::id());
+
+        fs::write(&tmp_file, cache.dump()).map_err(|e| format!("{tmp_file}: {e}"))?;
+        fs::rename(&tmp_file, cache_file).map_err(|e| format!("{cache_file}: {e}"))?;
+
+        Ok(())
+    }
+
     /// Parse the IDL as a string
-    fn parse_string(xml: &str) -> EgResult<Parser> {
+    pub(crate) fn parse_string(xml: &str) -> EgResult<Parser> {
         let doc = match roxmltree::Document::parse(xml) {
             Ok(d) => d,
             Err(e) => Err(format!("Error parsing XML string for IDL: {e}"))?,
@@ -10,6 +10,24 @@ use std::env;
 const DEFAULT_OSRF_CONFIG: &str = "/openils/conf/opensrf_core.xml";
 const DEFAULT_IDL_PATH: &str = "/openils/conf/fm_IDL.xml";
 
+/// Options for connecting to OpenSRF and loading host settings.
+///
+/// The worker max_requests and keepalive overrides take precedence
+/// over the values in the service's unix_config host settings.  The
+/// order of precedence for each is:
+///
+/// 1. A value set directly on the options.
+/// 2. The OSRF_WORKER_MAX_REQUESTS / OSRF_WORKER_KEEPALIVE environment
+///    variables, read by InitOptions::new().
+/// 3. The unix_config/max_requests / unix_config/keepalive host settings.
+/// 4. The built-in defaults of 5000 requests and 5 seconds.
+///
+/// Invalid environment values are ignored and logged once logging
+/// is initialized.
+///
+/// Build options from InitOptions::new() with struct update syntax,
+/// e.g. `InitOptions { skip_logging: true, ..InitOptions::new() }`,
+/// so callers keep compiling as new options are added.
 pub struct InitOptions {
     /// Skip logging initialization.
     /// Useful if changes to the logging config first.
@@ -20,6 +38,13 @@ pub struct InitOptions {
 
     /// Application name to use with syslog.
     pub appname: Option<String>,
+
+    /// Max requests a worker handles before exiting.
+    pub max_requests: Option<usize>,
+
+    /// Seconds a worker waits for the next request in a stateful
+    /// conversation before disconnecting.
+    pub keepalive: Option<usize>,
 }
 
 impl InitOptions {
@@ -28,6 +53,30 @@ impl InitOptions {
             skip_logging: false,
             skip_host_settings: false,
             appname: None,
+            max_requests: env_usize("OSRF_WORKER_MAX_REQUESTS"),
+            keepalive: env_usize("OSRF_WORKER_KEEPALIVE"),
+        }
+    }
+}
+
+/// Environment variables read by InitOptions::new().
+const WORKER_ENV_VARS: &[&str] = &["OSRF_WORKER_MAX_REQUESTS", "OSRF_WORKER_KEEPALIVE"];
+
+/// Numeric value of an environment variable.
+///
+/// Unset and non-numeric values are ignored.
+fn env_usize(name: &str) -> Option<usize> {
+    env::var(name).ok()?.parse::<usize>().ok()
+}
+
+/// Log any worker environment variables InitOptions::new() ignored
+/// because they are not numeric.
+fn warn_invalid_env() {
+    for name in WORKER_ENV_VARS {
+        if let Ok(value) = env::var(name) {
+            if env_usize(name).is_none() {
+                log::warn!("Ignoring invalid value for {name}: {value}");
+            }
         }
     }
 }
@@ -116,6 +165,8 @@ pub fn osrf_init(options: &InitOptions) -> EgResult<Client> {
             .or_else(|e| Err(format!("Error initializing logger: {e}")))?;
     }
 
+    warn_invalid_env();
+
     // Save the config as the one-true-global-osrf-config
     config.store()?;
 
@@ -141,18 +192,28 @@ pub fn with_options(options: &InitOptions) -> EgResult<Client> {
 }
 
 /// Locate and parse the IDL file.
+///
+/// If the EG_IDL_CACHE_FILE environment variable is set, the parsed
+/// IDL is cached in (and loaded from) that file.
 pub fn load_idl() -> EgResult<()> {
+    let cache_file = env::var("EG_IDL_CACHE_FILE").ok();
+
+    let load = |fname: &str| match cache_file.as_deref() {
+        Some(cache) => idl::Parser::load_file_cached(fname, cache),
+        None => idl::Parser::load_file(fname),
+    };
+
     if let Ok(v) = env::var("EG_IDL_FILE") {
-        return idl::Parser::load_file(&v);
+        return load(&v);
     }
 
     if HostSettings::is_loaded() {
         if let Some(fname) = HostSettings::get("/IDL")?.as_str() {
-            return idl::Parser::load_file(fname);
+            return load(fname);
         }
     }
 
-    idl::Parser::load_file(DEFAULT_IDL_PATH)
+    load(DEFAULT_IDL_PATH)
 }
 
 /// Create a new connection using pre-compiled context components.  Useful
@@ -25,6 +25,8 @@ pub mod norm;
 pub mod osrf;
 pub mod result;
 pub mod samples;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod util;
 pub mod value;
 
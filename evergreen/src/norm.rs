@@ -1,9 +1,44 @@
 use regex::Regex;
+use unicode_normalization::UnicodeNormalization;
 
 const REGEX_CONTROL_CODES: &str = r#"[\p{Cc}\p{Cf}\p{Co}\p{Lm}\p{Mc}\p{Me}\p{Mn}]"#;
 const REGEX_PUNCTUATION: &str =
     r#"[\p{Pc}\p{Pd}\p{Pe}\p{Pf}\p{Pi}\p{Po}\p{Ps}\p{Sk}\p{Sm}\p{So}\p{Zl}\p{Zp}\p{Zs}]"#;
 
+/// Normalize a barcode for lookup.
+///
+/// Applies Unicode NFC normalization, removes control and format
+/// characters (e.g. zero-width spaces), and trims surrounding
+/// whitespace, so visually identical barcodes compare equal.
+///
+/// ```
+/// use evergreen::norm::normalize_barcode;
+///
+/// assert_eq!(normalize_barcode(" 1234\u{200B}5 "), "12345");
+/// assert_eq!(normalize_barcode("Cafe\u{0301}"), "Caf\u{00E9}");
+/// ```
+pub fn normalize_barcode(barcode: &str) -> String {
+    barcode
+        .nfc()
+        .filter(|c| !c.is_control() && !is_format_char(*c))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// True for invisible Unicode formatting characters (category Cf)
+/// commonly found in pasted or scanned text.
+fn is_format_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{FEFF}'
+    )
+}
+
 /// Container for precompiled regexes so we're not forced to compile
 /// them repetitively, which is very innefficient.
 pub struct Normalizer {
@@ -0,0 +1,145 @@
+//! Per-method circuit breaker for API calls.
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Circuit breaker settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakerConfig {
+    /// Number of consecutive failures or timeouts which trip the breaker.
+    pub failure_threshold: usize,
+
+    /// How long a tripped breaker rejects calls before allowing
+    /// a trial call through.
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BreakerState {
+    /// Calls are passed through as normal.
+    Closed,
+    /// Calls are rejected until the cooldown expires.
+    Open,
+    /// The cooldown has expired.  The next call determines whether
+    /// the breaker closes or trips again.
+    HalfOpen,
+}
+
+impl fmt::Display for BreakerState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half-open",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    /// Consecutive failures since the last success.
+    failures: usize,
+
+    /// Set when the breaker trips.
+    opened: Option<Instant>,
+}
+
+/// Tracks consecutive failures per service+method and short-circuits
+/// calls to methods which keep failing.
+///
+/// ```
+/// use evergreen::osrf::breaker::*;
+/// use std::time::Duration;
+///
+/// let mut breaker = CircuitBreaker::new(BreakerConfig {
+///     failure_threshold: 2,
+///     cooldown: Duration::from_secs(60),
+/// });
+///
+/// breaker.record_failure("open-ils.actor", "opensrf.system.echo");
+/// assert!(breaker.allow("open-ils.actor", "opensrf.system.echo"));
+///
+/// breaker.record_failure("open-ils.actor", "opensrf.system.echo");
+/// assert!(!breaker.allow("open-ils.actor", "opensrf.system.echo"));
+/// assert_eq!(
+///     breaker.state("open-ils.actor", "opensrf.system.echo"),
+///     BreakerState::Open
+/// );
+///
+/// // Other methods are unaffected.
+/// assert!(breaker.allow("open-ils.actor", "opensrf.system.time"));
+/// ```
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    circuits: HashMap<String, Circuit>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            circuits: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &BreakerConfig {
+        &self.config
+    }
+
+    fn key(service: &str, method: &str) -> String {
+        format!("{service}/{method}")
+    }
+
+    /// Current state of the breaker for the service+method.
+    pub fn state(&self, service: &str, method: &str) -> BreakerState {
+        let Some(circuit) = self.circuits.get(&Self::key(service, method)) else {
+            return BreakerState::Closed;
+        };
+
+        match circuit.opened {
+            Some(t) if t.elapsed() < self.config.cooldown => BreakerState::Open,
+            Some(_) => BreakerState::HalfOpen,
+            None => BreakerState::Closed,
+        }
+    }
+
+    /// Returns false if calls to the service+method should be
+    /// rejected without being sent.
+    pub fn allow(&self, service: &str, method: &str) -> bool {
+        self.state(service, method) != BreakerState::Open
+    }
+
+    /// Record a failed or timed out call.
+    ///
+    /// Trips the breaker when the failure threshold is reached or
+    /// when a half-open trial call fails.
+    pub fn record_failure(&mut self, service: &str, method: &str) {
+        let state = self.state(service, method);
+        let threshold = self.config.failure_threshold;
+
+        let circuit = self.circuits.entry(Self::key(service, method)).or_default();
+
+        circuit.failures += 1;
+
+        if state == BreakerState::HalfOpen || circuit.failures >= threshold {
+            if state != BreakerState::Open {
+                log::warn!(
+                    "Circuit breaker tripped for {service} {method} after {} failure(s)",
+                    circuit.failures
+                );
+            }
+            circuit.opened = Some(Instant::now());
+        }
+    }
+
+    /// Record a successful call, closing the breaker.
+    pub fn record_success(&mut self, service: &str, method: &str) {
+        if let Some(circuit) = self.circuits.remove(&Self::key(service, method)) {
+            if circuit.opened.is_some() {
+                log::info!("Circuit breaker closed for {service} {method}");
+            }
+        }
+    }
+}
@@ -4,13 +4,69 @@ use crate::osrf::logging::Logger;
 use crate::osrf::message::TransportMessage;
 use crate::util;
 use crate::EgResult;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use redis::{Commands, ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
 use std::fmt;
+use std::io::{Read, Write};
+
+/// Every gzip stream starts with these bytes.  Serialized JSON never
+/// does, so they tell us which bus messages are compressed.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Serialize a bus message, gzip-compressing it when it exceeds
+/// the threshold.
+///
+/// ```
+/// use evergreen::osrf::bus;
+///
+/// let json = r#"{"to":"x","body":[]}"#.to_string();
+/// let chunk = bus::encode_chunk(json.clone(), Some(5)).unwrap();
+///
+/// assert_ne!(chunk, json.as_bytes());
+/// assert_eq!(bus::decode_chunk(chunk).unwrap(), json);
+/// ```
+pub fn encode_chunk(json: String, compress_threshold: Option<usize>) -> EgResult<Vec<u8>> {
+    match compress_threshold {
+        Some(size) if json.len() > size => {}
+        _ => return Ok(json.into_bytes()),
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+
+    encoder
+        .write_all(json.as_bytes())
+        .or_else(|e| Err(format!("Error compressing message: {e}")))?;
+
+    encoder
+        .finish()
+        .or_else(|e| Err(format!("Error compressing message: {e}").into()))
+}
+
+/// Translate a bus message, compressed or not, into a JSON string.
+pub fn decode_chunk(chunk: Vec<u8>) -> EgResult<String> {
+    if !chunk.starts_with(&GZIP_MAGIC) {
+        return String::from_utf8(chunk)
+            .or_else(|e| Err(format!("Bus message is not valid UTF-8: {e}").into()));
+    }
+
+    let mut json = String::new();
+
+    GzDecoder::new(chunk.as_slice())
+        .read_to_string(&mut json)
+        .or_else(|e| Err(format!("Error decompressing message: {e}")))?;
+
+    Ok(json)
+}
 
 /// Manages a Redis connection.
 pub struct Bus {
     connection: redis::Connection,
 
+    /// Retained so the connection can be re-established.
+    connection_info: ConnectionInfo,
+
     /// Every bus connection has a unique client address.
     address: BusAddress,
 
@@ -22,6 +78,9 @@ pub struct Bus {
     /// messages to be parsed and serialized without concern for
     /// IDL-classed information stored in the message.
     raw_data_mode: bool,
+
+    /// Messages larger than this are sent compressed.
+    compress_threshold: Option<usize>,
 }
 
 impl Bus {
@@ -30,12 +89,7 @@ impl Bus {
 
         log::trace!("Bus::new() connecting to {:?}", info);
 
-        let client = redis::Client::open(info)
-            .or_else(|e| Err(format!("Error opening Redis connection: {e}")))?;
-
-        let connection = client
-            .get_connection()
-            .or_else(|e| Err(format!("Bus connect error: {e}")))?;
+        let connection = Bus::open_connection(&info)?;
 
         let username = config.username();
         let domain = config.domain().name();
@@ -43,18 +97,47 @@ impl Bus {
 
         let bus = Bus {
             connection,
+            connection_info: info,
             raw_data_mode: false,
             address: addr,
             router_name: config.router_name().to_string(),
+            compress_threshold: config.compress_threshold(),
         };
 
         Ok(bus)
     }
 
+    fn open_connection(info: &ConnectionInfo) -> EgResult<redis::Connection> {
+        let client = redis::Client::open(info.clone())
+            .or_else(|e| Err(format!("Error opening Redis connection: {e}")))?;
+
+        let connection = client
+            .get_connection()
+            .or_else(|e| Err(format!("Bus connect error: {e}")))?;
+
+        Ok(connection)
+    }
+
+    /// Replace our Redis connection with a new one, e.g. after the
+    /// connection was reset by the server or a proxy.
+    ///
+    /// Our bus address is retained.
+    pub fn reconnect(&mut self) -> EgResult<()> {
+        log::info!("{self} reconnecting to Redis");
+        self.connection = Bus::open_connection(&self.connection_info)?;
+        Ok(())
+    }
+
     pub fn set_raw_data_mode(&mut self, on: bool) {
         self.raw_data_mode = on;
     }
 
+    /// Compress messages larger than this many bytes.  None disables
+    /// compression.  Compressed messages are always accepted.
+    pub fn set_compress_threshold(&mut self, size: Option<usize>) {
+        self.compress_threshold = size;
+    }
+
     /// Generates the Redis connection Info
     ///
     /// Builds the connection info by hand because it gives us more
@@ -122,13 +205,13 @@ impl Bus {
             None => self.address().as_str().to_string(),
         };
 
-        let value: String;
+        let value: Vec<u8>;
 
         if timeout == 0 {
             // non-blocking
 
             // LPOP returns a scalar response.
-            value = match self.connection().lpop(&recipient, None) {
+            let popped: Option<Vec<u8>> = match self.connection().lpop(&recipient, None) {
                 Ok(c) => c,
                 Err(e) => match e.kind() {
                     redis::ErrorKind::TypeError => {
@@ -138,6 +221,11 @@ impl Bus {
                     _ => return Err(format!("recv_one_chunk failed: {e}").into()),
                 },
             };
+
+            value = match popped {
+                Some(v) => v,
+                None => return Ok(None),
+            };
         } else {
             // Blocking
 
@@ -146,7 +234,7 @@ impl Bus {
                 timeout = 0;
             }
 
-            let mut resp: Vec<String> = self
+            let mut resp: Vec<Vec<u8>> = self
                 .connection()
                 .blpop(&recipient, timeout as usize)
                 .or_else(|e| Err(format!("Redis blpop error recipient={recipient} : {e}")))?;
@@ -161,6 +249,8 @@ impl Bus {
             }
         }
 
+        let value = decode_chunk(value)?;
+
         log::trace!("recv_one_value() pulled from bus: {}", value);
 
         Ok(Some(value))
@@ -311,7 +401,9 @@ impl Bus {
 
         log::trace!("send() writing chunk to={}: {}", recipient, json_str);
 
-        let res: Result<i32, _> = self.connection().rpush(recipient, json_str);
+        let chunk = encode_chunk(json_str, self.compress_threshold)?;
+
+        let res: Result<i32, _> = self.connection().rpush(recipient, chunk);
 
         if let Err(e) = res {
             return Err(format!("Error in send() {e}").into());
@@ -357,13 +449,12 @@ impl Bus {
 
     /// Returns an array slice as a Vec of Strings.
     pub fn lrange(&mut self, key: &str, start: isize, stop: isize) -> EgResult<Vec<String>> {
-        let res: Result<Vec<String>, _> = self.connection().lrange(key, start, stop);
+        let res: Result<Vec<Vec<u8>>, _> = self.connection().lrange(key, start, stop);
 
-        if let Err(e) = res {
-            return Err(format!("Error in lrange(): {e}").into());
+        match res {
+            Ok(chunks) => chunks.into_iter().map(decode_chunk).collect(),
+            Err(e) => Err(format!("Error in lrange(): {e}").into()),
         }
-
-        Ok(res.unwrap())
     }
 
     /// Set the expire time on the specified key to 'timeout' seconds from now.
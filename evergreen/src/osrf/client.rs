@@ -1,10 +1,13 @@
 use crate::osrf::addr::BusAddress;
+use crate::osrf::breaker::{BreakerConfig, BreakerState, CircuitBreaker};
 use crate::osrf::bus;
 use crate::osrf::conf;
 use crate::osrf::message;
 use crate::osrf::params::ApiParams;
 use crate::osrf::session::ClientSession;
+use crate::osrf::session::Request;
 use crate::osrf::session::ResponseIterator;
+use crate::osrf::session::DEFAULT_REQUEST_TIMEOUT;
 use crate::util;
 use crate::{EgResult, EgValue};
 use log::info;
@@ -31,6 +34,9 @@ pub struct ClientSingleton {
     /// Queue of receieved transport messages that have yet to be
     /// processed by any sessions.
     backlog: Vec<message::TransportMessage>,
+
+    /// Optional per-method circuit breaker applied to send_recv_one().
+    breaker: Option<CircuitBreaker>,
 }
 
 impl ClientSingleton {
@@ -48,6 +54,7 @@ impl ClientSingleton {
             bus: Some(bus),
             backlog: Vec::new(),
             remote_bus_map: HashMap::new(),
+            breaker: None,
         }
     }
 
@@ -337,6 +344,41 @@ impl Client {
         self.singleton().borrow_mut().bus_mut().clear_bus()
     }
 
+    /// Re-establish our Bus connection.
+    ///
+    /// Pending messages in our backlog are discarded, since any
+    /// replies they carry are from requests that can no longer
+    /// be completed.
+    pub fn reconnect(&self) -> EgResult<()> {
+        self.singleton().borrow_mut().clear_backlog();
+        self.singleton().borrow_mut().bus_mut().reconnect()
+    }
+
+    /// Enable the per-method circuit breaker, or disable it with None.
+    ///
+    /// The breaker is shared by all clones of this Client.
+    pub fn set_circuit_breaker(&self, config: Option<BreakerConfig>) {
+        self.singleton().borrow_mut().breaker = config.map(CircuitBreaker::new);
+    }
+
+    /// Our circuit breaker settings, if a breaker is enabled.
+    pub fn circuit_breaker_config(&self) -> Option<BreakerConfig> {
+        self.singleton()
+            .borrow()
+            .breaker
+            .as_ref()
+            .map(|b| *b.config())
+    }
+
+    /// Breaker state for the service+method, if a breaker is enabled.
+    pub fn circuit_breaker_state(&self, service: &str, method: &str) -> Option<BreakerState> {
+        self.singleton()
+            .borrow()
+            .breaker
+            .as_ref()
+            .map(|b| b.state(service, method))
+    }
+
     /// Wrapper for ClientSingleton::send_router_command()
     pub fn send_router_command(
         &self,
@@ -375,6 +417,10 @@ impl Client {
     ///
     /// This still waits for all responses to arrive before returning the
     /// first, so the request can be marked as complete and cleaned up.
+    ///
+    /// If a circuit breaker is enabled, errors and timeouts count as
+    /// failures, and calls to a method whose breaker is open return
+    /// an error without being sent.
     pub fn send_recv_one(
         &self,
         service: &str,
@@ -382,8 +428,58 @@ impl Client {
         params: impl Into<ApiParams>,
     ) -> EgResult<Option<EgValue>> {
         let mut ses = self.session(service);
-        let mut req = ses.request(method, params)?;
 
-        req.first()
+        if self.singleton().borrow().breaker.is_none() {
+            return ses.request(method, params)?.first();
+        }
+
+        if let Some(breaker) = self.singleton().borrow().breaker.as_ref() {
+            if !breaker.allow(service, method) {
+                return Err(format!("Circuit breaker open for {service} {method}").into());
+            }
+        }
+
+        let result = ses
+            .request(method, params)
+            .and_then(|mut req| Client::first_or_timeout(&mut req));
+
+        if let Some(breaker) = self.singleton().borrow_mut().breaker.as_mut() {
+            match result {
+                Ok(Some(_)) => breaker.record_success(service, method),
+                Ok(None) => {
+                    log::warn!("Request timed out: {service} {method}");
+                    breaker.record_failure(service, method);
+                }
+                Err(_) => breaker.record_failure(service, method),
+            }
+        }
+
+        result.map(|r| r.flatten())
+    }
+
+    /// Like Request::first(), but gives up once the request timeout
+    /// passes without a response.
+    ///
+    /// Returns None on timeout, Some(None) when the request completes
+    /// without a response.
+    fn first_or_timeout(req: &mut Request) -> EgResult<Option<Option<EgValue>>> {
+        let mut resp: Option<EgValue> = None;
+
+        while !req.complete() {
+            match req.recv_with_timeout(DEFAULT_REQUEST_TIMEOUT)? {
+                Some(r) => {
+                    if resp.is_none() {
+                        resp = Some(r);
+                    }
+                }
+                None => {
+                    if !req.complete() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        Ok(Some(resp))
     }
 }
@@ -110,6 +110,7 @@ pub struct BusClient {
     logging: LogOptions,
     settings_config: Option<String>,
     routers: Vec<ClientRouter>,
+    compress_threshold: Option<usize>,
 }
 
 impl BusClient {
@@ -138,6 +139,14 @@ impl BusClient {
     pub fn routers(&self) -> &Vec<ClientRouter> {
         &self.routers
     }
+    /// Messages larger than this many bytes are sent gzip-compressed.
+    ///
+    /// Every client and service reading from the bus must be able to
+    /// decompress messages before this is enabled.  None disables
+    /// compression.
+    pub fn compress_threshold(&self) -> Option<usize> {
+        self.compress_threshold
+    }
     pub fn set_domain(&mut self, domain: &str) {
         // Assumes other aspects of the domain are identical
         self.domain.name = domain.to_string();
@@ -404,6 +413,7 @@ impl ConfigBuilder {
         let mut password = "";
         let mut router_name = "router";
         let mut settings_config: Option<String> = None;
+        let mut compress_threshold: Option<usize> = None;
 
         for child in node.children() {
             match child.tag_name().name() {
@@ -427,6 +437,14 @@ impl ConfigBuilder {
                         settings_config = Some(t.to_string());
                     }
                 }
+                "compress_threshold" => {
+                    if let Some(t) = child.text() {
+                        let size = t
+                            .parse::<usize>()
+                            .or_else(|e| Err(format!("Invalid compress_threshold: {t} {e}")))?;
+                        compress_threshold = Some(size);
+                    }
+                }
                 _ => {}
             }
         }
@@ -435,6 +453,7 @@ impl ConfigBuilder {
             domain,
             logging,
             settings_config,
+            compress_threshold,
             routers: Vec::new(),
             username: username.to_string(),
             password: password.to_string(),
@@ -5,6 +5,7 @@ use crate::EgResult;
 use crate::EgValue;
 use json::JsonValue;
 use std::fmt;
+use std::time::Duration;
 
 pub type MethodHandler = fn(
     &mut Box<dyn app::ApplicationWorker>,
@@ -208,6 +209,14 @@ pub struct MethodDef {
     pub param_count: ParamCount,
     pub handler: MethodHandler,
     pub params: Option<Vec<Param>>,
+
+    /// Verify parameter types against our param definitions before
+    /// calling the handler.  Off unless the method opts in.
+    pub strict_params: bool,
+
+    /// Calls running longer than this are answered with a Timeout
+    /// status and the worker is recycled.  None means no limit.
+    pub max_duration: Option<Duration>,
 }
 
 impl MethodDef {
@@ -218,6 +227,8 @@ impl MethodDef {
             params: None,
             desc: None,
             name: name.to_string(),
+            strict_params: false,
+            max_duration: None,
         }
     }
 
@@ -241,6 +252,69 @@ impl MethodDef {
         self.params.as_ref()
     }
 
+    pub fn strict_params(&self) -> bool {
+        self.strict_params
+    }
+
+    /// Methods may opt in to parameter type checks.  Otherwise, the
+    /// handler is left to make sense of what it's given.
+    pub fn set_strict_params(&mut self, strict: bool) {
+        self.strict_params = strict;
+    }
+
+    /// Validate the params of a call to this method if it uses
+    /// strict params.
+    pub fn check_params(&self, params: &[EgValue]) -> Result<(), String> {
+        if self.strict_params {
+            self.validate_params(params)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration
+    }
+
+    /// Handlers run synchronously and cannot be interrupted, so a
+    /// call which exceeds this limit is only detected once it returns.
+    pub fn set_max_duration(&mut self, max: Duration) {
+        self.max_duration = Some(max);
+    }
+
+    /// Verify the parameter types are correct, at least superficially.
+    ///
+    /// Only parameters which have a definition are checked.  NULL
+    /// placeholders for non-required parameters are allowed.
+    ///
+    /// Returns Err naming the first mismatched parameter.
+    pub fn validate_params(&self, params: &[EgValue]) -> Result<(), String> {
+        let Some(param_defs) = self.params() else {
+            return Ok(());
+        };
+
+        let minimum = self.param_count.minimum() as usize;
+
+        // There may be more param defs than parameters if some
+        // params are optional.
+        for (idx, (param_def, param_val)) in param_defs.iter().zip(params).enumerate() {
+            if idx >= minimum && param_val.is_null() {
+                continue;
+            }
+
+            if !param_def.datatype.matches(param_val) {
+                return Err(format!(
+                    "Invalid type for parameter {idx} '{}': wanted={} got={}",
+                    param_def.name,
+                    param_def.datatype,
+                    param_val.dump()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn desc(&self) -> Option<&str> {
         self.desc.as_deref()
     }
@@ -269,7 +343,9 @@ impl MethodDef {
 
         EgValue::from_json_value_plain(json::object! {
             "api_name": self.name(),
-            "argc": self.param_count().to_string(),
+            // Perl OpenSRF reports the minimum number of params.
+            "argc": self.param_count().minimum(),
+            "param_count": self.param_count().to_string(),
             "params": pa.into_json_value(),
             // All Rust methods are streaming.
             "stream": JsonValue::Boolean(true),
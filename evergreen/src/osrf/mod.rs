@@ -1,6 +1,7 @@
 //! OpenSRF Components
 pub mod addr;
 pub mod app;
+pub mod breaker;
 pub mod bus;
 pub mod cache;
 pub mod client;
@@ -15,7 +15,7 @@ use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 /// Warn when there are fewer than this many idle threads
 const IDLE_THREAD_WARN_THRESHOLD: usize = 1;
@@ -32,6 +32,17 @@ const DEFAULT_MIN_IDLE_WORKERS: usize = 1;
 pub struct WorkerThread {
     pub state: WorkerState,
     pub join_handle: thread::JoinHandle<()>,
+
+    /// When the worker last reported a state change.
+    pub state_time: Instant,
+}
+
+impl WorkerThread {
+    /// True if the worker has been Active for longer than max_active
+    /// without reporting a new state.
+    pub fn is_hung(&self, max_active: Duration) -> bool {
+        self.state == WorkerState::Active && self.state_time.elapsed() > max_active
+    }
 }
 
 pub struct Server {
@@ -40,6 +51,15 @@ pub struct Server {
     client: Client,
     // Worker threads are tracked via their bus address.
     workers: HashMap<u64, WorkerThread>,
+
+    /// Workers that were replaced after exceeding max_active_time.
+    /// Threads cannot be killed, so we keep an eye on them in case
+    /// they eventually recover or exit.
+    hung_workers: HashMap<u64, WorkerThread>,
+
+    /// Max time a worker may remain Active without reporting a new
+    /// state before it's considered hung and replaced.
+    max_active_time: Option<Duration>,
     // Each thread gets a simple numeric ID.
     worker_id_gen: u64,
     to_parent_tx: mpsc::SyncSender<WorkerStateEvent>,
@@ -56,9 +76,53 @@ pub struct Server {
     /// For comparision, the OSRF C code has no min/max idle support
     /// either.
     min_idle_workers: usize,
+
+    /// Overrides for the max_requests and keepalive host settings,
+    /// passed along to each worker.
+    max_requests: Option<usize>,
+    keepalive: Option<usize>,
 }
 
 impl Server {
+    /// Run the service's startup steps without handling any requests.
+    ///
+    /// Verifies the OpenSRF config and bus connection, host settings,
+    /// a round trip over the bus, and application init (which loads
+    /// the IDL for Evergreen services).
+    pub fn self_test(mut application: Box<dyn app::Application>) -> util::SelfTest {
+        let mut test = util::SelfTest::new();
+        let service = application.name().to_string();
+
+        let mut options = init::InitOptions::new();
+        options.appname = Some(service.clone());
+        options.skip_host_settings = true;
+
+        let client = match test.check("OpenSRF config and bus connect", || {
+            init::osrf_init(&options)
+        }) {
+            Some(c) => c,
+            None => return test,
+        };
+
+        test.check("host settings", || HostSettings::load(&client));
+
+        test.check("bus ping", || {
+            client
+                .send_recv_one("opensrf.settings", "opensrf.system.echo", "ping")?
+                .ok_or_else(|| "opensrf.settings did not respond".into())
+        });
+
+        test.check(&format!("{service} init"), || {
+            application.init(client.clone())
+        });
+
+        test.check(&format!("{service} method registration"), || {
+            application.register_methods(client.clone())
+        });
+
+        test
+    }
+
     pub fn start(application: Box<dyn app::Application>) -> EgResult<()> {
         let service = application.name();
 
@@ -80,6 +144,11 @@ impl Server {
             .as_usize()
             .unwrap_or(DEFAULT_MAX_WORKERS);
 
+        let max_active_time =
+            HostSettings::get(&format!("apps/{service}/unix_config/max_active_time"))?
+                .as_usize()
+                .map(|n| Duration::from_secs(n as u64));
+
         // We have a single to-parent channel whose trasmitter is cloned
         // per thread.  Communication from worker threads to the parent
         // are synchronous so the parent always knows exactly how many
@@ -102,7 +171,11 @@ impl Server {
             to_parent_tx: tx,
             to_parent_rx: rx,
             workers: HashMap::new(),
+            hung_workers: HashMap::new(),
+            max_active_time,
             sig_tracker: SignalTracker::new(),
+            max_requests: options.max_requests,
+            keepalive: options.keepalive,
         };
 
         server.listen()
@@ -141,6 +214,8 @@ impl Server {
         let service = self.service().to_string();
         let factory = self.app().worker_factory();
         let sig_tracker = self.sig_tracker.clone();
+        let max_requests = self.max_requests;
+        let keepalive = self.keepalive;
 
         log::trace!("server: spawning a new worker {worker_id}");
 
@@ -152,6 +227,8 @@ impl Server {
                 worker_id,
                 methods,
                 to_parent_tx,
+                max_requests,
+                keepalive,
             );
         });
 
@@ -160,6 +237,7 @@ impl Server {
             WorkerThread {
                 state: WorkerState::Idle,
                 join_handle: handle,
+                state_time: Instant::now(),
             },
         );
     }
@@ -171,6 +249,8 @@ impl Server {
         worker_id: u64,
         methods: Arc<HashMap<String, method::MethodDef>>,
         to_parent_tx: mpsc::SyncSender<WorkerStateEvent>,
+        max_requests: Option<usize>,
+        keepalive: Option<usize>,
     ) {
         log::trace!("Creating new worker {worker_id}");
 
@@ -187,6 +267,9 @@ impl Server {
             }
         };
 
+        worker.set_max_requests(max_requests);
+        worker.set_keepalive(keepalive);
+
         log::trace!("Worker {worker_id} going into listen()");
 
         worker.listen(factory);
@@ -286,6 +369,12 @@ impl Server {
         method.set_desc("Respond with system time in epoch seconds");
         hash.insert(name.to_string(), method);
 
+        let name = "opensrf.system.worker_stats";
+        let mut method =
+            method::MethodDef::new(name, method::ParamCount::Zero, system_method_worker_stats);
+        method.set_desc("Request, error, and busy time totals for the responding worker");
+        hash.insert(name.to_string(), method);
+
         let name = "opensrf.system.method.all";
         let mut method = method::MethodDef::new(
             name,
@@ -302,6 +391,38 @@ impl Server {
 
         hash.insert(name.to_string(), method);
 
+        let name = "opensrf.system.method_list";
+        let mut method = method::MethodDef::new(
+            name,
+            method::ParamCount::Range(0, 1),
+            system_method_introspect,
+        );
+        method.set_desc("List published API names");
+
+        method.add_param(method::Param {
+            name: String::from("prefix"),
+            datatype: method::ParamDataType::String,
+            desc: Some(String::from("API name prefix filter")),
+        });
+
+        hash.insert(name.to_string(), method);
+
+        let name = "opensrf.system.method_list.all";
+        let mut method = method::MethodDef::new(
+            name,
+            method::ParamCount::Range(0, 1),
+            system_method_introspect,
+        );
+        method.set_desc("List published API definitions");
+
+        method.add_param(method::Param {
+            name: String::from("prefix"),
+            datatype: method::ParamDataType::String,
+            desc: Some(String::from("API name prefix filter")),
+        });
+
+        hash.insert(name.to_string(), method);
+
         let name = "opensrf.system.method.all.summary";
         let mut method = method::MethodDef::new(
             name,
@@ -349,6 +470,8 @@ impl Server {
             // Always check for failed threads.
             work_performed = self.check_failed_threads() || work_performed;
 
+            work_performed = self.check_hung_threads() || work_performed;
+
             if self.sig_tracker.any_shutdown_requested() {
                 log::info!("We received a stop signal, exiting");
                 break;
@@ -434,6 +557,44 @@ impl Server {
         handled
     }
 
+    /// Replace workers that have been Active for longer than
+    /// max_active_time without reporting progress.
+    ///
+    /// Returns true if work was done.
+    fn check_hung_threads(&mut self) -> bool {
+        self.hung_workers
+            .retain(|_, w| !w.join_handle.is_finished());
+
+        let max_active = match self.max_active_time {
+            Some(m) => m,
+            None => return false,
+        };
+
+        let hung: Vec<u64> = self
+            .workers
+            .iter()
+            .filter(|(_, v)| v.is_hung(max_active))
+            .map(|(k, _)| *k)
+            .collect();
+
+        let mut handled = false;
+        for worker_id in hung {
+            handled = true;
+            log::error!(
+                "server: worker {worker_id} has been active for more than {}s; replacing it",
+                max_active.as_secs()
+            );
+
+            if let Some(worker) = self.workers.remove(&worker_id) {
+                self.hung_workers.insert(worker_id, worker);
+            }
+
+            self.spawn_threads();
+        }
+
+        handled
+    }
+
     fn remove_thread(&mut self, worker_id: &u64) {
         log::trace!("server: removing thread {}", worker_id);
         self.workers.remove(worker_id);
@@ -447,6 +608,15 @@ impl Server {
 
         let worker_id = evt.worker_id();
 
+        if let Some(worker) = self.hung_workers.remove(&worker_id) {
+            if evt.state() == WorkerState::Exiting {
+                log::info!("server: hung worker {worker_id} has exited");
+                return;
+            }
+            log::info!("server: hung worker {worker_id} has recovered");
+            self.workers.insert(worker_id, worker);
+        }
+
         let worker: &mut WorkerThread = match self.workers.get_mut(&worker_id) {
             Some(w) => w,
             None => {
@@ -461,6 +631,7 @@ impl Server {
         } else {
             log::trace!("server: updating thread state: {:?}", worker_id);
             worker.state = evt.state();
+            worker.state_time = Instant::now();
         }
 
         let idle = self.idle_thread_count();
@@ -535,6 +706,19 @@ fn system_method_time(
     }
 }
 
+fn system_method_worker_stats(
+    _worker: &mut Box<dyn app::ApplicationWorker>,
+    session: &mut session::ServerSession,
+    _method: &message::MethodCall,
+) -> EgResult<()> {
+    let stats = match session.worker_stats() {
+        Some(s) => s.to_eg_value(),
+        None => return Err("Worker stats are not available".into()),
+    };
+
+    session.respond_complete(stats)
+}
+
 fn system_method_introspect(
     worker: &mut Box<dyn app::ApplicationWorker>,
     session: &mut session::ServerSession,
@@ -564,6 +748,10 @@ fn system_method_introspect(
         if let Some(meth) = worker.methods().get(name) {
             if method.method().contains("summary") {
                 session.respond(meth.to_summary_string())?;
+            } else if method.method().starts_with("opensrf.system.method_list")
+                && !method.method().contains(".all")
+            {
+                session.respond(name)?;
             } else {
                 session.respond(meth.to_eg_value())?;
             }
@@ -10,6 +10,7 @@ use crate::osrf::message::Payload;
 use crate::osrf::message::Status;
 use crate::osrf::message::TransportMessage;
 use crate::osrf::params::ApiParams;
+use crate::osrf::worker::WorkerStats;
 use crate::util;
 use crate::{EgResult, EgValue};
 use std::cell::RefCell;
@@ -17,6 +18,7 @@ use std::cell::RefMut;
 use std::collections::VecDeque;
 use std::fmt;
 use std::rc::Rc;
+use std::sync::Arc;
 
 const CONNECT_TIMEOUT: i32 = 10;
 pub const DEFAULT_REQUEST_TIMEOUT: i32 = 60;
@@ -759,6 +761,9 @@ pub struct ServerSession {
 
     /// Responses collected to be packed into an "atomic" response array.
     atomic_resp_queue: Option<Vec<EgValue>>,
+
+    /// API call totals for the worker running this session.
+    worker_stats: Option<Arc<WorkerStats>>,
 }
 
 impl fmt::Display for ServerSession {
@@ -783,9 +788,18 @@ impl ServerSession {
             responded_complete: false,
             thread: thread.to_string(),
             atomic_resp_queue: None,
+            worker_stats: None,
         }
     }
 
+    pub fn worker_stats(&self) -> Option<&WorkerStats> {
+        self.worker_stats.as_deref()
+    }
+
+    pub fn set_worker_stats(&mut self, stats: Arc<WorkerStats>) {
+        self.worker_stats = Some(stats);
+    }
+
     pub fn last_thread_trace(&self) -> usize {
         self.last_thread_trace
     }
@@ -943,4 +957,32 @@ impl ServerSession {
     pub fn respond_complete(&mut self, value: impl Into<EgValue>) -> EgResult<()> {
         self.respond_with_parts(Some(value.into()), true)
     }
+
+    /// Respond with each value as it's produced by the iterator, then
+    /// send the Request Complete message.
+    ///
+    /// Each value goes out in its own Result message (or into the
+    /// atomic response queue for atomic requests), so large result
+    /// sets never have to be collected in memory first.
+    ///
+    /// Stops at the first error, which is returned to the caller
+    /// without sending the Request Complete message.
+    ///
+    /// Returns the number of values sent.
+    pub fn respond_streaming<I, V>(&mut self, values: I) -> EgResult<usize>
+    where
+        I: IntoIterator<Item = EgResult<V>>,
+        V: Into<EgValue>,
+    {
+        let mut count = 0;
+
+        for value in values {
+            self.respond(value?)?;
+            count += 1;
+        }
+
+        self.send_complete()?;
+
+        Ok(count)
+    }
 }
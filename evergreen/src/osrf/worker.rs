@@ -15,10 +15,13 @@ use crate::osrf::sclient::HostSettings;
 use crate::osrf::session::ServerSession;
 use crate::util;
 use crate::EgResult;
+use crate::EgValue;
+use chrono::Timelike;
 use mptc::signals::SignalTracker;
 use std::cell::RefMut;
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
@@ -27,6 +30,37 @@ use std::time;
 // How often each worker wakes to check for shutdown signals, etc.
 const IDLE_WAKE_TIME: i32 = 5;
 
+/// How many times a worker tries to reconnect to the bus after a
+/// failed recv() before giving up and exiting.
+const DEFAULT_BUS_RECONNECT_ATTEMPTS: usize = 3;
+
+/// Seconds a worker waits for a stateful conversation to finish
+/// after a shutdown is requested.
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: u64 = 30;
+
+/// Longest pause between bus reconnect attempts.
+const MAX_BUS_RECONNECT_DELAY: u64 = 30;
+
+/// Pause before bus reconnect attempt number `attempt` (starting at 1).
+///
+/// The delay doubles with each attempt, up to MAX_BUS_RECONNECT_DELAY
+/// seconds, so a pool of workers does not hammer a bus which is
+/// still recovering.
+///
+/// ```
+/// use evergreen::osrf::worker::bus_reconnect_delay;
+/// use std::time::Duration;
+///
+/// assert_eq!(bus_reconnect_delay(1), Duration::from_secs(1));
+/// assert_eq!(bus_reconnect_delay(3), Duration::from_secs(4));
+/// assert_eq!(bus_reconnect_delay(20), Duration::from_secs(30));
+/// ```
+pub fn bus_reconnect_delay(attempt: usize) -> time::Duration {
+    let exp = attempt.saturating_sub(1).min(16) as u32;
+    let secs = 2u64.pow(exp).min(MAX_BUS_RECONNECT_DELAY);
+    time::Duration::from_secs(secs)
+}
+
 /// Each worker thread is in one of these states.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum WorkerState {
@@ -35,6 +69,184 @@ pub enum WorkerState {
     Exiting,
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DrainAction {
+    /// Keep working.
+    Continue,
+    /// Nothing is in progress.  Exit now.
+    Exit,
+    /// The conversation in progress ran past the grace period.
+    Expired,
+}
+
+/// Lets a stateful conversation which is in progress when a shutdown
+/// is requested finish, within a grace period, before the worker exits.
+///
+/// ```
+/// use evergreen::osrf::worker::{DrainAction, ShutdownDrain};
+/// use std::time::Duration;
+///
+/// let mut drain = ShutdownDrain::new(Duration::from_secs(30));
+/// assert_eq!(drain.check(false, true), DrainAction::Continue);
+/// assert_eq!(drain.check(true, false), DrainAction::Exit);
+/// ```
+#[derive(Debug)]
+pub struct ShutdownDrain {
+    grace: time::Duration,
+    /// When we first saw a shutdown request mid-conversation.
+    started: Option<time::Instant>,
+}
+
+impl ShutdownDrain {
+    pub fn new(grace: time::Duration) -> Self {
+        ShutdownDrain {
+            grace,
+            started: None,
+        }
+    }
+
+    /// True once a shutdown arrived during a conversation.
+    pub fn draining(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub fn check(&mut self, shutdown_requested: bool, connected: bool) -> DrainAction {
+        if !shutdown_requested {
+            return DrainAction::Continue;
+        }
+
+        if !connected {
+            return DrainAction::Exit;
+        }
+
+        let started = *self.started.get_or_insert_with(time::Instant::now);
+
+        if started.elapsed() >= self.grace {
+            DrainAction::Expired
+        } else {
+            DrainAction::Continue
+        }
+    }
+}
+
+/// Running totals for the API calls handled by a single worker.
+///
+/// Totals only ever grow, so they can be sampled and diffed over
+/// time.
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    worker_id: u64,
+    requests: AtomicUsize,
+    errors: AtomicUsize,
+    busy_micros: AtomicU64,
+}
+
+impl WorkerStats {
+    pub fn new(worker_id: u64) -> Self {
+        WorkerStats {
+            worker_id,
+            ..Default::default()
+        }
+    }
+
+    /// Record a completed API call.
+    pub fn record(&self, elapsed: time::Duration, success: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+
+        if !success {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.busy_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Total API calls handled.
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Total API calls which returned an error.
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent in API handlers.
+    pub fn busy_time(&self) -> time::Duration {
+        time::Duration::from_micros(self.busy_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn to_eg_value(&self) -> EgValue {
+        EgValue::from_json_value_plain(json::object! {
+            "worker_id": self.worker_id,
+            "requests": self.requests(),
+            "errors": self.errors(),
+            "busy_time": self.busy_time().as_secs_f64(),
+        })
+    }
+}
+
+/// Watches a single API call which has a max_duration.
+///
+/// The watchdog thread logs a warning as soon as the limit passes
+/// so long-running calls are visible while they are still running.
+/// Since handlers cannot be interrupted, the caller decides what to
+/// do once the call returns.
+pub struct MethodWatchdog {
+    started: time::Instant,
+    limit: time::Duration,
+    done_tx: mpsc::Sender<()>,
+}
+
+impl MethodWatchdog {
+    pub fn start(label: &str, limit: time::Duration) -> Self {
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let label = label.to_string();
+
+        thread::spawn(move || {
+            // Any value, or the sender being dropped, means the
+            // call has returned.
+            if let Err(mpsc::RecvTimeoutError::Timeout) = done_rx.recv_timeout(limit) {
+                log::warn!("{label} is still running after {}s", limit.as_secs_f64());
+            }
+        });
+
+        MethodWatchdog {
+            started: time::Instant::now(),
+            limit,
+            done_tx,
+        }
+    }
+
+    /// Stop watching.  Returns true if the call exceeded its limit.
+    pub fn finish(self) -> bool {
+        self.done_tx.send(()).ok();
+        self.started.elapsed() > self.limit
+    }
+}
+
+/// Split off the requests in a stateless batch which may be handed
+/// to other workers for concurrent processing.
+///
+/// A batch is only split when every message is a Request, i.e. there
+/// are no Connect/Disconnect messages which must be handled serially
+/// by the same worker.  At most `max_concurrent` requests are handled
+/// at once:  the first is left in the batch, along with any beyond
+/// the limit, and the rest are returned.
+pub fn split_batch(body: &mut Vec<Message>, max_concurrent: usize) -> Vec<Message> {
+    if max_concurrent < 2 || body.len() < 2 {
+        return Vec::new();
+    }
+
+    if body.iter().any(|m| m.mtype() != &MessageType::Request) {
+        return Vec::new();
+    }
+
+    let end = body.len().min(max_concurrent);
+
+    body.drain(1..end).collect()
+}
+
 #[derive(Debug)]
 pub struct WorkerStateEvent {
     pub worker_id: u64,
@@ -50,6 +262,131 @@ impl WorkerStateEvent {
     }
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RecycleAction {
+    /// Keep working.
+    Continue,
+    /// The request limit was reached during a peak window.  Keep
+    /// working.  Only reported for the first deferred check.
+    Defer,
+    /// Exit so the worker can be replaced.
+    Recycle,
+}
+
+/// Hours of the day during which a worker which has reached its
+/// max_requests limit keeps running instead of exiting, so workers
+/// are not respawned during peak traffic.
+#[derive(Debug, Clone)]
+pub struct RecycleSchedule {
+    /// (start_hour, end_hour) pairs.  The end hour is exclusive.
+    /// Windows whose start hour is after the end hour wrap midnight.
+    windows: Vec<(u32, u32)>,
+
+    /// Maximum number of requests a worker may handle beyond its
+    /// max_requests value while recycling is deferred.
+    max_deferred: usize,
+
+    /// Returns the current hour of the day (0-23).
+    clock: fn() -> u32,
+
+    /// True once recycling has been deferred.
+    deferred: bool,
+}
+
+impl Default for RecycleSchedule {
+    fn default() -> Self {
+        RecycleSchedule {
+            windows: Vec::new(),
+            max_deferred: 0,
+            clock: || crate::date::now().hour(),
+            deferred: false,
+        }
+    }
+}
+
+impl RecycleSchedule {
+    /// Parse a comma-separated list of hour ranges, e.g. "8-12,13-18".
+    ///
+    /// ```
+    /// use evergreen::osrf::worker::RecycleSchedule;
+    ///
+    /// let sched = RecycleSchedule::parse("22-2", 10).unwrap();
+    /// assert!(sched.in_window(23));
+    /// assert!(sched.in_window(1));
+    /// assert!(!sched.in_window(2));
+    ///
+    /// assert!(RecycleSchedule::parse("8-25", 10).is_err());
+    /// ```
+    pub fn parse(windows: &str, max_deferred: usize) -> EgResult<Self> {
+        let mut sched = RecycleSchedule {
+            max_deferred,
+            ..Default::default()
+        };
+
+        for part in windows.split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+
+            let (start, end) = part
+                .split_once('-')
+                .ok_or_else(|| format!("Invalid recycle window: {part}"))?;
+
+            let start = start.trim().parse::<u32>().ok().filter(|h| *h < 24);
+            let end = end.trim().parse::<u32>().ok().filter(|h| *h <= 24);
+
+            match (start, end) {
+                (Some(s), Some(e)) => sched.windows.push((s, e)),
+                _ => return Err(format!("Invalid recycle window: {part}").into()),
+            }
+        }
+
+        Ok(sched)
+    }
+
+    /// True if the hour (0-23) falls within any of our windows.
+    pub fn in_window(&self, hour: u32) -> bool {
+        self.windows.iter().any(|(start, end)| {
+            if start <= end {
+                hour >= *start && hour < *end
+            } else {
+                hour >= *start || hour < *end
+            }
+        })
+    }
+
+    /// True if a worker which has handled `requests` requests should
+    /// keep running at the given hour.
+    pub fn keep_running(&self, hour: u32, requests: usize, max_requests: usize) -> bool {
+        if requests < max_requests {
+            return true;
+        }
+
+        requests < max_requests + self.max_deferred && self.in_window(hour)
+    }
+
+    /// Replace the clock used by check(), e.g. for testing.
+    pub fn set_clock(&mut self, clock: fn() -> u32) {
+        self.clock = clock;
+    }
+
+    /// Decide whether a worker which has handled `requests` requests
+    /// should keep running at the current hour.
+    pub fn check(&mut self, requests: usize, max_requests: usize) -> RecycleAction {
+        if !self.keep_running((self.clock)(), requests, max_requests) {
+            return RecycleAction::Recycle;
+        }
+
+        if requests >= max_requests && !self.deferred {
+            self.deferred = true;
+            return RecycleAction::Defer;
+        }
+
+        RecycleAction::Continue
+    }
+}
+
 /// A Worker runs in its own thread and responds to API requests.
 pub struct Worker {
     service: String,
@@ -76,6 +413,30 @@ pub struct Worker {
 
     /// Channel for sending worker state info to our parent.
     to_parent_tx: mpsc::SyncSender<WorkerStateEvent>,
+
+    /// Maximum number of requests from a single stateless batch
+    /// handled concurrently.  Extra requests are passed back to the
+    /// service queue for other workers to pick up.  Values less than
+    /// 2 mean batches are processed serially.
+    max_batch_concurrency: usize,
+
+    /// Set when a method exceeded its max_duration.  The worker exits
+    /// once its current conversation is done so a fresh worker can
+    /// take its place.
+    recycle_requested: bool,
+
+    /// API call totals, shared with our sessions for introspection.
+    stats: Arc<WorkerStats>,
+
+    /// Bus reconnect attempts made after a failed recv() before the
+    /// worker gives up and exits.
+    bus_reconnect_attempts: usize,
+
+    /// Overrides the max_requests host setting when set.
+    max_requests: Option<usize>,
+
+    /// Overrides the keepalive host setting when set.
+    keepalive: Option<usize>,
 }
 
 impl fmt::Display for Worker {
@@ -103,6 +464,12 @@ impl Worker {
             to_parent_tx,
             session: None,
             connected: false,
+            max_batch_concurrency: 0,
+            recycle_requested: false,
+            stats: Arc::new(WorkerStats::new(worker_id)),
+            bus_reconnect_attempts: DEFAULT_BUS_RECONNECT_ATTEMPTS,
+            max_requests: None,
+            keepalive: None,
         })
     }
 
@@ -126,6 +493,16 @@ impl Worker {
         self.worker_id
     }
 
+    /// Use this max_requests value instead of the host setting.
+    pub fn set_max_requests(&mut self, max_requests: Option<usize>) {
+        self.max_requests = max_requests;
+    }
+
+    /// Use this keepalive value instead of the host setting.
+    pub fn set_keepalive(&mut self, keepalive: Option<usize>) {
+        self.keepalive = keepalive;
+    }
+
     /// Wait for and process inbound API calls.
     //pub fn listen(&mut self, mut appworker: Box<dyn app::ApplicationWorker>) {
     pub fn listen(&mut self, factory: app::ApplicationWorkerFactory) {
@@ -138,17 +515,48 @@ impl Worker {
             return;
         }
 
-        let max_requests: usize =
+        let max_requests: usize = self.max_requests.unwrap_or_else(|| {
             HostSettings::get(&format!("apps/{}/unix_config/max_requests", self.service))
                 .expect("Host Settings Not Retrieved")
                 .as_usize()
-                .unwrap_or(5000);
+                .unwrap_or(5000)
+        });
 
-        let keepalive: usize =
+        let keepalive: usize = self.keepalive.unwrap_or_else(|| {
             HostSettings::get(&format!("apps/{}/unix_config/keepalive", self.service))
                 .expect("Host Settings Not Retrieved")
                 .as_usize()
-                .unwrap_or(5);
+                .unwrap_or(5)
+        });
+
+        self.max_batch_concurrency = HostSettings::get(&format!(
+            "apps/{}/unix_config/max_batch_concurrency",
+            self.service
+        ))
+        .expect("Host Settings Not Retrieved")
+        .as_usize()
+        .unwrap_or(0);
+
+        self.bus_reconnect_attempts = HostSettings::get(&format!(
+            "apps/{}/unix_config/bus_reconnect_attempts",
+            self.service
+        ))
+        .expect("Host Settings Not Retrieved")
+        .as_usize()
+        .unwrap_or(DEFAULT_BUS_RECONNECT_ATTEMPTS);
+
+        let grace_period = HostSettings::get(&format!(
+            "apps/{}/unix_config/shutdown_grace_period",
+            self.service
+        ))
+        .expect("Host Settings Not Retrieved")
+        .as_usize()
+        .map(|v| v as u64)
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE_PERIOD);
+
+        let mut drain = ShutdownDrain::new(time::Duration::from_secs(grace_period));
+
+        let mut recycle_schedule = self.recycle_schedule(max_requests);
 
         let mut requests: usize = 0;
 
@@ -162,7 +570,44 @@ impl Worker {
 
         let my_addr = self.client.address().as_str().to_string();
 
-        while requests < max_requests {
+        loop {
+            match recycle_schedule.check(requests, max_requests) {
+                RecycleAction::Continue => {}
+                RecycleAction::Defer => {
+                    log::info!("{selfstr} deferring recycle during peak hours");
+                }
+                RecycleAction::Recycle => break,
+            }
+
+            // A shutdown only interrupts a conversation which runs
+            // past the grace period.  Since we only listen at our own
+            // address while connected, no new requests are taken.
+            let was_draining = drain.draining();
+
+            match drain.check(self.sig_tracker.any_shutdown_requested(), self.connected) {
+                DrainAction::Continue => {
+                    if drain.draining() && !was_draining {
+                        log::info!("{selfstr} shutting down after the current conversation");
+                    }
+                }
+                DrainAction::Exit => {
+                    log::info!("{selfstr} received a stop signal");
+                    break;
+                }
+                DrainAction::Expired => {
+                    log::warn!("{selfstr} conversation exceeded the shutdown grace period");
+
+                    self.reply_with_status(MessageStatus::Timeout, "Server is shutting down")
+                        .ok();
+
+                    if let Err(e) = appworker.end_session() {
+                        log::error!("end_session() returned an error: {e}");
+                    }
+
+                    break;
+                }
+            }
+
             let timeout: i32;
             let sent_to: &str;
 
@@ -239,6 +684,11 @@ impl Worker {
                 }
             }
 
+            if self.recycle_requested {
+                log::info!("{selfstr} exiting after a method exceeded its max duration");
+                break;
+            }
+
             // Did we get a shutdown signal?  Check this after
             // "end_session()" so we don't interrupt a conversation to
             // shutdown.
@@ -260,6 +710,35 @@ impl Worker {
         self.reset().ok();
     }
 
+    /// Load the optional hours during which worker recycling is deferred.
+    ///
+    /// The number of extra requests handled while deferring defaults
+    /// to max_requests.
+    fn recycle_schedule(&self, max_requests: usize) -> RecycleSchedule {
+        let prefix = format!("apps/{}/unix_config", self.service);
+
+        let windows = match HostSettings::get(&format!("{prefix}/recycle_defer_hours")) {
+            Ok(v) => match v.as_str() {
+                Some(s) => s.to_string(),
+                None => return RecycleSchedule::default(),
+            },
+            Err(_) => return RecycleSchedule::default(),
+        };
+
+        let max_deferred = HostSettings::get(&format!("{prefix}/recycle_defer_max_requests"))
+            .ok()
+            .and_then(|v| v.as_usize())
+            .unwrap_or(max_requests);
+
+        match RecycleSchedule::parse(&windows, max_deferred) {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("{self} ignoring recycle_defer_hours: {e}");
+                RecycleSchedule::default()
+            }
+        }
+    }
+
     /// Call recv() on our message bus and process the response.
     ///
     /// Return value consists of (work_occurred, msg_handled).
@@ -279,8 +758,15 @@ impl Worker {
         let msg_op = match recv_result {
             Ok(o) => o,
             Err(e) => {
-                // There's a good chance an error in recv() means the
-                // thread/system is unusable, so let the worker exit.
+                log::error!("{selfstr} bus recv() failed: {e}");
+
+                // The bus may just be blipping.  Try to reconnect
+                // before giving up on this worker.
+                if self.reconnect_bus() {
+                    return Ok((false, false));
+                }
+
+                // The thread/system is unusable, so let the worker exit.
                 //
                 // Avoid a tight thread respawn loop with a short pause.
                 thread::sleep(time::Duration::from_secs(1));
@@ -330,6 +816,32 @@ impl Worker {
         Ok((true, true)) // work occurred, message handled
     }
 
+    /// Try to replace our bus connection, pausing a little longer
+    /// before each attempt.
+    ///
+    /// Any session in progress is discarded, since its caller may
+    /// have given up on us.  Returns true if we reconnected.
+    fn reconnect_bus(&mut self) -> bool {
+        for attempt in 1..=self.bus_reconnect_attempts {
+            thread::sleep(bus_reconnect_delay(attempt));
+
+            if let Err(e) = self.client.reconnect() {
+                log::warn!("{self} bus reconnect attempt #{attempt} failed: {e}");
+                continue;
+            }
+
+            if let Err(e) = self.reset() {
+                log::warn!("{self} could not reset after reconnecting: {e}");
+                continue;
+            }
+
+            log::info!("{self} reconnected to the bus after {attempt} attempt(s)");
+            return true;
+        }
+
+        false
+    }
+
     /// Tell our parent we're about to perform some work.
     fn set_active(&mut self) -> EgResult<()> {
         if let Err(e) = self.notify_state(WorkerState::Active) {
@@ -363,13 +875,24 @@ impl Worker {
         if self.session.is_none() || self.session().thread().ne(tmsg.thread()) {
             log::trace!("server: creating new server session for {}", tmsg.thread());
 
-            self.session = Some(ServerSession::new(
+            let mut session = ServerSession::new(
                 self.client.clone(),
                 &self.service,
                 tmsg.thread(),
                 0, // thread trace -- updated later as needed
                 BusAddress::from_str(tmsg.from())?,
-            ));
+            );
+
+            session.set_worker_stats(self.stats.clone());
+
+            self.session = Some(session);
+        }
+
+        if !self.connected {
+            let batch = split_batch(tmsg.body_mut(), self.max_batch_concurrency);
+            if !batch.is_empty() {
+                self.requeue_batch(&tmsg, batch)?;
+            }
         }
 
         for msg in tmsg.body_mut().drain(..) {
@@ -379,6 +902,38 @@ impl Worker {
         Ok(())
     }
 
+    /// Send each request back to our service queue as its own
+    /// transport message so idle workers can process them while we
+    /// handle the rest of the batch.
+    ///
+    /// Replies go directly to the original caller, who matches them
+    /// to its requests by thread trace.
+    fn requeue_batch(&mut self, tmsg: &TransportMessage, batch: Vec<Message>) -> EgResult<()> {
+        let service_addr = BusAddress::for_service(
+            self.client.address().username(),
+            self.client.address().domain(),
+            &self.service,
+        );
+
+        log::debug!(
+            "{self} passing {} batched requests to {}",
+            batch.len(),
+            service_addr.as_str()
+        );
+
+        for msg in batch {
+            let mut req =
+                TransportMessage::with_body(service_addr.as_str(), tmsg.from(), tmsg.thread(), msg);
+            req.set_osrf_xid(tmsg.osrf_xid());
+
+            self.client_internal_mut()
+                .get_domain_bus(service_addr.domain())?
+                .send(req)?;
+        }
+
+        Ok(())
+    }
+
     // Clear our local message bus and reset state maintenance values.
     fn reset(&mut self) -> EgResult<()> {
         self.connected = false;
@@ -509,32 +1064,36 @@ impl Worker {
 
         // Verify paramter types are correct, at least superficially.
         // Do this after deserialization.
-        if let Some(param_defs) = method_def.params() {
-            for (idx, param_def) in param_defs.iter().enumerate() {
-                // There may be more param defs than parameters if
-                // some param are optional.
-                if let Some(param_val) = method_call.params().get(idx) {
-                    if idx >= pcount.minimum().into() && param_val.is_null() {
-                        // NULL placeholders for non-required parameters are
-                        // allowed.
-                        continue;
-                    }
-                    if !param_def.datatype.matches(param_val) {
-                        return self.reply_bad_request(&format!(
-                            "Invalid paramter type: wanted={} got={}",
-                            param_def.datatype,
-                            param_val.clone().dump()
-                        ));
-                    }
-                } else {
-                    // More defs than actual params. Verification complete.
-                    break;
-                }
-            }
+        if let Err(e) = method_def.check_params(method_call.params()) {
+            return self.reply_bad_request(&format!("method={api_name} {e}"));
         }
 
+        let watchdog = method_def
+            .max_duration()
+            .map(|max| MethodWatchdog::start(&format!("{self} method {api_name}"), max));
+
+        let started = time::Instant::now();
+
         // Call the API
-        if let Err(err) = (method_def.handler())(appworker, self.session_mut(), &method_call) {
+        let result = (method_def.handler())(appworker, self.session_mut(), &method_call);
+
+        self.stats.record(started.elapsed(), result.is_ok());
+
+        let timed_out = watchdog.map(|w| w.finish()).unwrap_or(false);
+
+        if timed_out {
+            let max = method_def.max_duration().unwrap_or_default();
+
+            log::error!(
+                "{self} method {} exceeded max duration of {}s",
+                method_call.method(),
+                max.as_secs_f64()
+            );
+
+            self.recycle_requested = true;
+        }
+
+        if let Err(err) = result {
             let msg = format!("{self} method {} failed with {err}", method_call.method());
             log::error!("{msg}");
             appworker.api_call_error(&method_call, err);
@@ -542,6 +1101,17 @@ impl Worker {
             Err(msg)?;
         }
 
+        if timed_out {
+            if !self.session().responded_complete() {
+                return self.reply_with_status(
+                    MessageStatus::Timeout,
+                    &format!("Method exceeded max duration: {}", method_call.method()),
+                );
+            }
+
+            return Ok(());
+        }
+
         if !self.session().responded_complete() {
             self.session_mut().send_complete()
         } else {
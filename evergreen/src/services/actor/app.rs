@@ -1,19 +1,47 @@
 use eg::osrf::app::{Application, ApplicationWorker, ApplicationWorkerFactory};
 use eg::osrf::message;
 use eg::osrf::method::MethodDef;
+use eg::osrf::sclient::HostSettings;
 use eg::Client;
+use eg::Editor;
 use eg::EgError;
 use eg::EgResult;
+use eg::EgValue;
 use evergreen as eg;
 use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::OnceLock;
 
 // Import our local methods module.
 use crate::methods;
 
 const APPNAME: &str = "open-ils.rs-actor";
 
+/// Org unit used as the context for API calls which may run without
+/// an authtoken or an explicit org unit.  Validated at startup.
+static DEFAULT_ORG_ID: OnceLock<Option<i64>> = OnceLock::new();
+
+/// Returns the configured default org unit ID, if any.
+pub fn default_org_id() -> Option<i64> {
+    DEFAULT_ORG_ID.get().copied().flatten()
+}
+
+/// Read the default org unit ID from its app setting value.
+///
+/// An unset value means no default org unit.  Anything other than an
+/// org unit ID, e.g. an org unit shortname, is an error.
+pub fn parse_default_org(value: &EgValue) -> EgResult<Option<i64>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+
+    match value.as_int() {
+        Some(id) => Ok(Some(id)),
+        None => Err(format!("{APPNAME} default_org_unit must be an org unit ID: {value}").into()),
+    }
+}
+
 /// Our main application class.
 pub struct RsActorApplication {}
 
@@ -21,6 +49,35 @@ impl RsActorApplication {
     pub fn new() -> Self {
         RsActorApplication {}
     }
+
+    /// Read the optional default org unit from our app settings and
+    /// verify it refers to a real org unit.
+    fn load_default_org(&self, client: Client) -> EgResult<()> {
+        let path = format!("apps/{APPNAME}/app_settings/default_org_unit");
+
+        let org_id = match parse_default_org(HostSettings::get(&path)?)? {
+            Some(id) => id,
+            None => {
+                log::info!("{APPNAME} has no default org unit configured");
+                DEFAULT_ORG_ID.set(None).ok();
+                return Ok(());
+            }
+        };
+
+        let mut editor = Editor::new(&client);
+
+        if editor.retrieve("aou", org_id)?.is_none() {
+            return Err(format!("{APPNAME} default_org_unit {org_id} does not exist").into());
+        }
+
+        log::info!("{APPNAME} using default org unit {org_id}");
+
+        if DEFAULT_ORG_ID.set(Some(org_id)).is_err() {
+            return Err("Cannot set the default org unit more than once".into());
+        }
+
+        Ok(())
+    }
 }
 
 impl Application for RsActorApplication {
@@ -29,9 +86,9 @@ impl Application for RsActorApplication {
     }
 
     /// Load the IDL and perform any other needed global startup work.
-    fn init(&mut self, _client: Client) -> EgResult<()> {
+    fn init(&mut self, client: Client) -> EgResult<()> {
         eg::init::load_idl()?;
-        Ok(())
+        self.load_default_org(client)
     }
 
     /// Tell the Server what methods we want to publish.
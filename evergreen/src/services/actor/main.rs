@@ -1,10 +1,23 @@
 use eg::osrf::server::Server;
+use eg::util;
 use evergreen as eg;
+use std::process;
 pub mod app;
 pub mod methods;
 
+#[cfg(test)]
+mod tests;
+
 fn main() {
-    if let Err(e) = Server::start(Box::new(app::RsActorApplication::new())) {
+    let application = Box::new(app::RsActorApplication::new());
+
+    if util::self_test_requested() {
+        let test = Server::self_test(application);
+        println!("{}", test.report());
+        process::exit(test.exit_code());
+    }
+
+    if let Err(e) = Server::start(application) {
         log::error!("Exiting on server failure: {e}");
     } else {
         log::info!("Server exited normally");
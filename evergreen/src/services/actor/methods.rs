@@ -1,5 +1,5 @@
 use eg::common::penalty;
-use eg::common::settings::Settings;
+use eg::common::settings::{self, Settings};
 use eg::common::user;
 use eg::osrf::app::ApplicationWorker;
 use eg::osrf::message;
@@ -181,6 +181,29 @@ pub static METHODS: &[StaticMethodDef] = &[
             },
         ],
     },
+    StaticMethodDef {
+        name: "org_unit.settings.update",
+        desc: "Create, update, or delete org unit settings",
+        param_count: ParamCount::Exactly(3),
+        handler: update_org_settings,
+        params: &[
+            StaticParam {
+                name: "Authtoken",
+                datatype: ParamDataType::String,
+                desc: "",
+            },
+            StaticParam {
+                name: "Org Unit ID",
+                datatype: ParamDataType::Number,
+                desc: "",
+            },
+            StaticParam {
+                name: "Settings",
+                datatype: ParamDataType::Object,
+                desc: "Hash of setting names to values.  Null values delete the setting",
+            },
+        ],
+    },
 ];
 
 pub fn get_barcodes(
@@ -300,6 +323,26 @@ pub fn user_has_work_perm_at_batch(
     session.respond(map)
 }
 
+/// Org unit to use as the settings context, if any, beyond the org
+/// unit potentially linked to the requestor's workstation.
+///
+/// If the caller requests values for a specific org unit, that
+/// supersedes the workstation org unit.  With no org unit and no
+/// requestor, fall back to the configured default org unit.
+pub fn settings_context_org(
+    param_org: Option<i64>,
+    has_requestor: bool,
+    default_org: Option<i64>,
+) -> Option<i64> {
+    if param_org.is_some() {
+        param_org
+    } else if has_requestor {
+        None
+    } else {
+        default_org
+    }
+}
+
 pub fn retrieve_cascade_settigs(
     worker: &mut Box<dyn ApplicationWorker>,
     session: &mut ServerSession,
@@ -331,9 +374,13 @@ pub fn retrieve_cascade_settigs(
 
     let mut settings = Settings::new(&editor);
 
-    // If the caller requests values for a specific org unit, that
-    // supersedes the org unit potentially linked to the workstation.
-    if let Some(org_id) = method.param(2).as_i64() {
+    let context_org = settings_context_org(
+        method.param(2).as_i64(),
+        editor.requestor().is_some(),
+        app::default_org_id(),
+    );
+
+    if let Some(org_id) = context_org {
         settings.set_org_id(org_id);
     }
 
@@ -383,7 +430,10 @@ pub fn ou_setting_ancestor_default_batch(
         if !editor.apply_authtoken(token)? {
             return session.respond(editor.event());
         }
-        settings.set_user_id(editor.requestor_id()?);
+        // Note the requestor is not added to the settings context.
+        // This API returns org unit values (from the requested org
+        // or its nearest ancestor) and user/workstation settings
+        // of the same name would otherwise take precedence.
     }
 
     // Pre-cache the settings en masse, then pull each from the settings
@@ -523,3 +573,59 @@ pub fn update_penalties(
 
     session.respond(1)
 }
+
+pub fn update_org_settings(
+    worker: &mut Box<dyn ApplicationWorker>,
+    session: &mut ServerSession,
+    method: &message::MethodCall,
+) -> EgResult<()> {
+    let worker = app::RsActorWorker::downcast(worker)?;
+    let authtoken = method.param(0).str()?;
+    let org_id = method.param(1).int()?;
+    let values = method.param(2);
+
+    let mut editor = Editor::with_auth_xact(worker.client(), authtoken);
+
+    if !editor.checkauth()? {
+        return session.respond(editor.event());
+    }
+
+    for (name, value) in values.entries() {
+        if !can_update_org_setting(&mut editor, name, org_id)? {
+            editor.rollback()?;
+            return session.respond(editor.event());
+        }
+
+        settings::apply_org_setting(&mut editor, org_id, name, value)?;
+    }
+
+    editor.commit()?;
+
+    // Let long-lived Settings instances know values have changed.
+    settings::invalidate_cache()?;
+
+    session.respond(true)
+}
+
+/// True if the requestor may change the named setting at the org
+/// unit, either via UPDATE_ORG_UNIT_SETTING_ALL or the setting's
+/// own update permission.
+fn can_update_org_setting(editor: &mut Editor, name: &str, org_id: i64) -> EgResult<bool> {
+    if editor.allowed_at("UPDATE_ORG_UNIT_SETTING_ALL", org_id)? {
+        return Ok(true);
+    }
+
+    let stype = editor
+        .retrieve("coust", name)?
+        .ok_or_else(|| format!("No such setting: {name}"))?;
+
+    let perm = match stype["update_perm"].as_int() {
+        Some(id) => editor
+            .retrieve("ppl", id)?
+            .ok_or_else(|| format!("No such permission: {id}"))?["code"]
+            .string()?,
+        None => format!("UPDATE_ORG_UNIT_SETTING.{name}"),
+    };
+
+    editor.allowed_at(&perm, org_id)
+}
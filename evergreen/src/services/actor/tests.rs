@@ -0,0 +1,33 @@
+use crate::app;
+use crate::methods;
+use eg::EgValue;
+use evergreen as eg;
+
+#[test]
+fn parse_default_org() {
+    assert_eq!(app::parse_default_org(&EgValue::Null).unwrap(), None);
+    assert_eq!(app::parse_default_org(&EgValue::from(4)).unwrap(), Some(4));
+
+    // An org unit shortname is a startup error, not "no default".
+    assert!(app::parse_default_org(&EgValue::from("BR1")).is_err());
+}
+
+#[test]
+fn settings_context_org() {
+    // No session and no org unit uses the configured default.
+    assert_eq!(methods::settings_context_org(None, false, Some(4)), Some(4));
+    assert_eq!(methods::settings_context_org(None, false, None), None);
+
+    // A requested org unit wins over the default.
+    assert_eq!(
+        methods::settings_context_org(Some(2), false, Some(4)),
+        Some(2)
+    );
+    assert_eq!(
+        methods::settings_context_org(Some(2), true, Some(4)),
+        Some(2)
+    );
+
+    // With a session, the workstation org unit applies instead.
+    assert_eq!(methods::settings_context_org(None, true, Some(4)), None);
+}
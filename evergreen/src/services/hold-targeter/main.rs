@@ -1,10 +1,20 @@
 use eg::osrf::server::Server;
+use eg::util;
 use evergreen as eg;
+use std::process;
 pub mod app;
 pub mod methods;
 
 fn main() {
-    if let Err(e) = Server::start(Box::new(app::HoldTargeterApplication::new())) {
+    let application = Box::new(app::HoldTargeterApplication::new());
+
+    if util::self_test_requested() {
+        let test = Server::self_test(application);
+        println!("{}", test.report());
+        process::exit(test.exit_code());
+    }
+
+    if let Err(e) = Server::start(application) {
         log::error!("Exiting on server failure: {e}");
     } else {
         log::info!("Server exited normally");
@@ -38,6 +38,7 @@ pub fn target(
     let mut return_throttle = 1;
     let mut return_count = false;
     let mut find_copy = None;
+    let mut hold_id = None;
 
     // Apply user-supplied options if we have any.
     if let Some(options) = method.params().get(0) {
@@ -49,6 +50,9 @@ pub fn target(
         if let Ok(c) = options["find_copy"].int() {
             find_copy = Some(c);
         }
+        if let Ok(h) = options["hold"].int() {
+            hold_id = Some(h);
+        }
         if let Ok(c) = options["parallel_count"].int() {
             tgtr.set_parallel_count(c as u8);
         }
@@ -68,6 +72,12 @@ pub fn target(
 
     tgtr.init()?;
 
+    if let Some(id) = hold_id {
+        // Target a single hold, e.g. a staff retarget request.
+        let ctx = tgtr.target_hold(id, find_copy)?;
+        return session.respond(ctx.to_json());
+    }
+
     let list = tgtr.find_holds_to_target()?;
 
     let total = list.len();
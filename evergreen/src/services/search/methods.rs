@@ -73,18 +73,20 @@ pub fn catalog_record_summary(
 
     let mut editor = Editor::new(worker.client());
 
-    for rec_id in method.param(1).members() {
-        let rec_id = rec_id.int()?;
-        let summary = bib::catalog_record_summary(
+    // Summaries are sent as they are built instead of being
+    // collected first, since callers may request many records.
+    let summaries = method.param(1).members().map(|rec_id| {
+        bib::catalog_record_summary(
             &mut editor,
             org_id,
-            rec_id,
+            rec_id.int()?,
             is_staff,
             false, /* TODO is_meta */
-        )?;
+        )
+        .map(|summary| summary.into_value())
+    });
 
-        session.respond(summary.into_value())?;
-    }
+    session.respond_streaming(summaries)?;
 
     Ok(())
 }
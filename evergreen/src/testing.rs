@@ -0,0 +1,152 @@
+//! Test helpers for code which reads data via the Editor.
+//!
+//! The Editor, Bus, and OpenSRF sessions all require a live Redis
+//! connection, so they cannot be swapped out for in-memory versions
+//! without a transport abstraction.  MockEditor mirrors the read-only
+//! Editor API and serves canned responses instead, so lookup logic
+//! written against it can be exercised without any infrastructure.
+//!
+//! Unlike the Editor, whose reads go through its OpenSRF session and
+//! transaction state, MockEditor reads only require a shared borrow.
+//! Registering canned responses requires a mutable borrow.
+//!
+//! Both implement EditorRead, so functions which take an EditorRead,
+//! e.g. common::user::fines_summary(), run against either.
+use crate::editor::EditorRead;
+use crate::result::EgResult;
+use crate::EgValue;
+use std::cell::RefCell;
+
+/// Serves canned responses for retrieve, search, and json_query calls.
+///
+/// Searches and queries are matched on the query value provided.
+///
+/// ```
+/// use evergreen as eg;
+/// use eg::testing::MockEditor;
+///
+/// let mut editor = MockEditor::new();
+/// editor.add_retrieve("aou", 1, eg::hash! {"id": 1, "shortname": "CONS"});
+///
+/// let editor = &editor;
+/// let org = editor.retrieve("aou", 1).unwrap().unwrap();
+/// assert_eq!(org["shortname"].as_str(), Some("CONS"));
+/// assert!(editor.retrieve("aou", 2).unwrap().is_none());
+/// assert_eq!(editor.calls().len(), 2);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockEditor {
+    /// (idlclass, id, response)
+    retrieves: Vec<(String, EgValue, EgValue)>,
+    /// (idlclass, query, responses)
+    searches: Vec<(String, EgValue, Vec<EgValue>)>,
+    /// (query, responses)
+    queries: Vec<(EgValue, Vec<EgValue>)>,
+    /// Every call made, e.g. "retrieve aou 1", in order.
+    calls: RefCell<Vec<String>>,
+    /// Errors returned, in order, by upcoming reads.
+    read_failures: RefCell<Vec<String>>,
+}
+
+impl MockEditor {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Calls made against this editor, in the order they were made.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+
+    /// Respond to retrieve(idlclass, id) with the provided value.
+    pub fn add_retrieve(&mut self, idlclass: &str, id: impl Into<EgValue>, value: EgValue) {
+        self.retrieves
+            .push((idlclass.to_string(), id.into(), value));
+    }
+
+    /// Respond to search(idlclass, query) with the provided values.
+    pub fn add_search(&mut self, idlclass: &str, query: EgValue, values: Vec<EgValue>) {
+        self.searches.push((idlclass.to_string(), query, values));
+    }
+
+    /// Respond to json_query(query) with the provided values.
+    pub fn add_json_query(&mut self, query: EgValue, values: Vec<EgValue>) {
+        self.queries.push((query, values));
+    }
+
+    /// Fail the next read with the provided error message, e.g. to
+    /// simulate a connection reset.
+    pub fn fail_next_read(&mut self, msg: &str) {
+        self.read_failures.get_mut().push(msg.to_string());
+    }
+
+    fn maybe_fail(&self) -> EgResult<()> {
+        let mut failures = self.read_failures.borrow_mut();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures.remove(0).into())
+        }
+    }
+
+    pub fn retrieve(
+        &self,
+        idlclass: &str,
+        id: impl Into<EgValue>,
+    ) -> EgResult<Option<EgValue>> {
+        let id = id.into();
+        self.calls
+            .borrow_mut()
+            .push(format!("retrieve {idlclass} {}", id.dump()));
+        self.maybe_fail()?;
+
+        Ok(self
+            .retrieves
+            .iter()
+            .find(|(c, i, _)| c == idlclass && *i == id)
+            .map(|(_, _, v)| v.clone()))
+    }
+
+    /// Unmatched searches return no results.
+    pub fn search(&self, idlclass: &str, query: EgValue) -> EgResult<Vec<EgValue>> {
+        self.calls
+            .borrow_mut()
+            .push(format!("search {idlclass} {}", query.dump()));
+        self.maybe_fail()?;
+
+        Ok(self
+            .searches
+            .iter()
+            .find(|(c, q, _)| c == idlclass && *q == query)
+            .map(|(_, _, v)| v.clone())
+            .unwrap_or_default())
+    }
+
+    /// Unmatched queries are an error, since json_query results
+    /// are rarely empty in practice.
+    pub fn json_query(&self, query: EgValue) -> EgResult<Vec<EgValue>> {
+        let dump = query.dump();
+        self.calls.borrow_mut().push(format!("json_query {dump}"));
+        self.maybe_fail()?;
+
+        self.queries
+            .iter()
+            .find(|(q, _)| *q == query)
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| format!("No canned response for query: {dump}").into())
+    }
+}
+
+impl EditorRead for MockEditor {
+    fn retrieve(&mut self, idlclass: &str, id: EgValue) -> EgResult<Option<EgValue>> {
+        MockEditor::retrieve(self, idlclass, id)
+    }
+
+    fn search(&mut self, idlclass: &str, query: EgValue) -> EgResult<Vec<EgValue>> {
+        MockEditor::search(self, idlclass, query)
+    }
+
+    fn json_query(&mut self, query: EgValue) -> EgResult<Vec<EgValue>> {
+        MockEditor::json_query(self, query)
+    }
+}
@@ -1,7 +1,43 @@
+use crate as eg;
+use crate::common::billing;
+use crate::common::circ::{IntervalCache, OverduePreview};
+use crate::common::org::{ClosedRange, OrgClosings, OrgOpenState, OrgTree};
+use crate::common::penalty;
+use crate::common::transit;
+use crate::common::user;
+use crate::constants as C;
+use crate::date;
+use crate::editor;
+use crate::idl::{self, DataType, FleshBuilder};
+use crate::norm;
+use crate::osrf::breaker::{BreakerConfig, BreakerState, CircuitBreaker};
+use crate::osrf::bus;
 use crate::osrf::message::Message;
+use crate::osrf::message::MessageType;
+use crate::osrf::message::MethodCall;
 use crate::osrf::message::Payload;
 use crate::osrf::message::TransportMessage;
+use crate::osrf::method::{MethodDef, Param, ParamCount, ParamDataType};
+use crate::osrf::server::WorkerThread;
+use crate::osrf::worker;
+use crate::osrf::worker::DrainAction;
+use crate::osrf::worker::MethodWatchdog;
+use crate::osrf::worker::RecycleAction;
+use crate::osrf::worker::RecycleSchedule;
+use crate::osrf::worker::ShutdownDrain;
+use crate::osrf::worker::WorkerState;
+use crate::osrf::worker::WorkerStats;
+use crate::testing::MockEditor;
+use crate::util;
+use crate::util::SelfTest;
+use crate::EgResult;
+use crate::EgValue;
 use json;
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 const TRANSPORT_MSG_JSON: &str = r#"{
     "to":"my-to",
@@ -54,3 +90,1099 @@ fn parse_opensrf_message() {
     let msg = msg_op.unwrap();
     assert_eq!(msg.ingress(), "opensrf");
 }
+
+#[test]
+fn recycle_schedule() {
+    let sched = RecycleSchedule::parse("8-12, 13-18", 100).unwrap();
+
+    // Under the limit, always keep running.
+    assert!(sched.keep_running(3, 10, 500));
+
+    // Over the limit inside a peak window, keep running.
+    assert!(sched.keep_running(9, 500, 500));
+    assert!(sched.keep_running(17, 599, 500));
+
+    // Over the limit outside a peak window, recycle.
+    assert!(!sched.keep_running(12, 500, 500));
+    assert!(!sched.keep_running(20, 500, 500));
+
+    // Deferral is bounded.
+    assert!(!sched.keep_running(9, 600, 500));
+
+    // No windows, no deferral.
+    let sched = RecycleSchedule::default();
+    assert!(!sched.keep_running(9, 500, 500));
+}
+
+static RECYCLE_HOUR: AtomicU32 = AtomicU32::new(0);
+
+#[test]
+fn recycle_schedule_clock() {
+    let mut sched = RecycleSchedule::parse("8-12", 10).unwrap();
+    sched.set_clock(|| RECYCLE_HOUR.load(Ordering::Relaxed));
+
+    RECYCLE_HOUR.store(9, Ordering::Relaxed);
+    assert_eq!(sched.check(99, 100), RecycleAction::Continue);
+
+    // Deferral is only reported once.
+    assert_eq!(sched.check(100, 100), RecycleAction::Defer);
+    assert_eq!(sched.check(100, 100), RecycleAction::Continue);
+    assert_eq!(sched.check(105, 100), RecycleAction::Continue);
+
+    // The peak window ends.
+    RECYCLE_HOUR.store(12, Ordering::Relaxed);
+    assert_eq!(sched.check(105, 100), RecycleAction::Recycle);
+
+    // Deferral is bounded.
+    RECYCLE_HOUR.store(9, Ordering::Relaxed);
+    assert_eq!(sched.check(110, 100), RecycleAction::Recycle);
+}
+
+#[test]
+fn split_batch() {
+    let request = |trace| {
+        Message::new(
+            MessageType::Request,
+            trace,
+            Payload::Method(MethodCall::new("opensrf.system.echo", vec![])),
+        )
+    };
+    let traces = |msgs: &Vec<Message>| msgs.iter().map(|m| m.thread_trace()).collect::<Vec<_>>();
+
+    // Serial by default.
+    let mut body: Vec<Message> = (1..=4).map(request).collect();
+    assert!(worker::split_batch(&mut body, 0).is_empty());
+    assert!(worker::split_batch(&mut body, 1).is_empty());
+    assert_eq!(body.len(), 4);
+
+    // Bounded; extra requests stay with the batch in order.
+    let batch = worker::split_batch(&mut body, 3);
+    assert_eq!(traces(&batch), vec![2, 3]);
+    assert_eq!(traces(&body), vec![1, 4]);
+
+    // Connected conversations are never split.
+    let mut body = vec![
+        Message::new(MessageType::Connect, 1, Payload::NoPayload),
+        request(2),
+        request(3),
+    ];
+    assert!(worker::split_batch(&mut body, 10).is_empty());
+    assert_eq!(body.len(), 3);
+}
+
+#[test]
+fn overdue_preview() {
+    let due = date::parse_datetime("2024-03-01T12:00:00-0500").unwrap();
+    let day = 86400;
+
+    // Not yet due
+    let now = date::parse_datetime("2024-02-28T12:00:00-0500").unwrap();
+    let preview = OverduePreview::calculate(&due, &now, 0.10, day, 2.0, 0);
+    assert_eq!(preview, OverduePreview::default());
+
+    // 4 days overdue, under the max fine.
+    let now = date::parse_datetime("2024-03-05T12:00:00-0500").unwrap();
+    let preview = OverduePreview::calculate(&due, &now, 0.10, day, 2.0, 0);
+    assert_eq!(preview.days_overdue, 4);
+    assert_eq!(preview.fine, 0.40);
+
+    // 30 days overdue, capped at the max fine.
+    let now = date::parse_datetime("2024-03-31T12:00:00-0400").unwrap();
+    let preview = OverduePreview::calculate(&due, &now, 0.10, day, 2.0, 0);
+    assert_eq!(preview.days_overdue, 30);
+    assert_eq!(preview.fine, 2.0);
+
+    // Overdue, but still within a 3 day grace period.
+    let now = date::parse_datetime("2024-03-03T18:00:00-0500").unwrap();
+    let preview = OverduePreview::calculate(&due, &now, 0.10, day, 2.0, day * 3);
+    assert_eq!(preview.days_overdue, 3);
+    assert_eq!(preview.fine, 0.0);
+}
+
+#[test]
+fn hung_worker_detection() {
+    let max_active = Duration::from_secs(30);
+
+    // A worker that went Active a minute ago and never reported back.
+    let mut worker = WorkerThread {
+        state: WorkerState::Active,
+        join_handle: thread::spawn(|| {}),
+        state_time: Instant::now() - Duration::from_secs(60),
+    };
+
+    assert!(worker.is_hung(max_active));
+    assert!(!worker.is_hung(Duration::from_secs(120)));
+
+    // Idle workers are never considered hung.
+    worker.state = WorkerState::Idle;
+    assert!(!worker.is_hung(max_active));
+
+    // Recent progress resets the clock.
+    worker.state = WorkerState::Active;
+    worker.state_time = Instant::now();
+    assert!(!worker.is_hung(max_active));
+}
+
+#[test]
+fn org_closed_dates() {
+    let dt = |s: &str| date::parse_datetime(s).unwrap();
+
+    let closings = OrgClosings {
+        // Closed Sundays
+        closed_weekdays: vec![0],
+        ranges: vec![
+            // Multi-day closure Wed Dec 24 - Fri Dec 26, 2025
+            ClosedRange {
+                start: dt("2025-12-24T00:00:00-0500"),
+                end: dt("2025-12-26T23:59:59-0500"),
+                full_day: true,
+            },
+            // Closed Mon Dec 29 afternoon
+            ClosedRange {
+                start: dt("2025-12-29T12:00:00-0500"),
+                end: dt("2025-12-29T17:00:00-0500"),
+                full_day: false,
+            },
+        ],
+    };
+
+    // Tuesday before the closure
+    assert!(!closings.is_closed(&dt("2025-12-23T10:00:00-0500")));
+    assert_eq!(
+        closings.next_open_date(&dt("2025-12-23T10:00:00-0500")),
+        OrgOpenState::Open
+    );
+
+    // Every day of the multi-day closure is closed.
+    assert!(closings.is_closed(&dt("2025-12-24T10:00:00-0500")));
+    assert!(closings.is_closed(&dt("2025-12-25T23:00:00-0500")));
+    assert!(closings.is_closed(&dt("2025-12-26T08:00:00-0500")));
+
+    // Reopens Saturday
+    assert_eq!(
+        closings.next_open_date(&dt("2025-12-24T10:00:00-0500")),
+        OrgOpenState::OpensOnDate(dt("2025-12-27T10:00:00-0500"))
+    );
+
+    // Saturday is open, then skip Sunday
+    assert!(!closings.is_closed(&dt("2025-12-27T10:00:00-0500")));
+    assert_eq!(
+        closings.next_open_date(&dt("2025-12-28T10:00:00-0500")),
+        OrgOpenState::OpensOnDate(dt("2025-12-29T10:00:00-0500"))
+    );
+
+    // Partial-day closing only covers its own hours.
+    assert!(!closings.is_closed(&dt("2025-12-29T10:00:00-0500")));
+    assert!(closings.is_closed(&dt("2025-12-29T14:00:00-0500")));
+    assert_eq!(
+        closings.next_open_date(&dt("2025-12-29T14:00:00-0500")),
+        OrgOpenState::OpensOnDate(dt("2025-12-30T14:00:00-0500"))
+    );
+
+    let never = OrgClosings {
+        closed_weekdays: (0..7).collect(),
+        ranges: vec![],
+    };
+
+    assert_eq!(
+        never.next_open_date(&dt("2025-12-23T10:00:00-0500")),
+        OrgOpenState::Never
+    );
+}
+
+#[test]
+fn idl_coerce_value() {
+    let coerce = |dt, v: EgValue| idl::coerce_value(&dt, &v);
+
+    // Null passes through for every type.
+    assert!(coerce(DataType::Int, EgValue::Null).unwrap().is_null());
+    assert!(coerce(DataType::Timestamp, EgValue::Null)
+        .unwrap()
+        .is_null());
+
+    // Numbers, including lenient numeric strings.
+    assert_eq!(
+        coerce(DataType::Int, EgValue::from(7)).unwrap().as_i64(),
+        Some(7)
+    );
+    assert_eq!(
+        coerce(DataType::Id, EgValue::from("12")).unwrap().as_i64(),
+        Some(12)
+    );
+    assert_eq!(
+        coerce(DataType::OrgUnit, EgValue::from("4"))
+            .unwrap()
+            .as_i64(),
+        Some(4)
+    );
+    assert!(coerce(DataType::Int, EgValue::from("1.5")).is_err());
+    assert!(coerce(DataType::Int, EgValue::from("abc")).is_err());
+
+    assert_eq!(
+        coerce(DataType::Float, EgValue::from("1.25"))
+            .unwrap()
+            .as_f64(),
+        Some(1.25)
+    );
+    assert_eq!(
+        coerce(DataType::Money, EgValue::from(3)).unwrap().as_f64(),
+        Some(3.0)
+    );
+    assert!(coerce(DataType::Money, EgValue::from("$3")).is_err());
+
+    // Bools follow boolish() rules.
+    assert_eq!(
+        coerce(DataType::Bool, EgValue::from("t"))
+            .unwrap()
+            .as_bool(),
+        Some(true)
+    );
+    assert_eq!(
+        coerce(DataType::Bool, EgValue::from("f"))
+            .unwrap()
+            .as_bool(),
+        Some(false)
+    );
+    assert_eq!(
+        coerce(DataType::Bool, EgValue::from(0)).unwrap().as_bool(),
+        Some(false)
+    );
+    assert_eq!(
+        coerce(DataType::Bool, EgValue::from(true))
+            .unwrap()
+            .as_bool(),
+        Some(true)
+    );
+
+    // Text
+    assert_eq!(
+        coerce(DataType::Text, EgValue::from(5)).unwrap().as_str(),
+        Some("5")
+    );
+    assert!(coerce(DataType::Text, EgValue::from(false)).is_err());
+
+    // Timestamps are normalized to ISO format.
+    let ts = coerce(
+        DataType::Timestamp,
+        EgValue::from("2024-03-01T12:00:00-05:00"),
+    )
+    .unwrap();
+    assert_eq!(ts.as_str(), Some("2024-03-01T12:00:00-0500"));
+    assert!(coerce(DataType::Timestamp, EgValue::from("yesterday")).is_err());
+    assert!(coerce(DataType::Timestamp, EgValue::from(5)).is_err());
+
+    // Non-scalars are refused.
+    assert!(coerce(DataType::Text, EgValue::new_array()).is_err());
+}
+
+#[test]
+fn self_test() {
+    // Mock checks which build on one another like service startup.
+    let run = |bus_ok: bool| {
+        let mut test = SelfTest::new();
+
+        let bus = test.check("bus connect", || match bus_ok {
+            true => Ok("bus"),
+            false => Err("connection refused".into()),
+        });
+
+        if let Some(bus) = bus {
+            test.check("bus ping", || Ok(format!("{bus} pong")));
+        }
+
+        test
+    };
+
+    let test = run(true);
+    assert!(test.passed());
+    assert_eq!(test.exit_code(), 0);
+    assert_eq!(test.report(), "PASS bus connect\nPASS bus ping");
+
+    let test = run(false);
+    assert!(!test.passed());
+    assert_eq!(test.exit_code(), 1);
+    assert_eq!(test.report(), "FAIL bus connect: connection refused");
+}
+
+#[test]
+fn circuit_breaker() {
+    let service = "open-ils.circ";
+    let method = "open-ils.circ.checkout.full";
+
+    let mut breaker = CircuitBreaker::new(BreakerConfig {
+        failure_threshold: 3,
+        cooldown: Duration::from_millis(50),
+    });
+
+    assert_eq!(breaker.state(service, method), BreakerState::Closed);
+
+    // A success resets the consecutive failure count.
+    breaker.record_failure(service, method);
+    breaker.record_failure(service, method);
+    breaker.record_success(service, method);
+    breaker.record_failure(service, method);
+    assert!(breaker.allow(service, method));
+
+    breaker.record_failure(service, method);
+    breaker.record_failure(service, method);
+    assert_eq!(breaker.state(service, method), BreakerState::Open);
+    assert!(!breaker.allow(service, method));
+
+    // Breakers are per-method.
+    assert!(breaker.allow(service, "open-ils.circ.checkin"));
+
+    // Once the cooldown passes, a trial call is allowed.
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(breaker.state(service, method), BreakerState::HalfOpen);
+    assert!(breaker.allow(service, method));
+
+    // A failed trial trips the breaker again immediately.
+    breaker.record_failure(service, method);
+    assert_eq!(breaker.state(service, method), BreakerState::Open);
+
+    // A successful trial closes the breaker.
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(breaker.state(service, method), BreakerState::HalfOpen);
+    breaker.record_success(service, method);
+    assert_eq!(breaker.state(service, method), BreakerState::Closed);
+
+    breaker.record_failure(service, method);
+    assert!(breaker.allow(service, method));
+}
+
+#[test]
+fn payment_allocation() {
+    let balances = [(1, 2.50), (2, 1.10), (3, 4.00)];
+
+    // Exact payment of everything owed.
+    let (payments, remaining) = billing::allocate_payment(7.60, &balances);
+    assert_eq!(payments, vec![(1, 2.50), (2, 1.10), (3, 4.00)]);
+    assert_eq!(remaining, 0.0);
+
+    // Partial payment spread across multiple transactions.
+    let (payments, remaining) = billing::allocate_payment(3.00, &balances);
+    assert_eq!(payments, vec![(1, 2.50), (2, 0.50)]);
+    assert_eq!(remaining, 0.0);
+
+    // Overpayment leaves the excess unallocated.
+    let (payments, remaining) = billing::allocate_payment(10.00, &balances);
+    assert_eq!(payments.len(), 3);
+    assert_eq!(remaining, 2.40);
+
+    // Nothing owed.
+    let (payments, remaining) = billing::allocate_payment(1.00, &[(4, 0.0), (5, -1.0)]);
+    assert!(payments.is_empty());
+    assert_eq!(remaining, 1.00);
+
+    assert_eq!(billing::payment_class("cash_payment").unwrap(), "mcp");
+    assert!(billing::payment_class("bitcoin").is_err());
+}
+
+#[test]
+fn targeted_payments() {
+    let balances = [(1, 2.50), (2, 1.10), (3, 4.00)];
+
+    // Partial and full payments to specific transactions.
+    let results = billing::check_targeted_payments(&[(3, 1.25), (2, 1.10)], &balances).unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].xact_id, 3);
+    assert_eq!(results[0].amount, 1.25);
+    assert_eq!(results[0].balance_owed, 2.75);
+    assert_eq!(results[1].balance_owed, 0.0);
+
+    // Multiple payments to one transaction count against its balance.
+    let results = billing::check_targeted_payments(&[(1, 2.00), (1, 0.50)], &balances).unwrap();
+    assert_eq!(results[1].balance_owed, 0.0);
+    assert!(billing::check_targeted_payments(&[(1, 2.00), (1, 0.51)], &balances).is_err());
+
+    // Overpayments, unknown transactions, and non-positive amounts.
+    assert!(billing::check_targeted_payments(&[(2, 1.11)], &balances).is_err());
+    assert!(billing::check_targeted_payments(&[(9, 1.00)], &balances).is_err());
+    assert!(billing::check_targeted_payments(&[(1, 0.0)], &balances).is_err());
+    assert!(billing::check_targeted_payments(&[(1, -1.0)], &balances).is_err());
+
+    assert!(billing::check_targeted_payments(&[], &balances)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn mock_editor_user_summaries() {
+    let mut editor = MockEditor::new();
+
+    editor.add_retrieve("ocirccount", 10, eg::hash! {"out": 2, "overdue": 1});
+    editor.add_search(
+        "mous",
+        eg::hash! {"usr": 10},
+        vec![eg::hash! {"usr": 10, "balance_owed": "1.25"}],
+    );
+
+    let counts = user::open_checkout_counts(&mut editor, 10).unwrap();
+    assert_eq!(counts["total_out"].as_int(), Some(3));
+
+    let fines = user::fines_summary(&mut editor, 10).unwrap();
+    assert_eq!(fines["balance_owed"].as_str(), Some("1.25"));
+
+    // Users with no circs or fines rows get zeroed summaries.
+    let counts = user::open_checkout_counts(&mut editor, 11).unwrap();
+    assert_eq!(counts["total_count"].as_int(), Some(0));
+
+    let fines = user::fines_summary(&mut editor, 11).unwrap();
+    assert_eq!(fines["balance_owed"].as_int(), Some(0));
+
+    assert_eq!(
+        editor.calls(),
+        vec![
+            "retrieve ocirccount 10",
+            r#"search mous {"usr":10}"#,
+            "retrieve ocirccount 11",
+            r#"search mous {"usr":11}"#,
+        ]
+    );
+
+    // Hold counts are a json_query, which needs a canned response.
+    assert!(user::active_hold_counts(&mut editor, 10).is_err());
+
+    let query = eg::hash! {
+        select: {ahr: ["pickup_lib", "current_shelf_lib", "behind_desk"]},
+        from: "ahr",
+        where: {
+            usr: 10,
+            fulfillment_time: EgValue::Null,
+            cancel_time: EgValue::Null,
+        }
+    };
+
+    editor.add_json_query(
+        query,
+        vec![
+            eg::hash! {"pickup_lib": 4, "current_shelf_lib": 4},
+            eg::hash! {"pickup_lib": 4, "current_shelf_lib": EgValue::Null},
+        ],
+    );
+
+    let holds = user::active_hold_counts(&mut editor, 10).unwrap();
+    assert_eq!(holds["total"].as_int(), Some(2));
+    assert_eq!(holds["ready"].as_int(), Some(1));
+}
+
+#[test]
+fn barcode_normalization() {
+    // "é" as a single code point vs. "e" plus a combining accent.
+    let composed = "LIB\u{00E9}0001";
+    let decomposed = "LIBe\u{0301}0001";
+
+    assert_ne!(composed, decomposed);
+    assert_eq!(
+        norm::normalize_barcode(composed),
+        norm::normalize_barcode(decomposed)
+    );
+
+    // Embedded zero-width space and byte order mark.
+    assert_eq!(norm::normalize_barcode("1234\u{200B}5678"), "12345678");
+    assert_eq!(norm::normalize_barcode("\u{FEFF}12345678"), "12345678");
+
+    // Control characters and surrounding whitespace.
+    assert_eq!(norm::normalize_barcode(" 12345678\r\n"), "12345678");
+
+    // Plain ASCII is unchanged.
+    assert_eq!(norm::normalize_barcode("12345678"), "12345678");
+}
+
+#[test]
+fn batched_overdue_previews() {
+    let now = date::parse_datetime("2024-03-10T12:00:00-0500").unwrap();
+
+    let circs = vec![
+        eg::hash! {
+            "due_date": "2024-03-01T23:59:59-0500",
+            "recurring_fine": 0.25,
+            "fine_interval": "1 day",
+            "max_fine": 2.00,
+        },
+        eg::hash! {
+            "due_date": "2024-03-09T23:59:59-0500",
+            "recurring_fine": 0.10,
+            "fine_interval": "1 hour",
+            "max_fine": 5.00,
+        },
+        eg::hash! {
+            "due_date": "2024-03-05T23:59:59-0500",
+            "recurring_fine": 0.25,
+            "fine_interval": "1 day",
+            "max_fine": 2.00,
+            "stop_fines": "CLAIMSRETURNED",
+        },
+        eg::hash! {
+            "due_date": "2024-03-08T23:59:59-0500",
+            "recurring_fine": 0.25,
+            "fine_interval": "1 day",
+            "max_fine": 2.00,
+        },
+    ];
+
+    // Per-item previews, each with its own cache.
+    let singles: Vec<OverduePreview> = circs
+        .iter()
+        .map(|c| OverduePreview::from_circ(c, &now, 0, &mut IntervalCache::new()).unwrap())
+        .collect();
+
+    // One pass sharing a cache.
+    let mut intervals = IntervalCache::new();
+    let batched: Vec<OverduePreview> = circs
+        .iter()
+        .map(|c| OverduePreview::from_circ(c, &now, 0, &mut intervals).unwrap())
+        .collect();
+
+    assert_eq!(singles, batched);
+    assert_eq!(batched[0].fine, 2.00);
+    assert_eq!(batched[2], OverduePreview::default());
+
+    // Only the two distinct fine intervals were parsed.  The stopped
+    // circ never needed its interval.
+    assert_eq!(intervals.parsed(), 2);
+}
+
+#[test]
+fn penalty_threshold_selection() {
+    // Group tree: Users(2) -> Patrons(3) -> Students(4)
+    // Org tree: CONS(1) -> SYS1(2) -> BR1(4)
+    let grp_ancestors = [4, 3, 2];
+    let org_ancestors = [4, 2, 1];
+
+    let pgpt = |grp: i64, org_unit: i64, threshold: f64| {
+        eg::hash! {
+            "grp": grp,
+            "org_unit": org_unit,
+            "penalty": penalty::PATRON_EXCEEDS_FINES,
+            "threshold": threshold,
+        }
+    };
+
+    let thresholds = vec![pgpt(2, 1, 10.0), pgpt(3, 1, 5.0), pgpt(3, 4, 7.5)];
+
+    // Closest group wins, then closest org unit.
+    assert_eq!(
+        penalty::closest_threshold(&thresholds, &grp_ancestors, &org_ancestors).unwrap(),
+        Some(7.5)
+    );
+
+    // A consortium-wide value for a nearer group beats a branch-level
+    // value for a more distant group.
+    let thresholds = vec![pgpt(2, 4, 10.0), pgpt(4, 1, 3.0)];
+    assert_eq!(
+        penalty::closest_threshold(&thresholds, &grp_ancestors, &org_ancestors).unwrap(),
+        Some(3.0)
+    );
+
+    // Thresholds outside the patron's groups or orgs do not apply.
+    let thresholds = vec![pgpt(5, 1, 10.0), pgpt(4, 3, 2.0)];
+    assert_eq!(
+        penalty::closest_threshold(&thresholds, &grp_ancestors, &org_ancestors).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn method_param_validation() {
+    let mut method = MethodDef::new("opensrf.test", ParamCount::Range(1, 2), |_, _, _| Ok(()));
+
+    // No param definitions, nothing to check.
+    assert!(method.validate_params(&[EgValue::from("abc")]).is_ok());
+
+    method.add_param(Param {
+        name: "user_id".to_string(),
+        datatype: ParamDataType::Number,
+        desc: None,
+    });
+    method.add_param(Param {
+        name: "options".to_string(),
+        datatype: ParamDataType::Object,
+        desc: None,
+    });
+
+    // Methods do not check param types unless they opt in.
+    assert!(!method.strict_params());
+    assert!(method.check_params(&[EgValue::from("abc")]).is_ok());
+
+    method.set_strict_params(true);
+    assert!(method.check_params(&[EgValue::from("abc")]).is_err());
+    assert!(method.validate_params(&[EgValue::from(1)]).is_ok());
+    assert!(method
+        .validate_params(&[EgValue::from(1), eg::hash! {"a": 1}])
+        .is_ok());
+
+    // NULL placeholders are fine for optional params only.
+    assert!(method
+        .validate_params(&[EgValue::from(1), EgValue::Null])
+        .is_ok());
+
+    let err = method
+        .validate_params(&[EgValue::Null, eg::hash! {"a": 1}])
+        .unwrap_err();
+    assert!(err.contains("parameter 0 'user_id'"));
+
+    let err = method
+        .validate_params(&[EgValue::from(1), EgValue::from("abc")])
+        .unwrap_err();
+    assert!(err.contains("parameter 1 'options'"));
+    assert!(err.contains("wanted=Object"));
+
+    method.set_strict_params(false);
+    assert!(!method.strict_params());
+    assert!(method
+        .check_params(&[EgValue::from(1), EgValue::from("abc")])
+        .is_ok());
+}
+
+#[test]
+fn method_introspection() {
+    let mut method = MethodDef::new("opensrf.test", ParamCount::Range(1, 2), |_, _, _| Ok(()));
+    method.set_desc("Test method");
+    method.add_param(Param {
+        name: "user_id".to_string(),
+        datatype: ParamDataType::Number,
+        desc: Some("User ID".to_string()),
+    });
+
+    // Same keys as Perl OpenSRF method introspection.
+    let spec = method.to_eg_value();
+    assert_eq!(spec["api_name"].as_str(), Some("opensrf.test"));
+    assert_eq!(spec["argc"].int().unwrap(), 1);
+    assert_eq!(spec["param_count"].as_str(), Some("Range 1..2"));
+    assert_eq!(spec["desc"].as_str(), Some("Test method"));
+    assert_eq!(spec["stream"].as_bool(), Some(true));
+    assert_eq!(spec["params"][0]["name"].as_str(), Some("user_id"));
+    assert_eq!(spec["params"][0]["datatype"].as_str(), Some("Number"));
+    assert_eq!(spec["params"][0]["desc"].as_str(), Some("User ID"));
+}
+
+#[test]
+fn method_watchdog() {
+    let watchdog = MethodWatchdog::start("opensrf.test", Duration::from_secs(60));
+    assert!(!watchdog.finish());
+
+    let watchdog = MethodWatchdog::start("opensrf.test", Duration::from_millis(10));
+    thread::sleep(Duration::from_millis(30));
+    assert!(watchdog.finish());
+
+    let mut method = MethodDef::new("opensrf.test", ParamCount::Zero, |_, _, _| Ok(()));
+    assert_eq!(method.max_duration(), None);
+
+    method.set_max_duration(Duration::from_secs(5));
+    assert_eq!(method.max_duration(), Some(Duration::from_secs(5)));
+}
+
+#[test]
+fn worker_stats() {
+    let stats = WorkerStats::new(7);
+
+    stats.record(Duration::from_millis(250), true);
+    stats.record(Duration::from_millis(500), false);
+    stats.record(Duration::from_millis(250), true);
+
+    assert_eq!(stats.requests(), 3);
+    assert_eq!(stats.errors(), 1);
+    assert_eq!(stats.busy_time(), Duration::from_secs(1));
+
+    let value = stats.to_eg_value();
+    assert_eq!(value["worker_id"].int().unwrap(), 7);
+    assert_eq!(value["requests"].int().unwrap(), 3);
+    assert_eq!(value["errors"].int().unwrap(), 1);
+    assert_eq!(value["busy_time"].float().unwrap(), 1.0);
+}
+
+#[test]
+fn shutdown_drain() {
+    let grace = Duration::from_millis(50);
+
+    // Stop signal arrives mid-conversation.
+    let mut drain = ShutdownDrain::new(grace);
+    assert_eq!(drain.check(false, true), DrainAction::Continue);
+    assert_eq!(drain.check(true, true), DrainAction::Continue);
+    assert!(drain.draining());
+
+    // The conversation finishes within the grace period.
+    assert_eq!(drain.check(true, false), DrainAction::Exit);
+
+    // The conversation runs past the grace period.
+    let mut drain = ShutdownDrain::new(grace);
+    assert_eq!(drain.check(true, true), DrainAction::Continue);
+    thread::sleep(grace);
+    assert_eq!(drain.check(true, true), DrainAction::Expired);
+}
+
+#[test]
+fn bus_compression() {
+    let small = TRANSPORT_MSG_JSON.to_string();
+    let large = format!("[{}]", vec![TRANSPORT_MSG_JSON; 50].join(","));
+    let threshold = Some(small.len() + 1);
+
+    // Below the threshold, messages are sent as-is.
+    let chunk = bus::encode_chunk(small.clone(), threshold).unwrap();
+    assert_eq!(chunk, small.as_bytes());
+    assert_eq!(bus::decode_chunk(chunk).unwrap(), small);
+
+    // Above it, they are compressed.
+    let chunk = bus::encode_chunk(large.clone(), threshold).unwrap();
+    assert!(chunk.len() < large.len());
+    assert_ne!(chunk, large.as_bytes());
+    assert_eq!(bus::decode_chunk(chunk).unwrap(), large);
+
+    // Compression disabled.
+    let chunk = bus::encode_chunk(large.clone(), None).unwrap();
+    assert_eq!(chunk, large.as_bytes());
+
+    assert!(bus::decode_chunk(vec![0x1f, 0x8b, 0x00]).is_err());
+    assert!(bus::decode_chunk(vec![0xff, 0xfe]).is_err());
+}
+
+#[test]
+fn mock_editor_borrows() {
+    let mut editor = MockEditor::new();
+
+    // Registering responses requires a mutable borrow.
+    editor.add_retrieve("aou", 1, eg::hash! {"id": 1, "shortname": "CONS"});
+    editor.fail_next_read("Connection reset by peer");
+
+    // Reads work through any number of shared borrows.
+    let first = &editor;
+    let second = &editor;
+
+    assert!(first.retrieve("aou", 1).is_err());
+
+    let org = second.retrieve("aou", 1).unwrap().unwrap();
+    assert_eq!(org["shortname"].as_str(), Some("CONS"));
+    assert!(first.search("aou", eg::hash! {"id": 2}).unwrap().is_empty());
+
+    assert_eq!(
+        editor.calls(),
+        vec![
+            "retrieve aou 1".to_string(),
+            "retrieve aou 1".to_string(),
+            r#"search aou {"id":2}"#.to_string(),
+        ]
+    );
+}
+
+#[test]
+fn editor_read_retry() {
+    let mut editor = MockEditor::new();
+    editor.add_retrieve("au", 10, eg::hash! {"id": 10, "usrname": "jdoe"});
+
+    let mut reconnects = 0;
+
+    // Connection reset once, then the read succeeds on a new connection.
+    editor.fail_next_read("Redis blpop error: Connection reset by peer (os error 104)");
+
+    let user = editor::retry_read_once(
+        &mut editor,
+        |e| e.retrieve("au", 10),
+        |_| {
+            reconnects += 1;
+            Ok(())
+        },
+    )
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(user["usrname"].as_str(), Some("jdoe"));
+    assert_eq!(reconnects, 1);
+    assert_eq!(editor.calls().len(), 2);
+
+    // Only one retry is attempted.
+    editor.fail_next_read("Broken pipe (os error 32)");
+    editor.fail_next_read("Broken pipe (os error 32)");
+
+    let result = editor::retry_read_once(
+        &mut editor,
+        |e| e.retrieve("au", 10),
+        |_| {
+            reconnects += 1;
+            Ok(())
+        },
+    );
+
+    assert!(result.is_err());
+    assert_eq!(reconnects, 2);
+
+    // Other errors are not retried.
+    editor.fail_next_read("Unexpected response to method");
+
+    let result = editor::retry_read_once(
+        &mut editor,
+        |e| e.search("au", eg::hash! {"id": 10}),
+        |_| {
+            reconnects += 1;
+            Ok(())
+        },
+    );
+
+    assert!(result.is_err());
+    assert_eq!(reconnects, 2);
+    assert_eq!(editor.calls().len(), 5);
+}
+
+#[test]
+fn rate_limiter() {
+    let window = Duration::from_millis(200);
+    let mut limiter = util::RateLimiter::new(2, window);
+
+    assert!(limiter.try_acquire());
+    assert!(limiter.try_acquire());
+
+    // Excess operations are refused and not counted.
+    assert!(!limiter.try_acquire());
+    assert!(!limiter.try_acquire());
+
+    let wait = limiter.wait_time();
+    assert!(wait > Duration::ZERO && wait <= window);
+
+    thread::sleep(wait);
+
+    assert_eq!(limiter.wait_time(), Duration::ZERO);
+    assert!(limiter.try_acquire());
+
+    let mut never = util::RateLimiter::new(0, window);
+    assert!(!never.try_acquire());
+    assert_eq!(never.wait_time(), window);
+}
+
+/// Small IDL for parser tests, with optional extra class definitions.
+fn fixture_idl(extra: &str) -> String {
+    format!(
+        r#"<IDL xmlns="http://opensrf.org/spec/IDL/base/v1"
+  xmlns:oils_persist="http://open-ils.org/spec/opensrf/IDL/persistence/v1"
+  xmlns:oils_obj="http://open-ils.org/spec/opensrf/IDL/objects/v1"
+  xmlns:reporter="http://open-ils.org/spec/opensrf/IDL/reporter/v1">
+  <class id="aou" oils_obj:fieldmapper="actor::org_unit"
+    oils_persist:tablename="actor.org_unit" reporter:label="Org Unit">
+    <fields oils_persist:primary="id">
+      <field name="id" reporter:datatype="id"/>
+      <field name="parent_ou" reporter:datatype="link"/>
+      <field name="ou_type" reporter:datatype="link"/>
+      <field name="shortname" reporter:datatype="text" reporter:selector="shortname"/>
+      <field name="children" oils_persist:virtual="true" reporter:datatype="link"/>
+    </fields>
+    <links>
+      <link field="parent_ou" reltype="has_a" key="id" map="" class="aou"/>
+      <link field="ou_type" reltype="has_a" key="id" map="" class="aout"/>
+      <link field="children" reltype="has_many" key="parent_ou" map="" class="aou"/>
+    </links>
+  </class>
+  <class id="aout" oils_obj:fieldmapper="actor::org_unit_type"
+    oils_persist:tablename="actor.org_unit_type" reporter:label="Org Unit Type">
+    <fields oils_persist:primary="id">
+      <field name="id" reporter:datatype="id"/>
+      <field name="name" reporter:datatype="text"/>
+      <field name="can_have_users" reporter:datatype="bool"/>
+    </fields>
+  </class>
+  {extra}
+</IDL>"#
+    )
+}
+
+#[test]
+fn idl_parse_cache() {
+    let dir = env::temp_dir();
+    let pid = std::process::id();
+    let idl_file = dir.join(format!("eg-test-idl-{pid}.xml"));
+    let cache_file = dir.join(format!("eg-test-idl-{pid}.cache"));
+    let idl_path = idl_file.to_str().unwrap();
+    let cache_path = cache_file.to_str().unwrap();
+
+    fs::write(&idl_file, fixture_idl("")).unwrap();
+    fs::remove_file(&cache_file).ok();
+
+    // No cache yet.  The XML is parsed and the cache is written.
+    let parsed = idl::Parser::parse_file_cached(idl_path, cache_path).unwrap();
+    assert!(cache_file.exists());
+
+    let cached = idl::Parser::parse_file_cached(idl_path, cache_path).unwrap();
+    assert_eq!(parsed.classes()["aou"], cached.classes()["aou"]);
+
+    let aou = &cached.classes()["aou"];
+    assert_eq!(aou.pkey(), Some("id"));
+    assert_eq!(aou.selector(), Some("shortname"));
+    assert_eq!(aou.fields()["isdeleted"].array_pos(), 7);
+    assert_eq!(aou.links()["parent_ou"].class(), "aou");
+
+    // A fresh cache is used as-is.
+    let content = fs::read_to_string(&cache_file).unwrap();
+    fs::write(&cache_file, content.replace("Org Unit", "Cached Org Unit")).unwrap();
+    let cached = idl::Parser::parse_file_cached(idl_path, cache_path).unwrap();
+    assert_eq!(cached.classes()["aou"].label(), "Cached Org Unit");
+
+    // Changing the IDL file makes the cache stale.
+    fs::write(
+        &idl_file,
+        fixture_idl(r#"<class id="au"><fields><field name="id"/></fields></class>"#),
+    )
+    .unwrap();
+    let reparsed = idl::Parser::parse_file_cached(idl_path, cache_path).unwrap();
+    assert_eq!(reparsed.classes()["aou"].label(), "Org Unit");
+    assert!(reparsed.classes().contains_key("au"));
+
+    // An unreadable cache falls back to parsing the XML.
+    fs::write(&cache_file, "not json").unwrap();
+    let reparsed = idl::Parser::parse_file_cached(idl_path, cache_path).unwrap();
+    assert!(reparsed.classes().contains_key("au"));
+
+    fs::remove_file(&idl_file).ok();
+    fs::remove_file(&cache_file).ok();
+}
+
+#[test]
+fn idl_field_lookup() {
+    let parser = idl::Parser::parse_string(&fixture_idl("")).unwrap();
+
+    assert_eq!(parser.class("aou").unwrap().label(), "Org Unit");
+    assert!(parser.class("xyz").is_none());
+
+    let field = parser.field("aou", "shortname").unwrap();
+    assert_eq!(field.datatype(), &DataType::Text);
+    assert!(!field.is_virtual());
+
+    let field = parser.field("aou", "children").unwrap();
+    assert_eq!(field.datatype(), &DataType::Link);
+    assert!(field.is_virtual());
+
+    assert!(parser.field("aou", "xyz").is_none());
+    assert!(parser.field("xyz", "id").is_none());
+
+    let link = parser.field_link("aou", "children").unwrap();
+    assert_eq!(link.reltype(), idl::RelType::HasMany);
+    assert_eq!(link.key(), "parent_ou");
+    assert!(link.map().is_none());
+
+    assert_eq!(
+        parser.linked_class("aou", "ou_type").unwrap().classname(),
+        "aout"
+    );
+    assert_eq!(
+        parser.linked_class("aou", "parent_ou").unwrap().classname(),
+        "aou"
+    );
+
+    // Fields without links.
+    assert!(parser.field_link("aou", "shortname").is_none());
+    assert!(parser.linked_class("aout", "name").is_none());
+    assert!(parser.linked_class("xyz", "id").is_none());
+}
+
+#[test]
+fn flesh_builder() {
+    let parser = idl::Parser::parse_string(&fixture_idl("")).unwrap();
+
+    let flesh = FleshBuilder::with_parser(&parser)
+        .depth(2)
+        .flesh("aou", &["ou_type", "parent_ou"])
+        .flesh("aou", &["parent_ou", "children"])
+        .build()
+        .unwrap();
+
+    assert_eq!(flesh["flesh"].as_usize(), Some(2));
+    assert_eq!(flesh["flesh_fields"]["aou"].len(), 3);
+    assert!(flesh["flesh_fields"]["aou"].contains("children"));
+
+    let flesh = FleshBuilder::with_parser(&parser).build().unwrap();
+    assert_eq!(flesh["flesh"].as_usize(), Some(1));
+    assert!(flesh["flesh_fields"].is_object());
+
+    // Typos and non-link fields are caught.
+    let err = FleshBuilder::with_parser(&parser)
+        .flesh("aou", &["ou_typ"])
+        .flesh("aout", &["name"])
+        .build()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Class 'aou' cannot flesh 'ou_typ'");
+
+    assert!(FleshBuilder::with_parser(&parser)
+        .flesh("aout", &["name"])
+        .build()
+        .is_err());
+
+    assert!(FleshBuilder::with_parser(&parser)
+        .flesh("xyz", &["id"])
+        .build()
+        .is_err());
+}
+
+#[test]
+fn transit_abort_checks() {
+    let transit = eg::hash! {
+        "id": 1,
+        "target_copy": 10,
+        "copy_status": C::COPY_STATUS_AVAILABLE,
+        "dest_recv_time": EgValue::Null,
+        "cancel_time": EgValue::Null,
+    };
+
+    // Hold transits carry the hold alongside the base transit fields.
+    let mut hold_transit = transit.clone();
+    hold_transit["hold"] = EgValue::from(5);
+    hold_transit["copy_status"] = EgValue::from(C::COPY_STATUS_ON_HOLDS_SHELF);
+
+    for t in [&transit, &hold_transit] {
+        assert!(transit::transit_is_open(t));
+
+        let mut received = t.clone();
+        received["dest_recv_time"] = EgValue::from("2025-01-02T10:00:00-0500");
+        assert!(!transit::transit_is_open(&received));
+
+        let mut canceled = t.clone();
+        canceled["cancel_time"] = EgValue::from("2025-01-02T10:00:00-0500");
+        assert!(!transit::transit_is_open(&canceled));
+
+        assert_eq!(
+            transit::aborted_copy_status(t["copy_status"].int().unwrap()),
+            C::COPY_STATUS_CANCELED_TRANSIT
+        );
+    }
+
+    // Statuses staff would want kept survive the abort.
+    for status in [
+        C::COPY_STATUS_LOST,
+        C::COPY_STATUS_MISSING,
+        C::COPY_STATUS_DAMAGED,
+    ] {
+        assert_eq!(transit::aborted_copy_status(status), status);
+    }
+}
+
+#[test]
+fn org_tree() {
+    //       1
+    //     /   \
+    //    2     3
+    //   / \     \
+    //  4   5     6
+    let orgs: Vec<EgValue> = [
+        (1, None),
+        (2, Some(1)),
+        (3, Some(1)),
+        (5, Some(2)),
+        (4, Some(2)),
+        (6, Some(3)),
+    ]
+    .into_iter()
+    .map(|(id, parent): (i64, Option<i64>)| eg::hash! {"id": id, "parent_ou": parent})
+    .collect();
+
+    let tree = OrgTree::from_org_units(&orgs).unwrap();
+
+    assert_eq!(tree.ancestors(1), vec![1]);
+    assert_eq!(tree.ancestors(5), vec![1, 2, 5]);
+    assert_eq!(tree.descendants(1), vec![1, 2, 3, 4, 5, 6]);
+    assert_eq!(tree.descendants(3), vec![3, 6]);
+    assert_eq!(tree.descendants(4), vec![4]);
+    assert_eq!(tree.full_path(2), vec![1, 2, 4, 5]);
+    assert_eq!(tree.full_path(6), vec![1, 3, 6]);
+
+    // Unknown org units.
+    assert!(!tree.contains(9));
+    assert!(tree.ancestors(9).is_empty());
+    assert!(tree.descendants(9).is_empty());
+    assert!(tree.full_path(9).is_empty());
+}
@@ -3,7 +3,7 @@ use crate::EgValue;
 use json::JsonValue;
 use rand::Rng;
 use socket2::{Domain, Socket, Type};
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::fs;
 use std::net::{SocketAddr, TcpListener};
 use std::path::Path;
@@ -96,6 +96,109 @@ pub fn json_usize(value: &JsonValue) -> Option<usize> {
     None
 }
 
+/// Converts a JSON string or number to an owned String.
+///
+/// Err for null, boolean, array, and object values.
+///
+/// ```
+/// use evergreen::util;
+/// use json;
+/// let v = json::from(12);
+/// assert_eq!(util::json_string(&v).unwrap(), "12");
+/// let v = json::from("hello");
+/// assert_eq!(util::json_string(&v).unwrap(), "hello");
+/// let v = json::JsonValue::Null;
+/// assert!(util::json_string(&v).is_err());
+/// ```
+pub fn json_string(value: &JsonValue) -> Result<String, String> {
+    if let Some(s) = value.as_str() {
+        Ok(s.to_string())
+    } else if value.is_number() {
+        Ok(value.dump())
+    } else {
+        Err(format!("Cannot coerce to string: {}", value.dump()))
+    }
+}
+
+/// Converts a JSON string or number to an owned String.
+///
+/// None for null or any other value that cannot be stringified.
+///
+/// ```
+/// use evergreen::util;
+/// use json;
+/// let v = json::from(1.5);
+/// assert_eq!(util::json_string_opt(&v).as_deref(), Some("1.5"));
+/// let v = json::from("hello");
+/// assert_eq!(util::json_string_opt(&v).as_deref(), Some("hello"));
+/// let v = json::JsonValue::Null;
+/// assert_eq!(util::json_string_opt(&v), None);
+/// ```
+pub fn json_string_opt(value: &JsonValue) -> Option<String> {
+    json_string(value).ok()
+}
+
+/// Members of a JSON array, or a scalar value as a one-element list.
+fn json_list_members(value: &JsonValue) -> Vec<&JsonValue> {
+    if value.is_array() {
+        value.members().collect()
+    } else {
+        vec![value]
+    }
+}
+
+/// Converts a JSON array of numbers or numeric strings to a Vec<i64>.
+///
+/// A scalar value is treated as a one-element list.  Err if any
+/// element cannot be converted, reporting the offending index.
+///
+/// ```
+/// use evergreen::util;
+/// use json;
+/// let v = json::array![1, "2", 3.0];
+/// assert_eq!(util::json_int_vec(&v).unwrap(), vec![1, 2, 3]);
+/// let v = json::from(7);
+/// assert_eq!(util::json_int_vec(&v).unwrap(), vec![7]);
+/// let v = json::array![1, "two"];
+/// assert!(util::json_int_vec(&v).unwrap_err().contains("index 1"));
+/// ```
+#[allow(deprecated)]
+pub fn json_int_vec(value: &JsonValue) -> Result<Vec<i64>, String> {
+    json_list_members(value)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, v)| {
+            json_int(&EgValue::from_json_value_plain(v.clone()))
+                .map_err(|e| format!("Invalid int value at index {idx}: {e}"))
+        })
+        .collect()
+}
+
+/// Converts a JSON array of strings or numbers to a Vec<String>.
+///
+/// A scalar value is treated as a one-element list.  Err if any
+/// element cannot be converted, reporting the offending index.
+///
+/// ```
+/// use evergreen::util;
+/// use json;
+/// let v = json::array!["a", 2];
+/// assert_eq!(util::json_string_vec(&v).unwrap(), vec!["a", "2"]);
+/// let v = json::from("solo");
+/// assert_eq!(util::json_string_vec(&v).unwrap(), vec!["solo"]);
+/// let v = json::array!["a", null];
+/// assert!(util::json_string_vec(&v).unwrap_err().contains("index 1"));
+/// ```
+pub fn json_string_vec(value: &JsonValue) -> Result<Vec<String>, String> {
+    json_list_members(value)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, v)| {
+            json_string(v).map_err(|e| format!("Invalid string value at index {idx}: {e}"))
+        })
+        .collect()
+}
+
 /// Simple seconds-based countdown timer.
 /// ```
 /// use evergreen::util;
@@ -142,6 +245,83 @@ impl Timer {
     }
 }
 
+/// Limits the number of operations allowed within a sliding time window.
+///
+/// ```
+/// use evergreen::util::RateLimiter;
+/// use std::time::Duration;
+///
+/// let mut limiter = RateLimiter::new(2, Duration::from_secs(60));
+/// assert!(limiter.try_acquire());
+/// assert!(limiter.try_acquire());
+/// assert!(!limiter.try_acquire());
+/// assert!(limiter.wait_time() > Duration::ZERO);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    /// Start times of the operations within the current window.
+    events: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: usize, window: Duration) -> Self {
+        RateLimiter {
+            limit,
+            window,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn prune(&mut self) {
+        while let Some(t) = self.events.front() {
+            if t.elapsed() >= self.window {
+                self.events.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Record an operation and return true if it's within the limit.
+    ///
+    /// Operations which exceed the limit are not recorded.
+    pub fn try_acquire(&mut self) -> bool {
+        self.prune();
+
+        if self.events.len() < self.limit {
+            self.events.push_back(Instant::now());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long until another operation will be allowed.
+    pub fn wait_time(&mut self) -> Duration {
+        self.prune();
+
+        if self.events.len() < self.limit {
+            return Duration::ZERO;
+        }
+
+        match self.events.front() {
+            Some(t) => self.window.saturating_sub(t.elapsed()),
+            // Zero limit.  Nothing is ever allowed.
+            None => self.window,
+        }
+    }
+}
+
 /// Creates a (JSON) String verion of a list of method parameters,
 /// replacing params with a generic REDACTED message for log-protected
 /// methods.
@@ -221,6 +401,84 @@ impl Pager {
     }
 }
 
+/// Command line flag which asks a service binary to run its startup
+/// checks and exit instead of handling requests.
+pub const SELF_TEST_ARG: &str = "--self-test";
+
+/// True if the service was started with --self-test.
+pub fn self_test_requested() -> bool {
+    std::env::args().any(|a| a == SELF_TEST_ARG)
+}
+
+/// Collects the pass/fail results of a series of named startup checks.
+///
+/// ```
+/// use evergreen::util::SelfTest;
+///
+/// let mut test = SelfTest::new();
+/// assert_eq!(test.check("math", || Ok(2 + 2)), Some(4));
+/// assert!(test.passed());
+///
+/// let res: Option<()> = test.check("broken", || Err("nope".into()));
+/// assert!(res.is_none());
+/// assert!(!test.passed());
+/// assert_eq!(test.exit_code(), 1);
+/// assert_eq!(test.report(), "PASS math\nFAIL broken: nope");
+/// ```
+#[derive(Debug, Default)]
+pub struct SelfTest {
+    results: Vec<(String, Result<(), String>)>,
+}
+
+impl SelfTest {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Run a check and record its outcome.
+    ///
+    /// Returns the check's value on success so later checks can
+    /// build on it.
+    pub fn check<T>(&mut self, name: &str, f: impl FnOnce() -> EgResult<T>) -> Option<T> {
+        match f() {
+            Ok(v) => {
+                self.results.push((name.to_string(), Ok(())));
+                Some(v)
+            }
+            Err(e) => {
+                self.results.push((name.to_string(), Err(e.to_string())));
+                None
+            }
+        }
+    }
+
+    /// True if every check passed.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, r)| r.is_ok())
+    }
+
+    /// Process exit code: 0 if all checks passed, 1 otherwise.
+    pub fn exit_code(&self) -> i32 {
+        if self.passed() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// One PASS/FAIL line per check.
+    pub fn report(&self) -> String {
+        self.results
+            .iter()
+            .map(|(name, r)| match r {
+                Ok(_) => format!("PASS {name}"),
+                Err(e) => format!("FAIL {name}: {e}"),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
 /// Subtract value b from value a while compensating for common floating
 /// point math problems.
 pub fn fpdiff(a: f64, b: f64) -> f64 {
@@ -334,10 +592,54 @@ pub fn tcp_listener(address: &str, port: u16, read_timeout: u64) -> EgResult<Tcp
     Ok(socket.into())
 }
 
+/// Coerce a value to an i64.
+///
+/// Whole-valued floats, e.g. 12.0 from a numeric cast, are accepted.
+///
+/// ```
+/// #![allow(deprecated)]
+/// use evergreen as eg;
+/// use eg::util;
+///
+/// assert_eq!(util::json_int(&eg::EgValue::from(12)).unwrap(), 12);
+/// assert_eq!(util::json_int(&eg::EgValue::from("-3")).unwrap(), -3);
+/// assert_eq!(util::json_int(&eg::EgValue::from(12.0)).unwrap(), 12);
+/// assert!(util::json_int(&eg::EgValue::from(12.5)).is_err());
+/// assert!(util::json_int(&eg::EgValue::Null).is_err());
+/// ```
 #[deprecated(note = "See EgValue::as_int()")]
 pub fn json_int(v: &EgValue) -> EgResult<i64> {
-    Ok(v.as_int()
-        .ok_or_else(|| format!("Cannot coerce to int: {}", v.dump()))?)
+    if let Some(i) = v.as_int() {
+        return Ok(i);
+    }
+
+    if v.is_number() {
+        if let Some(f) = v.as_float() {
+            if f.fract() == 0.0 && f >= i64::MIN as f64 && f <= i64::MAX as f64 {
+                return Ok(f as i64);
+            }
+        }
+    }
+
+    Err(format!("Cannot coerce to int: {}", v.dump()).into())
+}
+
+/// Coerce a value to a u64, rejecting negative numbers.
+///
+/// ```
+/// use evergreen as eg;
+/// use eg::util;
+///
+/// assert_eq!(util::json_uint(&eg::EgValue::from(12)).unwrap(), 12);
+/// assert_eq!(util::json_uint(&eg::EgValue::from(7.0)).unwrap(), 7);
+/// assert!(util::json_uint(&eg::EgValue::from(-1)).is_err());
+/// assert!(util::json_uint(&eg::EgValue::from("-1")).is_err());
+/// ```
+#[allow(deprecated)]
+pub fn json_uint(v: &EgValue) -> EgResult<u64> {
+    let i = json_int(v)?;
+
+    u64::try_from(i).map_err(|_| format!("Cannot coerce to unsigned int: {}", v.dump()).into())
 }
 
 #[deprecated(note = "See EgValue::as_bool() / boolish()")]
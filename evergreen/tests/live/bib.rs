@@ -0,0 +1,80 @@
+use crate::util;
+use eg::common::bib;
+use eg::samples;
+use eg::EgResult;
+use evergreen as eg;
+use std::collections::HashMap;
+
+const EMPTY_MR_FINGERPRINT: &str = "_EG_TEST_";
+
+pub fn run_live_tests(tester: &mut util::Tester) -> EgResult<()> {
+    tester.timer.start();
+
+    metarecord_with_bibs(tester)?;
+    tester.timer.log("bibs_for_metarecord() populated");
+
+    metarecord_without_bibs(tester)?;
+    tester.timer.log("bibs_for_metarecord() empty");
+
+    Ok(())
+}
+
+fn metarecord_with_bibs(tester: &mut util::Tester) -> EgResult<()> {
+    let ops = eg::hash! {"limit": 500, "order_by": {"mmrsm": "metarecord"}};
+    let maps = tester
+        .editor
+        .search_with_ops("mmrsm", eg::hash! {"id": {">": 0}}, ops)?;
+
+    let mut sources: HashMap<i64, Vec<i64>> = HashMap::new();
+    for map in maps.iter() {
+        sources
+            .entry(map["metarecord"].int()?)
+            .or_default()
+            .push(map["source"].int()?);
+    }
+
+    let (mr_id, mut bib_ids) = match sources.into_iter().find(|(_, v)| v.len() > 1) {
+        Some(s) => s,
+        None => {
+            println!("Skipping metarecord tests; no multi-bib metarecords found");
+            return Ok(());
+        }
+    };
+
+    // Deleted records are not included.
+    let query = eg::hash! {"id": bib_ids.clone(), "deleted": "f"};
+    let live_ids = tester.editor.search("bre", query)?;
+    bib_ids.retain(|id| live_ids.iter().any(|b| b.id().ok() == Some(*id)));
+    bib_ids.sort();
+
+    assert_eq!(
+        bib::bibs_for_metarecord(&mut tester.editor, mr_id)?,
+        bib_ids
+    );
+
+    Ok(())
+}
+
+fn metarecord_without_bibs(tester: &mut util::Tester) -> EgResult<()> {
+    let mut mr = eg::hash! {
+        fingerprint: EMPTY_MR_FINGERPRINT,
+        master_record: samples::ACN_RECORD,
+    };
+    mr.bless("mmr")?;
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    let mr = e.create(mr)?;
+    e.commit()?;
+
+    let bib_ids = bib::bibs_for_metarecord(&mut tester.editor, mr.id()?)?;
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.delete(mr)?;
+    e.commit()?;
+
+    assert!(bib_ids.is_empty());
+
+    Ok(())
+}
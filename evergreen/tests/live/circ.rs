@@ -1,11 +1,14 @@
 use crate::util;
 use eg::common::circulator::Circulator;
 use eg::constants as C;
+use eg::result::EgError;
 use eg::result::EgResult;
 use eg::EgValue;
 use evergreen as eg;
 use std::collections::HashMap;
 
+const BLOCK_RENEWS_SETTING: &str = "circ.block_renews_for_holds";
+
 pub fn run_live_tests(tester: &mut util::Tester) -> EgResult<()> {
     util::login(tester)?;
     tester.timer.start();
@@ -19,6 +22,9 @@ pub fn run_live_tests(tester: &mut util::Tester) -> EgResult<()> {
     checkout(tester)?;
     tester.timer.log("checkout()");
 
+    renew_with_hold_block(tester)?;
+    tester.timer.log("renew_with_hold_block()");
+
     checkin_item_at_home(tester)?;
     tester.timer.log("checkin_item_at_home()");
 
@@ -187,3 +193,96 @@ fn checkin_item_remote(tester: &mut util::Tester) -> EgResult<()> {
 
     Ok(())
 }
+
+fn renew_options(tester: &util::Tester) -> HashMap<String, EgValue> {
+    let mut options: HashMap<String, EgValue> = HashMap::new();
+    options.insert(
+        "copy_barcode".to_string(),
+        EgValue::from(tester.samples.acp_barcode.as_str()),
+    );
+    options.insert("selfcheck_renewal".to_string(), EgValue::from(true));
+    options
+}
+
+/// Renew our checked out item with the holds block enabled, first
+/// with a hold the item could fill, then without.
+fn renew_with_hold_block(tester: &mut util::Tester) -> EgResult<()> {
+    let query = eg::hash! {
+        name: BLOCK_RENEWS_SETTING,
+        org_unit: tester.samples.aou_id,
+    };
+
+    if !tester.editor.search("aous", query)?.is_empty() {
+        // Don't clobber a local configuration.
+        println!("Skipping renewal hold tests; {BLOCK_RENEWS_SETTING} is set");
+        return Ok(());
+    }
+
+    let mut setting = eg::hash! {
+        org_unit: tester.samples.aou_id,
+        name: BLOCK_RENEWS_SETTING,
+        value: "true",
+    };
+    setting.bless("aous")?;
+
+    let copy_id = tester.samples.get_default_acp(&mut tester.editor)?.id()?;
+    let patron = tester.editor.requestor().unwrap().clone();
+
+    // Force holds are always considered permitted for their copy.
+    let mut hold = eg::hash! {
+        hold_type: "F",
+        target: copy_id,
+        current_copy: copy_id,
+        usr: patron.id()?,
+        requestor: patron.id()?,
+        pickup_lib: tester.samples.aou_id,
+        request_lib: tester.samples.aou_id,
+        selection_ou: tester.samples.aou_id,
+        selection_depth: 0,
+    };
+    hold.bless("ahr")?;
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    let setting = e.create(setting)?;
+    let hold = e.create(hold)?;
+    e.commit()?;
+
+    let options = renew_options(tester);
+    tester.editor.xact_begin()?;
+    let mut circulator = Circulator::new(&mut tester.editor, options)?;
+
+    match circulator.renew() {
+        Err(EgError::Event(evt)) => assert_eq!(evt.textcode(), "COPY_NEEDED_FOR_HOLD"),
+        Err(e) => return Err(e),
+        Ok(()) => panic!("Renewal should be blocked by a hold"),
+    }
+    circulator.rollback()?;
+
+    tester.timer.log("Renewal blocked by hold");
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.delete(hold)?;
+    e.commit()?;
+
+    let options = renew_options(tester);
+    tester.editor.xact_begin()?;
+    let mut circulator = Circulator::new(&mut tester.editor, options)?;
+    circulator.renew()?;
+    circulator.commit()?;
+
+    let evt = circulator
+        .events()
+        .get(0)
+        .ok_or(format!("Renew returned no result!"))?;
+
+    assert!(evt.is_success());
+
+    tester.timer.log("Renewal succeeded without hold");
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.delete(setting)?;
+    e.commit()
+}
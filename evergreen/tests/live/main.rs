@@ -1,8 +1,11 @@
 use evergreen as eg;
 mod auth;
+mod bib;
 mod cache;
 mod circ;
 mod json_query;
+mod penalty;
+mod settings;
 mod store;
 mod util;
 
@@ -41,5 +44,11 @@ fn main() -> eg::EgResult<()> {
 
     json_query::run_live_tests(&mut tester)?;
 
+    settings::run_live_tests(&mut tester)?;
+
+    penalty::run_live_tests(&mut tester)?;
+
+    bib::run_live_tests(&mut tester)?;
+
     Ok(())
 }
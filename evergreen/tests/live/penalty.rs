@@ -0,0 +1,56 @@
+use crate::util;
+use eg::common::penalty;
+use eg::samples;
+use eg::EgResult;
+use eg::EgValue;
+use evergreen as eg;
+
+// PATRON_EXCEEDS_FINES, PATRON_EXCEEDS_OVERDUE_COUNT
+const BLOCKING_PENALTIES: [i64; 2] = [1, 2];
+
+pub fn run_live_tests(tester: &mut util::Tester) -> EgResult<()> {
+    tester.timer.start();
+
+    let e = &mut tester.editor;
+
+    e.xact_begin()?;
+    tester.samples.delete_default_au(e)?;
+    let user = tester.samples.create_default_au(e)?;
+
+    for penalty in BLOCKING_PENALTIES {
+        let ausp = EgValue::create(
+            "ausp",
+            eg::hash! {
+                usr: user.id()?,
+                org_unit: samples::AOU_BR1_ID,
+                standing_penalty: penalty,
+            },
+        )?;
+        e.create(ausp)?;
+    }
+
+    e.commit()?;
+
+    tester.timer.log("Created patron with blocking penalties");
+
+    let descriptions =
+        penalty::active_block_descriptions(&mut tester.editor, user.id()?, samples::AOU_BR1_ID)?;
+
+    assert_eq!(descriptions.len(), BLOCKING_PENALTIES.len());
+
+    for penalty in BLOCKING_PENALTIES {
+        let csp = tester.editor.retrieve("csp", penalty)?.unwrap();
+        assert!(descriptions.contains(&csp["label"].str()?.to_string()));
+    }
+
+    tester.timer.log("Found blocking penalty descriptions");
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    tester.samples.delete_default_au(e)?;
+    e.commit()?;
+
+    tester.timer.log("Deleted patron");
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use crate::util;
+use eg::common::settings::{self, Settings};
+use eg::samples;
+use eg::EgResult;
+use eg::EgValue;
+use evergreen as eg;
+use std::time::Duration;
+
+const SETTING_NAME: &str = "global.juvenile_age_threshold";
+const ROOT_ORG_ID: i64 = 1;
+
+pub fn run_live_tests(tester: &mut util::Tester) -> EgResult<()> {
+    tester.timer.start();
+
+    let query = eg::hash! {
+        name: SETTING_NAME,
+        org_unit: [ROOT_ORG_ID, samples::AOU_BR1_ID],
+    };
+
+    let existing = tester.editor.search("aous", query)?;
+
+    if existing
+        .iter()
+        .any(|s| s["org_unit"].as_int() == Some(samples::AOU_BR1_ID))
+    {
+        // Leaf value would mask the inherited value.
+        println!("Skipping settings tests; {SETTING_NAME} is set at BR1");
+        return Ok(());
+    }
+
+    // Use the existing root value if there is one.  Otherwise create
+    // one and remove it when we're done.
+    let (root_setting, created) = match existing.into_iter().next() {
+        Some(s) => (s, false),
+        None => (create_root_setting(tester)?, true),
+    };
+
+    tester.timer.log("Found root org setting");
+
+    let expected = EgValue::parse(root_setting["value"].str()?)?;
+
+    let mut settings = Settings::new(&tester.editor);
+    let value = settings.get_value_at_org(SETTING_NAME, samples::AOU_BR1_ID)?;
+
+    assert_eq!(value, &expected);
+
+    tester.timer.log("Leaf org inherits root org setting");
+
+    let root_setting = check_invalidation(tester, &mut settings, root_setting)?;
+
+    tester.timer.log("Setting change seen after invalidation");
+
+    if created {
+        let e = &mut tester.editor;
+        e.xact_begin()?;
+        e.delete(root_setting)?;
+        e.commit()?;
+
+        tester.timer.log("Deleted root org setting");
+    }
+
+    Ok(())
+}
+
+fn create_root_setting(tester: &mut util::Tester) -> EgResult<EgValue> {
+    let mut aous = eg::hash! {
+        org_unit: ROOT_ORG_ID,
+        name: SETTING_NAME,
+        value: "\"18 years\"",
+    };
+
+    aous.bless("aous")?;
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    let aous = e.create(aous)?;
+    e.commit()?;
+
+    Ok(aous)
+}
+
+/// Change the root setting value and verify our Settings instance
+/// notices once the settings cache is invalidated.
+fn check_invalidation(
+    tester: &mut util::Tester,
+    settings: &mut Settings,
+    mut root_setting: EgValue,
+) -> EgResult<EgValue> {
+    settings.set_max_age(Duration::from_secs(0));
+
+    let orig_value = root_setting["value"].take();
+
+    root_setting["value"] = EgValue::from("\"99 years\"");
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.update(root_setting.clone())?;
+    e.commit()?;
+
+    settings::invalidate_cache()?;
+
+    let value = settings.get_value_at_org(SETTING_NAME, samples::AOU_BR1_ID)?;
+    assert_eq!(value.as_str(), Some("99 years"));
+
+    // A second change within the same second is also noticed.
+    root_setting["value"] = EgValue::from("\"98 years\"");
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.update(root_setting.clone())?;
+    e.commit()?;
+
+    settings::invalidate_cache()?;
+
+    let value = settings.get_value_at_org(SETTING_NAME, samples::AOU_BR1_ID)?;
+    assert_eq!(value.as_str(), Some("98 years"));
+
+    root_setting["value"] = orig_value;
+
+    let e = &mut tester.editor;
+    e.xact_begin()?;
+    e.update(root_setting.clone())?;
+    e.commit()?;
+
+    settings::invalidate_cache()?;
+
+    Ok(root_setting)
+}
@@ -38,6 +38,7 @@ fn main() -> EgResult<()> {
         skip_logging: false,
         skip_host_settings: true,
         appname: Some("sip2-mediator".to_string()),
+        ..eg::init::InitOptions::new()
     };
 
     let ctx = eg::init::with_options(&options)?;
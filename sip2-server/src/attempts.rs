@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Failed password attempts for a single patron.
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: usize,
+    /// When the first failure in the current window occurred.
+    started: Instant,
+}
+
+/// Tracks failed patron password attempts across all SIP sessions.
+///
+/// Shared by every Session so a patron cannot reset their attempt
+/// count by reconnecting.
+#[derive(Debug, Default)]
+pub struct PasswordAttempts {
+    failures: Mutex<HashMap<i64, Failures>>,
+}
+
+impl PasswordAttempts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of failed attempts the patron has left within the
+    /// current lockout window.
+    ///
+    /// Zero means the patron is locked out until the window expires.
+    pub fn remaining(&self, user_id: i64, max: usize, window: Duration) -> usize {
+        let mut failures = self.failures.lock().unwrap();
+
+        match failures.get(&user_id) {
+            Some(f) if f.started.elapsed() < window => max.saturating_sub(f.count),
+            Some(_) => {
+                failures.remove(&user_id);
+                max
+            }
+            None => max,
+        }
+    }
+
+    /// Record a failed attempt and return the number of attempts left.
+    pub fn fail(&self, user_id: i64, max: usize, window: Duration) -> usize {
+        let mut failures = self.failures.lock().unwrap();
+
+        let entry = failures.entry(user_id).or_insert(Failures {
+            count: 0,
+            started: Instant::now(),
+        });
+
+        if entry.started.elapsed() >= window {
+            entry.count = 0;
+            entry.started = Instant::now();
+        }
+
+        entry.count += 1;
+
+        max.saturating_sub(entry.count)
+    }
+
+    /// Clear the failure count after a successful attempt.
+    pub fn reset(&self, user_id: i64) {
+        self.failures.lock().unwrap().remove(&user_id);
+    }
+}
@@ -1,6 +1,5 @@
 use super::item;
 use super::session::Session;
-use chrono::NaiveDateTime;
 use eg::common::circulator::Circulator;
 use eg::constants as C;
 use eg::result::EgResult;
@@ -62,7 +61,7 @@ impl Session {
 
         let barcode = msg
             .get_field_value("AB")
-            .ok_or_else(|| format!("handle_item_info() missing item barcode"))?;
+            .ok_or_else(|| format!("handle_checkin() missing item barcode"))?;
 
         let current_loc_op = msg.get_field_value("AP");
         let return_date = &msg.fixed_fields()[2];
@@ -116,6 +115,7 @@ impl Session {
                 ("BG", &item.owning_loc),
                 ("BT", &item.fee_type),
                 ("CI", "N"), // security inhibit
+                ("CS", &item.call_number),
             ],
         )
         .unwrap();
@@ -180,6 +180,7 @@ impl Session {
             &[
                 ("AB", &barcode),
                 ("AO", self.account().settings().institution()),
+                ("AF", "Item not found"),
                 ("CV", AlertType::Unknown.into()),
             ],
         )
@@ -220,11 +221,8 @@ impl Session {
         }
 
         if return_date.trim().len() == 18 {
-            let fmt = sip2::spec::SIP_DATE_FORMAT;
-
-            // Use NaiveDate since SIP dates don't typically include a
-            // time zone value.
-            if let Some(sip_date) = NaiveDateTime::parse_from_str(return_date, fmt).ok() {
+            // SIP dates don't typically include a time zone value.
+            if let Ok(sip_date) = sip2::util::parse_sip_date(return_date) {
                 let iso_date = sip_date.format("%Y-%m-%d").to_string();
                 log::info!("{self} Checking in with backdate: {iso_date}");
 
@@ -241,7 +239,7 @@ impl Session {
         }
 
         if !args.has_key("circ_lib") {
-            args["circ_lib"] = EgValue::from(self.get_ws_org_id()?);
+            args["circ_lib"] = EgValue::from(self.get_circ_org_id()?);
         }
 
         let method = match ovride {
@@ -377,11 +375,8 @@ impl Session {
         }
 
         if return_date.trim().len() == 18 {
-            let fmt = sip2::spec::SIP_DATE_FORMAT;
-
-            // Use NaiveDate since SIP dates don't typically include a
-            // time zone value.
-            if let Some(sip_date) = NaiveDateTime::parse_from_str(return_date, fmt).ok() {
+            // SIP dates don't typically include a time zone value.
+            if let Ok(sip_date) = sip2::util::parse_sip_date(return_date) {
                 let iso_date = sip_date.format("%Y-%m-%d").to_string();
                 log::info!("{self} Checking in with backdate: {iso_date}");
 
@@ -400,7 +395,10 @@ impl Session {
         }
 
         if !options.contains_key("circ_lib") {
-            options.insert("circ_lib".to_string(), EgValue::from(self.get_ws_org_id()?));
+            options.insert(
+                "circ_lib".to_string(),
+                EgValue::from(self.get_circ_org_id()?),
+            );
         }
 
         log::info!("{self} checkin with params: {:?}", options);
@@ -560,7 +558,7 @@ impl Session {
             }
         }
 
-        if pickup_lib_id == self.get_ws_org_id()? {
+        if pickup_lib_id == self.get_circ_org_id()? {
             result.alert_type = Some(AlertType::LocalHold);
         } else {
             result.alert_type = Some(AlertType::RemoteHold);
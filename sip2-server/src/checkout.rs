@@ -1,7 +1,10 @@
 use super::item::Item;
 use super::patron::Patron;
 use super::session::Session;
+use eg::common::circ;
 use eg::common::circulator::Circulator;
+use eg::common::noncat;
+use eg::common::renew::{self, RenewFailure};
 use eg::date;
 use eg::result::EgResult;
 use eg::EgValue;
@@ -13,6 +16,67 @@ const RENEW_OVERRIDE_METHOD: &str = "open-ils.circ.renew.override";
 const CHECKOUT_METHOD: &str = "open-ils.circ.checkout.full";
 const CHECKOUT_OVERRIDE_METHOD: &str = "open-ils.circ.checkout.full.override";
 
+/// How a checkout request is handled, based on the SIP renewal policy
+/// flag and any open circulation for the item.
+#[derive(Debug, PartialEq)]
+pub enum CheckoutAction {
+    /// Standard checkout.
+    Checkout,
+    /// Item is checked out to this patron and renewals are allowed.
+    Renew,
+    /// Item is checked out to this patron and renewals are not allowed.
+    AlreadyOut,
+}
+
+impl CheckoutAction {
+    /// Items checked out to other patrons are treated as standard
+    /// checkouts so the ILS (and any configured overrides) decide.
+    pub fn new(renew_ok: bool, circ_patron_id: Option<i64>, patron_id: i64) -> CheckoutAction {
+        if circ_patron_id != Some(patron_id) {
+            CheckoutAction::Checkout
+        } else if renew_ok {
+            CheckoutAction::Renew
+        } else {
+            CheckoutAction::AlreadyOut
+        }
+    }
+}
+
+/// Returns true if a circulation may be renewed.
+///
+/// `limit` is the renewal policy limit for the item's circ modifier and
+/// `renewals_used` the number of times the circulation has already been
+/// renewed.  Without a limit, the renewals remaining on the circulation,
+/// as set by the circ policy matrix, decide.
+pub fn renewal_allowed(
+    limit: Option<u32>,
+    renewals_used: Option<i64>,
+    renewal_remaining: Option<i64>,
+) -> bool {
+    match limit {
+        Some(max) => renewals_used.unwrap_or(0) < max as i64,
+        None => renewal_remaining.unwrap_or(1) > 0,
+    }
+}
+
+/// Screen message (AF) for a failed checkout or renewal event.
+///
+/// TODO gettext() can be used for these string literals, but it's a
+/// massive dependency for just a couple of sentences.  There's likely
+/// a better approach.
+fn checkout_event_message(textcode: &str) -> &'static str {
+    match textcode {
+        "OPEN_CIRCULATION_EXISTS" => "This item is already checked out",
+        "COPY_NEEDED_FOR_HOLD" => "This item is needed for a hold",
+        "PATRON_CARD_INACTIVE" => "Patron card is inactive",
+        "PATRON_INACTIVE" => "Patron account is inactive",
+        "PATRON_ACCOUNT_EXPIRED" => "Patron account has expired",
+        "MAX_RENEWALS_REACHED" => "This item has no renewals remaining",
+        "ACTION_CIRCULATION_NOT_FOUND" => "This item is not checked out to this patron",
+        _ => "Patron is not allowed to checkout the selected item",
+    }
+}
+
 pub struct CheckoutResult {
     /// Presence of a circ_id implies success.
     circ_id: Option<i64>,
@@ -57,31 +121,237 @@ impl Session {
         log::info!("{self} Checking out item {item_barcode} to patron {patron_barcode}");
 
         let fee_ack_op = msg.get_field_value("BO");
+        let password_op = self
+            .account()
+            .settings()
+            .password_to_verify("checkout", msg.get_field_value("AD"));
+
+        if let Some(nc_type) = self
+            .account()
+            .settings()
+            .noncat_type_for_barcode(&item_barcode)
+        {
+            return self.handle_noncat_checkout(
+                &item_barcode,
+                &patron_barcode,
+                password_op,
+                nc_type,
+            );
+        }
 
         let item = match self.get_item_details(&item_barcode)? {
             Some(c) => c,
             None => return Ok(self.checkout_item_not_found(&item_barcode, &patron_barcode)),
         };
 
-        let patron = match self.get_patron_details(&patron_barcode, None, None)? {
+        let patron = match self.get_patron_details(&patron_barcode, password_op, None)? {
             Some(c) => c,
             None => return Ok(self.checkout_item_not_found(&item_barcode, &patron_barcode)),
         };
 
+        if let Some(result) = self.checkout_password_refusal(&patron) {
+            return self.compile_checkout_response(&item, &patron, &result);
+        }
+
         let renew_ok = msg.fixed_fields()[0].value().eq("Y");
-        let same_patron = item.circ_patron_id.unwrap_or(-1) == patron.id;
+        let action = CheckoutAction::new(renew_ok, item.circ_patron_id, patron.id);
+
+        log::debug!("{self} Checkout of {item_barcode} handled as {action:?}");
+
+        if action == CheckoutAction::AlreadyOut
+            && self.account().settings().block_duplicate_checkout()
+        {
+            log::info!("{self} Item {item_barcode} is already checked out to {patron_barcode}");
+
+            let mut result = CheckoutResult::new();
+            result.screen_msg = Some("This item is already checked out");
+
+            return self.compile_checkout_response(&item, &patron, &result);
+        }
+
+        let is_renewal = action == CheckoutAction::Renew;
+
+        if is_renewal && !self.renewal_permitted_by_policy(&item)? {
+            log::info!("{self} Renewal of {item_barcode} refused by renewal policy");
+
+            let mut result = CheckoutResult::new();
+            result.screen_msg = Some("Item may not be renewed");
+
+            return self.compile_checkout_response(&item, &patron, &result);
+        }
 
         let result = self.checkout(
             &item_barcode,
             &patron_barcode,
             fee_ack_op.is_some(),
-            renew_ok && same_patron, // is_renewal
+            is_renewal,
             self.account().settings().checkout_override_all(),
         )?;
 
         self.compile_checkout_response(&item, &patron, &result)
     }
 
+    /// Checkout of a non-cataloged item, e.g. a guest pass, identified
+    /// by a configured item barcode prefix.
+    fn handle_noncat_checkout(
+        &mut self,
+        item_barcode: &str,
+        patron_barcode: &str,
+        password_op: Option<&str>,
+        noncat_type: i64,
+    ) -> EgResult<sip2::Message> {
+        log::info!("{self} Item {item_barcode} is a non-cataloged item of type {noncat_type}");
+
+        let patron = match self.get_patron_details(patron_barcode, password_op, None)? {
+            Some(p) => p,
+            None => return Ok(self.checkout_item_not_found(item_barcode, patron_barcode)),
+        };
+
+        // Standalone transaction; cloning is just easier here.
+        let mut editor = self.editor().clone();
+
+        let nc_type = match editor.retrieve("cnct", noncat_type)? {
+            Some(t) => t,
+            None => {
+                log::error!("{self} Invalid non-cataloged type configured: {noncat_type}");
+                return Ok(self.checkout_item_not_found(item_barcode, patron_barcode));
+            }
+        };
+
+        let title = nc_type["name"].str()?;
+        let mut result = self
+            .checkout_password_refusal(&patron)
+            .unwrap_or_else(CheckoutResult::new);
+
+        if result.screen_msg.is_none() && patron.charge_denied {
+            log::info!("{self} Non-cat checkout refused for {patron_barcode}: charge denied");
+            result.screen_msg = Some(checkout_event_message(""));
+        }
+
+        if result.screen_msg.is_some() {
+            return Ok(self.compile_noncat_checkout_response(
+                item_barcode,
+                title,
+                &patron,
+                &result,
+            ));
+        }
+
+        let circ_lib = self.get_circ_org_id()?;
+
+        editor.xact_begin()?;
+
+        let circs = match noncat::checkout(&mut editor, patron.id, noncat_type, circ_lib, 1, None) {
+            Ok(c) => c,
+            Err(e) => {
+                editor.rollback()?;
+                return Err(e);
+            }
+        };
+
+        editor.commit()?;
+
+        if let Some(circ) = circs.first() {
+            // Due date is calculated from the non-cat type's duration.
+            result.circ_id = Some(circ.id()?);
+            result.due_date = Some(self.sip_due_date(circ["duedate"].str()?)?);
+        }
+
+        Ok(self.compile_noncat_checkout_response(item_barcode, title, &patron, &result))
+    }
+
+    fn compile_noncat_checkout_response(
+        &self,
+        item_barcode: &str,
+        title: &str,
+        patron: &Patron,
+        result: &CheckoutResult,
+    ) -> sip2::Message {
+        let mut resp = sip2::Message::from_values(
+            &sip2::spec::M_CHECKOUT_RESP,
+            &[
+                sip2::util::num_bool(result.circ_id.is_some()), // checkin ok
+                "N",                                            // renew ok
+                "N",                                            // magnetic
+                "N",                                            // desensitize
+                &sip2::util::sip_date_now(),                    // timestamp
+            ],
+            &[
+                ("AA", &patron.barcode),
+                ("AB", item_barcode),
+                ("AJ", title),
+                ("AO", self.account().settings().institution()),
+                ("CI", "N"), // security inhibit
+            ],
+        )
+        .unwrap();
+
+        resp.maybe_add_field("AF", result.screen_msg.as_deref());
+        resp.maybe_add_field("AH", result.due_date.as_deref());
+
+        if let Some(id) = result.circ_id {
+            resp.add_field("BK", &format!("{id}"));
+        }
+
+        resp
+    }
+
+    /// Returns a refused checkout result if the account requires a
+    /// verified password for checkouts and the patron's was not verified.
+    fn checkout_password_refusal(&self, patron: &Patron) -> Option<CheckoutResult> {
+        let settings = self.account().settings();
+
+        if !settings.password_refused("checkout", patron.password_verified) {
+            return None;
+        }
+
+        log::info!(
+            "{self} Checkout refused for {}: password not verified",
+            patron.barcode
+        );
+
+        let mut result = CheckoutResult::new();
+        result.screen_msg = Some("Invalid patron password");
+
+        Some(result)
+    }
+
+    /// Format an ISO due date for a checkout response.
+    fn sip_due_date(&self, iso_date: &str) -> EgResult<String> {
+        if self.account().settings().due_date_use_sip_date_format() {
+            let due_dt = date::parse_datetime(iso_date)?;
+            Ok(sip2::util::format_sip_date(&due_dt))
+        } else {
+            Ok(iso_date.to_string())
+        }
+    }
+
+    /// Consult the per-circ-modifier renewal policy and the open
+    /// circulation's renewal count before calling the renewal API.
+    ///
+    /// Returns false if the renewal is known to be disallowed.  Otherwise,
+    /// the renewal API, i.e. the full circ matrix, makes the decision.
+    fn renewal_permitted_by_policy(&mut self, item: &Item) -> EgResult<bool> {
+        let limit = item
+            .circ_modifier
+            .as_deref()
+            .and_then(|m| self.account().settings().renewal_limit(m));
+
+        let renewals_used = match (limit, item.circ_id) {
+            (Some(max), Some(circ_id)) if max > 0 => {
+                let chain = circ::summarize_circ_chain(self.editor_mut(), circ_id)?;
+                Some(chain["num_circs"].int()? - 1)
+            }
+            _ => None,
+        };
+
+        Ok(renewal_allowed(
+            limit,
+            renewals_used,
+            item.renewal_remaining,
+        ))
+    }
+
     fn compile_checkout_response(
         &self,
         item: &Item,
@@ -177,6 +447,7 @@ impl Session {
             eg::hash! {
                 copy_barcode: item_barcode,
                 patron_barcode: patron_barcode,
+                circ_lib: self.get_circ_org_id()?,
             },
         ];
 
@@ -222,12 +493,7 @@ impl Session {
                 result.renewal_remaining = circ["renewal_remaining"].int()?;
 
                 let iso_date = circ["due_date"].as_str().unwrap(); // required
-                if self.account().settings().due_date_use_sip_date_format() {
-                    let due_dt = date::parse_datetime(iso_date)?;
-                    result.due_date = Some(sip2::util::sip_date_from_dt(&due_dt));
-                } else {
-                    result.due_date = Some(iso_date.to_string());
-                }
+                result.due_date = Some(self.sip_due_date(iso_date)?);
 
                 return Ok(result);
             } else {
@@ -254,14 +520,7 @@ impl Session {
             }
         }
 
-        // TODO gettext() can be used for these string literals below, but
-        // it's a massive dependency for just a couple of sentences.
-        // There's likely a better approach.
-        if evt.textcode().eq("OPEN_CIRCULATION_EXISTS") {
-            result.screen_msg = Some("This item is already checked out");
-        } else {
-            result.screen_msg = Some("Patron is not allowed to checkout the selected item");
-        }
+        result.screen_msg = Some(checkout_event_message(evt.textcode()));
 
         Ok(result)
     }
@@ -276,10 +535,20 @@ impl Session {
         is_renewal: bool,
         ovride: bool,
     ) -> EgResult<CheckoutResult> {
+        if is_renewal && !ovride {
+            return self.renew_native(item_barcode, patron_barcode, fee_ack);
+        }
+
         let mut options: HashMap<String, EgValue> = HashMap::new();
 
         options.insert("copy_barcode".to_string(), item_barcode.into());
         options.insert("patron_barcode".to_string(), patron_barcode.into());
+        options.insert("circ_lib".to_string(), self.get_circ_org_id()?.into());
+
+        if is_renewal {
+            // Holds blocks are not overridable from the self-check.
+            options.insert("selfcheck_renewal".to_string(), EgValue::from(true));
+        }
 
         // Standalone transaction; cloning is just easier here.
         let mut editor = self.editor().clone();
@@ -327,12 +596,7 @@ impl Session {
                 result.renewal_remaining = circ["renewal_remaining"].int()?;
 
                 let iso_date = circ["due_date"].as_str().unwrap(); // required
-                if self.account().settings().due_date_use_sip_date_format() {
-                    let due_dt = date::parse_datetime(iso_date)?;
-                    result.due_date = Some(sip2::util::sip_date_from_dt(&due_dt));
-                } else {
-                    result.due_date = Some(iso_date.to_string());
-                }
+                result.due_date = Some(self.sip_due_date(iso_date)?);
 
                 return Ok(result);
             } else {
@@ -360,15 +624,77 @@ impl Session {
             }
         }
 
-        // TODO gettext() can be used for these string literals below, but
-        // it's a massive dependency for just a couple of sentances.
-        // There's likely a better approach.
-        if evt.textcode().eq("OPEN_CIRCULATION_EXISTS") {
-            result.screen_msg = Some("This item is already checked out");
-        } else {
-            result.screen_msg = Some("Patron is not allowed to checkout the selected item");
+        result.screen_msg = Some(checkout_event_message(evt.textcode()));
+
+        Ok(result)
+    }
+
+    /// Renewal that runs within the current thread as a direct
+    /// Rust call.
+    ///
+    /// Refusals which the account is configured to override are
+    /// retried as override renewals.
+    fn renew_native(
+        &mut self,
+        item_barcode: &str,
+        patron_barcode: &str,
+        fee_ack: bool,
+    ) -> EgResult<CheckoutResult> {
+        // Standalone transaction; cloning is just easier here.
+        let mut editor = self.editor().clone();
+        let circ_lib = self.get_circ_org_id()?;
+
+        let outcome = renew::renew_by_copy_barcode(
+            &mut editor,
+            patron_barcode,
+            item_barcode,
+            Some(circ_lib),
+        )?;
+
+        let mut result = CheckoutResult::new();
+        result.was_renewal = true;
+
+        let failure = match outcome {
+            Ok(renewal) => {
+                log::debug!(
+                    "{self} Renewal of {item_barcode} created circ {}",
+                    renewal.circ_id
+                );
+
+                result.circ_id = Some(renewal.circ_id);
+                result.renewal_remaining = renewal.renewal_remaining;
+                result.due_date = Some(self.sip_due_date(&renewal.due_date)?);
+
+                return Ok(result);
+            }
+            Err(f) => f,
+        };
+
+        log::info!("{self} Renewal of {item_barcode} refused: {failure:?}");
+
+        // Hold blocks are not overridable from the self-check.
+        if failure != RenewFailure::HoldPresent {
+            let textcode = failure.textcode();
+
+            let can_override = self
+                .account()
+                .settings()
+                .checkout_override()
+                .iter()
+                .any(|c| c == textcode);
+
+            // Caller acknowledges a fee is required.
+            let fee_acked = fee_ack
+                && (textcode == "ITEM_DEPOSIT_FEE_REQUIRED"
+                    || textcode == "ITEM_RENTAL_FEE_REQUIRED");
+
+            if can_override || fee_acked {
+                return self.checkout(item_barcode, patron_barcode, fee_ack, true, true);
+            }
         }
 
+        result.screen_msg = Some(checkout_event_message(failure.textcode()));
+
         Ok(result)
     }
 }
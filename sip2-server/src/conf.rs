@@ -14,10 +14,28 @@ fn set_bool(g: &yaml_rust::Yaml, k: &str, f: &mut bool) {
 /// (or other) signal.
 pub const SIP_SHUTDOWN_POLL_INTERVAL: u64 = 3;
 
+/// Patron blocks which may be given a screen message template.
+/// Listed in the order their messages are composed.
+pub const PATRON_BLOCKS: &[&str] = &["expired", "charge-denied", "holds-denied", "max-fines"];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Msg64HoldDatatype {
     Barcode,
     Title,
+    BibId,
+}
+
+impl From<&str> for Msg64HoldDatatype {
+    fn from(s: &str) -> Msg64HoldDatatype {
+        let s = s.to_lowercase();
+        if s.starts_with("t") {
+            Self::Title
+        } else if s.starts_with("bib") {
+            Self::BibId
+        } else {
+            Self::Barcode
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,6 +64,46 @@ impl From<&str> for AvFormat {
     }
 }
 
+/// How the patron's recall privilege is derived.
+///
+/// Evergreen has no recall holds, so recall privileges are inferred
+/// from other patron privileges or penalties.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecallPolicy {
+    /// Recalls are denied when checkouts are denied.
+    Checkout,
+    /// Recalls are denied when checkouts or renewals are denied.
+    CheckoutRenew,
+    /// Recalls are denied when the patron has the standing penalty
+    /// with this ID.
+    Penalty(i64),
+}
+
+impl RecallPolicy {
+    pub fn recall_denied(
+        &self,
+        charge_denied: bool,
+        renew_denied: bool,
+        has_penalty: bool,
+    ) -> bool {
+        match self {
+            Self::Checkout => charge_denied,
+            Self::CheckoutRenew => charge_denied || renew_denied,
+            Self::Penalty(_) => has_penalty,
+        }
+    }
+}
+
+/// How to handle heavy requests (patron and item lookups) which
+/// exceed the configured per-session limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeavyOpPolicy {
+    /// Respond with a failure response and screen message.
+    Reject,
+    /// Wait until the request falls within the limit.
+    Delay,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FieldFilter {
     field_code: String,
@@ -68,9 +126,12 @@ pub struct SipSettings {
     due_date_use_sip_date_format: bool,
     patron_status_permit_all: bool,
     patron_status_permit_loans: bool,
+    patron_status_distinct_blocks: bool,
     msg64_hold_items_available: bool,
     checkin_holds_as_transits: bool,
     msg64_hold_datatype: Msg64HoldDatatype,
+    msg64_hold_fallback: Vec<Msg64HoldDatatype>,
+    msg64_hold_placeholder: Option<String>,
     msg64_summary_datatype: Msg64SummaryDatatype,
     av_format: AvFormat,
     checkout_override_all: bool,
@@ -81,6 +142,40 @@ pub struct SipSettings {
     sc_status_library_info: bool,
     use_native_checkin: bool,
     use_native_checkout: bool,
+    max_message_length: Option<usize>,
+    fee_paid_detail: bool,
+    fee_paid_balance_field: Option<String>,
+    renewal_policy: HashMap<String, u32>,
+    password_required: Vec<String>,
+    item_info_overdue_field: Option<String>,
+    item_info_days_overdue_field: Option<String>,
+    block_duplicate_checkout: bool,
+    password_max_attempts: Option<usize>,
+    password_lockout_window: u64,
+    password_attempts_report: bool,
+    password_max_length: Option<usize>,
+    patron_status_expire_date: bool,
+    expire_date_use_sip_date_format: bool,
+    item_info_locations: bool,
+    item_info_call_number: bool,
+    item_info_author_field: Option<String>,
+    item_info_max_field_length: usize,
+    heavy_op_limit: Option<usize>,
+    heavy_op_window: u64,
+    heavy_op_policy: HeavyOpPolicy,
+    patron_status_permit_profiles: Vec<String>,
+    holds_ready_field: Option<String>,
+    protocol_version_fields: bool,
+    recall_policy: RecallPolicy,
+    refresh_stale_summaries: bool,
+    normalize_barcodes: bool,
+    patron_fee_limit: bool,
+    noncat_prefixes: Vec<(String, i64)>,
+    patron_status_lost_penalty: i64,
+    patron_block_messages: HashMap<String, String>,
+    checkin_ok: bool,
+    checkout_ok: bool,
+    renewal_ok: bool,
 }
 
 impl SipSettings {
@@ -90,9 +185,12 @@ impl SipSettings {
             due_date_use_sip_date_format: true,
             patron_status_permit_all: false,
             patron_status_permit_loans: false,
+            patron_status_distinct_blocks: true,
             msg64_hold_items_available: false,
             checkin_holds_as_transits: false,
             msg64_hold_datatype: Msg64HoldDatatype::Barcode,
+            msg64_hold_fallback: Vec::new(),
+            msg64_hold_placeholder: None,
             msg64_summary_datatype: Msg64SummaryDatatype::Barcode,
             av_format: AvFormat::ThreeM,
             checkout_override_all: false,
@@ -103,6 +201,40 @@ impl SipSettings {
             field_filters: Vec::new(),
             use_native_checkin: false,
             use_native_checkout: false,
+            max_message_length: None,
+            fee_paid_detail: false,
+            fee_paid_balance_field: None,
+            renewal_policy: HashMap::new(),
+            password_required: Vec::new(),
+            item_info_overdue_field: None,
+            item_info_days_overdue_field: None,
+            block_duplicate_checkout: true,
+            password_max_attempts: None,
+            password_lockout_window: 900,
+            password_attempts_report: false,
+            password_max_length: None,
+            patron_status_expire_date: false,
+            expire_date_use_sip_date_format: false,
+            item_info_locations: false,
+            item_info_call_number: false,
+            item_info_author_field: None,
+            item_info_max_field_length: 255,
+            heavy_op_limit: None,
+            heavy_op_window: 60,
+            heavy_op_policy: HeavyOpPolicy::Reject,
+            patron_status_permit_profiles: Vec::new(),
+            holds_ready_field: None,
+            protocol_version_fields: false,
+            recall_policy: RecallPolicy::CheckoutRenew,
+            refresh_stale_summaries: false,
+            normalize_barcodes: false,
+            patron_fee_limit: false,
+            noncat_prefixes: Vec::new(),
+            patron_status_lost_penalty: 5, // PATRON_EXCEEDS_LOST_COUNT
+            patron_block_messages: HashMap::new(),
+            checkin_ok: true,
+            checkout_ok: true,
+            renewal_ok: true,
         }
     }
     /// If true, uses the native Rust checkin API.
@@ -129,6 +261,27 @@ impl SipSettings {
     pub fn patron_status_permit_loans(&self) -> bool {
         self.patron_status_permit_loans
     }
+    /// Field code used to report the number of holds ready for pickup
+    /// in patron status and info responses.  None omits the count.
+    pub fn holds_ready_field(&self) -> Option<&str> {
+        self.holds_ready_field.as_deref()
+    }
+    /// Names of permission group profiles whose patrons are
+    /// permitted all actions, like patron_status_permit_all.
+    pub fn patron_status_permit_profiles(&self) -> &Vec<String> {
+        &self.patron_status_permit_profiles
+    }
+    /// If true, the excessive-fines penalty only sets the fines position
+    /// of the patron status, instead of also flagging the fees and
+    /// items-billed positions.
+    pub fn patron_status_distinct_blocks(&self) -> bool {
+        self.patron_status_distinct_blocks
+    }
+    /// Standing penalty which sets the too many items lost position
+    /// of the patron status.
+    pub fn patron_status_lost_penalty(&self) -> i64 {
+        self.patron_status_lost_penalty
+    }
     /// Limit holds list to available holds
     pub fn msg64_hold_items_available(&self) -> bool {
         self.msg64_hold_items_available
@@ -141,6 +294,16 @@ impl SipSettings {
     pub fn msg64_hold_datatype(&self) -> &Msg64HoldDatatype {
         &self.msg64_hold_datatype
     }
+    /// Datatypes to try, in order, when a hold cannot be reported
+    /// using msg64_hold_datatype.
+    pub fn msg64_hold_fallback(&self) -> &Vec<Msg64HoldDatatype> {
+        &self.msg64_hold_fallback
+    }
+    /// Reported for holds which cannot be reported using any of the
+    /// configured datatypes.  None drops such holds from the list.
+    pub fn msg64_hold_placeholder(&self) -> Option<&str> {
+        self.msg64_hold_placeholder.as_deref()
+    }
     /// Format for fine items
     pub fn av_format(&self) -> &AvFormat {
         &self.av_format
@@ -175,6 +338,185 @@ impl SipSettings {
     pub fn sc_status_library_info(&self) -> bool {
         self.sc_status_library_info
     }
+    /// Maximum length of a SIP response message, excluding the
+    /// sequence number, checksum, and terminator.
+    pub fn max_message_length(&self) -> Option<usize> {
+        self.max_message_length
+    }
+    /// Include applied payments and the remaining balance in
+    /// fee paid responses.
+    pub fn fee_paid_detail(&self) -> bool {
+        self.fee_paid_detail
+    }
+    /// Field code used to report the remaining balance in fee paid
+    /// responses when fee_paid_detail is enabled.
+    pub fn fee_paid_balance_field(&self) -> Option<&str> {
+        self.fee_paid_balance_field.as_deref()
+    }
+    /// Returns the maximum number of renewals for items with the
+    /// provided circ modifier, or None if the modifier has no policy.
+    pub fn renewal_limit(&self, circ_modifier: &str) -> Option<u32> {
+        self.renewal_policy.get(circ_modifier).copied()
+    }
+    /// Refuse checkouts of items already checked out to the same
+    /// patron when the SIP renewal policy flag is unset.
+    pub fn block_duplicate_checkout(&self) -> bool {
+        self.block_duplicate_checkout
+    }
+    /// True if the named operation (e.g. "checkout", "fee-paid")
+    /// requires a verified patron password.
+    pub fn password_required(&self, operation: &str) -> bool {
+        self.password_required.iter().any(|o| o.eq(operation))
+    }
+    /// Returns the patron password (AD) to verify for the named operation.
+    ///
+    /// Passwords are only verified for operations which require one, so
+    /// a mistyped password sent with other operations does not count
+    /// toward the password attempt lockout.
+    pub fn password_to_verify<'a>(
+        &self,
+        operation: &str,
+        password: Option<&'a str>,
+    ) -> Option<&'a str> {
+        password.filter(|_| self.password_required(operation))
+    }
+    /// True if the named operation must be refused because it requires
+    /// a verified patron password and the patron's was not verified.
+    pub fn password_refused(&self, operation: &str, verified: bool) -> bool {
+        self.password_required(operation) && !verified
+    }
+    /// True if item info responses for checked out items report
+    /// whether, or by how many days, the item is overdue.
+    pub fn item_info_overdue(&self) -> bool {
+        self.item_info_overdue_field.is_some() || self.item_info_days_overdue_field.is_some()
+    }
+    /// Item info field used for the Y/N overdue indicator.
+    pub fn item_info_overdue_field(&self) -> Option<&str> {
+        self.item_info_overdue_field.as_deref()
+    }
+    /// Item info field used for the number of days overdue.
+    pub fn item_info_days_overdue_field(&self) -> Option<&str> {
+        self.item_info_days_overdue_field.as_deref()
+    }
+    /// Report the owning library and shelving location as the item's
+    /// permanent location (AQ) and the transit destination as the
+    /// current location (AP) for items in transit.
+    pub fn item_info_locations(&self) -> bool {
+        self.item_info_locations
+    }
+    /// Add the item's call number (CS) to item info responses.
+    pub fn item_info_call_number(&self) -> bool {
+        self.item_info_call_number
+    }
+    /// Field code used to report the item's author in item info
+    /// responses.  SIP2 defines no author field, so this is vendor
+    /// specific.  None omits the author.
+    pub fn item_info_author_field(&self) -> Option<&str> {
+        self.item_info_author_field.as_deref()
+    }
+    /// Maximum length in characters of the title, author, and call
+    /// number values in item info responses.
+    pub fn item_info_max_field_length(&self) -> usize {
+        self.item_info_max_field_length
+    }
+    /// Maximum number of patron and item lookups a session may
+    /// perform within the heavy-op window.  None means no limit.
+    pub fn heavy_op_limit(&self) -> Option<usize> {
+        self.heavy_op_limit
+    }
+    /// Heavy-op limit window in seconds.
+    pub fn heavy_op_window(&self) -> u64 {
+        self.heavy_op_window
+    }
+    pub fn heavy_op_policy(&self) -> HeavyOpPolicy {
+        self.heavy_op_policy
+    }
+    /// Omit response fields which are not supported by the SIP
+    /// protocol version the client reports in SC Status.
+    pub fn protocol_version_fields(&self) -> bool {
+        self.protocol_version_fields
+    }
+    pub fn recall_policy(&self) -> &RecallPolicy {
+        &self.recall_policy
+    }
+    /// Read patron summaries from the primary database after this
+    /// session makes a payment.
+    pub fn refresh_stale_summaries(&self) -> bool {
+        self.refresh_stale_summaries
+    }
+    /// Apply Unicode normalization and strip invisible characters
+    /// from patron and item barcodes in SIP requests.
+    pub fn normalize_barcodes(&self) -> bool {
+        self.normalize_barcodes
+    }
+    /// Report the patron's fine threshold (CC fee limit) in patron
+    /// info responses.
+    pub fn patron_fee_limit(&self) -> bool {
+        self.patron_fee_limit
+    }
+    /// Checkin ok flag reported in ACS Status responses.
+    pub fn checkin_ok(&self) -> bool {
+        self.checkin_ok
+    }
+    /// Checkout ok flag reported in ACS Status responses.
+    pub fn checkout_ok(&self) -> bool {
+        self.checkout_ok
+    }
+    /// Renewal policy flag reported in ACS Status responses.
+    pub fn renewal_ok(&self) -> bool {
+        self.renewal_ok
+    }
+    /// Screen message template for the named patron block, e.g.
+    /// "expired", "charge-denied", "holds-denied", "max-fines".
+    pub fn patron_block_message(&self, block: &str) -> Option<&str> {
+        self.patron_block_messages.get(block).map(|m| m.as_str())
+    }
+    /// True if any patron block message template lists the patron's
+    /// blocking penalties via {penalties}.
+    pub fn patron_block_penalties_wanted(&self) -> bool {
+        self.patron_block_messages
+            .values()
+            .any(|m| m.contains("{penalties}"))
+    }
+    /// Non-cataloged item type for checkouts of items whose barcode
+    /// starts with a configured prefix.  The first match wins.
+    pub fn noncat_type_for_barcode(&self, barcode: &str) -> Option<i64> {
+        self.noncat_prefixes
+            .iter()
+            .find(|(prefix, _)| barcode.starts_with(prefix.as_str()))
+            .map(|(_, t)| *t)
+    }
+    /// Number of failed patron password attempts allowed within the
+    /// lockout window.  None means attempts are not limited.
+    pub fn password_max_attempts(&self) -> Option<usize> {
+        self.password_max_attempts
+    }
+    /// Seconds after the first failed password attempt before the
+    /// patron's failure count resets.
+    pub fn password_lockout_window(&self) -> u64 {
+        self.password_lockout_window
+    }
+    /// Report the number of password attempts remaining (XA) in
+    /// patron status and patron info responses.
+    pub fn password_attempts_report(&self) -> bool {
+        self.password_attempts_report
+    }
+    /// Patron passwords longer than this many characters, after
+    /// trailing pad characters are removed, are rejected without
+    /// being verified.
+    pub fn password_max_length(&self) -> Option<usize> {
+        self.password_max_length
+    }
+    /// Add the patron expire date (PA) to patron status responses.
+    /// Patron info responses always include it.
+    pub fn patron_status_expire_date(&self) -> bool {
+        self.patron_status_expire_date
+    }
+    /// Report the patron expire date (PA) in SIP date format in the
+    /// local time zone instead of YYYYMMDD.
+    pub fn expire_date_use_sip_date_format(&self) -> bool {
+        self.expire_date_use_sip_date_format
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -187,6 +529,10 @@ pub struct SipAccount {
     workstation: Option<String>,
     activity_as: Option<String>,
     checkin_block_on_checked_out: bool,
+    log_activity: bool,
+    org_unit: Option<String>,
+    org_unit_id: Option<i64>,
+    patron_enable_penalties: Vec<String>,
 }
 
 impl SipAccount {
@@ -205,6 +551,10 @@ impl SipAccount {
             workstation: None,
             activity_as: None,
             checkin_block_on_checked_out: false,
+            log_activity: true,
+            org_unit: None,
+            org_unit_id: None,
+            patron_enable_penalties: Vec::new(),
         }
     }
 
@@ -236,6 +586,36 @@ impl SipAccount {
     pub fn checkin_block_on_checked_out(&self) -> bool {
         self.checkin_block_on_checked_out
     }
+    /// Record patron lookups in actor.usr_activity.
+    pub fn log_activity(&self) -> bool {
+        self.log_activity
+    }
+    /// Org unit shortname or ID this account circulates at.
+    ///
+    /// When unset, the workstation (or ILS user home) org is used.
+    pub fn org_unit(&self) -> Option<&str> {
+        self.org_unit.as_deref()
+    }
+    /// ID of the org_unit, once resolved at login.
+    pub fn org_unit_id(&self) -> Option<i64> {
+        self.org_unit_id
+    }
+    pub fn set_org_unit_id(&mut self, id: i64) {
+        self.org_unit_id = Some(id)
+    }
+    /// Standing penalty types (config.standing_penalty names or IDs)
+    /// which Patron Enable requests may remove.  Empty means Patron
+    /// Enable is not permitted for this account.
+    pub fn patron_enable_penalties(&self) -> &Vec<String> {
+        &self.patron_enable_penalties
+    }
+}
+
+/// Format of activity log entries.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActivityLogFormat {
+    Text,
+    Json,
 }
 
 /// Global SIP configuration.
@@ -250,6 +630,14 @@ pub struct Config {
     setting_groups: HashMap<String, SipSettings>,
     accounts: HashMap<String, SipAccount>,
     sc_status_before_login: bool,
+    structured_errors: bool,
+    refuse_before_login: bool,
+    retry_reads: bool,
+    settings_max_age: u64,
+    activity_log: Option<ActivityLogFormat>,
+    activity_log_redact: bool,
+    tls_cert_file: Option<String>,
+    tls_key_file: Option<String>,
     currency: String,
     source: Option<yaml_rust::Yaml>,
 }
@@ -267,6 +655,14 @@ impl Config {
             accounts: HashMap::new(),
             currency: "USD".to_string(),
             sc_status_before_login: false,
+            structured_errors: false,
+            retry_reads: false,
+            settings_max_age: 60,
+            activity_log: None,
+            activity_log_redact: true,
+            tls_cert_file: None,
+            tls_key_file: None,
+            refuse_before_login: false,
             source: None,
         }
     }
@@ -276,15 +672,19 @@ impl Config {
         let yaml_text = fs::read_to_string(filename)
             .or_else(|e| Err(format!("Error reading YAML configuration file: {e}")))?;
 
-        let mut yaml_docs = YamlLoader::load_from_str(&yaml_text)
+        self.read_yaml_str(&yaml_text)
+            .map_err(|e| format!("{e} for config {filename}"))
+    }
+
+    /// Parse a YAML configuration string.
+    pub fn read_yaml_str(&mut self, yaml_text: &str) -> Result<(), String> {
+        let mut yaml_docs = YamlLoader::load_from_str(yaml_text)
             .or_else(|e| Err(format!("Error parsing configuration file as YAML: {e}")))?;
 
         let root = if yaml_docs.len() > 0 {
             yaml_docs.remove(0)
         } else {
-            return Err(format!(
-                "Error unpacking YAML document for config {filename}"
-            ));
+            return Err(format!("Error unpacking YAML document"));
         };
 
         if let Some(v) = root["sip-address"].as_str() {
@@ -315,6 +715,45 @@ impl Config {
             self.sc_status_before_login = v;
         }
 
+        if let Some(v) = root["structured-errors"].as_bool() {
+            self.structured_errors = v;
+        }
+
+        if let Some(v) = root["refuse-before-login"].as_bool() {
+            self.refuse_before_login = v;
+        }
+
+        if let Some(v) = root["retry-reads"].as_bool() {
+            self.retry_reads = v;
+        }
+
+        if let Some(v) = root["settings-max-age"].as_i64() {
+            self.settings_max_age = v as u64;
+        }
+
+        match root["activity-log"].as_str() {
+            Some("text") => self.activity_log = Some(ActivityLogFormat::Text),
+            Some("json") => self.activity_log = Some(ActivityLogFormat::Json),
+            Some(s) => log::warn!("Unknown activity-log format: {s}"),
+            None => {}
+        }
+
+        if let Some(v) = root["activity-log-redact"].as_bool() {
+            self.activity_log_redact = v;
+        }
+
+        if let Some(v) = root["tls-cert-file"].as_str() {
+            self.tls_cert_file = Some(v.to_string());
+        }
+
+        if let Some(v) = root["tls-key-file"].as_str() {
+            self.tls_key_file = Some(v.to_string());
+        }
+
+        if self.tls_cert_file.is_some() != self.tls_key_file.is_some() {
+            return Err(format!("TLS requires both tls-cert-file and tls-key-file"));
+        }
+
         self.add_setting_groups(&root);
         self.add_accounts(&root)?;
 
@@ -352,6 +791,15 @@ impl Config {
                 "patron-status-permit-loans",
                 &mut grp.patron_status_permit_loans,
             );
+            set_bool(
+                group,
+                "patron-status-distinct-blocks",
+                &mut grp.patron_status_distinct_blocks,
+            );
+
+            if let Some(id) = group["patron-status-lost-penalty"].as_i64() {
+                grp.patron_status_lost_penalty = id;
+            }
             set_bool(
                 group,
                 "msg64-hold-items-available",
@@ -376,12 +824,107 @@ impl Config {
 
             set_bool(group, "use-native-checkin", &mut grp.use_native_checkin);
             set_bool(group, "use-native-checkout", &mut grp.use_native_checkout);
+            set_bool(group, "fee-paid-detail", &mut grp.fee_paid_detail);
+            set_bool(group, "item-info-locations", &mut grp.item_info_locations);
+            set_bool(
+                group,
+                "item-info-call-number",
+                &mut grp.item_info_call_number,
+            );
+            set_bool(
+                group,
+                "protocol-version-fields",
+                &mut grp.protocol_version_fields,
+            );
+            set_bool(
+                group,
+                "refresh-stale-summaries",
+                &mut grp.refresh_stale_summaries,
+            );
+            set_bool(group, "normalize-barcodes", &mut grp.normalize_barcodes);
+            set_bool(group, "checkin-ok", &mut grp.checkin_ok);
+            set_bool(group, "checkout-ok", &mut grp.checkout_ok);
+            set_bool(group, "renewal-ok", &mut grp.renewal_ok);
+            set_bool(group, "patron-fee-limit", &mut grp.patron_fee_limit);
+            set_bool(
+                group,
+                "block-duplicate-checkout",
+                &mut grp.block_duplicate_checkout,
+            );
+            set_bool(
+                group,
+                "password-attempts-report",
+                &mut grp.password_attempts_report,
+            );
+            set_bool(
+                group,
+                "patron-status-expire-date",
+                &mut grp.patron_status_expire_date,
+            );
+            set_bool(
+                group,
+                "expire-date-use-sip-date-format",
+                &mut grp.expire_date_use_sip_date_format,
+            );
+
+            if let Some(v) = group["password-max-attempts"].as_i64() {
+                grp.password_max_attempts = Some(v as usize);
+            }
+            if let Some(v) = group["password-lockout-window"].as_i64() {
+                grp.password_lockout_window = v as u64;
+            }
+            if let Some(v) = group["password-max-length"].as_i64() {
+                grp.password_max_length = Some(v as usize);
+            }
+
+            if let Some(v) = group["max-message-length"].as_i64() {
+                grp.max_message_length = Some(v as usize);
+            }
+
+            if let Some(s) = group["holds-ready-field"].as_str() {
+                grp.holds_ready_field = Some(s.to_string());
+            }
+            if let Some(s) = group["fee-paid-balance-field"].as_str() {
+                grp.fee_paid_balance_field = Some(s.to_string());
+            }
+            if let Some(s) = group["item-info-overdue-field"].as_str() {
+                grp.item_info_overdue_field = Some(s.to_string());
+            }
+            if let Some(s) = group["item-info-days-overdue-field"].as_str() {
+                grp.item_info_days_overdue_field = Some(s.to_string());
+            }
+            if let Some(s) = group["item-info-author-field"].as_str() {
+                grp.item_info_author_field = Some(s.to_string());
+            }
+            if let Some(v) = group["item-info-max-field-length"].as_i64() {
+                grp.item_info_max_field_length = v as usize;
+            }
+
+            if let Some(v) = group["heavy-op-limit"].as_i64() {
+                grp.heavy_op_limit = Some(v as usize);
+            }
+            if let Some(v) = group["heavy-op-window"].as_i64() {
+                grp.heavy_op_window = v as u64;
+            }
+            if let Some(s) = group["heavy-op-policy"].as_str() {
+                if s.to_lowercase().starts_with("d") {
+                    grp.heavy_op_policy = HeavyOpPolicy::Delay;
+                }
+            }
 
             if let Some(s) = group["msg64-hold-datatype"].as_str() {
-                if s.to_lowercase().starts_with("t") {
-                    grp.msg64_hold_datatype = Msg64HoldDatatype::Title;
+                grp.msg64_hold_datatype = s.into();
+            }
+            if let Some(types) = group["msg64-hold-fallback"].as_vec() {
+                for t in types {
+                    if let Some(s) = t.as_str() {
+                        grp.msg64_hold_fallback.push(s.into());
+                    }
                 }
             }
+            if let Some(s) = group["msg64-hold-placeholder"].as_str() {
+                grp.msg64_hold_placeholder = Some(s.to_string());
+            }
             if let Some(s) = group["msg64-summary-datatype"].as_str() {
                 if s.to_lowercase().starts_with("t") {
                     grp.msg64_summary_datatype = Msg64SummaryDatatype::Title;
@@ -390,6 +933,14 @@ impl Config {
             if let Some(s) = group["av-format"].as_str() {
                 grp.av_format = s.into();
             }
+            match group["recall-privilege"].as_str() {
+                Some("checkout") => grp.recall_policy = RecallPolicy::Checkout,
+                Some("penalty") => match group["recall-penalty"].as_i64() {
+                    Some(id) => grp.recall_policy = RecallPolicy::Penalty(id),
+                    None => log::warn!("recall-privilege 'penalty' requires recall-penalty"),
+                },
+                _ => {}
+            }
 
             if group["checkin-override"].is_array() {
                 for ovride in group["checkin-override"].as_vec().unwrap() {
@@ -424,6 +975,67 @@ impl Config {
                 }
             }
 
+            if let Some(profiles) = group["patron-status-permit-profiles"].as_vec() {
+                for profile in profiles {
+                    if let Some(name) = profile.as_str() {
+                        grp.patron_status_permit_profiles.push(name.to_string());
+                    }
+                }
+            }
+
+            if group["password-required"].is_array() {
+                for op in group["password-required"].as_vec().unwrap() {
+                    if let Some(op) = op.as_str() {
+                        grp.password_required.push(op.to_string());
+                    }
+                }
+            }
+
+            if group["renewal-policy"].is_array() {
+                for policy in group["renewal-policy"].as_vec().unwrap() {
+                    let modifier = match policy["circ-modifier"].as_str() {
+                        Some(m) => m.to_string(),
+                        None => continue,
+                    };
+
+                    // Renewals which are not allowed have a limit of zero.
+                    // Allowed renewals without a limit are left to the
+                    // circ policy matrix.
+                    if policy["renewals-allowed"].as_bool() == Some(false) {
+                        grp.renewal_policy.insert(modifier, 0);
+                    } else if let Some(max) = policy["max-renewals"].as_i64() {
+                        grp.renewal_policy.insert(modifier, max.max(0) as u32);
+                    }
+                }
+            }
+
+            if group["patron-block-messages"].is_array() {
+                for msg in group["patron-block-messages"].as_vec().unwrap() {
+                    let block = msg["block"].as_str();
+                    let message = msg["message"].as_str();
+
+                    if let (Some(b), Some(m)) = (block, message) {
+                        if !PATRON_BLOCKS.contains(&b) {
+                            log::warn!("Unknown patron block in patron-block-messages: {b}");
+                            continue;
+                        }
+                        grp.patron_block_messages
+                            .insert(b.to_string(), m.to_string());
+                    }
+                }
+            }
+
+            if group["noncat-barcode-prefixes"].is_array() {
+                for nc in group["noncat-barcode-prefixes"].as_vec().unwrap() {
+                    let prefix = nc["prefix"].as_str();
+                    let noncat_type = nc["noncat-type"].as_i64();
+
+                    if let (Some(p), Some(t)) = (prefix, noncat_type) {
+                        grp.noncat_prefixes.push((p.to_string(), t));
+                    }
+                }
+            }
+
             log::debug!("Adding setting group '{name}'");
             self.setting_groups.insert(name.to_string(), grp);
         }
@@ -453,12 +1065,28 @@ impl Config {
                 if let Some(ws) = account["activity-as"].as_str() {
                     acct.activity_as = Some(ws.to_string());
                 }
+                if let Some(org) = account["org-unit"].as_str() {
+                    acct.org_unit = Some(org.to_string());
+                } else if let Some(id) = account["org-unit"].as_i64() {
+                    acct.org_unit = Some(id.to_string());
+                }
 
                 set_bool(
                     &account,
                     "checkin-block-on-checked-out",
                     &mut acct.checkin_block_on_checked_out,
                 );
+                set_bool(&account, "log-activity", &mut acct.log_activity);
+
+                if account["patron-enable-penalties"].is_array() {
+                    for pen in account["patron-enable-penalties"].as_vec().unwrap() {
+                        if let Some(name) = pen.as_str() {
+                            acct.patron_enable_penalties.push(name.to_string());
+                        } else if let Some(id) = pen.as_i64() {
+                            acct.patron_enable_penalties.push(id.to_string());
+                        }
+                    }
+                }
 
                 self.accounts.insert(username.to_string(), acct);
             }
@@ -470,6 +1098,9 @@ impl Config {
     pub fn get_account(&self, username: &str) -> Option<&SipAccount> {
         self.accounts.get(username)
     }
+    pub fn accounts(&self) -> impl Iterator<Item = &SipAccount> {
+        self.accounts.values()
+    }
     pub fn currency(&self) -> &str {
         &self.currency
     }
@@ -494,4 +1125,41 @@ impl Config {
     pub fn sc_status_before_login(&self) -> bool {
         self.sc_status_before_login
     }
+    /// Log a JSON record for each failed SIP request.
+    pub fn structured_errors(&self) -> bool {
+        self.structured_errors
+    }
+    /// Reply to requests sent before login with a failure response
+    /// instead of ending the session.
+    pub fn refuse_before_login(&self) -> bool {
+        self.refuse_before_login
+    }
+    /// Retry Evergreen reads once when the connection was reset
+    /// while the session sat idle.
+    pub fn retry_reads(&self) -> bool {
+        self.retry_reads
+    }
+    /// Seconds a session may use cached Evergreen org unit settings
+    /// before checking whether they have changed.
+    pub fn settings_max_age(&self) -> u64 {
+        self.settings_max_age
+    }
+    /// Log an ACT line for each SIP request in this format.
+    /// None disables activity logging.
+    pub fn activity_log(&self) -> Option<ActivityLogFormat> {
+        self.activity_log
+    }
+    /// Mask patron and item barcodes in activity log entries and
+    /// structured error records.
+    pub fn activity_log_redact(&self) -> bool {
+        self.activity_log_redact
+    }
+    /// PEM certificate chain and private key files.  When set, SIP
+    /// clients must connect via TLS.  Otherwise, plain TCP is used.
+    pub fn tls_files(&self) -> Option<(&str, &str)> {
+        match (&self.tls_cert_file, &self.tls_key_file) {
+            (Some(c), Some(k)) => Some((c, k)),
+            _ => None,
+        }
+    }
 }
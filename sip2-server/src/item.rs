@@ -1,4 +1,7 @@
+use super::conf;
 use super::session::Session;
+use super::util;
+use eg::common::circ::OverduePreview;
 use eg::constants as C;
 use eg::date;
 use eg::result::EgResult;
@@ -14,10 +17,12 @@ pub struct Item {
     pub circ_status: &'static str,
     pub fee_type: &'static str,
     pub title: String,
+    pub author: Option<String>,
     pub current_loc: String,
     pub permanent_loc: String,
     pub destination_loc: String,
     pub owning_loc: String,
+    pub call_number: String,
     pub deposit_amount: f64,
     pub magnetic_media: bool,
     pub hold_queue_length: usize,
@@ -25,6 +30,14 @@ pub struct Item {
     pub hold_pickup_date: Option<String>,
     pub hold_patron_barcode: Option<String>,
     pub circ_patron_id: Option<i64>,
+    /// ID of the item's open circulation, if any.
+    pub circ_id: Option<i64>,
+    pub circ_modifier: Option<String>,
+    /// Renewals remaining on the item's open circulation, if any.
+    pub renewal_remaining: Option<i64>,
+    /// Number of days the item is overdue, zero if it's checked out
+    /// and not overdue, None if it's not checked out.
+    pub overdue_days: Option<i64>,
 }
 
 impl Session {
@@ -38,7 +51,7 @@ impl Session {
         let flesh = eg::hash! {
             flesh: 3,
             flesh_fields: {
-                acp: ["circ_lib", "call_number",
+                acp: ["circ_lib", "call_number", "location",
                     "stat_cat_entry_copy_maps", "circ_modifier"],
                 acn: ["owning_lib", "record"],
                 bre: ["simple_record"],
@@ -57,18 +70,29 @@ impl Session {
         let copy_status = copy["status"].int()?;
 
         let mut circ_patron_id: Option<i64> = None;
+        let mut circ_id: Option<i64> = None;
         let mut due_date: Option<String> = None;
+        let mut renewal_remaining: Option<i64> = None;
+        let mut overdue_days: Option<i64> = None;
 
         if let Some(circ) = self.get_copy_circ(&copy, copy_status)? {
             circ_patron_id = Some(circ["usr"].int()?);
+            circ_id = Some(circ.id()?);
+            renewal_remaining = circ["renewal_remaining"].as_int();
 
             if let Some(iso_date) = circ["due_date"].as_str() {
+                let due_dt = util::parse_timestamp(iso_date)?;
+
                 if self.account().settings().due_date_use_sip_date_format() {
-                    let due_dt = date::parse_datetime(iso_date)?;
-                    due_date = Some(sip2::util::sip_date_from_dt(&due_dt));
+                    due_date = Some(sip2::util::format_sip_date(&due_dt));
                 } else {
                     due_date = Some(iso_date.to_string());
                 }
+
+                if self.account().settings().item_info_overdue() {
+                    let now = self.circ_lib_now()?;
+                    overdue_days = Some(days_overdue(&due_dt, &now));
+                }
             }
         }
 
@@ -85,6 +109,9 @@ impl Session {
             dest_location = transit["dest"]["shortname"].as_str().unwrap().to_string();
         }
 
+        let (current_loc, permanent_loc) =
+            item_locations(self.account().settings(), copy, transit_op.as_ref());
+
         let mut hold_pickup_date_op: Option<String> = None;
         let mut hold_patron_barcode_op: Option<String> = None;
         let mut hold_queue_length = 0;
@@ -107,6 +134,15 @@ impl Session {
             }
         }
 
+        if copy_status == C::COPY_STATUS_CHECKED_OUT {
+            hold_queue_length = self.get_copy_hold_queue_length(copy)?;
+        }
+
+        let call_number = copy["call_number"]["label"]
+            .as_str()
+            .unwrap_or("")
+            .to_string();
+
         let deposit_amount = copy["deposit_amount"].float()?;
 
         let mut fee_type = "01";
@@ -121,14 +157,19 @@ impl Session {
             .as_str()
             .unwrap_or("001");
         let magnetic_media = copy["circ_modifier"]["magnetic_media"].boolish();
+        let circ_modifier = copy["circ_modifier"]["code"]
+            .as_str()
+            .map(|m| m.to_string());
 
-        let (title, _) = self.get_copy_title_author(&copy)?;
+        // Pre-cataloged items use their dummy title and author.
+        let (title, author) = self.get_copy_title_author(&copy)?;
         let title = title.unwrap_or(String::new());
 
         Ok(Some(Item {
             barcode: barcode.to_string(),
             due_date,
             title,
+            author,
             copy_status: copy_status,
             circ_lib: circ_lib_id,
             deposit_amount,
@@ -136,14 +177,19 @@ impl Session {
             magnetic_media,
             fee_type: fee_type,
             circ_status: circ_status,
-            current_loc: circ_lib.to_string(),
-            permanent_loc: circ_lib.to_string(),
+            current_loc,
+            permanent_loc,
             destination_loc: dest_location,
             owning_loc: owning_lib.to_string(),
+            call_number,
             media_type: media_type.to_string(),
             hold_pickup_date: hold_pickup_date_op,
             hold_patron_barcode: hold_patron_barcode_op,
             circ_patron_id,
+            circ_id,
+            circ_modifier,
+            renewal_remaining,
+            overdue_days,
         }))
     }
 
@@ -162,6 +208,8 @@ impl Session {
             }
         };
 
+        let max_len = self.account().settings().item_info_max_field_length();
+
         let mut resp = sip2::Message::from_values(
             &sip2::spec::M_ITEM_INFO_RESP,
             &[
@@ -172,7 +220,7 @@ impl Session {
             ],
             &[
                 ("AB", &item.barcode),
-                ("AJ", &item.title),
+                ("AJ", sip2::util::truncate_field(&item.title, max_len)),
                 ("AP", &item.current_loc),
                 ("AQ", &item.permanent_loc),
                 ("BG", &item.owning_loc),
@@ -189,6 +237,18 @@ impl Session {
         resp.maybe_add_field("CY", item.hold_patron_barcode.as_deref());
         resp.maybe_add_field("AH", item.due_date.as_deref());
 
+        if self.account().settings().item_info_call_number() && !item.call_number.is_empty() {
+            resp.add_field("CS", sip2::util::truncate_field(&item.call_number, max_len));
+        }
+
+        if let Some(code) = self.account().settings().item_info_author_field() {
+            if let Some(author) = item.author.as_deref() {
+                resp.add_field(code, sip2::util::truncate_field(author, max_len));
+            }
+        }
+
+        add_overdue_fields(self.account().settings(), &mut resp, item.overdue_days);
+
         Ok(resp)
     }
 
@@ -233,6 +293,33 @@ impl Session {
         Ok(holds.pop())
     }
 
+    /// Number of uncaptured holds currently targeting the copy.
+    fn get_copy_hold_queue_length(&mut self, copy: &EgValue) -> EgResult<usize> {
+        let query = eg::hash! {
+            select: {ahr: [{
+                column: "id",
+                transform: "count",
+                aggregate: 1,
+                alias: "count",
+            }]},
+            from: "ahr",
+            where: {
+                current_copy: copy.id()?,
+                capture_time: EgValue::Null,
+                cancel_time: EgValue::Null,
+                fulfillment_time: EgValue::Null,
+                frozen: "f",
+            }
+        };
+
+        let count = match self.editor_mut().json_query(query)?.first() {
+            Some(c) => c["count"].int()?,
+            None => 0,
+        };
+
+        Ok(count as usize)
+    }
+
     /// Find the active transit for a copy if one exists.
     fn get_copy_transit(&mut self, copy: &EgValue, copy_status: i64) -> EgResult<Option<EgValue>> {
         if copy_status != C::COPY_STATUS_IN_TRANSIT {
@@ -320,3 +407,63 @@ impl Session {
         Ok(circs.pop())
     }
 }
+
+/// Current (AP) and permanent (AQ) locations of a copy fleshed with its
+/// circ lib, location, and call number owning lib.
+///
+/// Both are the circ lib unless item-info-locations is enabled, in
+/// which case in-transit copies are reported at their destination and
+/// the permanent location includes the owning lib's shelving location.
+pub fn item_locations(
+    settings: &conf::SipSettings,
+    copy: &EgValue,
+    transit: Option<&EgValue>,
+) -> (String, String) {
+    let circ_lib = copy["circ_lib"]["shortname"].as_str().unwrap(); // required
+
+    if !settings.item_info_locations() {
+        return (circ_lib.to_string(), circ_lib.to_string());
+    }
+
+    let current_loc = match transit {
+        Some(t) => t["dest"]["shortname"].as_str().unwrap(), // required
+        None => circ_lib,
+    };
+
+    let owning_lib = copy["call_number"]["owning_lib"]["shortname"]
+        .as_str()
+        .unwrap(); // required
+
+    let permanent_loc = match copy["location"]["name"].as_str() {
+        Some(name) => format!("{owning_lib}: {name}"),
+        None => owning_lib.to_string(),
+    };
+
+    (current_loc.to_string(), permanent_loc)
+}
+
+/// Number of days a circulation due at `due_date` is overdue as of
+/// `now`, counting partial days as full days.  Zero if not overdue.
+pub fn days_overdue(due_date: &date::EgDate, now: &date::EgDate) -> i64 {
+    OverduePreview::calculate(due_date, now, 0.0, 0, 0.0, 0).days_overdue
+}
+
+/// Add the configured overdue indicator and days overdue fields to
+/// an item info response.  Items which are not checked out get neither.
+pub fn add_overdue_fields(
+    settings: &conf::SipSettings,
+    resp: &mut sip2::Message,
+    overdue_days: Option<i64>,
+) {
+    let Some(days) = overdue_days else {
+        return;
+    };
+
+    if let Some(code) = settings.item_info_overdue_field() {
+        resp.add_field(code, sip2::util::sip_bool(days > 0));
+    }
+
+    if let Some(code) = settings.item_info_days_overdue_field() {
+        resp.add_field(code, &format!("{days}"));
+    }
+}
@@ -2,7 +2,9 @@ use evergreen as eg;
 use mptc;
 use std::env;
 use std::path::Path;
+use std::process;
 
+mod attempts;
 mod checkin;
 mod checkout;
 mod conf;
@@ -11,6 +13,9 @@ mod patron;
 mod payment;
 mod server;
 mod session;
+#[cfg(test)]
+mod tests;
+mod tls;
 mod util;
 
 const DEFAULT_CONFIG_1: &str = "/usr/local/etc/eg-sip2-server.yml";
@@ -35,6 +40,12 @@ fn main() {
         panic!("No viable SIP2 Server Configuration Found");
     };
 
+    if eg::util::self_test_requested() {
+        let test = server::Server::self_test(config_file);
+        println!("{}", test.report());
+        process::exit(test.exit_code());
+    }
+
     let ctx = eg::init().expect("Evergreen Init");
 
     log::info!("SIP2 Server starting with config {config_file}");
@@ -48,6 +59,10 @@ fn main() {
     };
 
     // Support env vars as well?
+    // Each SIP session occupies one worker for its lifetime, so the
+    // worker cap is derived from max-clients.  Connections beyond
+    // max-clients are refused by the Server's session counter rather
+    // than left waiting for a worker to free up.
     let max_workers = stream.sip_config().max_clients();
     let min_workers = stream.sip_config().min_workers();
     let max_worker_requests = stream.sip_config().max_worker_requests();
@@ -1,9 +1,14 @@
+use super::attempts::PasswordAttempts;
 use super::conf;
 use super::session::Session;
+use super::util;
+use eg::common::penalty;
 use eg::date;
+use eg::idl::FleshBuilder;
 use eg::result::EgResult;
 use eg::EgValue;
 use evergreen as eg;
+use std::time::Duration;
 
 const EG_NULL: EgValue = EgValue::Null;
 const DEFAULT_LIST_ITEM_SIZE: usize = 10;
@@ -20,6 +25,16 @@ pub enum SummaryListType {
     Unsupported,
 }
 
+/// Subsets of a patron's open holds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum HoldFilter {
+    All,
+    /// On the shelf at the pickup library.
+    Ready,
+    /// Not on the shelf at the pickup library.
+    Unavailable,
+}
+
 #[derive(Debug, Clone)]
 pub struct SummaryListOptions {
     list_type: SummaryListType,
@@ -68,19 +83,27 @@ pub struct Patron {
     pub recall_denied: bool,
     pub holds_denied: bool,
     pub card_lost: bool,
+    pub max_charged: bool,
     pub max_overdue: bool,
+    pub max_claims_returned: bool,
+    pub max_lost: bool,
     pub max_fines: bool,
     pub recall_overdue: bool,
-    pub max_bills: bool,
     pub valid: bool,
     pub card_active: bool,
     pub balance_owed: f64,
+    /// Balance at which the patron is blocked for excessive fines.
+    pub fee_limit: Option<f64>,
     pub password_verified: bool,
+    /// Password attempts left before lockout, if attempts are limited.
+    pub password_attempts_remaining: Option<usize>,
     pub recall_count: usize,
     pub holds_count: usize,
     pub hold_ids: Vec<i64>,
     pub unavail_hold_ids: Vec<i64>,
     pub unavail_holds_count: usize,
+    /// Holds on the shelf at their pickup library.
+    pub holds_ready_count: usize,
     pub items_overdue_count: usize,
     pub items_overdue_ids: Vec<i64>,
     pub fine_count: usize,
@@ -95,6 +118,7 @@ pub struct Patron {
     pub home_lib: Option<String>,
     pub dob: Option<String>,
     pub expire_date: Option<String>,
+    pub expired: bool,
     pub net_access: Option<String>,
     pub profile: Option<String>,
     pub phone: Option<String>,
@@ -111,17 +135,22 @@ impl Patron {
             recall_denied: false,
             holds_denied: false,
             card_lost: false,
+            max_charged: false,
             max_overdue: false,
+            max_claims_returned: false,
+            max_lost: false,
             max_fines: false,
             recall_overdue: false,
-            max_bills: false,
             valid: false,
             card_active: false,
             balance_owed: 0.0,
+            fee_limit: None,
             password_verified: false,
+            password_attempts_remaining: None,
             recall_count: 0,
             holds_count: 0,
             unavail_holds_count: 0,
+            holds_ready_count: 0,
             items_overdue_count: 0,
             items_out_count: 0,
             fine_count: 0,
@@ -135,6 +164,7 @@ impl Patron {
             home_lib: None,
             dob: None,
             expire_date: None,
+            expired: false,
             net_access: None,
             profile: None,
             phone: None,
@@ -143,6 +173,14 @@ impl Patron {
 }
 
 impl Session {
+    /// Returns the total balance owed by a patron.
+    pub fn balance_summary(&mut self, user_id: i64) -> EgResult<f64> {
+        match self.editor_mut().retrieve("mous", user_id)? {
+            Some(summary) => util::parse_float_named("mous.balance_owed", &summary["balance_owed"]),
+            None => Ok(0.0),
+        }
+    }
+
     pub fn get_patron_details(
         &mut self,
         barcode: &str,
@@ -165,10 +203,9 @@ impl Session {
 
         patron.id = user.id()?;
         patron.password_verified = self.check_password(patron.id, password_op)?;
+        patron.password_attempts_remaining = self.password_attempts_remaining(patron.id);
 
-        if let Some(summary) = self.editor_mut().retrieve("mous", patron.id)? {
-            patron.balance_owed = summary["balance_owed"].float()?;
-        }
+        self.set_patron_summaries(&mut patron)?;
 
         if user["billing_address"].is_object() {
             patron.address = Some(self.format_address(&user["billing_address"]));
@@ -212,24 +249,78 @@ impl Session {
         }
 
         if let Some(expire) = user["expire_date"].as_str() {
-            if let Ok(date) = date::parse_datetime(expire) {
-                patron.expire_date = Some(date.format("%Y%m%d").to_string());
+            if let Ok(date) = util::parse_timestamp(expire) {
+                patron.expire_date = Some(format_expire_date(self.account().settings(), date));
             }
         }
 
         self.set_patron_privileges(&user, &mut patron)?;
-        self.set_patron_summary_items(&mut patron)?;
+
+        if self.account().settings().patron_fee_limit() {
+            let context_org = self.editor().perm_org();
+            patron.fee_limit = penalty::penalty_threshold(
+                self.editor_mut(),
+                user["profile"].id()?,
+                context_org,
+                penalty::PATRON_EXCEEDS_FINES,
+            )?;
+        }
 
         if let Some(ops) = summary_list_options {
             self.set_patron_summary_list_items(&mut patron, ops)?;
         }
 
-        self.log_activity(patron.id)?;
+        self.log_activity(patron.id, "verify");
 
         Ok(Some(patron))
     }
 
-    fn log_activity(&mut self, patron_id: i64) -> EgResult<()> {
+    /// Collect the patron's balance and circ/hold/fine summaries.
+    ///
+    /// If this session recently modified patron data and the account
+    /// is configured to refresh stale summaries, the summaries are read
+    /// within a transaction so they come from the primary database
+    /// instead of a replica which may lag behind it.
+    fn set_patron_summaries(&mut self, patron: &mut Patron) -> EgResult<()> {
+        let refresh = self.summaries_stale() && self.account().settings().refresh_stale_summaries();
+
+        if refresh {
+            log::debug!("{self} refreshing summaries for patron {}", patron.id);
+            self.editor_mut().xact_begin()?;
+        }
+
+        let result = self.balance_summary(patron.id).and_then(|balance| {
+            patron.balance_owed = balance;
+            self.set_patron_summary_items(patron)
+        });
+
+        if refresh {
+            self.editor_mut().rollback()?;
+
+            if result.is_ok() {
+                self.set_summaries_stale(false);
+            }
+        }
+
+        result
+    }
+
+    /// Record patron activity in actor.usr_activity.
+    ///
+    /// Failures are logged but otherwise ignored, since activity
+    /// logging should never prevent a SIP response.
+    pub fn log_activity(&mut self, patron_id: i64, action: &str) {
+        if !self.account().log_activity() {
+            return;
+        }
+
+        if let Err(e) = self.insert_activity(patron_id, action) {
+            log::warn!("{self} could not log activity for patron {patron_id}: {e}");
+            self.editor_mut().rollback().ok();
+        }
+    }
+
+    fn insert_activity(&mut self, patron_id: i64, action: &str) -> EgResult<()> {
         let who = self.account().activity_as().unwrap_or("sip2");
 
         let query = eg::hash! {
@@ -237,7 +328,7 @@ impl Session {
                 "actor.insert_usr_activity",
                 patron_id,
                 who,
-                "verify",
+                action,
                 "sip2", // ingress
             ]
         };
@@ -295,10 +386,11 @@ impl Session {
 
     fn add_fine_item(&mut self, xact: &EgValue) -> EgResult<String> {
         let is_circ = xact["xact_type"].as_str().unwrap().eq("circulation");
-        let last_btype = xact["last_billing_type"].as_str().unwrap(); // required
+        // Transactions with no billings have no last billing type.
+        let last_btype = xact["last_billing_type"].as_str().unwrap_or("");
 
         let xact_id = xact.id()?;
-        let balance_owed = xact["balance_owed"].float()?;
+        let balance_owed = util::parse_float_named("mbts.balance_owed", &xact["balance_owed"])?;
 
         let mut title: Option<String> = None;
         let mut author: Option<String> = None;
@@ -332,7 +424,9 @@ impl Session {
             conf::AvFormat::ThreeM | conf::AvFormat::SwyerA => {
                 line = format!("{} ${} \"{}\" ", xact_id, balance_owed, fee_type);
 
-                if is_circ {
+                // Fall back to the billing type label when there is
+                // no title to report.
+                if is_circ && !title.is_empty() {
                     line += title;
                 } else {
                     line += last_btype;
@@ -345,7 +439,7 @@ impl Session {
                     xact_id, balance_owed, fee_type
                 );
 
-                if is_circ {
+                if is_circ && !title.is_empty() {
                     line += &format!(", Title: {}", title);
                 } else {
                     line += &format!(", Title: {}", last_btype);
@@ -357,21 +451,19 @@ impl Session {
     }
 
     fn get_circ_title_author(&mut self, id: i64) -> EgResult<(Option<String>, Option<String>)> {
-        let flesh = eg::hash! {
-            flesh: 4,
-            flesh_fields: {
-                circ: ["target_copy"],
-                acp: ["call_number"],
-                acn: ["record"],
-                bre: ["simple_record"]
-            }
+        let flesh = FleshBuilder::new()
+            .depth(4)
+            .flesh("circ", &["target_copy"])
+            .flesh("acp", &["call_number"])
+            .flesh("acn", &["record"])
+            .flesh("bre", &["simple_record"])
+            .build()?;
+
+        let circ = match self.editor_mut().retrieve_with_ops("circ", id, flesh)? {
+            Some(c) => c,
+            None => return Ok((None, None)),
         };
 
-        let circ = self
-            .editor_mut()
-            .retrieve_with_ops("circ", id, flesh)?
-            .unwrap();
-
         self.get_copy_title_author(&circ["target_copy"])
     }
 
@@ -427,10 +519,9 @@ impl Session {
         let format = self.account().settings().msg64_summary_datatype();
 
         if format == &conf::Msg64SummaryDatatype::Barcode {
-            let flesh = eg::hash! {
-                flesh: 1,
-                flesh_fields: {circ: ["target_copy"]},
-            };
+            let flesh = FleshBuilder::new()
+                .flesh("circ", &["target_copy"])
+                .build()?;
 
             // If we have a circ ID, we have to have a circ.
             let circ = self
@@ -460,7 +551,16 @@ impl Session {
         summary_ops: &SummaryListOptions,
         unavail: bool,
     ) -> EgResult<()> {
-        let format = self.account().settings().msg64_hold_datatype().clone();
+        let settings = self.account().settings();
+
+        let mut datatypes = vec![settings.msg64_hold_datatype().clone()];
+        for datatype in settings.msg64_hold_fallback() {
+            if !datatypes.contains(datatype) {
+                datatypes.push(datatype.clone());
+            }
+        }
+
+        let placeholder = settings.msg64_hold_placeholder().map(str::to_string);
 
         let hold_ids = match unavail {
             true => &patron.unavail_hold_ids,
@@ -473,24 +573,29 @@ impl Session {
 
         for idx in offset..(offset + limit) {
             if let Some(id) = hold_ids.get(idx) {
-                trimmed_hold_ids.push(id);
+                trimmed_hold_ids.push(*id);
             }
         }
 
+        let holds = self.editor_mut().retrieve_multi("ahr", &trimmed_hold_ids)?;
+
         let mut hold_items: Vec<String> = Vec::new();
 
-        for hold_id in trimmed_hold_ids {
-            if let Some(hold) = self.editor_mut().retrieve("ahr", *hold_id)? {
-                if format == conf::Msg64HoldDatatype::Barcode {
-                    if let Some(copy) = self.find_copy_for_hold(&hold)? {
-                        hold_items.push(copy["barcode"].as_str().unwrap().to_string());
-                    }
-                } else {
-                    if let Some(title) = self.find_title_for_hold(&hold)? {
-                        hold_items.push(title);
-                    }
+        for hold in holds {
+            let hold_id = &hold["id"];
+
+            let mut label = None;
+            for datatype in datatypes.iter() {
+                label = self.hold_item_label(&hold, datatype)?;
+                if label.is_some() {
+                    break;
                 }
             }
+
+            match label.or_else(|| placeholder.clone()) {
+                Some(l) => hold_items.push(l),
+                None => log::debug!("{self} cannot report hold {hold_id} in holds list"),
+            }
         }
 
         patron.detail_items = Some(hold_items);
@@ -498,14 +603,42 @@ impl Session {
         Ok(())
     }
 
-    fn find_title_for_hold(&mut self, hold: &EgValue) -> EgResult<Option<String>> {
-        let hold_id = hold.id()?;
+    /// Describe a hold using the requested datatype.
+    ///
+    /// Returns None if the hold has no value for the datatype, e.g.
+    /// no copy is available to supply a barcode.
+    fn hold_item_label(
+        &mut self,
+        hold: &EgValue,
+        datatype: &conf::Msg64HoldDatatype,
+    ) -> EgResult<Option<String>> {
+        match datatype {
+            conf::Msg64HoldDatatype::Barcode => Ok(self
+                .find_copy_for_hold(hold)?
+                .and_then(|copy| copy["barcode"].as_str().map(str::to_string))),
+            conf::Msg64HoldDatatype::Title => self.find_title_for_hold(hold),
+            conf::Msg64HoldDatatype::BibId => {
+                Ok(self.find_bib_id_for_hold(hold)?.map(|id| id.to_string()))
+            }
+        }
+    }
+
+    fn find_bib_id_for_hold(&mut self, hold: &EgValue) -> EgResult<Option<i64>> {
+        let hold_id = util::parse_id_named("hold.id", &hold["id"])?;
         let bib_link = match self.editor_mut().retrieve("rhrr", hold_id)? {
             Some(l) => l,
             None => return Ok(None), // shouldn't be happen-able
         };
 
-        let bib_id = bib_link["bib_record"].int()?;
+        util::parse_id_named("rhrr.bib_record", &bib_link["bib_record"]).map(Some)
+    }
+
+    fn find_title_for_hold(&mut self, hold: &EgValue) -> EgResult<Option<String>> {
+        let bib_id = match self.find_bib_id_for_hold(hold)? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
         let search = eg::hash! {
             source: bib_id,
             name: "title",
@@ -525,12 +658,12 @@ impl Session {
     fn find_copy_for_hold(&mut self, hold: &EgValue) -> EgResult<Option<EgValue>> {
         if !hold["current_copy"].is_null() {
             // We have a captured copy.  Use it.
-            let copy_id = hold["current_copy"].int()?;
+            let copy_id = util::parse_id_named("hold.current_copy", &hold["current_copy"])?;
             return self.editor_mut().retrieve("acp", copy_id);
         }
 
         let hold_type = hold["hold_type"].as_str().unwrap(); // required
-        let hold_target = hold["target"].int()?;
+        let hold_target = util::parse_id_named("hold.target", &hold["target"])?;
 
         if hold_type.eq("C") || hold_type.eq("R") || hold_type.eq("F") {
             // These are all copy-level hold types
@@ -548,7 +681,7 @@ impl Session {
             let search = eg::hash! { metarecord: hold_target };
             let maps = self.editor_mut().search("mmrsm", search)?;
             for map in maps {
-                bre_ids.push(map["record"].int()?);
+                bre_ids.push(util::parse_id_named("mmrsm.record", &map["record"])?);
             }
         } else {
             bre_ids.push(hold_target);
@@ -590,6 +723,10 @@ impl Session {
         self.set_patron_hold_ids(patron, false, None, None)?;
         self.set_patron_hold_ids(patron, true, None, None)?;
 
+        if self.account().settings().holds_ready_field().is_some() {
+            self.set_patron_holds_ready_count(patron)?;
+        }
+
         if let Some(summary) = self.editor_mut().retrieve("ocirclist", patron.id)? {
             // overdue and out are packaged as comma-separated ID values.
             let overdue: Vec<i64> = summary["overdue"]
@@ -643,6 +780,45 @@ impl Session {
         self.editor_mut().search_with_ops("mbts", search, ops)
     }
 
+    /// Builds the "where" clause for a patron's open holds.
+    fn patron_hold_search(&self, patron_id: i64, filter: HoldFilter) -> EgValue {
+        let mut search = eg::hash! {
+            usr: patron_id,
+            fulfillment_time: EG_NULL,
+            cancel_time: EG_NULL,
+        };
+
+        match filter {
+            HoldFilter::All => {}
+            HoldFilter::Ready => {
+                search["current_shelf_lib"] = eg::hash! {"=": {"+ahr": "pickup_lib"}};
+            }
+            HoldFilter::Unavailable => {
+                search["-or"] = eg::array! [
+                  {current_shelf_lib: EG_NULL},
+                  {current_shelf_lib: {"!=": {"+ahr": "pickup_lib"}}}
+                ];
+            }
+        }
+
+        search
+    }
+
+    /// Count the patron's holds which are ready for pickup.
+    fn set_patron_holds_ready_count(&mut self, patron: &mut Patron) -> EgResult<()> {
+        let query = eg::hash! {
+            select: {ahr: [{column: "id", transform: "count", aggregate: 1, alias: "count"}]},
+            from: "ahr",
+            where: {"+ahr": self.patron_hold_search(patron.id, HoldFilter::Ready)},
+        };
+
+        if let Some(count) = self.editor_mut().json_query(query)?.first() {
+            patron.holds_ready_count = util::parse_id_named("ahr.count", &count["count"])? as usize;
+        }
+
+        Ok(())
+    }
+
     fn set_patron_hold_ids(
         &mut self,
         patron: &mut Patron,
@@ -650,20 +826,15 @@ impl Session {
         limit: Option<usize>,
         offset: Option<usize>,
     ) -> EgResult<()> {
-        let mut search = eg::hash! {
-            usr: patron.id,
-            fulfillment_time: EG_NULL,
-            cancel_time: EG_NULL,
+        let filter = if unavail {
+            HoldFilter::Unavailable
+        } else if self.account().settings().msg64_hold_items_available() {
+            HoldFilter::Ready
+        } else {
+            HoldFilter::All
         };
 
-        if unavail {
-            search["-or"] = eg::array! [
-              {current_shelf_lib: EG_NULL},
-              {current_shelf_lib: {"!=": {"+ahr": "pickup_lib"}}}
-            ];
-        } else if self.account().settings().msg64_hold_items_available() {
-            search["current_shelf_lib"] = eg::hash! {"=": {"+ahr": "pickup_lib"}};
-        }
+        let search = self.patron_hold_search(patron.id, filter);
 
         let mut query = eg::hash! {
             select: {ahr: ["id"]},
@@ -699,30 +870,29 @@ impl Session {
     }
 
     fn set_patron_privileges(&mut self, user: &EgValue, patron: &mut Patron) -> EgResult<()> {
-        let expire_date_str = user["expire_date"].as_str().unwrap(); // required
-        let expire_date = date::parse_datetime(&expire_date_str)?;
-
-        if expire_date < eg::date::now() {
-            // Patron is expired.  Don't bother checking other penalties, etc.
-
-            patron.charge_denied = true;
-            patron.renew_denied = true;
-            patron.recall_denied = true;
-            patron.holds_denied = true;
-
-            return Ok(());
-        }
-
-        if self.account().settings().patron_status_permit_all() {
-            // This setting group allows all patron actions regardless
-            // of penalties, fines, etc.
+        if patron_privileges_settled(self.account().settings(), user, patron)? {
             return Ok(());
         }
 
         let penalties = self.get_patron_penalties(patron.id)?;
 
+        let recall_policy = self.account().settings().recall_policy().clone();
+        let recall_penalty = match recall_policy {
+            conf::RecallPolicy::Penalty(id) => self.penalties_contain(id, &penalties)?,
+            _ => false,
+        };
+
+        // Penalty-based recall blocks apply regardless of other blocks.
+        patron.recall_denied = recall_policy.recall_denied(false, false, recall_penalty);
+
+        let lost_penalty = self.account().settings().patron_status_lost_penalty();
+
         patron.max_fines = self.penalties_contain(1, &penalties)?; // PATRON_EXCEEDS_FINES
         patron.max_overdue = self.penalties_contain(2, &penalties)?; // PATRON_EXCEEDS_OVERDUE_COUNT
+        patron.max_charged = self.penalties_contain(3, &penalties)?; // PATRON_EXCEEDS_CHECKOUT_COUNT
+        patron.max_lost = self.penalties_contain(lost_penalty, &penalties)?;
+        patron.max_claims_returned = self.claims_returned_exceeded(user)?;
+        patron.recall_overdue = self.has_recalled_overdues(patron)?;
         patron.card_active = user["card"]["active"].boolish();
 
         let blocked = user["barred"].boolish() || !user["active"].boolish() || !patron.card_active;
@@ -751,16 +921,69 @@ impl Session {
 
         // In evergreen, patrons cannot create Recall holds directly, but that
         // doesn't mean they would not have said privilege if the functionality
-        // existed.  Base the ability to perform recalls on whether they have
-        // checkout and holds privilege, since both would be needed for recalls.
-        patron.recall_denied = patron.charge_denied || patron.renew_denied;
+        // existed.  See RecallPolicy.
+        patron.recall_denied =
+            recall_policy.recall_denied(patron.charge_denied, patron.renew_denied, recall_penalty);
 
         Ok(())
     }
 
+    /// True if the patron has reached the claims returned limit
+    /// (circ.max_patron_claim_return_count) for their home library.
+    fn claims_returned_exceeded(&mut self, user: &EgValue) -> EgResult<bool> {
+        let count = user["claims_returned_count"].as_int().unwrap_or(0);
+        if count == 0 {
+            return Ok(false);
+        }
+
+        let home_ou = user["home_ou"].id()?;
+        let max = self
+            .settings_mut()
+            .get_value_at_org("circ.max_patron_claim_return_count", home_ou)?;
+
+        Ok(max.as_int().map(|m| count >= m).unwrap_or(false))
+    }
+
+    /// True if any of the patron's overdue items have been recalled.
+    ///
+    /// Evergreen does not flag recalled circulations.  When recalls
+    /// are enabled (circ.holds.recall_threshold), an overdue item is
+    /// treated as recalled if an open, uncaptured hold targets it.
+    fn has_recalled_overdues(&mut self, patron: &Patron) -> EgResult<bool> {
+        if patron.items_overdue_ids.is_empty() {
+            return Ok(false);
+        }
+
+        let circ_org = self.get_circ_org_id()?;
+
+        if self
+            .settings_mut()
+            .get_value_at_org("circ.holds.recall_threshold", circ_org)?
+            .is_null()
+        {
+            return Ok(false);
+        }
+
+        let query = eg::hash! {
+            select: {ahr: ["id"]},
+            from: {ahr: {circ: {field: "target_copy", fkey: "current_copy"}}},
+            where: {
+                "+ahr": {
+                    capture_time: EG_NULL,
+                    fulfillment_time: EG_NULL,
+                    cancel_time: EG_NULL,
+                },
+                "+circ": {id: patron.items_overdue_ids.clone()},
+            },
+            limit: 1,
+        };
+
+        Ok(!self.editor_mut().json_query(query)?.is_empty())
+    }
+
     fn penalties_contain(&self, penalty_id: i64, penalties: &Vec<EgValue>) -> EgResult<bool> {
         for pen in penalties.iter() {
-            let pen_id = pen.id()?;
+            let pen_id = util::parse_id_named("csp.id", &pen["id"])?;
             if pen_id == penalty_id {
                 return Ok(true);
             }
@@ -769,7 +992,7 @@ impl Session {
     }
 
     fn get_patron_penalties(&mut self, user_id: i64) -> EgResult<Vec<EgValue>> {
-        let ws_org = self.get_ws_org_id()?;
+        let circ_org = self.get_circ_org_id()?;
 
         let search = eg::hash! {
             select: {csp: ["id", "block_list"]},
@@ -791,7 +1014,7 @@ impl Session {
                                 }]
                             },
                             from: "aou",
-                            where: {id: ws_org}
+                            where: {id: circ_org}
                         }
                     }
                 }
@@ -804,15 +1027,22 @@ impl Session {
     fn get_user(&mut self, barcode: &str) -> EgResult<Option<EgValue>> {
         let search = eg::hash! { barcode: barcode };
 
-        let flesh = eg::hash! {
-            flesh: 3,
-            flesh_fields: {
-                ac: ["usr"],
-                au: ["billing_address", "mailing_address", "profile",
-                    "stat_cat_entries", "home_ou", "net_access_level"],
-                actscecm: ["stat_cat"]
-            }
-        };
+        let flesh = FleshBuilder::new()
+            .depth(3)
+            .flesh("ac", &["usr"])
+            .flesh(
+                "au",
+                &[
+                    "billing_address",
+                    "mailing_address",
+                    "profile",
+                    "stat_cat_entries",
+                    "home_ou",
+                    "net_access_level",
+                ],
+            )
+            .flesh("actscecm", &["stat_cat"])
+            .build()?;
 
         let mut cards = self.editor_mut().search_with_ops("ac", search, flesh)?;
 
@@ -826,14 +1056,48 @@ impl Session {
         Ok(Some(user))
     }
 
-    fn check_password(&mut self, user_id: i64, password_op: Option<&str>) -> EgResult<bool> {
-        let password = match password_op {
-            Some(p) => p,
-            None => return Ok(false),
-        };
+    pub fn check_password(&mut self, user_id: i64, password_op: Option<&str>) -> EgResult<bool> {
+        let settings = self.account().settings();
+
+        let password =
+            match password_precheck(settings, self.password_attempts(), user_id, password_op) {
+                Some(p) => p,
+                None => return Ok(false),
+            };
+
+        let max_op = settings.password_max_attempts();
+        let window = Duration::from_secs(settings.password_lockout_window());
 
         log::debug!("{self} verifying password for user ID {user_id}");
-        eg::common::user::verify_migrated_password(self.editor_mut(), user_id, password, false)
+        let verified = eg::common::user::verify_migrated_password(
+            self.editor_mut(),
+            user_id,
+            password,
+            false,
+        )?;
+
+        if let Some(max) = max_op {
+            if verified {
+                self.password_attempts().reset(user_id);
+            } else {
+                let remaining = self.password_attempts().fail(user_id, max, window);
+                log::info!(
+                    "{self} password failed for user ID {user_id}; {remaining} attempts left"
+                );
+            }
+        }
+
+        Ok(verified)
+    }
+
+    /// Number of password attempts the patron has left before lockout,
+    /// or None if password attempts are not limited.
+    pub fn password_attempts_remaining(&self, user_id: i64) -> Option<usize> {
+        let settings = self.account().settings();
+        let max = settings.password_max_attempts()?;
+        let window = Duration::from_secs(settings.password_lockout_window());
+
+        Some(self.password_attempts().remaining(user_id, max, window))
     }
 
     pub fn handle_patron_status(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
@@ -844,11 +1108,138 @@ impl Session {
         let password_op = msg.get_field_value("AD"); // optional
 
         let patron_op = self.get_patron_details(&barcode, password_op.as_deref(), None)?;
-        self.patron_response_common(
+        let mut resp = self.patron_response_common(
             &sip2::spec::M_PATRON_STATUS_RESP,
             &barcode,
             patron_op.as_ref(),
-        )
+        )?;
+
+        add_status_expire_date(self.account().settings(), &mut resp, patron_op.as_ref());
+
+        Ok(resp)
+    }
+
+    /// Patron Enable (25).
+    ///
+    /// Evergreen has no patron enable flag.  Instead, this removes any
+    /// active standing penalties of the types configured for the SIP
+    /// account (patron-enable-penalties), i.e. blocks applied manually
+    /// or via SIP.  Other penalties are left in place.
+    ///
+    /// Responds with the patron's status after the penalties are removed.
+    pub fn handle_patron_enable(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
+        let barcode = msg
+            .get_field_value("AA")
+            .ok_or_else(|| format!("handle_patron_enable() missing patron barcode"))?;
+
+        let password_op = msg.get_field_value("AD"); // optional
+
+        let mut patron = match self.get_patron_details(&barcode, password_op.as_deref(), None)? {
+            Some(p) => p,
+            None => {
+                return self.patron_response_common(
+                    &sip2::spec::M_PATRON_ENABLE_RESP,
+                    &barcode,
+                    None,
+                )
+            }
+        };
+
+        let screen_msg = if self.account().patron_enable_penalties().is_empty() {
+            log::info!("{self} Patron enable is not configured for this account");
+            Some("Patron enable is not permitted")
+        } else if self.account().settings().password_required("patron-enable")
+            && !patron.password_verified
+        {
+            log::info!("{self} Patron enable refused for {barcode}: password not verified");
+            Some("Invalid patron password")
+        } else {
+            self.remove_enable_penalties(patron.id)?
+        };
+
+        if screen_msg.is_none() {
+            // Refresh the patron's blocks.  Skip the password, which
+            // has already been checked.
+            if let Some(mut refreshed) = self.get_patron_details(&barcode, None, None)? {
+                refreshed.password_verified = patron.password_verified;
+                refreshed.password_attempts_remaining = patron.password_attempts_remaining;
+                patron = refreshed;
+            }
+        }
+
+        let mut resp = self.patron_response_common(
+            &sip2::spec::M_PATRON_ENABLE_RESP,
+            &barcode,
+            Some(&patron),
+        )?;
+
+        resp.maybe_add_field("AF", screen_msg);
+
+        Ok(resp)
+    }
+
+    /// Archive the patron's active standing penalties whose types are
+    /// removable via Patron Enable.
+    ///
+    /// Returns a screen message if the penalties could not be removed.
+    fn remove_enable_penalties(&mut self, user_id: i64) -> EgResult<Option<&'static str>> {
+        let mut penalty_ids = Vec::new();
+        let mut penalty_names = Vec::new();
+
+        for pen in self.account().patron_enable_penalties() {
+            match pen.parse::<i64>() {
+                Ok(id) => penalty_ids.push(id),
+                Err(_) => penalty_names.push(pen.to_string()),
+            }
+        }
+
+        if !penalty_names.is_empty() {
+            let query = eg::hash! {name: penalty_names};
+            for csp in self.editor_mut().search("csp", query)? {
+                penalty_ids.push(csp.id()?);
+            }
+        }
+
+        let query = eg::hash! {
+            usr: user_id,
+            standing_penalty: penalty_ids,
+            "-or": [
+                {stop_date: EG_NULL},
+                {stop_date: {">": "now"}},
+            ],
+        };
+
+        // Standalone transaction; cloning is just easier here.
+        let mut editor = self.editor().clone();
+        editor.xact_begin()?;
+
+        let penalties = editor.search("ausp", query)?;
+        let count = penalties.len();
+
+        for mut pen in penalties {
+            if !editor.allowed_at("UPDATE_USER", pen["org_unit"].int()?)? {
+                log::warn!(
+                    "{self} Not allowed to remove penalty {} for patron {user_id}",
+                    pen.id()?
+                );
+                editor.rollback()?;
+                return Ok(Some("Patron enable is not permitted"));
+            }
+
+            pen["stop_date"] = EgValue::from(date::to_iso(&date::now()));
+            editor.update(pen)?;
+        }
+
+        editor.commit()?;
+
+        log::info!("{self} Patron enable removed {count} penalties for patron {user_id}");
+
+        if count > 0 {
+            // Patron data may now lag behind on replicas.
+            self.set_summaries_stale(true);
+        }
+
+        Ok(None)
     }
 
     pub fn handle_patron_info(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
@@ -924,6 +1315,10 @@ impl Session {
         resp.maybe_add_field("PI", patron.net_access.as_deref());
         resp.maybe_add_field("PC", patron.profile.as_deref());
 
+        if let Some(limit) = patron.fee_limit {
+            resp.add_field("CC", &format!("{limit:.2}"));
+        }
+
         if let Some(detail_items) = patron.detail_items {
             let code = match list_type {
                 SummaryListType::HoldItems => "AS",
@@ -941,12 +1336,11 @@ impl Session {
     }
 
     fn patron_response_common(
-        &self,
+        &mut self,
         msg_spec: &'static sip2::spec::Message,
         barcode: &str,
         patron_op: Option<&Patron>,
     ) -> EgResult<sip2::Message> {
-        let sbool = |v| sip2::util::space_bool(v); // local shorthand
         let sipdate = sip2::util::sip_date_now();
 
         if patron_op.is_none() {
@@ -980,23 +1374,29 @@ impl Session {
 
         let patron = patron_op.unwrap();
 
-        let summary = format!(
-            "{}{}{}{}{}{}{}{}{}{}{}{}{}{}",
-            sbool(patron.charge_denied),
-            sbool(patron.renew_denied),
-            sbool(patron.recall_denied),
-            sbool(patron.holds_denied),
-            sbool(!patron.card_active),
-            " ", // max charged
-            sbool(patron.max_overdue),
-            " ", // max renewals
-            " ", // max claims returned
-            " ", // max lost
-            sbool(patron.max_fines),
-            sbool(patron.max_fines),
-            " ", // recall overdue
-            sbool(patron.max_fines)
-        );
+        // Without distinct blocks, the fines penalty is reported as
+        // excessive fines, excessive fees, and too many items billed.
+        // Evergreen has no separate fees or items billed limits.
+        let legacy_fines =
+            !self.account().settings().patron_status_distinct_blocks() && patron.max_fines;
+
+        let summary = sip2::util::PatronStatusFlags {
+            charge_denied: patron.charge_denied,
+            renew_denied: patron.renew_denied,
+            recall_denied: patron.recall_denied,
+            holds_denied: patron.holds_denied,
+            card_lost: !patron.card_active,
+            max_charged: patron.max_charged,
+            max_overdue: patron.max_overdue,
+            max_renewals: false, // no patron-level renewal limit
+            max_claims_returned: patron.max_claims_returned,
+            max_lost: patron.max_lost,
+            max_fines: patron.max_fines,
+            max_fees: legacy_fines,
+            recall_overdue: patron.recall_overdue,
+            max_billed: legacy_fines,
+        }
+        .to_sip();
 
         let mut resp = sip2::Message::from_values(
             msg_spec,
@@ -1026,10 +1426,35 @@ impl Session {
 
         resp.maybe_add_field("BD", patron.address.as_deref());
         resp.maybe_add_field("BE", patron.email.as_deref());
+        let penalties = self.patron_block_penalties(&patron)?;
+        let block_message = patron_block_message(self.account().settings(), &patron, &penalties);
+        resp.maybe_add_field("AF", block_message.as_deref());
+
+        add_holds_ready_field(self.account().settings(), &mut resp, &patron);
+
+        if self.account().settings().password_attempts_report() {
+            if let Some(remaining) = patron.password_attempts_remaining {
+                resp.add_field("XA", &format!("{remaining}"));
+            }
+        }
 
         Ok(resp)
     }
 
+    /// Labels of the patron's blocking penalties, fetched only when
+    /// the patron is blocked and a block message template wants them.
+    fn patron_block_penalties(&mut self, patron: &Patron) -> EgResult<Vec<String>> {
+        let blocked =
+            patron.expired || patron.charge_denied || patron.holds_denied || patron.max_fines;
+
+        if !blocked || !self.account().settings().patron_block_penalties_wanted() {
+            return Ok(Vec::new());
+        }
+
+        let org_id = self.get_circ_org_id()?;
+        penalty::active_block_descriptions(self.editor_mut(), patron.id, org_id)
+    }
+
     pub fn handle_end_patron_session(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
         let resp = sip2::Message::from_values(
             &sip2::spec::M_END_PATRON_SESSION_RESP,
@@ -1044,3 +1469,181 @@ impl Session {
         Ok(resp)
     }
 }
+
+/// Clean up a patron password for verification.
+///
+/// Returns None if the password must be refused without checking it
+/// against the ILS: it's missing, empty, too long, or the patron has
+/// exhausted their password attempts.
+pub fn password_precheck<'a>(
+    settings: &conf::SipSettings,
+    attempts: &PasswordAttempts,
+    user_id: i64,
+    password_op: Option<&'a str>,
+) -> Option<&'a str> {
+    // Some SIP clients send fixed-width AD fields padded
+    // with spaces or null bytes.
+    let password = password_op?.trim_end_matches([' ', '\0']);
+
+    if password.is_empty() || password.contains('\0') {
+        return None;
+    }
+
+    if let Some(max_len) = settings.password_max_length() {
+        if password.chars().count() > max_len {
+            log::warn!("Password for user ID {user_id} exceeds max length {max_len}");
+            return None;
+        }
+    }
+
+    if let Some(max) = settings.password_max_attempts() {
+        let window = Duration::from_secs(settings.password_lockout_window());
+
+        if attempts.remaining(user_id, max, window) == 0 {
+            log::warn!("Password attempts exhausted for user ID {user_id}");
+            return None;
+        }
+    }
+
+    Some(password)
+}
+
+/// Compose a screen message explaining why the patron is blocked
+/// from the configured block message templates.
+///
+/// Templates may contain {balance}, {expire_date}, and {penalties},
+/// the labels of the patron's blocking penalties.  Returns None if the
+/// patron is not blocked or no templates apply.
+pub fn patron_block_message(
+    settings: &conf::SipSettings,
+    patron: &Patron,
+    penalties: &[String],
+) -> Option<String> {
+    // Expired patrons are denied everything without any further
+    // checks, so the expiration is the only reason worth giving.
+    let blocks: &[(&str, bool)] = if patron.expired {
+        &[("expired", true)]
+    } else {
+        &[
+            ("charge-denied", patron.charge_denied),
+            ("holds-denied", patron.holds_denied),
+            ("max-fines", patron.max_fines),
+        ]
+    };
+
+    let balance = format!("{:.2}", patron.balance_owed);
+    let expire_date = patron.expire_date.as_deref().unwrap_or("");
+    let penalties = penalties.join(", ");
+
+    let messages: Vec<String> = blocks
+        .iter()
+        .filter(|(_, blocked)| *blocked)
+        .filter_map(|(block, _)| settings.patron_block_message(block))
+        .map(|t| {
+            t.replace("{balance}", &balance)
+                .replace("{expire_date}", expire_date)
+                .replace("{penalties}", &penalties)
+        })
+        .collect();
+
+    if messages.is_empty() {
+        None
+    } else {
+        Some(messages.join(" "))
+    }
+}
+
+/// Apply the patron privileges which need no penalty evaluation.
+///
+/// Returns true if the privileges are settled: the patron is expired,
+/// or the setting group or the patron's profile permits all actions.
+/// Otherwise the patron's penalties still need evaluating.
+pub fn patron_privileges_settled(
+    settings: &conf::SipSettings,
+    user: &EgValue,
+    patron: &mut Patron,
+) -> EgResult<bool> {
+    let expire_date_str = user["expire_date"].as_str().unwrap(); // required
+
+    // An unparseable expire date should not prevent the patron
+    // from being looked up.  Treat them as non-expired.
+    let expired = match util::parse_timestamp(expire_date_str) {
+        Ok(d) => d < eg::date::now(),
+        Err(e) => {
+            log::warn!("Cannot parse expire date for user {}: {e}", user.id()?);
+            false
+        }
+    };
+
+    if expired {
+        // Patron is expired.  Don't bother checking other penalties, etc.
+
+        patron.expired = true;
+        patron.charge_denied = true;
+        patron.renew_denied = true;
+        patron.recall_denied = true;
+        patron.holds_denied = true;
+
+        return Ok(true);
+    }
+
+    if settings.patron_status_permit_all() {
+        // This setting group allows all patron actions regardless
+        // of penalties, fines, etc.
+        return Ok(true);
+    }
+
+    if let Some(profile) = user["profile"]["name"].as_str() {
+        if settings
+            .patron_status_permit_profiles()
+            .iter()
+            .any(|p| p == profile)
+        {
+            // Trusted profiles skip penalty evaluation.
+            log::debug!("Skipping penalty checks for profile {profile}");
+            patron.card_active = user["card"]["active"].boolish();
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Add the count of holds ready for pickup to a patron response,
+/// if a field is configured for it.
+pub fn add_holds_ready_field(
+    settings: &conf::SipSettings,
+    resp: &mut sip2::Message,
+    patron: &Patron,
+) {
+    if let Some(code) = settings.holds_ready_field() {
+        resp.add_field(code, &sip2::util::sip_count4(patron.holds_ready_count));
+    }
+}
+
+/// Format a patron expire date per the account settings.
+pub fn format_expire_date(settings: &conf::SipSettings, date: date::EgDate) -> String {
+    if settings.expire_date_use_sip_date_format() {
+        sip2::util::format_sip_date(&date::to_local_timezone_fixed(date))
+    } else {
+        date.format("%Y%m%d").to_string()
+    }
+}
+
+/// Add the patron expire date (PA) to a patron status response, if
+/// enabled for the account.
+///
+/// Only the card expire date is reported.  Evergreen profiles have no
+/// privilege expiration of their own; the profile's permission
+/// interval only seeds the expire date of new accounts.
+pub fn add_status_expire_date(
+    settings: &conf::SipSettings,
+    resp: &mut sip2::Message,
+    patron_op: Option<&Patron>,
+) {
+    if settings.patron_status_expire_date() {
+        if let Some(patron) = patron_op {
+            resp.maybe_add_field("PA", patron.expire_date.as_deref());
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::conf::SipSettings;
 use super::patron::Patron;
 use super::session::Session;
 use eg::result::EgResult;
@@ -5,9 +6,16 @@ use eg::EgValue;
 use evergreen as eg;
 
 pub struct PaymentResult {
-    success: bool,
-    patron_barcode: String,
-    screen_msg: Option<String>,
+    pub success: bool,
+    pub patron_barcode: String,
+    pub screen_msg: Option<String>,
+    /// (transaction ID, amount) for each payment applied.
+    pub payments: Vec<(i64, f64)>,
+    /// Patron balance after payments are applied.
+    pub balance_owed: Option<f64>,
+    /// Portion of the payment amount that could not be applied
+    /// to any transaction.
+    pub amount_unapplied: f64,
 }
 
 impl PaymentResult {
@@ -16,10 +24,49 @@ impl PaymentResult {
             success: false,
             screen_msg: None,
             patron_barcode: patron_barcode.to_string(),
+            payments: Vec::new(),
+            balance_owed: None,
+            amount_unapplied: 0.0,
         }
     }
 }
 
+/// Create the fee paid response for a payment result.
+pub fn payment_response(settings: &SipSettings, result: &PaymentResult) -> sip2::Message {
+    let mut resp = sip2::Message::from_values(
+        &sip2::spec::M_FEE_PAID_RESP,
+        &[
+            sip2::util::sip_bool(result.success),
+            &sip2::util::sip_date_now(),
+        ],
+        &[
+            ("AA", &result.patron_barcode),
+            ("AO", settings.institution()),
+        ],
+    )
+    .unwrap();
+
+    resp.maybe_add_field("AF", result.screen_msg.as_deref());
+
+    if let Some(balance) = result.balance_owed {
+        // One print line per payment so the self-check can
+        // print a receipt.
+        for (xact_id, amount) in result.payments.iter() {
+            resp.add_field("AG", &format!("Paid {amount:.2} on transaction {xact_id}"));
+        }
+
+        let balance = format!("{balance:.2}");
+
+        resp.add_field("AG", &format!("Balance owed {balance}"));
+
+        if let Some(code) = settings.fee_paid_balance_field() {
+            resp.add_field(code, &balance);
+        }
+    }
+
+    resp
+}
+
 impl Session {
     pub fn handle_payment(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
         self.set_authtoken()?;
@@ -72,6 +119,14 @@ impl Session {
         let mut user = cards[0]["usr"].take();
         user["card"] = cards.remove(0);
 
+        if self.account().settings().password_required("fee-paid")
+            && !self.check_password(user.id()?, msg.get_field_value("AD"))?
+        {
+            log::info!("{self} Payment refused for {patron_barcode}: password not verified");
+            result.screen_msg = Some("Invalid patron password".to_string());
+            return Ok(self.compile_payment_response(&result));
+        }
+
         let payments: Vec<(i64, f64)>;
 
         // Caller can request to pay toward a specific transaction or have
@@ -102,27 +157,21 @@ impl Session {
             payments,
         )?;
 
+        if result.success && result.amount_unapplied > 0.0 {
+            // Payments were applied, but not for the full amount.
+            result.success = false;
+        }
+
+        if result.payments.len() > 0 && self.account().settings().fee_paid_detail() {
+            result.balance_owed = Some(self.balance_summary(user.id()?)?);
+        }
+
         Ok(self.compile_payment_response(&result))
     }
 
     /// Create the SIP response message
     fn compile_payment_response(&self, result: &PaymentResult) -> sip2::Message {
-        let mut resp = sip2::Message::from_values(
-            &sip2::spec::M_FEE_PAID_RESP,
-            &[
-                sip2::util::sip_bool(result.success),
-                &sip2::util::sip_date_now(),
-            ],
-            &[
-                ("AA", &result.patron_barcode),
-                ("AO", self.account().settings().institution()),
-            ],
-        )
-        .unwrap();
-
-        resp.maybe_add_field("AF", result.screen_msg.as_deref());
-
-        resp
+        payment_response(self.account().settings(), result)
     }
 
     /// Caller wants to pay a specific transaction by ID.  Make sure that's
@@ -214,7 +263,12 @@ impl Session {
         }
 
         if amount_remaining > 0.0 {
-            result.screen_msg = Some("Overpayment not allowed".to_string());
+            result.amount_unapplied = amount_remaining;
+            result.screen_msg = Some(format!(
+                "Overpayment not allowed. Applied {:.2} of {:.2}",
+                pay_amount - amount_remaining,
+                pay_amount
+            ));
             return Ok(payments);
         }
 
@@ -254,7 +308,7 @@ impl Session {
         };
 
         let mut pay_array = eg::array![];
-        for p in payments {
+        for p in payments.iter() {
             let sub_array = eg::array![p.0, p.1];
             pay_array.push(sub_array).ok();
         }
@@ -309,6 +363,10 @@ impl Session {
             }
         } else {
             result.success = true;
+            result.payments = payments;
+
+            // Patron fine summaries may lag behind the payment.
+            self.set_summaries_stale(true);
         }
 
         Ok(())
@@ -1,6 +1,9 @@
+use super::attempts::PasswordAttempts;
 use super::conf;
 use super::conf::Config;
 use super::session::Session;
+use super::tls;
+use eg::common::auth;
 use eg::osrf;
 use eg::EgValue;
 use evergreen as eg;
@@ -8,13 +11,48 @@ use mptc;
 use std::any::Any;
 use std::collections::HashMap;
 use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// If we get this many TCP errors in a row, with no successful connections
 /// in between, exit.
 const MAX_TCP_ERRORS: usize = 100;
 
+/// Counts connected SIP sessions so connections beyond max-clients
+/// can be refused.  Clones share the same count.
+#[derive(Clone, Default)]
+pub struct SessionCounter {
+    active: Arc<AtomicUsize>,
+}
+
+impl SessionCounter {
+    pub fn new() -> SessionCounter {
+        Default::default()
+    }
+
+    /// Claim a session slot, returning the new number of active
+    /// sessions, or None if `max` sessions are already active.
+    pub fn claim(&self, max: usize) -> Option<usize> {
+        let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if active > max {
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(active)
+    }
+
+    /// Free a slot claimed with claim().
+    pub fn release(&self) {
+        self.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
 /// Wraps the TCP stream created by the initial connection from a SIP client.
 struct SipConnectRequest {
     stream: Option<TcpStream>,
@@ -37,6 +75,12 @@ impl mptc::Request for SipConnectRequest {
 pub struct SessionFactory {
     shutdown: Arc<AtomicBool>,
 
+    /// Number of currently connected SIP sessions.
+    active_sessions: SessionCounter,
+
+    /// Failed patron password attempts, shared by all sessions.
+    password_attempts: Arc<PasswordAttempts>,
+
     sip_config: Arc<Config>,
 
     /// OpenSRF bus.
@@ -44,6 +88,9 @@ pub struct SessionFactory {
 
     /// Cache of org unit shortnames and IDs.
     org_cache: HashMap<i64, EgValue>,
+
+    /// Set when SIP clients connect via TLS.
+    tls_config: Option<Arc<rustls::ServerConfig>>,
 }
 
 impl mptc::RequestHandler for SessionFactory {
@@ -70,15 +117,41 @@ impl mptc::RequestHandler for SessionFactory {
         let sip_conf = self.sip_config.clone();
         let org_cache = self.org_cache.clone();
         let shutdown = self.shutdown.clone();
-
-        // Set in worker_start
-        let osrf_bus = self.osrf_bus.take().unwrap();
+        let password_attempts = self.password_attempts.clone();
 
         // request.stream is set in the call to next() that produced
         // this request.
         let stream = request.stream.take().unwrap();
 
-        let mut session = Session::new(sip_conf, osrf_bus, stream, shutdown, org_cache);
+        if let Ok(a) = stream.peer_addr() {
+            log::info!("New SIP connection from {a}");
+        }
+
+        let stream: Box<dyn sip2::SipStream> = match self.tls_config.as_ref() {
+            Some(config) => match tls::accept(config, stream) {
+                Ok(s) => Box::new(s),
+                Err(e) => {
+                    // Connection is already closed.  Free the session
+                    // slot and wait for the next client.
+                    log::warn!("Closing SIP connection: {e}");
+                    self.active_sessions.release();
+                    return Ok(());
+                }
+            },
+            None => Box::new(stream),
+        };
+
+        // Set in worker_start
+        let osrf_bus = self.osrf_bus.take().unwrap();
+
+        let mut session = Session::new(
+            sip_conf,
+            osrf_bus,
+            stream,
+            shutdown,
+            org_cache,
+            password_attempts,
+        );
 
         if let Err(e) = session.start() {
             // This is not necessarily an error.  The client may simply
@@ -87,6 +160,14 @@ impl mptc::RequestHandler for SessionFactory {
             log::info!("{session} exited with message: {e}");
         }
 
+        // Free the session slot claimed when the connection arrived.
+        self.active_sessions.release();
+
+        log::debug!(
+            "{session} closed; active sessions={}",
+            self.active_sessions.active()
+        );
+
         // Take our bus back so we don't have to reconnect in between
         // SIP clients.  This SIP Session is done with it.
         let mut bus = session.take_bus();
@@ -108,7 +189,7 @@ impl mptc::RequestHandler for SessionFactory {
 /// Listens for SIP client connections and passes them off to mptc:: for
 /// relaying to a Session worker.
 pub struct Server {
-    eg_ctx: eg::init::Context,
+    eg_ctx: eg::Client,
 
     /// Parsed config
     sip_config: Arc<Config>,
@@ -124,6 +205,18 @@ pub struct Server {
     /// Cache of org unit shortnames and IDs.
     org_cache: Option<HashMap<i64, EgValue>>,
 
+    /// Set when SIP clients connect via TLS.
+    tls_config: Option<Arc<rustls::ServerConfig>>,
+
+    /// Number of currently connected SIP sessions.
+    ///
+    /// Incremented here as connections arrive and decremented by the
+    /// SessionFactory as sessions end.
+    active_sessions: SessionCounter,
+
+    /// Failed patron password attempts, shared by all sessions.
+    password_attempts: Arc<PasswordAttempts>,
+
     tcp_error_count: usize,
 
     /// Inbound SIP connections start here.
@@ -132,10 +225,10 @@ pub struct Server {
 
 impl mptc::RequestStream for Server {
     fn next(&mut self) -> Result<Option<Box<dyn mptc::Request>>, String> {
-        let stream = match self.tcp_listener.accept() {
-            Ok((stream, _addr)) => {
+        let (stream, addr) = match self.tcp_listener.accept() {
+            Ok((stream, addr)) => {
                 self.tcp_error_count = 0;
-                stream
+                (stream, addr)
             }
             Err(e) => {
                 match e.kind() {
@@ -168,6 +261,16 @@ impl mptc::RequestStream for Server {
             }
         };
 
+        let max = self.sip_config.max_clients();
+
+        let Some(active) = self.active_sessions.claim(max) else {
+            log::warn!("SIPServer refusing connection from {addr}: max-clients {max} reached");
+            stream.shutdown(std::net::Shutdown::Both).ok();
+            return Ok(None);
+        };
+
+        log::debug!("SIPServer accepted connection from {addr}; active sessions={active}");
+
         Ok(Some(Box::new(SipConnectRequest {
             stream: Some(stream),
         })))
@@ -176,9 +279,12 @@ impl mptc::RequestStream for Server {
     fn new_handler(&mut self) -> Box<dyn mptc::RequestHandler> {
         let sf = SessionFactory {
             shutdown: self.shutdown.clone(),
+            active_sessions: self.active_sessions.clone(),
+            password_attempts: self.password_attempts.clone(),
             sip_config: self.sip_config.clone(),
             osrf_bus: None, // set in worker_start
             org_cache: self.org_cache.as_ref().unwrap().clone(),
+            tls_config: self.tls_config.clone(),
         };
 
         Box::new(sf)
@@ -186,7 +292,13 @@ impl mptc::RequestStream for Server {
 
     fn reload(&mut self) -> Result<(), String> {
         match Server::load_config(&self.sip_config_file) {
-            Ok(c) => self.sip_config = Arc::new(c),
+            Ok(c) => match Server::load_tls_config(&c) {
+                Ok(tls_config) => {
+                    self.sip_config = Arc::new(c);
+                    self.tls_config = tls_config;
+                }
+                Err(e) => log::error!("Error reloading TLS config.  Using old config. {e}"),
+            },
             Err(e) => log::error!("Error reloading config.  Using old config. {e}"),
         }
 
@@ -208,7 +320,7 @@ impl mptc::RequestStream for Server {
         log::info!("Server received mptc shutdown request");
 
         self.shutdown.store(true, Ordering::Relaxed);
-        self.eg_ctx.client().clear().ok();
+        self.eg_ctx.clear().ok();
     }
 }
 
@@ -217,8 +329,13 @@ impl Server {
         &self.sip_config
     }
 
-    pub fn setup(sip_config_file: &str, eg_ctx: eg::init::Context) -> Result<Server, String> {
+    pub fn setup(sip_config_file: &str, eg_ctx: eg::Client) -> Result<Server, String> {
         let sip_config = Server::load_config(sip_config_file)?;
+        let tls_config = Server::load_tls_config(&sip_config)?;
+
+        if tls_config.is_some() {
+            log::info!("SIP clients must connect via TLS");
+        }
 
         let tcp_listener = eg::util::tcp_listener(
             sip_config.sip_address(),
@@ -232,6 +349,9 @@ impl Server {
             sip_config: Arc::new(sip_config),
             sip_config_file: sip_config_file.to_string(),
             org_cache: None,
+            tls_config,
+            active_sessions: SessionCounter::new(),
+            password_attempts: Arc::new(PasswordAttempts::new()),
             tcp_error_count: 0,
             shutdown: Arc::new(AtomicBool::new(false)),
         };
@@ -241,15 +361,78 @@ impl Server {
         Ok(server)
     }
 
+    /// Verify the SIP config, Evergreen connectivity, and the staff
+    /// login for each SIP account without accepting any connections.
+    pub fn self_test(sip_config_file: &str) -> eg::util::SelfTest {
+        let mut test = eg::util::SelfTest::new();
+
+        let sip_config =
+            match test.check("SIP config", || Ok(Server::load_config(sip_config_file)?)) {
+                Some(c) => c,
+                None => return test,
+            };
+
+        if sip_config.tls_files().is_some() {
+            test.check("TLS certificate", || {
+                Ok(Server::load_tls_config(&sip_config)?)
+            });
+        }
+
+        let ctx = match test.check("Evergreen init", || eg::init()) {
+            Some(c) => c,
+            None => return test,
+        };
+
+        let client = &ctx;
+        let mut editor = eg::Editor::new(client);
+
+        test.check("org unit lookup", || {
+            editor
+                .search("aou", eg::hash! {"parent_ou": EgValue::Null})?
+                .pop()
+                .ok_or_else(|| "No root org unit found".into())
+        });
+
+        for account in sip_config.accounts() {
+            let ils_username = account.ils_username();
+
+            test.check(&format!("staff login for {ils_username}"), || {
+                let search = eg::hash! {usrname: ils_username, deleted: "f"};
+
+                let user_id = match editor.search("au", search)?.pop() {
+                    Some(u) => u.id()?,
+                    None => return Err(format!("No such user: {ils_username}").into()),
+                };
+
+                let mut args = auth::InternalLoginArgs::new(user_id, auth::LoginType::Staff);
+                args.workstation = account.workstation().map(|w| w.to_string());
+
+                let ses = auth::Session::internal_session_api(client, &args)?
+                    .ok_or_else(|| format!("Internal login failed for {ils_username}"))?;
+
+                auth::Session::logout(client, ses.token())
+            });
+        }
+
+        test
+    }
+
     fn load_config(filename: &str) -> Result<Config, String> {
         let mut sip_conf = conf::Config::new();
         sip_conf.read_yaml(filename)?;
         Ok(sip_conf)
     }
 
+    fn load_tls_config(sip_conf: &Config) -> Result<Option<Arc<rustls::ServerConfig>>, String> {
+        match sip_conf.tls_files() {
+            Some((cert_file, key_file)) => Ok(Some(tls::load_config(cert_file, key_file)?)),
+            None => Ok(None),
+        }
+    }
+
     /// Pre-cache data that's universally useful.
     fn precache(&mut self) -> Result<(), String> {
-        let mut e = eg::Editor::new(self.eg_ctx.client());
+        let mut e = eg::Editor::new(&self.eg_ctx);
 
         let search = eg::hash! {
             "id": {"!=": EgValue::Null},
@@ -263,6 +446,23 @@ impl Server {
             map.insert(org.id()?, org);
         }
 
+        // Make sure configured account org units exist before any
+        // sessions try to use them.
+        for account in self.sip_config.accounts() {
+            if let Some(org_unit) = account.org_unit() {
+                let exists = map.iter().any(|(id, org)| {
+                    id.to_string() == org_unit || org["shortname"].as_str() == Some(org_unit)
+                });
+
+                if !exists {
+                    return Err(format!(
+                        "SIP account '{}' has unknown org-unit '{org_unit}'",
+                        account.sip_username()
+                    ));
+                }
+            }
+        }
+
         self.org_cache = Some(map);
 
         Ok(())
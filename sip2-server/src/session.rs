@@ -1,36 +1,43 @@
+use super::attempts::PasswordAttempts;
 use super::conf;
-use eg::auth;
-use eg::auth::AuthSession;
-use eg::result::EgResult;
+use super::util;
+use eg::common::auth;
+use eg::common::auth::Session as AuthSession;
+use eg::common::settings::Settings;
+use eg::result::{EgError, EgResult};
 use eg::EgValue;
 use evergreen as eg;
 use sip2;
 use std::collections::HashMap;
 use std::fmt;
-use std::net;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-/* --------------------------------------------------------- */
-// By order of appearance in the INSTITUTION_SUPPORTS string:
-// patron status request
-// checkout
-// checkin
-// block patron
-// acs status
-// request sc/acs resend
-// login
-// patron information
-// end patron session
-// fee paid
-// item information
-// item status update
-// patron enable
-// hold
-// renew
-// renew all
-const INSTITUTION_SUPPORTS: &str = "YYYNYNYYNYYNNNYN";
-/* --------------------------------------------------------- */
+/// Request messages handled by handle_sip_request(), reported to
+/// clients in the ACS Status supported messages (BX) field.
+///
+/// Requests for messages not listed here are refused as unsupported.
+const SUPPORTED_MESSAGES: &[&str] = &["09", "11", "17", "23", "25", "35", "37", "63", "93", "99"];
+
+/// Repeatable fields which may be removed, least important first,
+/// to keep a response within the configured max message length.
+const TRIMMABLE_FIELDS: &[&str] = &["CD", "BU", "AV", "AU", "AT", "AS"];
+
+/// AF screen message added to responses that had fields trimmed.
+const TRUNCATED_NOTICE: &str = "Response truncated";
+
+/// Screen message for requests refused because the client has not
+/// logged in.
+const NOT_LOGGED_IN: &str = "SIP client is not logged in";
+
+/// Requests which collect patron or item details and are subject to
+/// the per-session heavy-op limit.
+const HEAVY_OPS: &[&str] = &["17", "23", "63"];
+
+/// Screen message for requests refused by the heavy-op limit.
+const TOO_MANY_REQUESTS: &str = "Too many requests. Please try again shortly";
 
 /// Manages a single SIP client connection.
 ///
@@ -56,34 +63,57 @@ pub struct Session {
 
     /// Cache of org unit shortnames and IDs.
     org_cache: HashMap<i64, EgValue>,
+
+    /// Failed patron password attempts, shared by all sessions.
+    password_attempts: Arc<PasswordAttempts>,
+
+    /// SIP protocol version reported by the client in SC Status.
+    protocol_version: Option<String>,
+
+    /// Set when this session has modified patron data (e.g. made a
+    /// payment) which may not yet be reflected in patron summaries.
+    summaries_stale: bool,
+
+    /// Limits patron and item lookups, if configured for the account.
+    heavy_ops: Option<eg::util::RateLimiter>,
+
+    /// Org unit settings, cached for the life of the session and
+    /// refreshed when settings change.
+    settings: Settings,
 }
 
 impl Session {
     pub fn new(
         sip_config: Arc<conf::Config>,
         osrf_bus: eg::osrf::bus::Bus,
-        stream: net::TcpStream,
+        stream: Box<dyn sip2::SipStream>,
         shutdown: Arc<AtomicBool>,
         org_cache: HashMap<i64, EgValue>,
+        password_attempts: Arc<PasswordAttempts>,
     ) -> Self {
-        if let Ok(a) = stream.peer_addr() {
-            log::info!("New SIP connection from {a}");
-        }
-
-        let mut con = sip2::Connection::from_stream(stream);
+        let mut con = sip2::Connection::from_sip_stream(stream);
         con.set_ascii(sip_config.ascii());
 
         let osrf_client = eg::Client::from_bus(osrf_bus);
 
-        let editor = eg::Editor::new(&osrf_client);
+        let mut editor = eg::Editor::new(&osrf_client);
+        editor.set_retry_reads(sip_config.retry_reads());
+
+        let mut settings = Settings::new(&editor);
+        settings.set_max_age(Duration::from_secs(sip_config.settings_max_age()));
 
         Session {
             editor,
+            settings,
             shutdown,
             sip_config,
             osrf_client,
             org_cache,
+            password_attempts,
             account: None,
+            protocol_version: None,
+            summaries_stale: false,
+            heavy_ops: None,
             sip_connection: con,
         }
     }
@@ -98,6 +128,22 @@ impl Session {
         &self.org_cache
     }
 
+    pub fn password_attempts(&self) -> &PasswordAttempts {
+        &self.password_attempts
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    pub fn summaries_stale(&self) -> bool {
+        self.summaries_stale
+    }
+
+    pub fn set_summaries_stale(&mut self, stale: bool) {
+        self.summaries_stale = stale;
+    }
+
     pub fn org_cache_mut(&mut self) -> &mut HashMap<i64, EgValue> {
         &mut self.org_cache
     }
@@ -125,10 +171,17 @@ impl Session {
         &mut self.osrf_client
     }
 
+    /// Editor for database calls and transaction control.
+    ///
+    /// All Editor database calls require a mutable borrow, since they
+    /// run through the editor's OpenSRF session and transaction state.
+    /// Values needed as arguments should be read via editor() first.
     pub fn editor_mut(&mut self) -> &mut eg::editor::Editor {
         &mut self.editor
     }
 
+    /// Editor for reading our own auth state, e.g. the authtoken,
+    /// requestor, and permission org.
     pub fn editor(&self) -> &eg::editor::Editor {
         &self.editor
     }
@@ -189,7 +242,7 @@ impl Session {
     /// Create a internal auth session in the ILS
     fn login(&mut self) -> EgResult<()> {
         let ils_user_id = self.get_ils_user_id()?;
-        let mut args = auth::AuthInternalLoginArgs::new(ils_user_id, "staff");
+        let mut args = auth::InternalLoginArgs::new(ils_user_id, auth::LoginType::Staff);
 
         if self.has_account() {
             if let Some(w) = self.account().workstation() {
@@ -197,7 +250,7 @@ impl Session {
             }
         }
 
-        let auth_ses = match AuthSession::internal_session(&self.osrf_client, &args)? {
+        let auth_ses = match AuthSession::internal_session_api(&self.osrf_client, &args)? {
             Some(s) => s,
             None => Err(format!("Internal Login failed"))?,
         };
@@ -235,18 +288,31 @@ impl Session {
 
             log::trace!("{self} waking from SIP message receive poll");
 
-            let sip_req = match sip_req_op {
+            let mut sip_req = match sip_req_op {
                 Some(r) => r,
                 None => continue,
             };
 
+            self.normalize_barcodes(&mut sip_req);
+
             log::trace!("{self} Read SIP message: {:?}", sip_req);
 
-            let mut sip_resp = self.handle_sip_request(&sip_req)?;
+            let mut sip_resp = match self.handle_sip_request(&sip_req) {
+                Ok(r) => r,
+                Err(e) => {
+                    self.log_sip_activity(&sip_req, None);
+                    self.log_structured_error(&sip_req, &e);
+                    return Err(e);
+                }
+            };
+
+            self.log_sip_activity(&sip_req, Some(&sip_resp));
 
             log::trace!("{self} server replying with {sip_resp:?}");
 
             self.redact_sip_response(&mut sip_resp);
+            self.apply_protocol_version(&mut sip_resp);
+            self.trim_sip_response(&mut sip_resp);
 
             log::trace!("{self} server response after redaction: {sip_resp:?}");
 
@@ -304,10 +370,113 @@ impl Session {
         }
     }
 
+    /// Normalize the patron (AA) and item (AB) barcodes in a request
+    /// so visually identical barcodes match during lookups.
+    fn normalize_barcodes(&self, req: &mut sip2::Message) {
+        if !self.has_account() || !self.account().settings().normalize_barcodes() {
+            return;
+        }
+
+        for field in req
+            .fields_mut()
+            .iter_mut()
+            .filter(|f| f.code() == "AA" || f.code() == "AB")
+        {
+            let normalized = eg::norm::normalize_barcode(field.value());
+            if normalized != field.value() {
+                log::debug!(
+                    "{self} normalized barcode {:?} to {normalized}",
+                    field.value()
+                );
+                field.set_value(&normalized);
+            }
+        }
+    }
+
+    /// Remove response fields which are not supported by the SIP
+    /// protocol version the client reported in SC Status.
+    fn apply_protocol_version(&self, resp: &mut sip2::Message) {
+        if !self.has_account() || !self.account().settings().protocol_version_fields() {
+            return;
+        }
+
+        let Some(version) = self.protocol_version.as_deref() else {
+            return;
+        };
+
+        let removed = sip2::util::apply_protocol_version(resp, version);
+        if removed > 0 {
+            log::debug!("{self} removed {removed} field(s) unsupported by SIP {version}");
+        }
+    }
+
+    /// Trim repeatable item list fields from responses which exceed
+    /// our configured maximum message length.
+    fn trim_sip_response(&self, resp: &mut sip2::Message) {
+        if !self.has_account() {
+            return;
+        }
+
+        let max_len = match self.account().settings().max_message_length() {
+            Some(l) => l,
+            None => return,
+        };
+
+        if resp.trim_to_length(max_len, TRIMMABLE_FIELDS, TRUNCATED_NOTICE) {
+            log::info!("{self} response trimmed to max length {max_len}");
+        }
+
+        let len = resp.to_sip().len();
+        if len > max_len {
+            log::warn!("{self} response length {len} exceeds max length {max_len}");
+        }
+    }
+
+    /// Log an ACT line for a SIP request, masking barcodes if configured.
+    ///
+    /// A response of None means the request failed with an error.
+    fn log_sip_activity(&self, msg: &sip2::Message, resp: Option<&sip2::Message>) {
+        let format = match self.sip_config().activity_log() {
+            Some(f) => f,
+            None => return,
+        };
+
+        let line = activity_log_line(
+            format,
+            msg,
+            resp,
+            self.account.as_ref().map(|a| a.sip_username()),
+            self.sip_config().activity_log_redact(),
+        );
+
+        log::info!("{line}");
+    }
+
+    /// Log a machine-readable record of a failed SIP request so
+    /// failures can be aggregated by operation, terminal, and type.
+    fn log_structured_error(&self, msg: &sip2::Message, err: &EgError) {
+        if !self.sip_config().structured_errors() {
+            return;
+        }
+
+        let record = structured_error_record(
+            msg,
+            self.account.as_ref().map(|a| a.sip_username()),
+            err,
+            self.sip_config().activity_log_redact(),
+        );
+
+        log::error!("SIP_ERROR {}", record.dump());
+    }
+
     /// Process a single SIP request.
     fn handle_sip_request(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
         let code = msg.spec().code;
 
+        if !SUPPORTED_MESSAGES.contains(&code) {
+            Err(format!("Unsupported SIP message code={code}"))?;
+        }
+
         if code.eq("99") {
             // May not require an existing login / account
             return self.handle_sc_status(msg);
@@ -320,14 +489,27 @@ impl Session {
 
         // All remaining request require authentication
         if self.account.is_none() {
+            if self.sip_config().refuse_before_login() {
+                if let Some(resp) = sip2::util::refusal_response(msg, NOT_LOGGED_IN) {
+                    log::warn!("{self} refusing message {code} sent before login");
+                    return Ok(resp);
+                }
+            }
             Err(format!("SIP client is not logged in"))?;
         }
 
+        if HEAVY_OPS.contains(&code) {
+            if let Some(resp) = self.limit_heavy_op(msg) {
+                return Ok(resp);
+            }
+        }
+
         match code {
             "09" => self.handle_checkin(msg),
             "11" => self.handle_checkout(msg),
             "17" => self.handle_item_info(msg),
             "23" => self.handle_patron_status(msg),
+            "25" => self.handle_patron_enable(msg),
             "35" => self.handle_end_patron_session(msg),
             "37" => self.handle_payment(msg),
             "63" => self.handle_patron_info(msg),
@@ -335,8 +517,40 @@ impl Session {
         }
     }
 
+    /// Apply the heavy-op limit to a request.
+    ///
+    /// Returns a refusal response if the request exceeds the limit
+    /// and the policy is to reject it.  Otherwise, waits as needed
+    /// and returns None so the request is processed.
+    fn limit_heavy_op(&mut self, msg: &sip2::Message) -> Option<sip2::Message> {
+        let policy = self.account().settings().heavy_op_policy();
+
+        let wait = self.heavy_ops.as_mut()?.wait_time();
+
+        if policy == conf::HeavyOpPolicy::Delay && !wait.is_zero() {
+            log::info!("{self} delaying heavy request {wait:?}");
+            thread::sleep(wait);
+        }
+
+        let limiter = self.heavy_ops.as_mut()?;
+
+        if limiter.try_acquire() {
+            return None;
+        }
+
+        let (limit, window) = (limiter.limit(), limiter.window());
+
+        log::warn!(
+            "{self} refusing message {}; exceeds {limit} heavy requests per {window:?}",
+            msg.spec().code,
+        );
+
+        sip2::util::refusal_response(msg, TOO_MANY_REQUESTS)
+    }
+
     fn handle_login(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
         self.account = None;
+        self.heavy_ops = None;
         let mut login_ok = "0";
 
         if let Some(username) = msg.get_field_value("CN") {
@@ -346,7 +560,20 @@ impl Session {
                 if let Some(account) = self.sip_config().get_account(&username) {
                     if account.sip_password().eq(password) {
                         login_ok = "1";
+
                         self.account = Some(account.clone());
+
+                        let settings = self.account().settings();
+                        if let Some(limit) = settings.heavy_op_limit() {
+                            let window = Duration::from_secs(settings.heavy_op_window());
+                            self.heavy_ops = Some(eg::util::RateLimiter::new(limit, window));
+                        }
+
+                        if !self.resolve_account_org()? {
+                            self.account = None;
+                            self.heavy_ops = None;
+                            login_ok = "0";
+                        }
                     }
                 } else {
                     log::warn!("No such SIP account: {username}");
@@ -361,26 +588,42 @@ impl Session {
         Ok(sip2::Message::from_ff_values(&sip2::spec::M_LOGIN_RESP, &[login_ok]).unwrap())
     }
 
-    fn handle_sc_status(&mut self, _msg: &sip2::Message) -> EgResult<sip2::Message> {
+    fn handle_sc_status(&mut self, msg: &sip2::Message) -> EgResult<sip2::Message> {
         if self.account.is_none() && !self.sip_config().sc_status_before_login() {
             Err(format!("SC Status before login disabled"))?;
         }
 
+        if let Some(ff) = msg.fixed_fields().get(2) {
+            log::info!("{self} client reports SIP protocol version {}", ff.value());
+            self.protocol_version = Some(ff.value().to_string());
+        }
+
+        let (checkin_ok, checkout_ok, renewal_ok) = match &self.account {
+            Some(a) => (
+                a.settings().checkin_ok(),
+                a.settings().checkout_ok(),
+                a.settings().renewal_ok(),
+            ),
+            None => (true, true, true),
+        };
+
+        let supported = sip2::util::supported_messages(SUPPORTED_MESSAGES);
+
         let mut resp = sip2::Message::from_values(
             &sip2::spec::M_ACS_STATUS,
             &[
-                "Y",   // online status
-                "Y",   // checkin ok
-                "Y",   // checkout ok
-                "Y",   // renewal policy
-                "N",   // status update
-                "N",   // offline ok
-                "999", // timeout
-                "999", // max retries
+                "Y",                               // online status
+                sip2::util::sip_bool(checkin_ok),  // checkin ok
+                sip2::util::sip_bool(checkout_ok), // checkout ok
+                sip2::util::sip_bool(renewal_ok),  // renewal policy
+                "N",                               // status update
+                "N",                               // offline ok
+                "999",                             // timeout
+                "999",                             // max retries
                 &sip2::util::sip_date_now(),
-                "2.00", // SIP version
+                sip2::spec::SIP_PROTOCOL_VERSION,
             ],
-            &[("BX", INSTITUTION_SUPPORTS)],
+            &[("BX", &supported)],
         )
         .unwrap();
 
@@ -392,7 +635,7 @@ impl Session {
                 // find its workstation / home org.
                 self.set_authtoken()?;
 
-                if let Some(org) = self.org_from_id(self.get_ws_org_id()?)? {
+                if let Some(org) = self.org_from_id(self.get_circ_org_id()?)? {
                     resp.add_field("AM", org["name"].as_str().unwrap());
                     resp.add_field("AN", org["shortname"].as_str().unwrap());
                 }
@@ -412,3 +655,69 @@ impl fmt::Display for Session {
         }
     }
 }
+
+/// Build the SIP_ERROR record for a failed SIP request, masking
+/// patron and item barcodes when `redact` is set.
+pub fn structured_error_record(
+    msg: &sip2::Message,
+    terminal: Option<&str>,
+    err: &EgError,
+    redact: bool,
+) -> json::JsonValue {
+    let (error_class, detail) = match err {
+        EgError::Event(e) => (e.textcode().to_string(), e.to_string()),
+        EgError::Debug(s) => ("INTERNAL_SERVER_ERROR".to_string(), s.to_string()),
+    };
+
+    json::object! {
+        "operation": msg.spec().label,
+        "code": msg.spec().code,
+        "terminal": terminal,
+        "institution": msg.get_field_value("AO"),
+        "patron": util::log_field_value(msg, "AA", redact),
+        "item": util::log_field_value(msg, "AB", redact),
+        "error_class": error_class,
+        "detail": detail,
+    }
+}
+
+/// Build the ACT line for a SIP request, masking patron and item
+/// barcodes when `redact` is set.
+///
+/// A response of None means the request failed with an error.
+pub fn activity_log_line(
+    format: conf::ActivityLogFormat,
+    msg: &sip2::Message,
+    resp: Option<&sip2::Message>,
+    terminal: Option<&str>,
+    redact: bool,
+) -> String {
+    let patron = util::log_field_value(msg, "AA", redact);
+    let item = util::log_field_value(msg, "AB", redact);
+    let outcome = match resp {
+        Some(r) => format!("ok:{}", r.spec().code),
+        None => "error".to_string(),
+    };
+
+    match format {
+        conf::ActivityLogFormat::Json => {
+            let record = json::object! {
+                "operation": msg.spec().label,
+                "code": msg.spec().code,
+                "terminal": terminal,
+                "patron": patron,
+                "item": item,
+                "outcome": outcome,
+            };
+
+            format!("ACT:SIP {}", record.dump())
+        }
+        conf::ActivityLogFormat::Text => format!(
+            "ACT:SIP [{}] {} patron={} item={} {outcome}",
+            terminal.unwrap_or("-"),
+            msg.spec().label,
+            patron.as_deref().unwrap_or("-"),
+            item.as_deref().unwrap_or("-"),
+        ),
+    }
+}
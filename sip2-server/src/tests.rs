@@ -0,0 +1,555 @@
+use super::attempts::PasswordAttempts;
+use super::checkout;
+use super::conf::{ActivityLogFormat, Config, SipSettings};
+use super::item;
+use super::patron;
+use super::payment::{self, PaymentResult};
+use super::server::SessionCounter;
+use super::session;
+use super::util;
+use eg::result::EgError;
+use eg::EgValue;
+use evergreen as eg;
+use std::time::Duration;
+
+/// Load a config with a single "sip-user" account whose setting
+/// group is extended with the provided YAML settings.
+fn load_config(settings: &str) -> Config {
+    let mut yaml = String::from("setting-groups:\n  - name: default\n    institution: example\n");
+
+    for line in settings.lines() {
+        yaml += &format!("    {line}\n");
+    }
+
+    yaml += "accounts:\n  - sip-username: sip-user\n    sip-password: sip-pass\n";
+    yaml += "    ils-username: admin\n    settings: default\n";
+
+    let mut config = Config::new();
+    config.read_yaml_str(&yaml).unwrap();
+    config
+}
+
+fn account_settings(config: &Config) -> &SipSettings {
+    config.get_account("sip-user").unwrap().settings()
+}
+
+#[test]
+fn payment_response_balance() {
+    let config = load_config("fee-paid-detail: true\nfee-paid-balance-field: ZB");
+
+    // Partial payment
+    let mut result = PaymentResult::new("patron1");
+    result.success = true;
+    result.payments = vec![(101, 5.0)];
+    result.balance_owed = Some(7.5);
+
+    let resp = payment::payment_response(account_settings(&config), &result);
+
+    assert_eq!(resp.fixed_fields()[0].value(), "Y");
+    assert_eq!(resp.get_field_value("ZB"), Some("7.50"));
+    assert_eq!(resp.get_field_value("BV"), None);
+
+    let lines: Vec<&str> = resp
+        .fields()
+        .iter()
+        .filter(|f| f.code() == "AG")
+        .map(|f| f.value())
+        .collect();
+
+    assert_eq!(
+        lines,
+        vec!["Paid 5.00 on transaction 101", "Balance owed 7.50"]
+    );
+
+    // Full payment
+    result.payments = vec![(101, 10.0), (102, 2.5)];
+    result.balance_owed = Some(0.0);
+
+    let resp = payment::payment_response(account_settings(&config), &result);
+
+    assert_eq!(resp.get_field_value("ZB"), Some("0.00"));
+    assert_eq!(resp.fields().iter().filter(|f| f.code() == "AG").count(), 3);
+
+    // Without a balance field, the balance is only printed.
+    let config = load_config("fee-paid-detail: true");
+    let resp = payment::payment_response(account_settings(&config), &result);
+
+    assert_eq!(resp.get_field_value("BV"), None);
+    assert_eq!(resp.get_field_value("ZB"), None);
+    assert!(resp
+        .fields()
+        .iter()
+        .any(|f| f.code() == "AG" && f.value() == "Balance owed 0.00"));
+}
+
+#[test]
+fn renewal_policy_fast_path() {
+    let config = load_config(
+        "renewal-policy:\n  - circ-modifier: book\n    max-renewals: 2\n  \
+         - circ-modifier: equipment\n    renewals-allowed: false",
+    );
+    let settings = account_settings(&config);
+
+    let book = settings.renewal_limit("book");
+    let dvd = settings.renewal_limit("dvd");
+
+    assert_eq!(book, Some(2));
+    assert_eq!(settings.renewal_limit("equipment"), Some(0));
+    assert_eq!(dvd, None);
+
+    // (renewals used, renewals remaining per the circ policy matrix)
+    // for a circulation with a matrix renewal limit of 2.
+    for (used, remaining) in [(0, 2), (1, 1), (2, 0)] {
+        let matrix = checkout::renewal_allowed(None, None, Some(remaining));
+
+        // A mapped modifier agrees with the matrix.
+        assert_eq!(
+            checkout::renewal_allowed(book, Some(used), Some(remaining)),
+            matrix
+        );
+
+        // An unmapped modifier falls back to the matrix.
+        assert_eq!(
+            checkout::renewal_allowed(dvd, None, Some(remaining)),
+            matrix
+        );
+    }
+
+    assert!(!checkout::renewal_allowed(Some(0), None, Some(2)));
+}
+
+#[test]
+fn checkout_password_required() {
+    let config = load_config("password-required:\n  - checkout");
+    let settings = account_settings(&config);
+
+    // With a password, it is verified and a valid one is accepted.
+    assert_eq!(
+        settings.password_to_verify("checkout", Some("1234")),
+        Some("1234")
+    );
+    assert!(!settings.password_refused("checkout", true));
+
+    // Without one, nothing is verified and the checkout is refused.
+    assert_eq!(settings.password_to_verify("checkout", None), None);
+    assert!(settings.password_refused("checkout", false));
+
+    // By default, passwords sent with a checkout are not verified,
+    // so they never count toward the password attempt lockout.
+    let config = load_config("");
+    let settings = account_settings(&config);
+
+    assert_eq!(settings.password_to_verify("checkout", Some("1234")), None);
+    assert!(!settings.password_refused("checkout", false));
+}
+
+#[test]
+fn item_info_overdue() {
+    let config = load_config("item-info-overdue-field: ZO\nitem-info-days-overdue-field: ZD");
+    let settings = account_settings(&config);
+    let now = util::parse_timestamp("2024-03-10T12:00:00-0500").unwrap();
+
+    let item_info = |overdue_days| {
+        let mut resp = sip2::Message::new(&sip2::spec::M_ITEM_INFO_RESP, vec![], vec![]);
+        item::add_overdue_fields(settings, &mut resp, overdue_days);
+        resp
+    };
+
+    // Overdue by a day and a half counts as 2 days overdue.
+    let due = util::parse_timestamp("2024-03-08T23:59:59-05:00").unwrap();
+    let resp = item_info(Some(item::days_overdue(&due, &now)));
+
+    assert_eq!(resp.get_field_value("ZO"), Some("Y"));
+    assert_eq!(resp.get_field_value("ZD"), Some("2"));
+
+    // Checked out and not yet due.
+    let due = util::parse_timestamp("2024-03-17T23:59:59.000000-05:00").unwrap();
+    let resp = item_info(Some(item::days_overdue(&due, &now)));
+
+    assert_eq!(resp.get_field_value("ZO"), Some("N"));
+    assert_eq!(resp.get_field_value("ZD"), Some("0"));
+
+    // Available items have no circulation to report on.
+    let resp = item_info(None);
+
+    assert_eq!(resp.get_field_value("ZO"), None);
+    assert_eq!(resp.get_field_value("ZD"), None);
+
+    // Unconfigured fields are omitted.
+    let config = load_config("");
+    let mut resp = sip2::Message::new(&sip2::spec::M_ITEM_INFO_RESP, vec![], vec![]);
+    item::add_overdue_fields(account_settings(&config), &mut resp, Some(3));
+
+    assert!(resp.fields().is_empty());
+}
+
+#[test]
+fn session_counter_max_clients() {
+    let max = 2;
+    let counter = SessionCounter::new();
+
+    // Factory workers share the server's count.
+    let worker_counter = counter.clone();
+
+    assert_eq!(counter.claim(max), Some(1));
+    assert_eq!(counter.claim(max), Some(2));
+
+    // One past the cap is refused without consuming a slot.
+    assert_eq!(counter.claim(max), None);
+    assert_eq!(counter.active(), 2);
+
+    // Closing a session frees its slot for the next client.
+    worker_counter.release();
+    assert_eq!(counter.active(), 1);
+    assert_eq!(counter.claim(max), Some(2));
+    assert_eq!(counter.claim(max), None);
+}
+
+#[test]
+fn item_info_locations() {
+    let copy = eg::hash! {
+        circ_lib: {shortname: "BR1"},
+        location: {name: "Stacks"},
+        call_number: {owning_lib: {shortname: "BR2"}},
+    };
+
+    let transit = eg::hash! {dest: {shortname: "BR3"}};
+
+    let config = load_config("item-info-locations: true");
+    let settings = account_settings(&config);
+
+    // At home, and checked out, which does not change its location.
+    let (current, permanent) = item::item_locations(settings, &copy, None);
+
+    assert_eq!(current, "BR1");
+    assert_eq!(permanent, "BR2: Stacks");
+
+    // In transit items are reported at their destination.
+    let (current, permanent) = item::item_locations(settings, &copy, Some(&transit));
+
+    assert_eq!(current, "BR3");
+    assert_eq!(permanent, "BR2: Stacks");
+
+    // Without a shelving location, only the owning lib is reported.
+    let mut no_location = copy.clone();
+    no_location["location"] = EgValue::Null;
+    let (_, permanent) = item::item_locations(settings, &no_location, None);
+
+    assert_eq!(permanent, "BR2");
+
+    // Disabled, both are the circ lib regardless of transit.
+    let config = load_config("");
+    let (current, permanent) =
+        item::item_locations(account_settings(&config), &copy, Some(&transit));
+
+    assert_eq!(current, "BR1");
+    assert_eq!(permanent, "BR1");
+}
+
+/// Patron status request for patron "patron1234" and item "item5678".
+fn patron_status_request() -> sip2::Message {
+    sip2::Message::new(
+        &sip2::spec::M_PATRON_STATUS,
+        vec![],
+        vec![
+            sip2::Field::new("AO", "example"),
+            sip2::Field::new("AA", "patron1234"),
+            sip2::Field::new("AB", "item5678"),
+        ],
+    )
+}
+
+#[test]
+fn structured_error_record() {
+    let msg = patron_status_request();
+    let err = EgError::from("Patron lookup failed");
+
+    let record = session::structured_error_record(&msg, Some("sip-user"), &err, false);
+
+    assert_eq!(record["operation"], msg.spec().label);
+    assert_eq!(record["code"], "23");
+    assert_eq!(record["terminal"], "sip-user");
+    assert_eq!(record["institution"], "example");
+    assert_eq!(record["patron"], "patron1234");
+    assert_eq!(record["item"], "item5678");
+    assert_eq!(record["error_class"], "INTERNAL_SERVER_ERROR");
+    assert_eq!(record["detail"], "Patron lookup failed");
+
+    let err = EgError::from(eg::EgEvent::new("ACTOR_USER_NOT_FOUND"));
+    let record = session::structured_error_record(&msg, None, &err, true);
+
+    assert!(record["terminal"].is_null());
+    assert_eq!(record["patron"], "******1234");
+    assert_eq!(record["item"], "****5678");
+    assert_eq!(record["error_class"], "ACTOR_USER_NOT_FOUND");
+}
+
+#[test]
+fn activity_log_line() {
+    let msg = patron_status_request();
+    let resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    let label = msg.spec().label;
+
+    let line = session::activity_log_line(
+        ActivityLogFormat::Text,
+        &msg,
+        Some(&resp),
+        Some("sip-user"),
+        false,
+    );
+
+    assert_eq!(
+        line,
+        format!("ACT:SIP [sip-user] {label} patron=patron1234 item=item5678 ok:24")
+    );
+
+    let line = session::activity_log_line(ActivityLogFormat::Text, &msg, None, None, true);
+
+    assert_eq!(
+        line,
+        format!("ACT:SIP [-] {label} patron=******1234 item=****5678 error")
+    );
+
+    for redact in [false, true] {
+        let line = session::activity_log_line(
+            ActivityLogFormat::Json,
+            &msg,
+            Some(&resp),
+            Some("sip-user"),
+            redact,
+        );
+
+        let record = json::parse(line.strip_prefix("ACT:SIP ").unwrap()).unwrap();
+        let (patron, item) = match redact {
+            true => ("******1234", "****5678"),
+            false => ("patron1234", "item5678"),
+        };
+
+        assert_eq!(record["operation"], label);
+        assert_eq!(record["code"], "23");
+        assert_eq!(record["terminal"], "sip-user");
+        assert_eq!(record["patron"], patron);
+        assert_eq!(record["item"], item);
+        assert_eq!(record["outcome"], "ok:24");
+    }
+}
+
+#[test]
+fn password_attempts_lockout() {
+    let attempts = PasswordAttempts::new();
+    let window = Duration::from_secs(600);
+
+    assert_eq!(attempts.remaining(1, 3, window), 3);
+    assert_eq!(attempts.fail(1, 3, window), 2);
+    assert_eq!(attempts.fail(1, 3, window), 1);
+    assert_eq!(attempts.remaining(1, 3, window), 1);
+    assert_eq!(attempts.fail(1, 3, window), 0);
+    assert_eq!(attempts.remaining(1, 3, window), 0);
+
+    // Other patrons are unaffected.
+    assert_eq!(attempts.remaining(2, 3, window), 3);
+
+    // A successful attempt restores the full count.
+    attempts.reset(1);
+    assert_eq!(attempts.remaining(1, 3, window), 3);
+
+    // Failures outside the window no longer count.
+    attempts.fail(1, 3, Duration::ZERO);
+    assert_eq!(attempts.remaining(1, 3, Duration::ZERO), 3);
+}
+
+#[test]
+fn password_precheck() {
+    let config = load_config(
+        "password-max-attempts: 2\npassword-lockout-window: 600\npassword-max-length: 8",
+    );
+    let settings = account_settings(&config);
+    let attempts = PasswordAttempts::new();
+    let window = Duration::from_secs(600);
+
+    assert_eq!(
+        patron::password_precheck(settings, &attempts, 1, Some("demo123  ")),
+        Some("demo123")
+    );
+    assert_eq!(
+        patron::password_precheck(settings, &attempts, 1, None),
+        None
+    );
+    assert_eq!(
+        patron::password_precheck(settings, &attempts, 1, Some("   ")),
+        None
+    );
+    assert_eq!(
+        patron::password_precheck(settings, &attempts, 1, Some("toolongpassword")),
+        None
+    );
+
+    // Once attempts are exhausted, check_password refuses the
+    // password without verifying it.
+    attempts.fail(1, 2, window);
+    assert!(patron::password_precheck(settings, &attempts, 1, Some("demo123")).is_some());
+
+    attempts.fail(1, 2, window);
+    assert_eq!(
+        patron::password_precheck(settings, &attempts, 1, Some("demo123")),
+        None
+    );
+
+    attempts.reset(1);
+    assert!(patron::password_precheck(settings, &attempts, 1, Some("demo123")).is_some());
+}
+
+#[test]
+fn patron_block_message_penalties() {
+    let config = load_config(
+        "patron-block-messages:\n  - block: charge-denied\n    \
+         message: \"Blocked: {penalties}.\"\n  - block: max-fines\n    \
+         message: \"Balance {balance}.\"",
+    );
+    let settings = account_settings(&config);
+    let penalties = vec![
+        "Patron exceeds fine threshold".to_string(),
+        "Alert".to_string(),
+    ];
+
+    assert!(settings.patron_block_penalties_wanted());
+
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+
+    // Not blocked, no message.
+    assert_eq!(
+        patron::patron_block_message(settings, &patron, &penalties),
+        None
+    );
+
+    patron.charge_denied = true;
+    patron.max_fines = true;
+    patron.balance_owed = 12.5;
+
+    assert_eq!(
+        patron::patron_block_message(settings, &patron, &penalties).as_deref(),
+        Some("Blocked: Patron exceeds fine threshold, Alert. Balance 12.50.")
+    );
+
+    let config = load_config("patron-block-messages:\n  - block: max-fines\n    message: Fines");
+    assert!(!account_settings(&config).patron_block_penalties_wanted());
+}
+
+#[test]
+fn patron_privileges_permit_profiles() {
+    let config = load_config("patron-status-permit-profiles: [\"Staff\"]");
+    let settings = account_settings(&config);
+
+    let mut user = eg::hash! {
+        id: 1,
+        expire_date: "2099-01-01T00:00:00-0500",
+        profile: {name: "Staff"},
+        card: {active: "t"},
+    };
+
+    // On the allow-list, penalty checks are skipped.
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+
+    assert!(patron::patron_privileges_settled(settings, &user, &mut patron).unwrap());
+    assert!(patron.card_active);
+    assert!(!patron.charge_denied);
+
+    // Off the list, penalties still need evaluating.
+    user["profile"]["name"] = EgValue::from("Patrons");
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+
+    assert!(!patron::patron_privileges_settled(settings, &user, &mut patron).unwrap());
+    assert!(!patron.card_active);
+
+    // Expired patrons are denied regardless of profile.
+    user["profile"]["name"] = EgValue::from("Staff");
+    user["expire_date"] = EgValue::from("2001-01-01T00:00:00-0500");
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+
+    assert!(patron::patron_privileges_settled(settings, &user, &mut patron).unwrap());
+    assert!(patron.expired);
+    assert!(patron.charge_denied);
+}
+
+#[test]
+fn holds_ready_count() {
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+    patron.holds_count = 5;
+    patron.holds_ready_count = 3;
+
+    let config = load_config("holds-ready-field: XR");
+    let mut resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    patron::add_holds_ready_field(account_settings(&config), &mut resp, &patron);
+
+    assert_eq!(resp.get_field_value("XR"), Some("0003"));
+
+    // Unconfigured, the count is omitted.
+    let config = load_config("");
+    let mut resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    patron::add_holds_ready_field(account_settings(&config), &mut resp, &patron);
+
+    assert!(resp.fields().is_empty());
+}
+
+#[test]
+fn patron_status_expire_date() {
+    let date = util::parse_timestamp("2027-06-30T23:59:59-0400").unwrap();
+
+    let config = load_config("patron-status-expire-date: true");
+    let settings = account_settings(&config);
+
+    let mut patron = patron::Patron::new("patron1234", "Test Patron".to_string());
+    patron.expire_date = Some(patron::format_expire_date(settings, date));
+
+    let mut resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    patron::add_status_expire_date(settings, &mut resp, Some(&patron));
+
+    assert_eq!(resp.get_field_value("PA"), Some("20270630"));
+
+    // Unknown patrons have no expire date to report.
+    let mut resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    patron::add_status_expire_date(settings, &mut resp, None);
+
+    assert_eq!(resp.get_field_value("PA"), None);
+
+    // Disabled by default.
+    let config = load_config("");
+    let mut resp = sip2::Message::new(&sip2::spec::M_PATRON_STATUS_RESP, vec![], vec![]);
+    patron::add_status_expire_date(account_settings(&config), &mut resp, Some(&patron));
+
+    assert_eq!(resp.get_field_value("PA"), None);
+
+    // SIP date format, in the server's local time zone.
+    let config = load_config("expire-date-use-sip-date-format: true");
+    let formatted = patron::format_expire_date(account_settings(&config), date);
+
+    assert_eq!(formatted.len(), 18);
+    assert_eq!(
+        formatted,
+        sip2::util::format_sip_date(&eg::date::to_local_timezone_fixed(date))
+    );
+}
+
+#[test]
+fn checkout_action() {
+    use checkout::CheckoutAction;
+
+    // Already checked out to this patron.
+    assert_eq!(CheckoutAction::new(true, Some(1), 1), CheckoutAction::Renew);
+    assert_eq!(
+        CheckoutAction::new(false, Some(1), 1),
+        CheckoutAction::AlreadyOut
+    );
+
+    // Not checked out, or checked out to another patron.
+    for renew_ok in [true, false] {
+        assert_eq!(
+            CheckoutAction::new(renew_ok, None, 1),
+            CheckoutAction::Checkout
+        );
+        assert_eq!(
+            CheckoutAction::new(renew_ok, Some(2), 1),
+            CheckoutAction::Checkout
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Clients which fail to complete the TLS handshake within this many
+/// seconds are disconnected.
+const HANDSHAKE_TIMEOUT: u64 = 10;
+
+/// Server-side TLS stream wrapping a SIP client's TCP connection.
+pub struct TlsStream {
+    stream: StreamOwned<ServerConnection, TcpStream>,
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl sip2::SipStream for TlsStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.sock.set_read_timeout(timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        self.stream.sock.shutdown(Shutdown::Both)
+    }
+}
+
+/// Load the server certificate chain and private key from PEM files.
+pub fn load_config(cert_file: &str, key_file: &str) -> Result<Arc<ServerConfig>, String> {
+    let file = File::open(cert_file)
+        .map_err(|e| format!("Cannot open TLS certificate file {cert_file}: {e}"))?;
+
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Invalid TLS certificate file {cert_file}: {e}"))?;
+
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {cert_file}"));
+    }
+
+    let file =
+        File::open(key_file).map_err(|e| format!("Cannot open TLS key file {key_file}: {e}"))?;
+
+    let key: PrivateKeyDer<'static> = rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Invalid TLS key file {key_file}: {e}"))?
+        .ok_or_else(|| format!("No private key found in {key_file}"))?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Invalid TLS certificate or key: {e}"))?;
+
+    Ok(Arc::new(config))
+}
+
+/// Complete the TLS handshake with a newly connected client.
+///
+/// On failure, the TCP connection is shut down.
+pub fn accept(config: &Arc<ServerConfig>, sock: TcpStream) -> Result<TlsStream, String> {
+    let conn = match ServerConnection::new(config.clone()) {
+        Ok(c) => c,
+        Err(e) => {
+            sock.shutdown(Shutdown::Both).ok();
+            return Err(format!("Cannot create TLS connection: {e}"));
+        }
+    };
+
+    let mut stream = StreamOwned::new(conn, sock);
+
+    if let Err(e) = handshake(&mut stream) {
+        stream.sock.shutdown(Shutdown::Both).ok();
+        return Err(format!("TLS handshake failed: {e}"));
+    }
+
+    Ok(TlsStream { stream })
+}
+
+fn handshake(stream: &mut StreamOwned<ServerConnection, TcpStream>) -> io::Result<()> {
+    stream
+        .sock
+        .set_read_timeout(Some(Duration::from_secs(HANDSHAKE_TIMEOUT)))?;
+
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+
+    // The Session applies its own read timeouts.
+    stream.sock.set_read_timeout(None)
+}
@@ -1,4 +1,6 @@
 use super::session::Session;
+use chrono::DateTime;
+use eg::date;
 use eg::result::EgResult;
 use eg::EgValue;
 use evergreen as eg;
@@ -67,6 +69,59 @@ impl Session {
         return Ok(None);
     }
 
+    /// Current time in the time zone (lib.timezone) of the org unit
+    /// this session circulates at.
+    pub fn circ_lib_now(&mut self) -> EgResult<date::EgDate> {
+        let org_id = self.get_circ_org_id()?;
+
+        match self
+            .settings_mut()
+            .get_value_at_org("lib.timezone", org_id)?
+            .as_str()
+        {
+            Some(tz) => date::set_timezone(date::now(), tz),
+            None => Ok(date::now()),
+        }
+    }
+
+    /// ID of the org unit this session circulates at.
+    ///
+    /// Uses the SIP account's org-unit when configured, otherwise
+    /// the workstation org.
+    pub fn get_circ_org_id(&self) -> EgResult<i64> {
+        if self.has_account() {
+            if let Some(id) = self.account().org_unit_id() {
+                return Ok(id);
+            }
+        }
+
+        self.get_ws_org_id()
+    }
+
+    /// Resolve the SIP account's org-unit shortname or ID.
+    ///
+    /// Returns false if the account has an org-unit which does not exist.
+    pub fn resolve_account_org(&mut self) -> EgResult<bool> {
+        let Some(org_unit) = self.account().org_unit().map(|o| o.to_string()) else {
+            return Ok(true);
+        };
+
+        let org_id = if let Ok(id) = org_unit.parse::<i64>() {
+            self.org_from_id(id)?.map(|o| o.id()).transpose()?
+        } else {
+            self.org_from_sn(&org_unit)?.map(|o| o.id()).transpose()?
+        };
+
+        let Some(org_id) = org_id else {
+            log::error!("{self} No such org unit for SIP account: {org_unit}");
+            return Ok(false);
+        };
+
+        self.account_mut().set_org_unit_id(org_id);
+
+        Ok(true)
+    }
+
     /// Panics if this session is not authenticated.
     pub fn get_ws_org_id(&self) -> EgResult<i64> {
         let requestor = self
@@ -168,3 +223,65 @@ impl Session {
         }
     }
 }
+
+/// Timestamp formats we may see from the database, tried in order
+/// after RFC 3339.
+const TIMESTAMP_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S%z", "%Y-%m-%dT%H:%M:%S%.f%z"];
+
+/// Parse a timestamp as returned by Postgres, e.g.
+/// "2024-01-02T03:04:05-0500", "2024-01-02T03:04:05.123456-05:00",
+/// or a bare "2024-01-02".
+pub fn parse_timestamp(value: &str) -> EgResult<date::EgDate> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(dt);
+    }
+
+    for fmt in TIMESTAMP_FORMATS {
+        if let Ok(dt) = DateTime::parse_from_str(value, fmt) {
+            return Ok(dt);
+        }
+    }
+
+    if value.len() == 10 {
+        return date::parse_datetime(value);
+    }
+
+    Err(format!("Unsupported timestamp format: '{value}'").into())
+}
+
+/// Parse an integer value, e.g. an ID, naming the field in the error
+/// so failures can be traced to their source, e.g.
+/// "Invalid int value for hold.id: null".
+pub fn parse_id_named(field: &str, value: &EgValue) -> EgResult<i64> {
+    value
+        .as_int()
+        .ok_or_else(|| format!("Invalid int value for {field}: {}", value.dump()).into())
+}
+
+/// Parse a float value, naming the field in the error.
+pub fn parse_float_named(field: &str, value: &EgValue) -> EgResult<f64> {
+    value
+        .as_float()
+        .ok_or_else(|| format!("Invalid float value for {field}: {}", value.dump()).into())
+}
+
+/// Value of a message field for logging, masked with redact_value()
+/// when requested.
+pub fn log_field_value(msg: &sip2::Message, code: &str, redact: bool) -> Option<String> {
+    msg.get_field_value(code).map(|v| match redact {
+        true => redact_value(v),
+        false => v.to_string(),
+    })
+}
+
+/// Mask all but the last 4 characters of a value, e.g. a barcode,
+/// for logging.
+pub fn redact_value(value: &str) -> String {
+    let len = value.chars().count();
+    if len <= 4 {
+        return "*".repeat(len);
+    }
+
+    let tail: String = value.chars().skip(len - 4).collect();
+    format!("{}{tail}", "*".repeat(len - 4))
+}
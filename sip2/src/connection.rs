@@ -2,6 +2,7 @@ use super::error::Error;
 use super::spec;
 use super::Message;
 use deunicode::deunicode;
+use std::io;
 use std::io::prelude::*;
 use std::net::{Shutdown, TcpStream};
 use std::str;
@@ -10,10 +11,30 @@ use std::time::Duration;
 // Read data from the socket in chunks this size.
 const READ_BUFSIZE: usize = 256;
 
+/// Byte stream which carries SIP messages, e.g. a TcpStream or a TLS
+/// stream wrapping one.
+pub trait SipStream: Read + Write + Send {
+    /// Set the read timeout of the underlying socket.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+
+    /// Shutdown the underlying socket.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl SipStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
 /// Manages a TCP connection to a SIP server and handles message sending
 /// and receiving.
 pub struct Connection {
-    tcp_stream: TcpStream,
+    tcp_stream: Box<dyn SipStream>,
 
     // If set, non-ASCII chars are removed from outbound messages.
     ascii: bool,
@@ -34,7 +55,7 @@ impl Connection {
 
         match TcpStream::connect(sip_host) {
             Ok(stream) => Ok(Connection {
-                tcp_stream: stream,
+                tcp_stream: Box::new(stream),
                 ascii: false,
             }),
             Err(s) => {
@@ -45,9 +66,14 @@ impl Connection {
     }
 
     pub fn from_stream(tcp_stream: TcpStream) -> Self {
+        Connection::from_sip_stream(Box::new(tcp_stream))
+    }
+
+    /// Create a connection from any SipStream, e.g. a TLS stream.
+    pub fn from_sip_stream(stream: Box<dyn SipStream>) -> Self {
         Connection {
             ascii: false,
-            tcp_stream: tcp_stream,
+            tcp_stream: stream,
         }
     }
 
@@ -59,7 +85,7 @@ impl Connection {
     pub fn disconnect(&self) -> Result<(), Error> {
         log::debug!("Connection::disconnect()");
 
-        match self.tcp_stream.shutdown(Shutdown::Both) {
+        match self.tcp_stream.shutdown() {
             Ok(_) => Ok(()),
             Err(s) => {
                 log::error!("disconnect() failed: {}", s);
@@ -81,7 +107,7 @@ impl Connection {
         // No need to redact here since SIP replies do not include passwords.
         log::info!("OUTBOUND: {}", msg_sip);
 
-        match self.tcp_stream.write(&msg_sip.as_bytes()) {
+        match self.tcp_stream.write_all(&msg_sip.as_bytes()) {
             Ok(_) => Ok(()),
             Err(s) => {
                 log::error!("send() failed: {}", s);
@@ -1,4 +1,5 @@
 pub use self::connection::Connection;
+pub use self::connection::SipStream;
 pub use self::error::Error;
 pub use self::message::Field;
 pub use self::message::FixedField;
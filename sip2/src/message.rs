@@ -232,6 +232,63 @@ impl Message {
         }
     }
 
+    /// Remove trailing occurrences of the listed fields, in the order
+    /// the codes are provided, until the SIP string version of the
+    /// message is no longer than `max_len`.
+    ///
+    /// If any fields are removed, an AF screen message containing
+    /// `notice` is added, accounting for its length.  Returns true
+    /// if the message was trimmed.
+    ///
+    /// ```
+    /// use sip2::{Message, Field};
+    /// use sip2::spec;
+    ///
+    /// let mut msg = Message::new(
+    ///     &spec::M_LOGIN,
+    ///     vec![],
+    ///     vec![
+    ///         Field::new("AU", "item1"),
+    ///         Field::new("AU", "item2"),
+    ///     ],
+    /// );
+    ///
+    /// assert_eq!(msg.to_sip(), "93AUitem1|AUitem2|");
+    /// assert!(msg.trim_to_length(17, &["AU"], "More"));
+    /// assert_eq!(msg.to_sip(), "93AFMore|AUitem1|");
+    /// ```
+    pub fn trim_to_length(&mut self, max_len: usize, codes: &[&str], notice: &str) -> bool {
+        let len = self.to_sip().len();
+
+        if len <= max_len {
+            return false;
+        }
+
+        let notice_field = Field::new(spec::F_SCREEN_MSG.code, notice);
+        let mut excess = len + notice_field.to_sip().len() - max_len;
+        let mut trimmed = false;
+
+        for code in codes {
+            while excess > 0 {
+                let pos = match self.fields.iter().rposition(|f| f.code().eq(*code)) {
+                    Some(p) => p,
+                    None => break,
+                };
+
+                let field = self.fields.remove(pos);
+                excess = excess.saturating_sub(field.to_sip().len());
+                trimmed = true;
+            }
+        }
+
+        if trimmed {
+            self.fields.push(notice_field);
+            self.sort_fields();
+        }
+
+        trimmed
+    }
+
     /// Return the first value with the specified field code.
     pub fn get_field_value(&self, code: &str) -> Option<&str> {
         if let Some(f) = self.fields().iter().filter(|f| f.code() == code).next() {
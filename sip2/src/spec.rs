@@ -172,6 +172,8 @@ impl Message {
             m if m == M_ITEM_INFO_RESP.code => Some(&M_ITEM_INFO_RESP),
             m if m == M_PATRON_STATUS.code => Some(&M_PATRON_STATUS),
             m if m == M_PATRON_STATUS_RESP.code => Some(&M_PATRON_STATUS_RESP),
+            m if m == M_PATRON_ENABLE.code => Some(&M_PATRON_ENABLE),
+            m if m == M_PATRON_ENABLE_RESP.code => Some(&M_PATRON_ENABLE_RESP),
             m if m == M_PATRON_INFO.code => Some(&M_PATRON_INFO),
             m if m == M_PATRON_INFO_RESP.code => Some(&M_PATRON_INFO_RESP),
             m if m == M_CHECKOUT.code => Some(&M_CHECKOUT),
@@ -774,6 +776,20 @@ pub const M_PATRON_STATUS_RESP: Message = Message {
     fixed_fields: &[&FF_PATRON_STATUS, &FF_LANGUAGE, &FF_DATE],
 };
 
+/// Message 25
+pub const M_PATRON_ENABLE: Message = Message {
+    code: "25",
+    label: "Patron Enable",
+    fixed_fields: &[&FF_DATE],
+};
+
+/// Message 26
+pub const M_PATRON_ENABLE_RESP: Message = Message {
+    code: "26",
+    label: "Patron Enable Response",
+    fixed_fields: &[&FF_PATRON_STATUS, &FF_LANGUAGE, &FF_DATE],
+};
+
 /// Message 63
 pub const M_PATRON_INFO: Message = Message {
     code: "63",
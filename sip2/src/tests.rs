@@ -2,6 +2,8 @@ use super::message::Field;
 use super::message::FixedField;
 use super::message::Message;
 use super::spec;
+use super::util;
+use chrono::DateTime;
 
 #[test]
 fn invalid_fixed_field() {
@@ -51,3 +53,191 @@ fn fixed_field_to_str() {
     let ff = FixedField::new(&spec::FF_MAX_PRINT_WIDTH, "999").unwrap();
     assert_eq!(ff.to_sip(), "999");
 }
+
+#[test]
+fn trim_to_length() {
+    let fields = || {
+        vec![
+            Field::new(spec::F_HOLD_ITEMS.code, "hold1"),
+            Field::new(spec::F_CHARGED_ITEMS.code, "charge1"),
+            Field::new(spec::F_CHARGED_ITEMS.code, "charge2"),
+        ]
+    };
+
+    let mut msg = Message::new(&spec::M_LOGIN, vec![], fields());
+    let sip = msg.to_sip();
+
+    // Under the cap; nothing changes.
+    assert_eq!(msg.trim_to_length(100, &["AU", "AS"], "Truncated"), false);
+    assert_eq!(msg.to_sip(), sip);
+
+    // Over the cap; trailing charged items go first.
+    let mut msg = Message::new(&spec::M_LOGIN, vec![], fields());
+    assert_eq!(msg.trim_to_length(25, &["AU", "AS"], "Truncated"), true);
+    assert_eq!(msg.to_sip(), "93AFTruncated|AShold1|");
+    assert!(msg.to_sip().len() <= 25);
+}
+
+#[test]
+fn sip_dates() {
+    let dt = DateTime::parse_from_rfc3339("1999-12-31T23:59:58+05:30").unwrap();
+    let sip_date = util::format_sip_date(&dt);
+
+    assert_eq!(sip_date, "19991231    235958");
+    assert_eq!(sip_date.len(), 18);
+
+    let parsed = util::parse_sip_date(&sip_date).unwrap();
+    assert_eq!(parsed, dt.naive_local());
+}
+
+#[test]
+fn bool_helpers() {
+    assert_eq!(util::sip_bool(true), "Y");
+    assert_eq!(util::sip_bool(false), "N");
+    assert_eq!(util::space_bool(true), "Y");
+    assert_eq!(util::space_bool(false), " ");
+    assert_eq!(util::num_bool(true), "1");
+    assert_eq!(util::num_bool(false), "0");
+}
+
+#[test]
+fn patron_status_message() {
+    // Charge and renewal denied, card lost, excessive fines.
+    let mut flags = [false; 14];
+    flags[0] = true;
+    flags[1] = true;
+    flags[4] = true;
+    flags[10] = true;
+
+    let status = util::patron_status(&flags);
+    assert_eq!(status, "YY  Y     Y   ");
+    assert_eq!(status.len(), 14);
+
+    let msg = Message::from_values(
+        &spec::M_PATRON_STATUS_RESP,
+        &[&status, "000", "20230405    060708"],
+        &[
+            ("AO", "example"),
+            ("AA", "12345"),
+            ("AE", "Doe, Jane"),
+            ("BL", util::sip_bool(true)),
+            ("CQ", util::sip_bool(false)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(
+        msg.to_sip(),
+        "24YY  Y     Y   00020230405    060708AA12345|AEDoe, Jane|AOexample|BLY|CQN|"
+    );
+}
+
+#[test]
+fn patron_status_flag_positions() {
+    let set: [fn(&mut util::PatronStatusFlags); 14] = [
+        |f| f.charge_denied = true,
+        |f| f.renew_denied = true,
+        |f| f.recall_denied = true,
+        |f| f.holds_denied = true,
+        |f| f.card_lost = true,
+        |f| f.max_charged = true,
+        |f| f.max_overdue = true,
+        |f| f.max_renewals = true,
+        |f| f.max_claims_returned = true,
+        |f| f.max_lost = true,
+        |f| f.max_fines = true,
+        |f| f.max_fees = true,
+        |f| f.recall_overdue = true,
+        |f| f.max_billed = true,
+    ];
+
+    assert_eq!(util::PatronStatusFlags::default().to_sip(), " ".repeat(14));
+
+    for (pos, setter) in set.iter().enumerate() {
+        let mut flags = util::PatronStatusFlags::default();
+        setter(&mut flags);
+
+        let status = flags.to_sip();
+        let yes: Vec<usize> = status.match_indices('Y').map(|(i, _)| i).collect();
+
+        assert_eq!(yes, vec![pos], "flag {pos} set in status '{status}'");
+    }
+}
+
+#[test]
+fn refusal_responses() {
+    let date = "20230405    060708";
+    let fields = [("AO", "example"), ("AA", "patron1"), ("AB", "item1")];
+
+    let requests = [
+        Message::from_values(&spec::M_CHECKIN, &["N", date, date], &fields),
+        Message::from_values(&spec::M_CHECKOUT, &["N", "N", date, date], &fields),
+        Message::from_values(&spec::M_ITEM_INFO, &[date], &fields),
+        Message::from_values(&spec::M_PATRON_STATUS, &["000", date], &fields),
+        Message::from_values(&spec::M_PATRON_ENABLE, &[date], &fields),
+        Message::from_values(&spec::M_RENEW, &["N", "N", date, date], &fields),
+        Message::from_values(&spec::M_END_PATRON_SESSION, &[date], &fields),
+        Message::from_values(&spec::M_FEE_PAID, &[date, "01", "00", "USD"], &fields),
+        Message::from_values(&spec::M_PATRON_INFO, &["000", date, "          "], &fields),
+    ];
+
+    let expected = [
+        ("10", "0"),
+        ("12", "0"),
+        ("18", "01"),
+        ("24", "YYYY          "),
+        ("26", "YYYY          "),
+        ("30", "0"),
+        ("36", "N"),
+        ("38", "N"),
+        ("64", "YYYY          "),
+    ];
+
+    for (req, (code, status)) in requests.iter().zip(expected) {
+        let req = req.as_ref().unwrap();
+        let resp = util::refusal_response(req, "Not logged in").unwrap();
+
+        assert_eq!(resp.spec().code, code);
+        assert_eq!(resp.fixed_fields()[0].value(), status);
+        assert_eq!(resp.get_field_value("AF").as_deref(), Some("Not logged in"));
+
+        // Serializes cleanly.
+        assert!(resp.to_sip().starts_with(code));
+    }
+
+    // No defined failure response for a login.
+    let login = Message::from_values(&spec::M_LOGIN, &["0", "0"], &[]).unwrap();
+    assert!(util::refusal_response(&login, "Not logged in").is_none());
+}
+
+#[test]
+fn protocol_version_fields() {
+    let sc_status = Message::from_sip("9900301.00").unwrap();
+    let version = sc_status.fixed_fields()[2].value();
+    assert_eq!(version, "1.00");
+
+    let mut resp = Message::from_values(
+        &spec::M_PATRON_STATUS_RESP,
+        &["              ", "000", "20230405    060708"],
+        &[
+            ("AA", "12345"),
+            ("AE", "Doe, Jane"),
+            ("AF", "Hello"),
+            ("AO", "example"),
+            ("BL", "Y"),
+            ("BV", "1.50"),
+            ("CQ", "Y"),
+        ],
+    )
+    .unwrap();
+
+    let before = resp.to_sip();
+    assert_eq!(util::apply_protocol_version(&mut resp, "2.00"), 0);
+    assert_eq!(resp.to_sip(), before);
+
+    assert_eq!(util::apply_protocol_version(&mut resp, version), 3);
+    assert_eq!(
+        resp.to_sip(),
+        "24              00020230405    060708AA12345|AEDoe, Jane|AFHello|AOexample|"
+    );
+}
@@ -1,7 +1,8 @@
 //! SIP utility functions
 use super::error;
+use super::message::Message;
 use super::spec;
-use chrono::{DateTime, FixedOffset, Local};
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime};
 use log::error;
 
 /// Clean up a string for inclusion in a SIP message
@@ -52,10 +53,66 @@ pub fn sip_date(iso_date: &str) -> Result<String, error::Error> {
 
 /// Same as sip_date(), but starting from a DateTime object.
 pub fn sip_date_from_dt(dt: &DateTime<FixedOffset>) -> String {
+    format_sip_date(dt)
+}
+
+/// Format a date/time using the SIP fixed-field date layout.
+///
+/// The date is formatted in its own time zone offset.  Translate the
+/// date into the desired time zone first as needed.
+///
+/// ```
+/// use sip2::util;
+/// use chrono::DateTime;
+///
+/// let dt = DateTime::parse_from_rfc3339("2023-04-05T06:07:08-04:00").unwrap();
+/// assert_eq!(util::format_sip_date(&dt), "20230405    060708");
+/// ```
+pub fn format_sip_date(dt: &DateTime<FixedOffset>) -> String {
     dt.format(spec::SIP_DATE_FORMAT).to_string()
 }
 
+/// Parse a SIP fixed-field date/time.
+///
+/// The 4-character time zone portion of the date is ignored, since
+/// SIP clients rarely populate it, so the result has no time zone.
+///
+/// ```
+/// use sip2::util;
+///
+/// let dt = util::parse_sip_date("20230405    060708").unwrap();
+/// assert_eq!(dt.format("%Y-%m-%d %H:%M:%S").to_string(), "2023-04-05 06:07:08");
+///
+/// let dt = util::parse_sip_date("20230405   Z060708").unwrap();
+/// assert_eq!(dt.format("%Y-%m-%d %H:%M:%S").to_string(), "2023-04-05 06:07:08");
+///
+/// assert!(util::parse_sip_date("2023-04-05").is_err());
+/// ```
+pub fn parse_sip_date(sip_date: &str) -> Result<NaiveDateTime, error::Error> {
+    if sip_date.len() != 18 || !sip_date.is_ascii() {
+        error!("Invalid SIP date: {sip_date}");
+        return Err(error::Error::DateFormatError);
+    }
+
+    // Replace the time zone portion with the spaces our format expects.
+    let text = format!("{}    {}", &sip_date[0..8], &sip_date[12..18]);
+
+    NaiveDateTime::parse_from_str(&text, spec::SIP_DATE_FORMAT).map_err(|e| {
+        error!("Error parsing SIP date: {sip_date} : {e}");
+        error::Error::DateFormatError
+    })
+}
+
 /// Returns "Y" on true, " " on false.
+///
+/// Used for fixed-field flag strings, e.g. the patron status, where
+/// an unset flag is a space rather than "N".
+///
+/// ```
+/// use sip2::util;
+/// assert_eq!(util::space_bool(true), "Y");
+/// assert_eq!(util::space_bool(false), " ");
+/// ```
 pub fn space_bool(value: bool) -> &'static str {
     match value {
         true => "Y",
@@ -63,6 +120,16 @@ pub fn space_bool(value: bool) -> &'static str {
     }
 }
 
+/// Returns "Y" on true, "N" on false.
+///
+/// Used for single-character yes/no fixed fields and variable-length
+/// fields like valid patron (BL) and valid patron password (CQ).
+///
+/// ```
+/// use sip2::util;
+/// assert_eq!(util::sip_bool(true), "Y");
+/// assert_eq!(util::sip_bool(false), "N");
+/// ```
 pub fn sip_bool(value: bool) -> &'static str {
     match value {
         true => "Y",
@@ -70,6 +137,13 @@ pub fn sip_bool(value: bool) -> &'static str {
     }
 }
 
+/// Returns "1" on true, "0" on false.
+///
+/// ```
+/// use sip2::util;
+/// assert_eq!(util::num_bool(true), "1");
+/// assert_eq!(util::num_bool(false), "0");
+/// ```
 pub fn num_bool(value: bool) -> &'static str {
     match value {
         true => "1",
@@ -77,7 +151,278 @@ pub fn num_bool(value: bool) -> &'static str {
     }
 }
 
+/// Build the 14-character patron status fixed field.
+///
+/// Flags are in spec order: charge privileges denied, renewal
+/// privileges denied, recall privileges denied, hold privileges
+/// denied, card reported lost, too many items charged, too many
+/// items overdue, too many renewals, too many claims of items
+/// returned, too many items lost, excessive outstanding fines,
+/// excessive outstanding fees, recall overdue, too many items billed.
+///
+/// A set flag means the patron is blocked and is reported as "Y".
+/// Unset flags are reported as a space.
+///
+/// ```
+/// use sip2::util;
+///
+/// let mut flags = [false; 14];
+/// assert_eq!(util::patron_status(&flags), "              ");
+///
+/// flags[0] = true;
+/// flags[13] = true;
+/// assert_eq!(util::patron_status(&flags), "Y            Y");
+/// ```
+pub fn patron_status(flags: &[bool; 14]) -> String {
+    flags.iter().map(|f| space_bool(*f)).collect()
+}
+
+/// Request message codes in the order they are reported in the
+/// ACS Status supported messages (BX) field: patron status, checkout,
+/// checkin, block patron, SC/ACS status, request SC/ACS resend, login,
+/// patron information, end patron session, fee paid, item information,
+/// item status update, patron enable, hold, renew, renew all.
+pub const SUPPORTED_MESSAGES_ORDER: &[&str] = &[
+    "23", "11", "09", "01", "99", "97", "93", "63", "35", "37", "17", "19", "25", "15", "29", "65",
+];
+
+/// Build the 16-character ACS Status supported messages (BX) value
+/// from the request message codes the ACS handles.
+///
+/// ```
+/// use sip2::util;
+///
+/// assert_eq!(util::supported_messages(&["99", "93", "11"]), "NYNNYNYNNNNNNNNN");
+/// assert_eq!(util::supported_messages(&[]), "NNNNNNNNNNNNNNNN");
+/// ```
+pub fn supported_messages(codes: &[&str]) -> String {
+    SUPPORTED_MESSAGES_ORDER
+        .iter()
+        .map(|c| sip_bool(codes.contains(c)))
+        .collect()
+}
+
+/// Named patron status conditions, one per patron status position.
+///
+/// A set flag means the patron is blocked and is reported as "Y".
+///
+/// ```
+/// use sip2::util::PatronStatusFlags;
+///
+/// let flags = PatronStatusFlags {
+///     charge_denied: true,
+///     max_fines: true,
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(flags.to_sip(), "Y         Y   ");
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PatronStatusFlags {
+    pub charge_denied: bool,
+    pub renew_denied: bool,
+    pub recall_denied: bool,
+    pub holds_denied: bool,
+    pub card_lost: bool,
+    pub max_charged: bool,
+    pub max_overdue: bool,
+    pub max_renewals: bool,
+    pub max_claims_returned: bool,
+    pub max_lost: bool,
+    pub max_fines: bool,
+    pub max_fees: bool,
+    pub recall_overdue: bool,
+    pub max_billed: bool,
+}
+
+impl PatronStatusFlags {
+    /// Build the 14-character patron status fixed field.
+    pub fn to_sip(&self) -> String {
+        patron_status(&[
+            self.charge_denied,
+            self.renew_denied,
+            self.recall_denied,
+            self.holds_denied,
+            self.card_lost,
+            self.max_charged,
+            self.max_overdue,
+            self.max_renewals,
+            self.max_claims_returned,
+            self.max_lost,
+            self.max_fines,
+            self.max_fees,
+            self.recall_overdue,
+            self.max_billed,
+        ])
+    }
+}
+
 /// Stringify a number left padded with zeros.
 pub fn sip_count4(value: usize) -> String {
     format!("{value:0>4}")
 }
+
+/// Truncate a field value to at most `max_chars` characters.
+///
+/// Counts characters, not bytes, so multi-byte values are never
+/// split mid-character.
+///
+/// ```
+/// use sip2::util;
+///
+/// assert_eq!(util::truncate_field("The Hobbit", 3), "The");
+/// assert_eq!(util::truncate_field("Café au lait", 4), "Café");
+/// assert_eq!(util::truncate_field("Short", 255), "Short");
+/// ```
+pub fn truncate_field(value: &str, max_chars: usize) -> &str {
+    match value.char_indices().nth(max_chars) {
+        Some((idx, _)) => &value[..idx],
+        None => value,
+    }
+}
+
+/// Build a failure response for a request we refuse to process, e.g.
+/// one sent before the SIP client has logged in.
+///
+/// The response reports failure in its fixed fields, echoes the
+/// institution and patron/item barcodes from the request, and includes
+/// the screen message (AF).  Returns None for message types which have
+/// no defined failure response.
+///
+/// ```
+/// use sip2::{spec, util, Message};
+///
+/// let req = Message::from_values(
+///     &spec::M_ITEM_INFO,
+///     &["20230405    060708"],
+///     &[("AO", "example"), ("AB", "item1")],
+/// ).unwrap();
+///
+/// let resp = util::refusal_response(&req, "Not logged in").unwrap();
+/// assert_eq!(resp.spec().code, "18");
+/// assert_eq!(resp.get_field_value("AB").as_deref(), Some("item1"));
+/// assert_eq!(resp.get_field_value("AF").as_deref(), Some("Not logged in"));
+/// ```
+pub fn refusal_response(request: &Message, screen_msg: &str) -> Option<Message> {
+    let now = sip_date_now();
+    let institution = request.get_field_value("AO").unwrap_or_default();
+    let patron = request.get_field_value("AA").unwrap_or_default();
+    let item = request.get_field_value("AB").unwrap_or_default();
+
+    let (spec, fixed_fields, fields): (_, Vec<&str>, Vec<(&str, &str)>) = match request.spec().code
+    {
+        "09" => (
+            &spec::M_CHECKIN_RESP,
+            vec!["0", "N", "N", "N", &now],
+            vec![("AO", institution), ("AB", item), ("AQ", "")],
+        ),
+        "11" => (
+            &spec::M_CHECKOUT_RESP,
+            vec!["0", "N", "N", "N", &now],
+            vec![
+                ("AO", institution),
+                ("AA", patron),
+                ("AB", item),
+                ("AJ", ""),
+            ],
+        ),
+        "17" => (
+            &spec::M_ITEM_INFO_RESP,
+            vec!["01", "00", "01", &now],
+            vec![("AB", item), ("AJ", "")],
+        ),
+        "23" => (
+            &spec::M_PATRON_STATUS_RESP,
+            vec!["YYYY          ", "000", &now],
+            vec![("AO", institution), ("AA", patron), ("AE", ""), ("BL", "N")],
+        ),
+        "25" => (
+            &spec::M_PATRON_ENABLE_RESP,
+            vec!["YYYY          ", "000", &now],
+            vec![("AO", institution), ("AA", patron), ("AE", ""), ("BL", "N")],
+        ),
+        "29" => (
+            &spec::M_RENEW_RESP,
+            vec!["0", "N", "N", "N", &now],
+            vec![
+                ("AO", institution),
+                ("AA", patron),
+                ("AB", item),
+                ("AJ", ""),
+            ],
+        ),
+        "35" => (
+            &spec::M_END_PATRON_SESSION_RESP,
+            vec!["N", &now],
+            vec![("AO", institution), ("AA", patron)],
+        ),
+        "37" => (
+            &spec::M_FEE_PAID_RESP,
+            vec!["N", &now],
+            vec![("AO", institution), ("AA", patron)],
+        ),
+        "63" => (
+            &spec::M_PATRON_INFO_RESP,
+            vec![
+                "YYYY          ",
+                "000",
+                &now,
+                "0000",
+                "0000",
+                "0000",
+                "0000",
+                "0000",
+                "0000",
+            ],
+            vec![("AO", institution), ("AA", patron), ("AE", ""), ("BL", "N")],
+        ),
+        _ => return None,
+    };
+
+    let mut resp = Message::from_values(spec, &fixed_fields, &fields).ok()?;
+    resp.add_field("AF", screen_msg);
+
+    Some(resp)
+}
+
+/// Variable-length fields introduced in SIP 2.00.
+///
+/// SIP 1.00 clients may not know how to parse these.
+pub const SIP2_FIELDS: &[&str] = &[
+    "BD", "BE", "BF", "BG", "BH", "BI", "BK", "BL", "BM", "BN", "BO", "BP", "BQ", "BR", "BS", "BT",
+    "BU", "BV", "BW", "BX", "BY", "BZ", "CA", "CB", "CC", "CD", "CF", "CG", "CH", "CI", "CJ", "CK",
+    "CL", "CM", "CN", "CO", "CP", "CQ",
+];
+
+/// True if the SIP protocol version, as reported in the SC Status
+/// protocol version fixed field, supports the field.
+///
+/// Unrecognized versions are assumed to support all fields.
+///
+/// ```
+/// use sip2::util;
+///
+/// assert!(util::version_supports_field("2.00", "BV"));
+/// assert!(!util::version_supports_field("1.00", "BV"));
+/// assert!(util::version_supports_field("1.00", "AF"));
+/// assert!(util::version_supports_field("bogus", "CQ"));
+/// ```
+pub fn version_supports_field(version: &str, code: &str) -> bool {
+    match version.trim().parse::<f32>() {
+        Ok(v) if v < 2.0 => !SIP2_FIELDS.contains(&code),
+        _ => true,
+    }
+}
+
+/// Remove fields from a message which are not supported by the
+/// SIP protocol version.
+///
+/// Returns the number of fields removed.
+pub fn apply_protocol_version(msg: &mut Message, version: &str) -> usize {
+    let count = msg.fields().len();
+
+    msg.fields_mut()
+        .retain(|f| version_supports_field(version, f.code()));
+
+    count - msg.fields().len()
+}